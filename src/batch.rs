@@ -0,0 +1,270 @@
+//! Batch requests, as `multipart/mixed` bodies.
+//!
+//! Google APIs and OData services accept several requests sent together as
+//! the parts of a single `multipart/mixed` request, and answer with a
+//! `multipart/mixed` response holding one response per part. [`Batch`]
+//! collects the requests, `RequestBuilder::batch` turns them into the body
+//! of the batch request and `HttpResponse::batch_parts` splits the answer
+//! back up. `Client::batch` does all three.
+//!
+//! ```rust
+//! use nightfly::batch::Batch;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let mut batch = Batch::new();
+//! batch
+//!     .push(client.get("https://www.googleapis.com/drive/v3/files/a").build()?)
+//!     .push(client.get("https://www.googleapis.com/drive/v3/files/b").build()?);
+//! for res in client.batch("https://www.googleapis.com/batch/drive/v3", &batch)? {
+//!     println!("{} {:?}", res.status(), res.headers().get("content-id"));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each request part has a `Content-ID` of its position, counting from 1,
+//! and the response parts keep the `Content-ID` the server gave them, which
+//! usually refers back to it. Servers answer in the order of the requests.
+
+use bytes::{Bytes, BytesMut};
+use http::header::{HeaderMap, HeaderName, HeaderValue, HOST};
+use http::Version;
+
+use crate::lunatic_impl::client::request_to_vec;
+use crate::parts::Part;
+use crate::{Body, HttpResponse, Request, ResponseBuilderExt, Url};
+
+static CONTENT_ID: HeaderName = HeaderName::from_static("content-id");
+
+/// Requests to send together as one `multipart/mixed` request, see the
+/// module documentation.
+#[derive(Clone, Debug)]
+pub struct Batch {
+    boundary: String,
+    requests: Vec<Request>,
+}
+
+impl Batch {
+    /// An empty batch.
+    pub fn new() -> Batch {
+        Batch {
+            boundary: format!("batch_{}", uuid::Uuid::new_v4().simple()),
+            requests: Vec::new(),
+        }
+    }
+
+    /// Adds a request to the batch. Its timeout is ignored, only the one of
+    /// the batch request applies.
+    pub fn push(&mut self, request: Request) -> &mut Batch {
+        self.requests.push(request);
+        self
+    }
+
+    /// The number of requests in the batch.
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Whether the batch has no requests.
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// The `Content-Type` of the batch request.
+    pub(crate) fn content_type(&self) -> String {
+        format!("multipart/mixed; boundary={}", self.boundary)
+    }
+
+    /// The body of the batch request.
+    pub(crate) fn body(&self) -> Bytes {
+        let mut body = BytesMut::new();
+        for (i, req) in self.requests.iter().enumerate() {
+            body.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+            body.extend_from_slice(b"Content-Type: application/http\r\n");
+            body.extend_from_slice(b"Content-Transfer-Encoding: binary\r\n");
+            body.extend_from_slice(format!("Content-ID: <{}>\r\n\r\n", i + 1).as_bytes());
+
+            let mut headers = req.headers().clone();
+            if let Some(host) = req.url().host_str() {
+                if let Ok(value) = HeaderValue::from_str(host) {
+                    headers.entry(HOST).or_insert(value);
+                }
+            }
+            let part_body = req.body().map(|body| Body::from(body.clone().into_bytes()));
+            body.extend_from_slice(&request_to_vec(
+                req.method().clone(),
+                req.url().clone(),
+                headers,
+                part_body,
+                Version::HTTP_11,
+            ));
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        body.freeze()
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Batch {
+        Batch::new()
+    }
+}
+
+/// Splits the `multipart/mixed` answer to a batch request, see
+/// `HttpResponse::batch_parts`.
+pub(crate) fn split(res: &HttpResponse) -> crate::Result<Vec<HttpResponse>> {
+    res.parts()?
+        .map(|part| {
+            parse_part(part?, res.url())
+                .map_err(|msg| crate::error::decode(msg.to_string()).with_url(res.url().clone()))
+        })
+        .collect()
+}
+
+/// Parses one part, a `application/http` response after the part's own
+/// headers.
+fn parse_part(part: Part, url: &Url) -> Result<HttpResponse, &'static str> {
+    let content_id = part.headers().get(&CONTENT_ID).cloned();
+    let http = part.into_bytes();
+
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(&http) {
+        Ok(httparse::Status::Complete(head_len)) => head_len,
+        _ => return Err("batch response part isn't an HTTP response"),
+    };
+    let mut builder = http::Response::builder()
+        .status(parsed.code.unwrap_or_default())
+        .version(match parsed.version {
+            Some(0) => Version::HTTP_10,
+            _ => Version::HTTP_11,
+        })
+        .url(url.clone());
+    let mut header_map = HeaderMap::new();
+    for header in parsed.headers.iter() {
+        let name = HeaderName::from_bytes(header.name.as_bytes());
+        let value = HeaderValue::from_bytes(header.value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            header_map.append(name, value);
+        }
+    }
+    if let Some(content_id) = content_id {
+        header_map.entry(CONTENT_ID.clone()).or_insert(content_id);
+    }
+    let mut body = http.slice(head_len..);
+    if let Some(len) = header_map
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(crate::lunatic_impl::framing::parse_length)
+    {
+        body.truncate(len as usize);
+    }
+    if let Some(headers) = builder.headers_mut() {
+        *headers = header_map;
+    }
+    let res = builder
+        .body(body)
+        .map_err(|_| "batch response part has an invalid status")?;
+    HttpResponse::from_http(res).map_err(|_| "batch response part has no Url")
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::CONTENT_TYPE;
+    use http::StatusCode;
+
+    use super::*;
+    use crate::Method;
+
+    #[lunatic::test]
+    fn encodes_requests() {
+        let mut batch = Batch::new();
+        let url = Url::parse("https://example.com/items/1?fields=name").unwrap();
+        batch.push(Request::new(Method::GET, url));
+        let mut post = Request::new(
+            Method::POST,
+            Url::parse("https://example.com/items").unwrap(),
+        );
+        *post.body_mut() = Some(Body::from("{}"));
+        batch.push(post);
+
+        let body = String::from_utf8(batch.body().to_vec()).unwrap();
+        let boundary = &batch.boundary;
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-Transfer-Encoding: binary\r\n\
+                 Content-ID: <1>\r\n\r\n\
+                 GET /items/1?fields=name HTTP/1.1\r\n\
+                 host: example.com\r\n\r\n\r\n\
+                 --{b}\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-Transfer-Encoding: binary\r\n\
+                 Content-ID: <2>\r\n\r\n\
+                 POST /items HTTP/1.1\r\n\
+                 host: example.com\r\n\
+                 content-length: 2\r\n\r\n\
+                 {{}}\r\n\
+                 --{b}--\r\n",
+                b = boundary
+            )
+        );
+        assert_eq!(
+            batch.content_type(),
+            format!("multipart/mixed; boundary={}", boundary)
+        );
+    }
+
+    #[lunatic::test]
+    fn splits_responses() {
+        let body = "preamble\r\n\
+                    --batch_x\r\n\
+                    Content-Type: application/http\r\n\
+                    Content-ID: <response-1>\r\n\r\n\
+                    HTTP/1.1 200 OK\r\n\
+                    Content-Type: application/json\r\n\
+                    Content-Length: 9\r\n\r\n\
+                    {\"id\":1}\n\r\n\
+                    --batch_x\r\n\
+                    Content-Type: application/http\r\n\r\n\
+                    HTTP/1.1 404 Not Found\r\n\r\n\
+                    \r\n\
+                    --batch_x--\r\n";
+        let url = Url::parse("https://example.com/batch").unwrap();
+        let res = http::Response::builder()
+            .header(CONTENT_TYPE, "multipart/mixed; boundary=batch_x")
+            .url(url.clone())
+            .body(body)
+            .unwrap();
+        let parts = HttpResponse::from_http(res).unwrap().batch_parts().unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].status(), StatusCode::OK);
+        assert_eq!(parts[0].headers()["content-id"], "<response-1>");
+        assert_eq!(parts[0].url(), &url);
+        assert_eq!(parts[0].body(), b"{\"id\":1}\n");
+        assert_eq!(parts[1].status(), StatusCode::NOT_FOUND);
+        assert!(parts[1].body().is_empty());
+    }
+
+    #[lunatic::test]
+    fn rejects_other_responses() {
+        let url = Url::parse("https://example.com/batch").unwrap();
+        let res = http::Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .url(url.clone())
+            .body("{}")
+            .unwrap();
+        assert!(HttpResponse::from_http(res).unwrap().batch_parts().is_err());
+
+        let res = http::Response::builder()
+            .header(CONTENT_TYPE, "multipart/mixed; boundary=b")
+            .url(url)
+            .body("--b\r\n\r\nHTTP/1.1 200 OK\r\n\r\n")
+            .unwrap();
+        assert!(HttpResponse::from_http(res).unwrap().batch_parts().is_err());
+    }
+}
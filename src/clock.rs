@@ -0,0 +1,171 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::lunatic_impl::remote::{Remote, Serve};
+
+/// A clock of your own, see [`TimeSource::new`].
+pub trait Clock: Send + 'static {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+
+    /// Wait for `duration`. A simulated clock usually just moves forward.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Where a `Client` gets the current time from and how it waits.
+///
+/// Everything time dependent in the client, like cookie expiry and request
+/// durations, goes through a `TimeSource`. The default reads the system
+/// clock; tests can plug in a [`Clock`] of their own to make time
+/// deterministic.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Simulated(SystemTime);
+///
+/// impl nightfly::Clock for Simulated {
+///     fn now(&self) -> SystemTime {
+///         self.0
+///     }
+///
+///     fn sleep(&mut self, duration: Duration) {
+///         self.0 += duration;
+///     }
+/// }
+///
+/// let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+/// let client = nightfly::Client::builder()
+///     .time_source(nightfly::TimeSource::new(Simulated(start)))
+///     .build();
+/// ```
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TimeSource {
+    clock: Option<RemoteClock>,
+}
+
+/// A `Clock` running in a process of its own, see `lunatic_impl::remote`.
+type RemoteClock = Remote<Tick, SystemTime>;
+
+/// A call to a clock's process, answered with the time after it.
+#[derive(Serialize, Deserialize)]
+enum Tick {
+    Now,
+    Sleep(Duration),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Ticking<C>(C);
+
+impl<C> Serve for Ticking<C>
+where
+    C: Clock + Serialize + DeserializeOwned,
+{
+    type Request = Tick;
+    type Response = SystemTime;
+
+    fn serve(&mut self, tick: Tick) -> SystemTime {
+        if let Tick::Sleep(duration) = tick {
+            self.0.sleep(duration);
+        }
+        self.0.now()
+    }
+}
+
+impl TimeSource {
+    /// Create a `TimeSource` from a [`Clock`].
+    ///
+    /// The clock is spawned in a process of its own, linked to the current
+    /// one, from its serialized state. Clones of the `TimeSource` share it.
+    pub fn new<C>(clock: C) -> TimeSource
+    where
+        C: Clock + Serialize + DeserializeOwned,
+    {
+        TimeSource {
+            clock: Some(Remote::spawn(Ticking(clock))),
+        }
+    }
+
+    /// The `TimeSource` backed by the system clock and `lunatic::sleep`.
+    pub fn system() -> TimeSource {
+        TimeSource { clock: None }
+    }
+
+    /// Get the current time.
+    pub fn now(&self) -> SystemTime {
+        match self.clock {
+            Some(ref clock) => clock.call(Tick::Now),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Time elapsed since `earlier`, or zero if the clock went backwards.
+    pub fn since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+
+    /// Block the current process for `duration`.
+    pub fn sleep(&self, duration: Duration) {
+        match self.clock {
+            Some(ref clock) => {
+                clock.call(Tick::Sleep(duration));
+            }
+            None => lunatic::sleep(duration),
+        }
+    }
+
+    /// Links the current process to the clock's.
+    pub(crate) fn link(&self) {
+        if let Some(ref clock) = self.clock {
+            clock.link();
+        }
+    }
+}
+
+impl fmt::Debug for TimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeSource").finish()
+    }
+}
+
+/// A clock that only moves when slept on, for tests.
+#[cfg(test)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Simulated(pub(crate) SystemTime);
+
+#[cfg(test)]
+impl Clock for Simulated {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.0 += duration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Simulated, TimeSource};
+
+    #[lunatic::test]
+    fn clones_share_the_clock() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let time = TimeSource::new(Simulated(start));
+        let json = serde_json::to_string(&time.clone()).unwrap();
+        let shipped: TimeSource = serde_json::from_str(&json).unwrap();
+
+        shipped.sleep(Duration::from_secs(5));
+        assert_eq!(time.since(start), Duration::from_secs(5));
+    }
+}
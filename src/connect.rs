@@ -0,0 +1,90 @@
+//! Hooks into new connections.
+//!
+//! A [`ConnectHook`] installed with `ClientBuilder::connect_hook` gets every
+//! TCP connection the client opens, right after it is connected and before
+//! the request is written to it. It can set socket options, or write a
+//! preamble such as a PROXY protocol header that the server expects first.
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use lunatic::net::TcpStream;
+//! use nightfly::connect::ConnectHook;
+//! use nightfly::signing::BoxError;
+//! use nightfly::Url;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct WriteTimeout(Duration);
+//!
+//! impl ConnectHook for WriteTimeout {
+//!     fn connected(&self, stream: &mut TcpStream, _url: &Url) -> Result<(), BoxError> {
+//!         stream.set_write_timeout(Some(self.0))?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let client = nightfly::Client::builder()
+//!     .connect_hook(WriteTimeout(Duration::from_secs(5)))
+//!     .build();
+//! ```
+//!
+//! The lunatic host opens TLS connections and performs their handshake in a
+//! single call, so the hook only gets plain `http` connections. A pooled
+//! connection was seen by the hook when it was opened, and isn't again.
+
+use lunatic::net::TcpStream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::lunatic_impl::remote::{Remote, Serve};
+use crate::signing::BoxError;
+use crate::Url;
+
+/// Sees every new TCP connection of a client, see the module documentation.
+///
+/// A hook runs in a process of its own, spawned from its serialized state
+/// when it is installed, which is why `ClientBuilder::connect_hook` also
+/// requires `Serialize` and `DeserializeOwned`. Each new connection is sent
+/// to that process and back.
+pub trait ConnectHook: Send + Sync + 'static {
+    /// Called with a connection to `url` that was just opened. An error
+    /// fails the request as a connect error.
+    fn connected(&self, stream: &mut TcpStream, url: &Url) -> Result<(), BoxError>;
+}
+
+/// A `ConnectHook` running in a process of its own, see
+/// `lunatic_impl::remote`.
+pub(crate) type RemoteConnectHook = Remote<(TcpStream, Url), (TcpStream, Result<(), String>)>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Hook<H>(H);
+
+impl<H> Serve for Hook<H>
+where
+    H: ConnectHook + Serialize + DeserializeOwned,
+{
+    type Request = (TcpStream, Url);
+    type Response = (TcpStream, Result<(), String>);
+
+    fn serve(&mut self, (mut stream, url): (TcpStream, Url)) -> Self::Response {
+        let res = self.0.connected(&mut stream, &url);
+        (stream, res.map_err(|e| e.to_string()))
+    }
+}
+
+pub(crate) fn spawn<H>(hook: H) -> RemoteConnectHook
+where
+    H: ConnectHook + Serialize + DeserializeOwned,
+{
+    Remote::spawn(Hook(hook))
+}
+
+impl ConnectHook for RemoteConnectHook {
+    fn connected(&self, stream: &mut TcpStream, url: &Url) -> Result<(), BoxError> {
+        // the stream goes to the hook's process and comes back
+        let (returned, res) = self.call((stream.clone(), url.clone()));
+        *stream = returned;
+        res.map_err(Into::into)
+    }
+}
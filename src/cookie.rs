@@ -1,13 +1,19 @@
 //! HTTP Cookies
 
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt;
-use std::sync::RwLock;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
 use std::time::SystemTime;
 
 use crate::header::{HeaderValue, SET_COOKIE};
+use crate::TimeSource;
 use bytes::Bytes;
 use http::HeaderMap;
+use serde::{Deserialize, Serialize};
 
 /// Actions for a persistent cookie store providing session support.
 pub trait CookieStore: Send + Sync {
@@ -26,11 +32,78 @@ pub struct Cookie<'a>(cookie_crate::Cookie<'a>);
 /// This type is exposed to allow creating one and filling it with some
 /// existing cookies more easily, before creating a `Client`.
 ///
-/// For more advanced scenarios, such as needing to serialize the store or
-/// manipulate it between requests, you may refer to the
-/// [reqwest_cookie_store crate](https://crates.io/crates/reqwest_cookie_store).
+/// [`Jar::snapshot`] takes a serializable copy of the cookies, to send
+/// them to another process or node.
+///
+/// The jar never grows past its [`CookieLimits`]; once a limit is exceeded the
+/// least recently used cookies are evicted first.
+///
+/// [`Jar::save_json`] and [`Jar::load_json`] keep cookies across restarts,
+/// and `ClientBuilder::cookie_file` does so for the jar of a `Client`.
+#[derive(Debug, Default)]
+pub struct Jar {
+    store: RwLock<cookie_store::CookieStore>,
+    limits: CookieLimits,
+    usage: Mutex<Usage>,
+    time: TimeSource,
+    // saved to after every response that sets cookies
+    file: Option<PathBuf>,
+    // kept to apply to the store again when it is replaced
+    #[cfg(feature = "cookies-psl")]
+    public_suffixes: Option<publicsuffix::List>,
+}
+
+/// The cookies of a [`Jar`], as taken by [`Jar::snapshot`] or
+/// `Client::cookie_snapshot`.
+///
+/// Unlike a `Jar`, a snapshot can be serialized, to send it to another
+/// process or node along with a `Client` and restore it there with
+/// [`Jar::restore`] or `ClientBuilder::restore_cookies`. It includes
+/// session cookies, which is all a login usually is.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableJar {
+    // each cookie as JSON, the format of `Jar::save_json`
+    cookies: Vec<String>,
+}
+
+impl SerializableJar {
+    /// The number of cookies in the snapshot.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Whether the snapshot holds no cookies.
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub(crate) fn from_json_lines(cookies: Vec<String>) -> SerializableJar {
+        SerializableJar { cookies }
+    }
+}
+
+/// Upper bounds on the number and size of cookies kept in a [`Jar`].
+///
+/// Sizes are measured as the length of a cookie's name plus its value.
+/// The default mirrors what common browsers allow: 180 cookies per domain
+/// and 3000 cookies in total, without any size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CookieLimits {
+    per_domain: Option<usize>,
+    per_domain_bytes: Option<usize>,
+    total: Option<usize>,
+    total_bytes: Option<usize>,
+}
+
+/// Bookkeeping for the LRU eviction of a `Jar`.
 #[derive(Debug, Default)]
-pub struct Jar(RwLock<cookie_store::CookieStore>);
+struct Usage {
+    tick: u64,
+    last_used: HashMap<CookieKey, u64>,
+}
+
+/// (domain, path, name) triple identifying a stored cookie.
+type CookieKey = (String, String, String);
 
 // ===== impl Cookie =====
 
@@ -135,9 +208,264 @@ impl fmt::Display for CookieParseError {
 
 impl std::error::Error for CookieParseError {}
 
+// ===== impl CookieLimits =====
+
+impl CookieLimits {
+    /// Limits that never evict any cookie.
+    pub fn unlimited() -> CookieLimits {
+        CookieLimits {
+            per_domain: None,
+            per_domain_bytes: None,
+            total: None,
+            total_bytes: None,
+        }
+    }
+
+    /// Set the maximum number of cookies kept for a single domain.
+    pub fn max_per_domain(mut self, max: usize) -> CookieLimits {
+        self.per_domain = Some(max);
+        self
+    }
+
+    /// Set the maximum combined size in bytes of the cookies kept for a single domain.
+    pub fn max_bytes_per_domain(mut self, max: usize) -> CookieLimits {
+        self.per_domain_bytes = Some(max);
+        self
+    }
+
+    /// Set the maximum number of cookies kept in the whole jar.
+    pub fn max_total(mut self, max: usize) -> CookieLimits {
+        self.total = Some(max);
+        self
+    }
+
+    /// Set the maximum combined size in bytes of all cookies kept in the jar.
+    pub fn max_total_bytes(mut self, max: usize) -> CookieLimits {
+        self.total_bytes = Some(max);
+        self
+    }
+
+    fn exceeded(count: usize, bytes: usize, max: Option<usize>, max_bytes: Option<usize>) -> bool {
+        matches!(max, Some(max) if count > max) || matches!(max_bytes, Some(max) if bytes > max)
+    }
+}
+
+impl Default for CookieLimits {
+    fn default() -> CookieLimits {
+        CookieLimits::unlimited()
+            .max_per_domain(180)
+            .max_total(3000)
+    }
+}
+
 // ===== impl Jar =====
 
 impl Jar {
+    /// Create an empty jar that enforces the given limits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nightfly::cookie::{CookieLimits, Jar};
+    ///
+    /// let jar = Jar::with_limits(CookieLimits::default().max_per_domain(20));
+    /// ```
+    pub fn with_limits(limits: CookieLimits) -> Jar {
+        Jar {
+            limits,
+            ..Jar::default()
+        }
+    }
+
+    /// Get the limits enforced by this jar.
+    pub fn limits(&self) -> CookieLimits {
+        self.limits
+    }
+
+    /// Use `time` instead of the system clock to decide whether cookies
+    /// have expired.
+    pub fn with_time_source(mut self, time: TimeSource) -> Jar {
+        self.time = time;
+        self
+    }
+
+    /// Reject cookies whose `Domain` is a public suffix, such as a cookie
+    /// for `github.io` set by `evil.github.io`, as browsers do.
+    ///
+    /// `list` is the text of the [Public Suffix List], which changes too
+    /// often to be built in. A cookie for a public suffix that is the
+    /// response's own host is kept, for that host only.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `list` isn't a valid public suffix list.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use nightfly::cookie::Jar;
+    ///
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let list = std::fs::read_to_string("public_suffix_list.dat")?;
+    /// let jar = Jar::default().with_public_suffix_list(&list)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [Public Suffix List]: https://publicsuffix.org/list/public_suffix_list.dat
+    #[cfg(feature = "cookies-psl")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies-psl")))]
+    pub fn with_public_suffix_list(mut self, list: &str) -> crate::Result<Jar> {
+        let list: publicsuffix::List = list
+            .parse()
+            .map_err(|e: publicsuffix::Error| crate::error::builder(e.to_string()))?;
+        let store = std::mem::take(self.store.get_mut().unwrap());
+        *self.store.get_mut().unwrap() = store.with_suffix_list(list.clone());
+        self.public_suffixes = Some(list);
+        Ok(self)
+    }
+
+    fn now(&self) -> time::OffsetDateTime {
+        time::OffsetDateTime::from(self.time.now())
+    }
+
+    /// Write the unexpired cookies in this jar to `writer`, one JSON object
+    /// per line.
+    ///
+    /// Session cookies, which a browser drops when it quits, are written
+    /// too, so that a long-running process stays logged in across restarts.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use nightfly::cookie::Jar;
+    ///
+    /// # fn run() -> std::io::Result<()> {
+    /// let jar = Jar::default();
+    /// jar.add_cookie_str("session=abc", &"https://example.com".parse().unwrap());
+    /// jar.save_json(&mut std::fs::File::create("cookies.json")?)?;
+    ///
+    /// let restored = Jar::default();
+    /// restored.load_json(std::io::BufReader::new(std::fs::File::open("cookies.json")?))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for line in self.to_json_lines() {
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Replace the cookies in this jar with the ones `save_json` wrote to
+    /// `reader`.
+    ///
+    /// Cookies that have expired since are skipped, and the jar's limits
+    /// apply as if the cookies had just been received.
+    pub fn load_json<R: BufRead>(&self, reader: R) -> io::Result<()> {
+        self.replace(reader.lines())
+    }
+
+    /// Take a copy of the cookies in this jar that can be sent to another
+    /// process, or node, and restored there with [`Jar::restore`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use nightfly::cookie::Jar;
+    ///
+    /// let jar = Jar::default();
+    /// jar.add_cookie_str("session=abc", &"https://example.com".parse().unwrap());
+    ///
+    /// let copy = Jar::default();
+    /// copy.restore(&jar.snapshot()).unwrap();
+    /// ```
+    pub fn snapshot(&self) -> SerializableJar {
+        SerializableJar {
+            cookies: self.to_json_lines(),
+        }
+    }
+
+    /// Replace the cookies in this jar with the ones of `snapshot`.
+    ///
+    /// As with [`Jar::load_json`], expired cookies are skipped and the
+    /// jar's limits apply.
+    pub fn restore(&self, snapshot: &SerializableJar) -> io::Result<()> {
+        self.replace(snapshot.cookies.iter().cloned().map(Ok))
+    }
+
+    /// The unexpired cookies, each as a JSON object.
+    pub(crate) fn to_json_lines(&self) -> Vec<String> {
+        let store = self.store.read().unwrap();
+        let now = self.now();
+        store
+            .iter_any()
+            .filter(|c| !c.expires_by(&now))
+            .map(|c| serde_json::to_string(c).expect("cookies serialize to JSON"))
+            .collect()
+    }
+
+    fn replace<I>(&self, lines: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = io::Result<String>>,
+    {
+        let now = self.now();
+        let mut cookies = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let cookie: cookie_store::Cookie<'static> = serde_json::from_str(&line)?;
+            if !cookie.expires_by(&now) {
+                cookies.push(Ok::<_, io::Error>(cookie));
+            }
+        }
+        let loaded = cookie_store::CookieStore::from_cookies(cookies, true)?;
+        #[cfg(feature = "cookies-psl")]
+        let loaded = match self.public_suffixes {
+            Some(ref list) => loaded.with_suffix_list(list.clone()),
+            None => loaded,
+        };
+
+        let mut store = self.store.write().unwrap();
+        *store = loaded;
+        let mut usage = self.usage.lock().unwrap();
+        usage.last_used.clear();
+        usage.touch(store.iter_any().map(cookie_key).collect::<Vec<_>>());
+        self.enforce_limits(&mut store, &mut usage);
+        Ok(())
+    }
+
+    /// Load the jar from `path`, if it exists.
+    pub(crate) fn load_file(&self, path: &Path) -> io::Result<()> {
+        match File::open(path) {
+            Ok(file) => self.load_json(BufReader::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the jar to `path` whenever a response sets cookies, see
+    /// `ClientBuilder::cookie_file`.
+    pub(crate) fn with_file(mut self, path: PathBuf) -> Jar {
+        self.file = Some(path);
+        self
+    }
+
+    /// Saves the jar through a temporary file, so that a crash never leaves
+    /// half a jar behind.
+    fn save_file(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        self.save_json(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp, path)
+    }
+
     /// Add a cookie to this jar.
     ///
     /// # Example
@@ -158,25 +486,135 @@ impl Jar {
             .ok()
             .map(|c| c.into_owned())
             .into_iter();
-        self.0.write().unwrap().store_response_cookies(cookies, url);
+        self.store(cookies, url);
+    }
+
+    fn store<I>(&self, cookies: I, url: &url::Url)
+    where
+        I: Iterator<Item = cookie_crate::Cookie<'static>>,
+    {
+        let cookies = cookies.collect::<Vec<_>>();
+        let names = cookies
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect::<Vec<_>>();
+        let mut store = self.store.write().unwrap();
+        store.store_response_cookies(cookies.into_iter(), url);
+
+        let mut usage = self.usage.lock().unwrap();
+        let touched = store
+            .iter_any()
+            .filter(|c| names.iter().any(|name| name == c.name()) && c.domain.matches(url))
+            .map(cookie_key)
+            .collect::<Vec<_>>();
+        usage.touch(touched);
+        self.enforce_limits(&mut store, &mut usage);
+    }
+
+    /// Evict least recently used cookies until every limit is satisfied.
+    ///
+    /// Expired cookies are always the first to go.
+    fn enforce_limits(&self, store: &mut cookie_store::CookieStore, usage: &mut Usage) {
+        let now = self.now();
+        let mut entries = store
+            .iter_any()
+            .map(|c| {
+                let key = cookie_key(c);
+                let last_used = usage.last_used.get(&key).copied().unwrap_or(0);
+                let size = c.name().len() + c.value().len();
+                (!c.expires_by(&now), last_used, size, key)
+            })
+            .collect::<Vec<_>>();
+        // oldest first
+        entries.sort();
+
+        let mut evicted = vec![false; entries.len()];
+        let mut per_domain: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (_, _, size, (domain, _, _)) in entries.iter() {
+            let (count, bytes) = per_domain.entry(domain).or_default();
+            *count += 1;
+            *bytes += size;
+        }
+        for (idx, (_, _, size, (domain, _, _))) in entries.iter().enumerate() {
+            let (count, bytes) = per_domain.get_mut(domain.as_str()).unwrap();
+            if CookieLimits::exceeded(
+                *count,
+                *bytes,
+                self.limits.per_domain,
+                self.limits.per_domain_bytes,
+            ) {
+                *count -= 1;
+                *bytes -= size;
+                evicted[idx] = true;
+            }
+        }
+
+        let (mut count, mut bytes) = per_domain
+            .values()
+            .fold((0, 0), |(count, bytes), (c, b)| (count + c, bytes + b));
+        for (idx, (_, _, size, _)) in entries.iter().enumerate() {
+            if !CookieLimits::exceeded(count, bytes, self.limits.total, self.limits.total_bytes) {
+                break;
+            }
+            if !evicted[idx] {
+                count -= 1;
+                bytes -= size;
+                evicted[idx] = true;
+            }
+        }
+
+        for (entry, _) in entries.iter().zip(evicted).filter(|(_, evicted)| *evicted) {
+            let (domain, path, name) = &entry.3;
+            lunatic_log::debug!("Evicting cookie {} for {}{}", name, domain, path);
+            store.remove(domain, path, name);
+            usage.last_used.remove(&entry.3);
+        }
+    }
+}
+
+impl Usage {
+    fn touch<I: IntoIterator<Item = CookieKey>>(&mut self, keys: I) {
+        self.tick += 1;
+        for key in keys {
+            self.last_used.insert(key, self.tick);
+        }
     }
 }
 
+fn cookie_key(cookie: &cookie_store::Cookie<'_>) -> CookieKey {
+    (
+        String::from(&cookie.domain),
+        String::from(&cookie.path),
+        cookie.name().to_string(),
+    )
+}
+
 impl CookieStore for Jar {
     fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
         let iter =
             cookie_headers.filter_map(|val| Cookie::parse(val).map(|c| c.0.into_owned()).ok());
 
-        self.0.write().unwrap().store_response_cookies(iter, url);
+        self.store(iter, url);
+
+        if let Some(ref path) = self.file {
+            if let Err(e) = self.save_file(path) {
+                lunatic_log::warn!("Failed to save cookies to {:?}: {:?}", path, e);
+            }
+        }
     }
 
     fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
-        let s = self
-            .0
-            .read()
+        let store = self.store.read().unwrap();
+        let now = self.now();
+        let mut matches = store.matches(url);
+        matches.retain(|c| !c.expires_by(&now));
+        self.usage
+            .lock()
             .unwrap()
-            .get_request_values(url)
-            .map(|(name, value)| format!("{}={}", name, value))
+            .touch(matches.iter().map(|c| cookie_key(c)));
+        let s = matches
+            .iter()
+            .map(|c| format!("{}={}", c.name(), c.value()))
             .collect::<Vec<_>>()
             .join("; ");
 
@@ -187,3 +625,133 @@ impl CookieStore for Jar {
         HeaderValue::from_maybe_shared(Bytes::from(s)).ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+
+    #[lunatic::test]
+    fn jar_evicts_least_recently_used_per_domain() {
+        let jar = Jar::with_limits(CookieLimits::unlimited().max_per_domain(2));
+        let url = "https://yolo.local".parse::<url::Url>().unwrap();
+
+        // the order of the cookies in the header isn't specified
+        let sorted = |jar: &Jar| {
+            let header = jar.cookies(&url).unwrap();
+            let mut cookies = header
+                .to_str()
+                .unwrap()
+                .split("; ")
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            cookies.sort_unstable();
+            cookies
+        };
+
+        jar.add_cookie_str("a=1", &url);
+        jar.add_cookie_str("b=2", &url);
+        // reading the cookies marks both as used, then `a` is refreshed
+        assert_eq!(sorted(&jar), vec!["a=1", "b=2"]);
+        jar.add_cookie_str("a=3", &url);
+        jar.add_cookie_str("c=4", &url);
+
+        assert_eq!(sorted(&jar), vec!["a=3", "c=4"]);
+    }
+
+    #[lunatic::test]
+    fn jar_expires_cookies_using_time_source() {
+        let in_an_hour = SystemTime::now() + std::time::Duration::from_secs(3600);
+        let jar = Jar::default().with_time_source(TimeSource::new(Simulated(in_an_hour)));
+        let url = "https://yolo.local".parse::<url::Url>().unwrap();
+
+        jar.add_cookie_str("short=1; Max-Age=60", &url);
+        jar.add_cookie_str("long=2; Max-Age=86400", &url);
+
+        assert_eq!(jar.cookies(&url).unwrap(), "long=2");
+    }
+
+    #[lunatic::test]
+    fn jar_saves_and_loads_cookies() {
+        let url = "https://yolo.local".parse::<url::Url>().unwrap();
+        let jar = Jar::default();
+        jar.add_cookie_str("session=abc", &url);
+        jar.add_cookie_str("theme=dark; Max-Age=86400", &url);
+        jar.add_cookie_str("gone=1; Max-Age=0", &url);
+
+        let mut saved = Vec::new();
+        jar.save_json(&mut saved).unwrap();
+        assert_eq!(saved.iter().filter(|b| **b == b'\n').count(), 2);
+
+        let restored = Jar::with_limits(CookieLimits::unlimited().max_per_domain(1));
+        restored.load_json(&saved[..]).unwrap();
+        let header = restored.cookies(&url).unwrap();
+        assert!(
+            header == "session=abc" || header == "theme=dark",
+            "{:?}",
+            header
+        );
+
+        let restored = Jar::default();
+        restored.load_json(&saved[..]).unwrap();
+        let header = restored.cookies(&url).unwrap();
+        let mut cookies = header.to_str().unwrap().split("; ").collect::<Vec<_>>();
+        cookies.sort_unstable();
+        assert_eq!(cookies, vec!["session=abc", "theme=dark"]);
+
+        assert!(restored.load_json(&b"not json\n"[..]).is_err());
+    }
+
+    #[lunatic::test]
+    fn jar_snapshots_survive_serialization() {
+        let url = "https://yolo.local".parse::<url::Url>().unwrap();
+        let jar = Jar::default();
+        jar.add_cookie_str("session=abc", &url);
+        jar.add_cookie_str("gone=1; Max-Age=0", &url);
+
+        let snapshot = jar.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        let sent: SerializableJar =
+            serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert_eq!(sent, snapshot);
+
+        let restored = Jar::default();
+        restored.add_cookie_str("old=1", &url);
+        restored.restore(&sent).unwrap();
+        assert_eq!(restored.cookies(&url).unwrap(), "session=abc");
+    }
+
+    #[cfg(feature = "cookies-psl")]
+    #[lunatic::test]
+    fn jar_rejects_public_suffix_cookies() {
+        let list = "// ===BEGIN ICANN DOMAINS===\nio\n// ===BEGIN PRIVATE DOMAINS===\ngithub.io\n";
+        let jar = Jar::default().with_public_suffix_list(list).unwrap();
+        let evil = "https://evil.github.io".parse::<url::Url>().unwrap();
+        let other = "https://other.github.io".parse::<url::Url>().unwrap();
+
+        jar.add_cookie_str("steal=1; Domain=github.io", &evil);
+        jar.add_cookie_str("own=1; Domain=evil.github.io", &evil);
+        assert!(jar.cookies(&other).is_none());
+        assert_eq!(jar.cookies(&evil).unwrap(), "own=1");
+
+        // the list outlives replacing the cookies
+        jar.restore(&SerializableJar::default()).unwrap();
+        jar.add_cookie_str("steal=1; Domain=github.io", &evil);
+        assert!(jar.cookies(&other).is_none());
+
+        assert!(Jar::default().with_public_suffix_list("\u{0}").is_err());
+    }
+
+    #[lunatic::test]
+    fn jar_enforces_total_size() {
+        let jar = Jar::with_limits(CookieLimits::unlimited().max_total_bytes(10));
+        let first = "https://one.local".parse::<url::Url>().unwrap();
+        let second = "https://two.local".parse::<url::Url>().unwrap();
+
+        jar.add_cookie_str("key=value", &first);
+        jar.add_cookie_str("other=value", &second);
+
+        assert!(jar.cookies(&first).is_none());
+        assert_eq!(jar.cookies(&second).unwrap(), "other=value");
+    }
+}
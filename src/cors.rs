@@ -0,0 +1,182 @@
+//! What a server allows, from the answer to an `OPTIONS` request.
+//!
+//! [`Capabilities`] collects the `Allow` header and the
+//! `Access-Control-Allow-*` headers of a response. Use
+//! `HttpResponse::capabilities` on any response, or `Client::preflight` to
+//! send the same preflight request a browser would before a cross-origin
+//! request.
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let caps = client.preflight(
+//!     "https://api.example.com/items",
+//!     "https://app.example.com",
+//!     nightfly::Method::PUT,
+//!     &["content-type"],
+//! )?;
+//! if caps.allows_method(&nightfly::Method::PUT) {
+//!     println!("PUT is allowed from the app");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use http::header::{
+    HeaderMap, HeaderName, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ALLOW,
+};
+use http::Method;
+
+/// The methods and cross-origin access a server allows, see the module
+/// documentation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Methods listed in `Allow`.
+    pub allow: Vec<Method>,
+    /// `Access-Control-Allow-Origin`, either an origin or `*`.
+    pub allow_origin: Option<String>,
+    /// Methods listed in `Access-Control-Allow-Methods`.
+    pub allow_methods: Vec<Method>,
+    /// Headers listed in `Access-Control-Allow-Headers`.
+    pub allow_headers: Vec<HeaderName>,
+    /// Whether `Access-Control-Allow-Credentials` is `true`.
+    pub allow_credentials: bool,
+    /// Headers listed in `Access-Control-Expose-Headers`.
+    pub expose_headers: Vec<HeaderName>,
+    /// How long the preflight answer may be cached, from
+    /// `Access-Control-Max-Age`.
+    pub max_age: Option<Duration>,
+}
+
+impl Capabilities {
+    /// Reads the capabilities from the headers of a response. Entries that
+    /// can't be parsed are skipped.
+    pub fn from_headers(headers: &HeaderMap) -> Capabilities {
+        Capabilities {
+            allow: list(headers, &ALLOW),
+            allow_origin: headers
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_owned()),
+            allow_methods: list(headers, &ACCESS_CONTROL_ALLOW_METHODS),
+            allow_headers: list(headers, &ACCESS_CONTROL_ALLOW_HEADERS),
+            allow_credentials: headers
+                .get(ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .map(|value| value.as_bytes() == b"true")
+                .unwrap_or(false),
+            expose_headers: list(headers, &ACCESS_CONTROL_EXPOSE_HEADERS),
+            max_age: headers
+                .get(ACCESS_CONTROL_MAX_AGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs),
+        }
+    }
+
+    /// Whether `method` may be used, by `Allow` or by
+    /// `Access-Control-Allow-Methods`. Simple methods are always allowed
+    /// cross-origin.
+    pub fn allows_method(&self, method: &Method) -> bool {
+        self.allow.contains(method)
+            || self.allow_methods.contains(method)
+            || self.allow_methods.iter().any(|m| m.as_str() == "*")
+            || (self.allow_origin.is_some()
+                && matches!(*method, Method::GET | Method::HEAD | Method::POST))
+    }
+
+    /// Whether a cross-origin request may send the header `name`.
+    pub fn allows_header(&self, name: &HeaderName) -> bool {
+        self.allow_headers
+            .iter()
+            .any(|allowed| allowed == name || allowed.as_str() == "*")
+    }
+
+    /// Whether a request from `origin` may read the response.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        match self.allow_origin.as_deref() {
+            Some("*") => !self.allow_credentials,
+            Some(allowed) => allowed == origin,
+            None => false,
+        }
+    }
+}
+
+/// The comma separated entries of every `name` header.
+fn list<T: FromStr>(headers: &HeaderMap, name: &HeaderName) -> Vec<T> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| entry.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[lunatic::test]
+    fn reads_preflight_answer() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ALLOW, HeaderValue::from_static("GET, HEAD, OPTIONS"));
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("https://app.example.com"),
+        );
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("PUT,DELETE"),
+        );
+        headers.append(
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Content-Type"),
+        );
+        headers.append(
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("X-Token, bad header"),
+        );
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+        headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("600"));
+
+        let caps = Capabilities::from_headers(&headers);
+        assert_eq!(caps.allow, [Method::GET, Method::HEAD, Method::OPTIONS]);
+        assert_eq!(caps.allow_methods, [Method::PUT, Method::DELETE]);
+        assert_eq!(caps.allow_headers.len(), 2);
+        assert!(caps.allow_credentials);
+        assert_eq!(caps.max_age, Some(Duration::from_secs(600)));
+
+        assert!(caps.allows_method(&Method::PUT));
+        assert!(caps.allows_method(&Method::POST));
+        assert!(!caps.allows_method(&Method::PATCH));
+        assert!(caps.allows_header(&HeaderName::from_static("x-token")));
+        assert!(!caps.allows_header(&HeaderName::from_static("x-other")));
+        assert!(caps.allows_origin("https://app.example.com"));
+        assert!(!caps.allows_origin("https://evil.example.com"));
+    }
+
+    #[lunatic::test]
+    fn wildcard_origin_excludes_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        assert!(Capabilities::from_headers(&headers).allows_origin("https://a.example"));
+
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+        assert!(!Capabilities::from_headers(&headers).allows_origin("https://a.example"));
+    }
+}
@@ -0,0 +1,279 @@
+//! Finding CSRF tokens and sending them back.
+//!
+//! Sites protected against cross-site request forgery hand out a token,
+//! usually in a header, a cookie, a `<meta name="csrf-token">` tag or a
+//! hidden form input, and reject state changing requests that don't echo it.
+//! [`CsrfToken::from_response`] looks in all of those places and remembers
+//! which header and form field the framework expects the token back in.
+//!
+//! ```rust
+//! use nightfly::csrf::CsrfToken;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let page = client.get("https://example.com/settings").send()?;
+//! if let Some(token) = CsrfToken::from_response(&page) {
+//!     // As a header...
+//!     client
+//!         .delete("https://example.com/api/sessions")
+//!         .csrf_token(&token)
+//!         .send()?;
+//!     // ...or as a form field.
+//!     client
+//!         .post("https://example.com/settings")
+//!         .form(&[token.form_field(), ("theme", "dark")])
+//!         .send()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Looking into HTML needs the `csrf-html` feature.
+
+use http::header::{HeaderMap, HeaderName};
+
+use crate::HttpResponse;
+
+const DEFAULT_HEADER: &str = "x-csrf-token";
+const DEFAULT_FIELD: &str = "csrf_token";
+
+/// Response headers that carry a token, and the request header to send it
+/// back in.
+const HEADERS: &[(&str, &str)] = &[
+    ("x-csrf-token", "x-csrf-token"),
+    ("x-xsrf-token", "x-xsrf-token"),
+    ("csrf-token", "csrf-token"),
+];
+
+/// Cookies that carry a token, with the header and form field that the
+/// frameworks setting them expect.
+#[cfg(feature = "cookies")]
+const COOKIES: &[(&str, &str, &str)] = &[
+    // Angular, Axios, Laravel
+    ("XSRF-TOKEN", "x-xsrf-token", "_token"),
+    // Django
+    ("csrftoken", "x-csrftoken", "csrfmiddlewaretoken"),
+    // Express csurf
+    ("_csrf", "x-csrf-token", "_csrf"),
+    ("csrf_token", DEFAULT_HEADER, DEFAULT_FIELD),
+];
+
+/// Hidden form inputs that carry a token, with the matching header.
+#[cfg(feature = "csrf-html")]
+const FIELDS: &[(&str, &str)] = &[
+    // Rails
+    ("authenticity_token", "x-csrf-token"),
+    // Django
+    ("csrfmiddlewaretoken", "x-csrftoken"),
+    // Laravel
+    ("_token", "x-csrf-token"),
+    ("_csrf", "x-csrf-token"),
+    ("csrf_token", DEFAULT_HEADER),
+];
+
+/// A CSRF token, with the header and form field to send it back in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsrfToken {
+    value: String,
+    header: HeaderName,
+    field: String,
+}
+
+impl CsrfToken {
+    /// A token sent back as `X-CSRF-Token` or as the `csrf_token` field.
+    pub fn new<T: Into<String>>(value: T) -> CsrfToken {
+        CsrfToken {
+            value: value.into(),
+            header: HeaderName::from_static(DEFAULT_HEADER),
+            field: DEFAULT_FIELD.to_string(),
+        }
+    }
+
+    /// Use `header` when sending the token with `RequestBuilder::csrf_token`.
+    pub fn with_header(mut self, header: HeaderName) -> CsrfToken {
+        self.header = header;
+        self
+    }
+
+    /// Use `field` as the name in `form_field`.
+    pub fn with_field<T: Into<String>>(mut self, field: T) -> CsrfToken {
+        self.field = field.into();
+        self
+    }
+
+    /// Get the token itself.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Get the header the token is sent back in.
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// Get the token as a `(name, value)` pair to add to a form.
+    pub fn form_field(&self) -> (&str, &str) {
+        (&self.field, &self.value)
+    }
+
+    /// Look for a token in the headers, cookies and, with the `csrf-html`
+    /// feature, the HTML body of `res`, in that order.
+    pub fn from_response(res: &HttpResponse) -> Option<CsrfToken> {
+        let token = CsrfToken::from_headers(res.headers());
+        #[cfg(feature = "cookies")]
+        let token = token.or_else(|| CsrfToken::from_cookies(res));
+        #[cfg(feature = "csrf-html")]
+        let token = token.or_else(|| CsrfToken::from_html(&String::from_utf8_lossy(&res.body)));
+        token
+    }
+
+    /// Look for a token in headers such as `X-CSRF-Token`.
+    pub fn from_headers(headers: &HeaderMap) -> Option<CsrfToken> {
+        HEADERS.iter().find_map(|(name, send_as)| {
+            let value = headers.get(*name)?.to_str().ok()?;
+            Some(CsrfToken::new(value).with_header(HeaderName::from_static(send_as)))
+        })
+    }
+
+    /// Look for a token in the cookies set by `res`, such as Django's
+    /// `csrftoken` or Angular's `XSRF-TOKEN`.
+    #[cfg(feature = "cookies")]
+    pub fn from_cookies(res: &HttpResponse) -> Option<CsrfToken> {
+        let cookies: Vec<_> = res.cookies().collect();
+        COOKIES.iter().find_map(|(name, header, field)| {
+            let cookie = cookies.iter().find(|cookie| cookie.name() == *name)?;
+            Some(
+                CsrfToken::new(cookie.value())
+                    .with_header(HeaderName::from_static(header))
+                    .with_field(*field),
+            )
+        })
+    }
+
+    /// Look for a token in a `<meta name="csrf-token">` tag or a hidden
+    /// form input such as Rails' `authenticity_token`.
+    ///
+    /// This is a small scanner over tags, not an HTML parser: it ignores
+    /// comments and scripts and doesn't decode entities.
+    #[cfg(feature = "csrf-html")]
+    pub fn from_html(html: &str) -> Option<CsrfToken> {
+        let mut field_token = None;
+        for (tag, attrs) in html::tags(html) {
+            let attr = |key: &str| {
+                attrs
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+            };
+            match tag.as_str() {
+                "meta" if attr("name") == Some("csrf-token") => {
+                    if let Some(value) = attr("content") {
+                        return Some(CsrfToken::new(value).with_field("authenticity_token"));
+                    }
+                }
+                "input" if field_token.is_none() => {
+                    let name = attr("name").unwrap_or_default();
+                    if let Some((field, header)) = FIELDS.iter().find(|(f, _)| *f == name) {
+                        if let Some(value) = attr("value") {
+                            field_token = Some(
+                                CsrfToken::new(value)
+                                    .with_header(HeaderName::from_static(header))
+                                    .with_field(*field),
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        field_token
+    }
+}
+
+#[cfg(feature = "csrf-html")]
+mod html {
+    /// Yields the lowercase name and attributes of every `<meta>` and
+    /// `<input>` tag in `html`.
+    pub(super) fn tags(html: &str) -> impl Iterator<Item = (String, Vec<(String, String)>)> + '_ {
+        html.split('<').skip(1).filter_map(|rest| {
+            let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+            let name_end = tag
+                .find(|c: char| c.is_ascii_whitespace() || c == '/')
+                .unwrap_or(tag.len());
+            let name = tag[..name_end].to_ascii_lowercase();
+            if name != "meta" && name != "input" {
+                return None;
+            }
+            Some((name, attributes(&tag[name_end..])))
+        })
+    }
+
+    fn attributes(mut s: &str) -> Vec<(String, String)> {
+        let mut attrs = Vec::new();
+        loop {
+            s = s.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+            if s.is_empty() {
+                return attrs;
+            }
+            let key_end = s
+                .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+                .unwrap_or(s.len());
+            let key = s[..key_end].to_ascii_lowercase();
+            s = s[key_end..].trim_start();
+            let value = if let Some(rest) = s.strip_prefix('=') {
+                let rest = rest.trim_start();
+                let (value, rest) = match rest.chars().next() {
+                    Some(quote @ '"') | Some(quote @ '\'') => {
+                        let rest = &rest[1..];
+                        let end = rest.find(quote).unwrap_or(rest.len());
+                        (&rest[..end], rest.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = rest
+                            .find(|c: char| c.is_ascii_whitespace())
+                            .unwrap_or(rest.len());
+                        (&rest[..end], &rest[end..])
+                    }
+                };
+                s = rest;
+                value.to_string()
+            } else {
+                String::new()
+            };
+            attrs.push((key, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[lunatic::test]
+    fn finds_token_in_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-xsrf-token", HeaderValue::from_static("abc"));
+        let token = CsrfToken::from_headers(&headers).unwrap();
+        assert_eq!(token.value(), "abc");
+        assert_eq!(token.header_name(), "x-xsrf-token");
+    }
+
+    #[cfg(feature = "csrf-html")]
+    #[lunatic::test]
+    fn finds_token_in_html() {
+        let meta = r#"<head><META name="csrf-token" content="m3ta"/></head>"#;
+        let token = CsrfToken::from_html(meta).unwrap();
+        assert_eq!(token.value(), "m3ta");
+        assert_eq!(token.form_field(), ("authenticity_token", "m3ta"));
+
+        let form = r#"<form><input type='hidden' name='csrfmiddlewaretoken' value='dj4ngo'>
+            <input type="text" name="user"></form>"#;
+        let token = CsrfToken::from_html(form).unwrap();
+        assert_eq!(token.form_field(), ("csrfmiddlewaretoken", "dj4ngo"));
+        assert_eq!(token.header_name(), "x-csrftoken");
+
+        assert_eq!(CsrfToken::from_html("<p>no token</p>"), None);
+    }
+}
@@ -3,6 +3,7 @@ use serde::Serialize;
 use std::error::Error as StdError;
 use std::fmt;
 use std::io;
+use std::time::Duration;
 
 use crate::SerializableResponse;
 use crate::{StatusCode, Url};
@@ -33,6 +34,14 @@ struct Inner {
     #[serde(skip)]
     source: Option<BoxError>,
     url: Option<Url>,
+    // sources don't survive serialization, so timeouts are kept here too
+    timed_out: Option<TimedOut>,
+    // ...and so are body length mismatches
+    length_mismatch: Option<LengthMismatch>,
+    // what was read of a body before reading it failed
+    partial_body: Option<PartialBody>,
+    // the header a request builder couldn't add
+    invalid_header: Option<InvalidHeader>,
 }
 
 impl Clone for Inner {
@@ -41,6 +50,10 @@ impl Clone for Inner {
             kind: self.kind.clone(),
             source: None,
             url: self.url.clone(),
+            timed_out: self.timed_out.clone(),
+            length_mismatch: self.length_mismatch,
+            partial_body: self.partial_body.clone(),
+            invalid_header: self.invalid_header.clone(),
         }
     }
 }
@@ -55,6 +68,10 @@ impl Error {
                 kind,
                 source: source.map(Into::into),
                 url: None,
+                timed_out: None,
+                length_mismatch: None,
+                partial_body: None,
+                invalid_header: None,
             }),
         }
     }
@@ -119,16 +136,48 @@ impl Error {
 
     /// Returns true if the error is related to a timeout.
     pub fn is_timeout(&self) -> bool {
+        self.timed_out().is_some()
+    }
+
+    /// Returns which phase of the request ran out of time, with the time it
+    /// took and the time it was allowed, if the error is a timeout that
+    /// recorded them.
+    pub fn timeout_detail(&self) -> Option<&TimeoutDetail> {
+        self.timed_out()?.0.as_ref()
+    }
+
+    fn timed_out(&self) -> Option<&TimedOut> {
+        if let Some(ref timed_out) = self.inner.timed_out {
+            return Some(timed_out);
+        }
+
         let mut source = self.source();
 
         while let Some(err) = source {
-            if err.is::<TimedOut>() {
-                return true;
+            if let Some(timed_out) = err.downcast_ref::<TimedOut>() {
+                return Some(timed_out);
+            }
+            if let Some(timed_out) = err
+                .downcast_ref::<Error>()
+                .and_then(|err| err.inner.timed_out.as_ref())
+            {
+                return Some(timed_out);
             }
             source = err.source();
         }
 
-        false
+        None
+    }
+
+    /// Returns true if the error is from failing to connect to the server.
+    pub fn is_connect(&self) -> bool {
+        matches!(self.inner.kind, Kind::Connect)
+    }
+
+    /// Returns true if the request couldn't be sent through the proxy it
+    /// was meant for, see `ClientBuilder::proxy`.
+    pub fn is_proxy(&self) -> bool {
+        matches!(self.inner.kind, Kind::Proxy)
     }
 
     /// Returns true if the error is related to the request
@@ -136,10 +185,41 @@ impl Error {
         matches!(self.inner.kind, Kind::Request)
     }
 
+    /// Returns true if the client refused to send the request because a
+    /// policy such as robots.txt disallows it.
+    pub fn is_disallowed(&self) -> bool {
+        matches!(self.inner.kind, Kind::Disallowed(_))
+    }
+
+    /// Returns true if the client refused to send the request because its
+    /// URL isn't allowed by `ClientBuilder::url_policy`.
+    pub fn is_policy(&self) -> bool {
+        matches!(self.inner.kind, Kind::Policy)
+    }
+
+    /// Returns true if a request or response was refused because its
+    /// `Content-Length`, `Transfer-Encoding`, chunks or line endings could be
+    /// read in more than one way, see `ClientBuilder::lenient_framing`.
+    pub fn is_framing(&self) -> bool {
+        matches!(self.inner.kind, Kind::Framing(_))
+    }
+
+    /// Returns true if a response repeated a header that may only appear
+    /// once, see `ClientBuilder::duplicate_headers`.
+    pub fn is_duplicate_header(&self) -> bool {
+        matches!(self.inner.kind, Kind::DuplicateHeader(_))
+    }
+
+    /// Returns true if the response body didn't match a digest sent by the
+    /// server, see `ClientBuilder::verify_integrity`.
+    pub fn is_integrity(&self) -> bool {
+        matches!(self.inner.kind, Kind::Integrity(_))
+    }
+
     /// Returns true if the error is related to the request or response body
-    // pub fn is_body(&self) -> bool {
-    //     matches!(self.inner.kind, Kind::Body)
-    // }
+    pub fn is_body(&self) -> bool {
+        matches!(self.inner.kind, Kind::Body)
+    }
 
     /// Returns true if the error is related to the serialisation of the body
     pub fn is_serialization(&self) -> bool {
@@ -151,6 +231,31 @@ impl Error {
         matches!(self.inner.kind, Kind::Decode)
     }
 
+    /// Returns the length declared by `Content-Length` and the number of bytes
+    /// received, if the error is a response body that was cut short or ran
+    /// past its declared length.
+    pub fn length_mismatch(&self) -> Option<&LengthMismatch> {
+        self.inner.length_mismatch.as_ref()
+    }
+
+    /// Returns the part of the response body that was read before reading
+    /// or decoding it failed, if any.
+    ///
+    /// A compressed body is only kept if the request was sent with
+    /// `RequestBuilder::tee`, as it isn't buffered as sent otherwise.
+    ///
+    /// Pass it to `RequestBuilder::resume` to fetch only the rest of the
+    /// body, instead of downloading all of it again.
+    pub fn partial_body(&self) -> Option<&PartialBody> {
+        self.inner.partial_body.as_ref()
+    }
+
+    /// Returns the header that couldn't be added to the request, if the
+    /// error is a builder error caused by an invalid header name or value.
+    pub fn invalid_header(&self) -> Option<&InvalidHeader> {
+        self.inner.invalid_header.as_ref()
+    }
+
     /// Returns the status code, if the error was generated from a response.
     pub fn status(&self) -> Option<StatusCode> {
         match self.inner.kind {
@@ -165,6 +270,11 @@ impl Error {
     pub(crate) fn into_io(self) -> io::Error {
         io::Error::new(io::ErrorKind::Other, self)
     }
+
+    pub(crate) fn with_partial_body(mut self, partial: PartialBody) -> Error {
+        self.inner.partial_body = Some(partial);
+        self
+    }
 }
 
 impl fmt::Debug for Error {
@@ -189,10 +299,21 @@ impl fmt::Display for Error {
         match self.inner.kind {
             Kind::Builder => f.write_str("builder error")?,
             Kind::Request => f.write_str("error sending request")?,
+            Kind::Connect => f.write_str("error trying to connect")?,
+            Kind::Proxy => f.write_str("error sending request through proxy")?,
             Kind::Body => f.write_str("request or response body error")?,
             Kind::Decode => f.write_str("error decoding response body")?,
             Kind::Redirect => f.write_str("error following redirect")?,
             Kind::Serialization => f.write_str("error while serialising body")?,
+            Kind::Disallowed(ref by) => write!(f, "request disallowed by {}", by)?,
+            Kind::Policy => f.write_str("URL not allowed by the client's URL policy")?,
+            Kind::Framing(ref problem) => write!(f, "ambiguous message framing: {}", problem)?,
+            Kind::DuplicateHeader(ref name) => {
+                write!(f, "response has conflicting {} headers", name)?
+            }
+            Kind::Integrity(ref digest) => {
+                write!(f, "response body does not match its {} digest", digest)?
+            }
             // Kind::Upgrade => f.write_str("error upgrading connection")?,
             Kind::Status(ref code) => {
                 let status = StatusCode::from_u16(*code).unwrap();
@@ -212,6 +333,12 @@ impl fmt::Display for Error {
 
         if let Some(e) = &self.inner.source {
             write!(f, ": {}", e)?;
+        } else if let Some(timed_out) = &self.inner.timed_out {
+            write!(f, ": {}", timed_out)?;
+        } else if let Some(mismatch) = &self.inner.length_mismatch {
+            write!(f, ": {}", mismatch)?;
+        } else if let Some(header) = &self.inner.invalid_header {
+            write!(f, ": {}", header)?;
         }
 
         Ok(())
@@ -228,11 +355,22 @@ impl StdError for Error {
 pub(crate) enum Kind {
     Builder,
     Request,
+    Connect,
+    Proxy,
     Redirect,
     Status(u16),
     Body,
     Decode,
     Serialization,
+    // what disallowed the request, e.g. "robots.txt"
+    Disallowed(String),
+    Policy,
+    // the header and algorithm of the digest that didn't match
+    Integrity(String),
+    // what was wrong with the framing of the message
+    Framing(String),
+    // the name of the header
+    DuplicateHeader(String),
     // Upgrade,
 }
 
@@ -242,13 +380,19 @@ pub(crate) fn builder<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Builder, Some(e))
 }
 
+pub(crate) fn invalid_header(header: InvalidHeader) -> Error {
+    let mut err = Error::new(Kind::Builder, Some(header.clone()));
+    err.inner.invalid_header = Some(header);
+    err
+}
+
 pub(crate) fn serialization<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Serialization, Some(e))
 }
 
-// pub(crate) fn body<E: Into<BoxError>>(e: E) -> Error {
-//     Error::new(Kind::Body, Some(e))
-// }
+pub(crate) fn body<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Body, Some(e))
+}
 
 pub(crate) fn decode<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Decode, Some(e))
@@ -258,8 +402,45 @@ pub(crate) fn request<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Request, Some(e))
 }
 
+pub(crate) fn connect(e: io::Error, url: Url) -> Error {
+    Error::new(Kind::Connect, Some(e)).with_url(url)
+}
+
+pub(crate) fn proxy<E: Into<BoxError>>(e: E, url: Url) -> Error {
+    Error::new(Kind::Proxy, Some(e)).with_url(url)
+}
+
 pub(crate) fn timeout(url: Url) -> Error {
-    Error::new(Kind::Request, Some(TimedOut)).with_url(url)
+    timed_out(TimedOut(None), url)
+}
+
+pub(crate) fn timeout_in(
+    phase: TimeoutPhase,
+    elapsed: Duration,
+    allowed: Duration,
+    url: Url,
+) -> Error {
+    timed_out(
+        TimedOut(Some(TimeoutDetail {
+            phase,
+            elapsed,
+            allowed,
+        })),
+        url,
+    )
+}
+
+fn timed_out(timed_out: TimedOut, url: Url) -> Error {
+    let mut err = Error::new(Kind::Request, Some(timed_out.clone())).with_url(url);
+    err.inner.timed_out = Some(timed_out);
+    err
+}
+
+pub(crate) fn length_mismatch(expected: u64, received: u64, url: Url) -> Error {
+    let mismatch = LengthMismatch { expected, received };
+    let mut err = Error::new(Kind::Decode, Some(mismatch)).with_url(url);
+    err.inner.length_mismatch = Some(mismatch);
+    err
 }
 
 pub(crate) fn redirect<E: Into<BoxError>>(e: E, url: Url) -> Error {
@@ -270,10 +451,34 @@ pub(crate) fn status_code(url: Url, status: StatusCode) -> Error {
     Error::new(Kind::Status(status.as_u16()), None::<Error>).with_url(url)
 }
 
+pub(crate) fn disallowed(by: &str, url: Url) -> Error {
+    Error::new(Kind::Disallowed(by.to_string()), None::<Error>).with_url(url)
+}
+
+pub(crate) fn policy(url: Url) -> Error {
+    Error::new(Kind::Policy, None::<Error>).with_url(url)
+}
+
+pub(crate) fn framing(problem: String, url: Url) -> Error {
+    Error::new(Kind::Framing(problem), None::<Error>).with_url(url)
+}
+
+pub(crate) fn duplicate_header(name: &str, url: Url) -> Error {
+    Error::new(Kind::DuplicateHeader(name.to_string()), None::<Error>).with_url(url)
+}
+
+pub(crate) fn integrity(digest: String, url: Url) -> Error {
+    Error::new(Kind::Integrity(digest), None::<Error>).with_url(url)
+}
+
 pub(crate) fn url_bad_scheme(url: Url) -> Error {
     Error::new(Kind::Builder, Some(BadScheme)).with_url(url)
 }
 
+pub(crate) fn unsupported_version(version: crate::Version, url: Url) -> Error {
+    Error::new(Kind::Builder, Some(UnsupportedVersion(version))).with_url(url)
+}
+
 // pub(crate) fn upgrade<E: Into<BoxError>>(e: E) -> Error {
 //     Error::new(Kind::Upgrade, Some(e))
 // }
@@ -297,14 +502,138 @@ pub(crate) fn decode_io(e: io::Error) -> Error {
     }
 }
 
+/// The part of a request that exceeded its time budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum TimeoutPhase {
+    /// Opening the TCP connection.
+    Connect,
+    /// Opening the TCP connection and completing the TLS handshake.
+    TlsHandshake,
+    /// Waiting for the first byte of the response after sending the request.
+    FirstByte,
+    /// Reading the response body.
+    Body,
+    /// Waiting for more of the response body, between two reads. It starts
+    /// over whenever bytes arrive.
+    BodyIdle,
+    /// The request as a whole, from sending it to reading the response.
+    Request,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TimeoutPhase::Connect => "connect",
+            TimeoutPhase::TlsHandshake => "TLS handshake",
+            TimeoutPhase::FirstByte => "waiting for the response",
+            TimeoutPhase::Body => "reading the response body",
+            TimeoutPhase::BodyIdle => "waiting for more of the response body",
+            TimeoutPhase::Request => "request",
+        })
+    }
+}
+
+/// Timing detail of a timeout error, see [`Error::timeout_detail`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeoutDetail {
+    /// The phase that ran out of time.
+    pub phase: TimeoutPhase,
+    /// How long the phase had been running when it was abandoned.
+    pub elapsed: Duration,
+    /// How long the phase was allowed to take.
+    pub allowed: Duration,
+}
+
+/// Sizes of a response body that didn't match its `Content-Length`, see
+/// [`Error::length_mismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LengthMismatch {
+    /// The length declared by the `Content-Length` header.
+    pub expected: u64,
+    /// The number of body bytes the server sent.
+    pub received: u64,
+}
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let how = if self.received < self.expected {
+            "truncated"
+        } else {
+            "too long"
+        };
+        write!(
+            f,
+            "body {}, Content-Length is {} bytes but {} were received",
+            how, self.expected, self.received
+        )
+    }
+}
+
+impl StdError for LengthMismatch {}
+
+/// The part of a response body that was read before reading or decoding it
+/// failed, see [`Error::partial_body`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialBody {
+    /// The body bytes read, as sent, before any decompression.
+    pub raw: Vec<u8>,
+    /// The `Content-Encoding` of the body, if it was compressed. The rest of
+    /// the body has to be appended to `raw` before decompressing it.
+    pub content_encoding: Option<String>,
+    /// The `ETag` of the response, or else its `Last-Modified` date, which
+    /// make sure the rest of the body belongs to the same version of it.
+    pub validator: Option<String>,
+}
+
+impl PartialBody {
+    /// The offset in the body from which to resume.
+    pub fn offset(&self) -> u64 {
+        self.raw.len() as u64
+    }
+}
+
+/// A header that couldn't be added to a request, see
+/// [`Error::invalid_header`].
+///
+/// The value is left out, as it may well be a secret such as a token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidHeader {
+    /// The name of the header. It is only missing if the name itself was
+    /// invalid and not given as text, see `RequestBuilder::try_header`.
+    pub name: Option<String>,
+    /// Whether the name of the header was invalid, rather than its value.
+    pub invalid_name: bool,
+}
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.name, self.invalid_name) {
+            (Some(name), true) => write!(f, "invalid header name {:?}", name),
+            (None, true) => f.write_str("invalid header name"),
+            (Some(name), false) => write!(f, "invalid value for header {}", name),
+            (None, false) => f.write_str("invalid header value"),
+        }
+    }
+}
+
+impl StdError for InvalidHeader {}
+
 // internal Error "sources"
 
-#[derive(Debug)]
-pub(crate) struct TimedOut;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TimedOut(Option<TimeoutDetail>);
 
 impl fmt::Display for TimedOut {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str("operation timed out")
+        match self.0 {
+            Some(ref detail) => write!(
+                f,
+                "{} timed out after {:?} (allowed {:?})",
+                detail.phase, detail.elapsed, detail.allowed
+            ),
+            None => f.write_str("operation timed out"),
+        }
     }
 }
 
@@ -321,6 +650,17 @@ impl fmt::Display for BadScheme {
 
 impl StdError for BadScheme {}
 
+#[derive(Debug)]
+pub(crate) struct UnsupportedVersion(crate::Version);
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} is not supported by the lunatic transport", self.0)
+    }
+}
+
+impl StdError for UnsupportedVersion {}
+
 // #[cfg(test)]
 mod tests {
     use super::*;
@@ -350,4 +690,75 @@ mod tests {
         let nested = super::request(io);
         assert!(nested.is_timeout());
     }
+
+    #[lunatic::test]
+    fn partial_body_survives_serialization() {
+        let partial = PartialBody {
+            raw: b"hel".to_vec(),
+            content_encoding: Some("gzip".to_string()),
+            validator: None,
+        };
+        let err = super::decode_io(io::Error::new(io::ErrorKind::Other, "bad data"))
+            .with_partial_body(partial.clone());
+        let err: Error = serde_json::from_str(&serde_json::to_string(&err).unwrap()).unwrap();
+
+        assert_eq!(err.partial_body(), Some(&partial));
+        assert_eq!(err.partial_body().unwrap().offset(), 3);
+    }
+
+    #[lunatic::test]
+    fn timeout_detail_survives_serialization() {
+        let url = Url::parse("http://localhost:3000/api").unwrap();
+        let err = super::timeout_in(
+            TimeoutPhase::Connect,
+            Duration::from_millis(1002),
+            Duration::from_secs(1),
+            url,
+        );
+        let err: Error = serde_json::from_str(&serde_json::to_string(&err).unwrap()).unwrap();
+
+        assert!(err.is_timeout());
+        let detail = err.timeout_detail().unwrap();
+        assert_eq!(detail.phase, TimeoutPhase::Connect);
+        assert_eq!(detail.allowed, Duration::from_secs(1));
+        assert!(err
+            .to_string()
+            .ends_with("connect timed out after 1.002s (allowed 1s)"));
+    }
+
+    #[lunatic::test]
+    fn length_mismatch_survives_serialization() {
+        let url = Url::parse("http://localhost:3000/api").unwrap();
+        let err = super::length_mismatch(100, 42, url);
+        let err: Error = serde_json::from_str(&serde_json::to_string(&err).unwrap()).unwrap();
+
+        assert!(err.is_decode());
+        assert_eq!(
+            err.length_mismatch(),
+            Some(&LengthMismatch {
+                expected: 100,
+                received: 42
+            })
+        );
+        assert!(err
+            .to_string()
+            .ends_with("body truncated, Content-Length is 100 bytes but 42 were received"));
+    }
+
+    #[lunatic::test]
+    fn invalid_header_survives_serialization() {
+        let header = InvalidHeader {
+            name: Some("authorization".to_string()),
+            invalid_name: false,
+        };
+        let err = super::invalid_header(header.clone());
+        let err: Error = serde_json::from_str(&serde_json::to_string(&err).unwrap()).unwrap();
+
+        assert!(err.is_builder());
+        assert_eq!(err.invalid_header(), Some(&header));
+        assert_eq!(
+            err.to_string(),
+            "builder error: invalid value for header authorization"
+        );
+    }
 }
@@ -0,0 +1,87 @@
+//! Polling a JSON resource with `If-None-Match`, see
+//! `Client::fetch_json_cached`.
+
+use http::header::{ETAG, IF_NONE_MATCH};
+use http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{Client, IntoUrl};
+
+/// The last value fetched by `Client::fetch_json_cached`, with its `ETag`.
+///
+/// Keep one per polled URL. It can be serialized to keep it across restarts
+/// or send it to another process.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry<T> {
+    etag: Option<String>,
+    value: Option<T>,
+    changed: bool,
+}
+
+impl<T> CacheEntry<T> {
+    /// An empty entry, the first fetch always downloads the value.
+    pub fn new() -> CacheEntry<T> {
+        CacheEntry {
+            etag: None,
+            value: None,
+            changed: false,
+        }
+    }
+
+    /// The last value fetched, if any.
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// The `ETag` the last value was sent with, if any.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// Whether the last fetch downloaded a new value, rather than being told
+    /// that the cached one is still current.
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+impl<T> Default for CacheEntry<T> {
+    fn default() -> CacheEntry<T> {
+        CacheEntry::new()
+    }
+}
+
+pub(crate) fn fetch_json<'a, T, U>(
+    client: &Client,
+    url: U,
+    entry: &'a mut CacheEntry<T>,
+) -> crate::Result<&'a T>
+where
+    T: DeserializeOwned,
+    U: IntoUrl,
+{
+    let mut req = client.get(url);
+    if let (Some(etag), Some(_)) = (&entry.etag, &entry.value) {
+        req = req.header(IF_NONE_MATCH, etag.as_str());
+    }
+    let res = req.send()?;
+    if res.status() == StatusCode::NOT_MODIFIED {
+        entry.changed = false;
+        // only sent in answer to an `If-None-Match`, which needs a value
+        return entry.value.as_ref().ok_or_else(|| {
+            crate::error::decode("304 Not Modified without a cached value")
+                .with_url(res.url().clone())
+        });
+    }
+
+    let res = res.error_for_status()?;
+    entry.etag = res
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let value = res.json()?;
+    entry.changed = true;
+    Ok(entry.value.insert(value))
+}
@@ -0,0 +1,214 @@
+//! Fault injection for resilience testing.
+//!
+//! A client built with [`ClientBuilder::fault_injection`] randomly delays
+//! requests, drops connections and answers with synthetic server errors, at
+//! the configured probabilities, so that code relying on the client can
+//! exercise its retry and circuit-breaking paths against a healthy server.
+//!
+//! Only available with the `fault-injection` feature, which is meant for
+//! test builds.
+//!
+//! [`ClientBuilder::fault_injection`]: crate::ClientBuilder::fault_injection
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{HttpResponse, TimeSource, Version};
+
+/// Faults to inject into the requests of a `Client`.
+///
+/// Every probability is checked independently for each request written to
+/// the wire, redirects included, and clamped to `0.0..=1.0`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use nightfly::fault::FaultInjection;
+///
+/// let faults = FaultInjection::new()
+///     .latency(0.2, Duration::from_millis(300))
+///     .drop_connection(0.05)
+///     .server_error(0.1)
+///     .seed(42);
+///
+/// let client = nightfly::Client::builder().fault_injection(faults).build();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultInjection {
+    latency: Option<(f64, Duration)>,
+    drop_connection: f64,
+    server_error: Option<(f64, u16)>,
+    seed: Option<u64>,
+}
+
+impl FaultInjection {
+    /// Inject no faults at all; enable them with the other methods.
+    pub fn new() -> FaultInjection {
+        FaultInjection::default()
+    }
+
+    /// Delay requests by `delay` with the given probability.
+    pub fn latency(mut self, probability: f64, delay: Duration) -> FaultInjection {
+        self.latency = Some((probability, delay));
+        self
+    }
+
+    /// Fail requests as if the connection was reset, with the given
+    /// probability.
+    pub fn drop_connection(mut self, probability: f64) -> FaultInjection {
+        self.drop_connection = probability;
+        self
+    }
+
+    /// Answer requests with a synthetic `503 Service Unavailable` response,
+    /// without contacting the server, with the given probability.
+    pub fn server_error(self, probability: f64) -> FaultInjection {
+        self.server_error_status(probability, StatusCode::SERVICE_UNAVAILABLE)
+    }
+
+    /// Like `server_error`, with a custom status code.
+    pub fn server_error_status(mut self, probability: f64, status: StatusCode) -> FaultInjection {
+        self.server_error = Some((probability, status.as_u16()));
+        self
+    }
+
+    /// Seed the random generator, to get the same faults on every run.
+    ///
+    /// By default the seed is taken from the client's `TimeSource`.
+    pub fn seed(mut self, seed: u64) -> FaultInjection {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// The outcome picked for a request.
+pub(crate) enum Fault {
+    DropConnection,
+    ServerError(StatusCode),
+}
+
+/// Applies a `FaultInjection` inside the client process.
+#[derive(Clone)]
+pub(crate) struct FaultInjector {
+    config: FaultInjection,
+    state: u64,
+}
+
+impl FaultInjector {
+    pub(crate) fn new(config: FaultInjection, time: &TimeSource) -> FaultInjector {
+        let seed = config.seed.unwrap_or_else(|| {
+            time.now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        FaultInjector {
+            config,
+            // xorshift gets stuck on zero
+            state: seed | 1,
+        }
+    }
+
+    /// Sleeps for the injected latency, if any, and picks the fault to
+    /// apply to the request.
+    pub(crate) fn inject(&mut self, time: &TimeSource) -> Option<Fault> {
+        if let Some((probability, delay)) = self.config.latency {
+            if self.hit(probability) {
+                time.sleep(delay);
+            }
+        }
+        if self.hit(self.config.drop_connection) {
+            return Some(Fault::DropConnection);
+        }
+        let (probability, status) = self.config.server_error?;
+        if self.hit(probability) {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            return Some(Fault::ServerError(status));
+        }
+        None
+    }
+
+    fn hit(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        ((self.state >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+impl fmt::Debug for FaultInjector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.config, f)
+    }
+}
+
+impl Fault {
+    pub(crate) fn into_result(self, url: Url) -> crate::Result<HttpResponse> {
+        match self {
+            Fault::DropConnection => Err(crate::error::request(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "connection dropped by fault injection",
+            ))
+            .with_url(url)),
+            Fault::ServerError(status) => Ok(HttpResponse {
+                body: Bytes::new(),
+                status,
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                url,
+                redirect_chain: vec![],
+                request_id: None,
+                raw_body: None,
+                raw_headers: vec![],
+                body_hash: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn probabilities_are_respected() {
+        let time = TimeSource::system();
+        let always = FaultInjection::new().server_error(1.0).seed(7);
+        let never = FaultInjection::new().drop_connection(0.0).seed(7);
+
+        let mut injector = FaultInjector::new(always, &time);
+        assert!(matches!(
+            injector.inject(&time),
+            Some(Fault::ServerError(StatusCode::SERVICE_UNAVAILABLE))
+        ));
+        let mut injector = FaultInjector::new(never, &time);
+        assert!((0..100).all(|_| injector.inject(&time).is_none()));
+    }
+
+    #[lunatic::test]
+    fn same_seed_same_faults() {
+        let time = TimeSource::system();
+        let faults = FaultInjection::new().drop_connection(0.5).seed(1234);
+        let run = || {
+            let mut injector = FaultInjector::new(faults.clone(), &time);
+            (0..64)
+                .map(|_| injector.inject(&time).is_some())
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+        assert!(first.iter().any(|hit| *hit) && first.iter().any(|hit| !*hit));
+    }
+}
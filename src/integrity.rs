@@ -0,0 +1,224 @@
+//! Checking response bodies against the digests sent by the server, and
+//! computing digests of request and response bodies.
+//!
+//! Enabled with `ClientBuilder::verify_integrity`. Three headers are
+//! understood, all computed over the body as sent, before decompression:
+//!
+//! - `Content-MD5` ([RFC 1864]), a base64 MD5 digest;
+//! - `Digest` ([RFC 3230]), e.g. `SHA-256=<base64>`;
+//! - `Repr-Digest` ([RFC 9530]), e.g. `sha-256=:<base64>:`.
+//!
+//! Digests with an algorithm other than MD5, SHA-1, SHA-256 or SHA-512 are
+//! ignored.
+//!
+//! `RequestBuilder::hash_body` computes a digest of a response body as it
+//! is read, for checksums published elsewhere.
+//!
+//! [RFC 1864]: https://www.rfc-editor.org/rfc/rfc1864
+//! [RFC 3230]: https://www.rfc-editor.org/rfc/rfc3230
+//! [RFC 9530]: https://www.rfc-editor.org/rfc/rfc9530
+
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+/// A digest algorithm, see `RequestBuilder::hash_body`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+    /// MD5, only for checksums that are still published as MD5.
+    Md5,
+    /// SHA-1.
+    Sha1,
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+}
+
+/// A digest computed over a body as it is read.
+#[derive(Clone)]
+pub(crate) enum BodyHasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl BodyHasher {
+    pub(crate) fn new(algorithm: Algorithm) -> BodyHasher {
+        match algorithm {
+            Algorithm::Md5 => BodyHasher::Md5(Md5::new()),
+            Algorithm::Sha1 => BodyHasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => BodyHasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => BodyHasher::Sha512(Sha512::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            BodyHasher::Md5(hasher) => hasher.update(data),
+            BodyHasher::Sha1(hasher) => hasher.update(data),
+            BodyHasher::Sha256(hasher) => hasher.update(data),
+            BodyHasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self {
+            BodyHasher::Md5(hasher) => hasher.finalize().to_vec(),
+            BodyHasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            BodyHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            BodyHasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Checks `body` against every digest header in `headers`.
+///
+/// Returns the name of the header and algorithm of the first digest that
+/// doesn't match.
+pub(crate) fn verify(headers: &HeaderMap, body: &[u8]) -> Result<(), String> {
+    let mut expected: Vec<(&str, String, String)> = Vec::new();
+    for value in headers.get_all("content-md5") {
+        if let Ok(value) = value.to_str() {
+            expected.push(("Content-MD5", "md5".to_string(), value.trim().to_string()));
+        }
+    }
+    for (header, strip_colons) in &[("Digest", false), ("Repr-Digest", true)] {
+        for value in headers.get_all(*header) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for member in value.split(',') {
+                let (algorithm, digest) = match member.split_once('=') {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+                let mut digest = digest.trim();
+                if *strip_colons {
+                    digest = digest.trim_matches(':');
+                }
+                expected.push((
+                    header,
+                    algorithm.trim().to_ascii_lowercase(),
+                    digest.to_string(),
+                ));
+            }
+        }
+    }
+
+    for (header, algorithm, digest) in expected {
+        let actual = match algorithm.as_str() {
+            "md5" => Md5::digest(body).to_vec(),
+            "sha" | "sha-1" => Sha1::digest(body).to_vec(),
+            "sha-256" => Sha256::digest(body).to_vec(),
+            "sha-512" => Sha512::digest(body).to_vec(),
+            _ => continue,
+        };
+        if base64::decode(&digest).ok().as_deref() != Some(&actual[..]) {
+            return Err(format!("{} {}", header, algorithm));
+        }
+    }
+    Ok(())
+}
+
+/// The RFC 9530 `Content-Digest` of a request body, using SHA-256.
+pub(crate) fn content_digest(body: &[u8]) -> HeaderValue {
+    let value = format!("sha-256=:{}:", base64::encode(Sha256::digest(body)));
+    HeaderValue::from_str(&value).expect("base64 is a valid header value")
+}
+
+/// Returns false for responses whose body isn't the whole representation
+/// the digests describe.
+pub(crate) fn applies_to(method: &Method, status: StatusCode) -> bool {
+    method != Method::HEAD
+        && status != StatusCode::NO_CONTENT
+        && status != StatusCode::NOT_MODIFIED
+        && status != StatusCode::PARTIAL_CONTENT
+        && !status.is_informational()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn header_map(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[lunatic::test]
+    fn accepts_matching_digests() {
+        let headers = header_map(&[
+            ("content-md5", "XrY7u+Ae7tCTyyK7j1rNww=="),
+            (
+                "digest",
+                "SHA=Kq5sNclPz7QV2+lfQIuc6R7oRu0=, unknown=abc, \
+                 SHA-256=uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=",
+            ),
+            (
+                "repr-digest",
+                "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:",
+            ),
+        ]);
+        assert_eq!(verify(&headers, b"hello world"), Ok(()));
+    }
+
+    #[lunatic::test]
+    fn computes_content_digest() {
+        let digest = content_digest(b"hello world");
+        assert_eq!(
+            digest,
+            "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("repr-digest", digest);
+        assert_eq!(verify(&headers, b"hello world"), Ok(()));
+    }
+
+    #[lunatic::test]
+    fn hashes_in_pieces() {
+        let mut hasher = BodyHasher::new(Algorithm::Sha256);
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert_eq!(
+            base64::encode(hasher.finish()),
+            "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+        );
+
+        let mut hasher = BodyHasher::new(Algorithm::Md5);
+        hasher.update(b"hello world");
+        assert_eq!(base64::encode(hasher.finish()), "XrY7u+Ae7tCTyyK7j1rNww==");
+    }
+
+    #[lunatic::test]
+    fn reports_the_mismatching_digest() {
+        let headers = header_map(&[
+            ("content-md5", "XrY7u+Ae7tCTyyK7j1rNww=="),
+            (
+                "repr-digest",
+                "sha-512=:MJ7MSJwS1utMxA9QyQLytNDtd+5RGnx6m808qG1M2G+YndNbxf9JlnDaNCVbRbDP2DDoH2Bdz33FVC6TrpzXbw==:",
+            ),
+        ]);
+        assert_eq!(verify(&headers, b"hello world"), Ok(()));
+        assert_eq!(
+            verify(&headers, b"hello w0rld"),
+            Err("Content-MD5 md5".to_string())
+        );
+
+        let headers = header_map(&[("repr-digest", "sha-256=:bm9wZQ==:")]);
+        assert_eq!(
+            verify(&headers, b"hello world"),
+            Err("Repr-Digest sha-256".to_string())
+        );
+    }
+}
@@ -122,6 +122,18 @@
 //! enabled or disabled:
 //!
 //! - **cookies**: Provides cookie session support.
+//! - **cookies-psl**: Rejects cookies set for a public suffix, such as
+//!   `github.io`, see `ClientBuilder::cookie_public_suffix_list`.
+//! - **csrf-html**: Lets `csrf::CsrfToken` find tokens in HTML pages.
+//! - **otel**: Records an OpenTelemetry span for every request.
+//! - **log**: Logs requests, responses and errors through the `log` crate.
+//! - **fault-injection**: Lets a `Client` inject faults into its requests,
+//!   for resilience tests.
+//! - **macros**: Provides the `request!`, `get!` and `post!` macros, building
+//!   a request from a URL format string, headers and a JSON body.
+//! - **mock**: Provides `mock::MockClient`, answering requests from canned
+//!   responses in unit tests.
+//! - **oauth1**: Signs requests with OAuth 1.0a, see `RequestBuilder::oauth1`.
 //!
 //!
 //! [client]: ./struct.Client.html
@@ -130,6 +142,7 @@
 //! [builder]: ./struct.RequestBuilder.html
 //! [serde]: http://serde.rs
 //! [redirect]: crate::redirect
+//! [TLS]: crate::tls
 //! [cargo-features]: https://doc.rust-lang.org/stable/cargo/reference/manifest.html#the-features-section
 
 pub use http::header;
@@ -139,13 +152,22 @@ pub use http::{HeaderMap, HeaderValue};
 pub use url::Url;
 
 // universal mods
+mod clock;
 #[macro_use]
 mod error;
+mod etag;
+mod integrity;
 mod into_url;
+mod probe;
 mod response;
 
-pub use self::error::{Error, Result};
+pub use self::error::{
+    Error, InvalidHeader, LengthMismatch, PartialBody, Result, TimeoutDetail, TimeoutPhase,
+};
+pub use self::etag::CacheEntry;
+pub use self::integrity::Algorithm;
 pub use self::into_url::IntoUrl;
+pub use self::probe::ContentInfo;
 pub use self::response::ResponseBuilderExt;
 
 /// Shortcut method to quickly make a `GET` request.
@@ -204,19 +226,60 @@ fn _assert_impls() {
 
 // #[cfg(feature = "multipart")]
 // pub use self::lunatic_impl::multipart;
+pub use self::lunatic_impl::client::{ClientConfig, DuplicateHeaders, Placement};
 pub use self::lunatic_impl::{
-    Body, Client, ClientBuilder, HttpResponse, Request, RequestBuilder, SerializableResponse,
+    Body, Chunks, Client, ClientBuilder, HttpResponse, Request, RequestBuilder,
+    SerializableRequest, SerializableResponse, TextChunks, SCHEMA_VERSION,
 };
-#[cfg(feature = "__tls")]
 // Re-exports, to be removed in a future release
-pub use tls::{Certificate, Identity};
+pub use tls::Certificate;
 
+pub mod batch;
+pub mod connect;
 #[cfg(feature = "cookies")]
 pub mod cookie;
+pub mod cors;
+pub mod csrf;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+#[cfg(feature = "log")]
+mod logging;
 mod lunatic_impl;
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod macros;
+pub mod metrics;
+pub mod mirror;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod notices;
+pub mod outbox;
+pub mod parts;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod recent;
+#[cfg(feature = "oauth1")]
+pub mod oauth1;
+#[cfg(feature = "otel")]
+mod otel;
 pub mod redirect;
-#[cfg(feature = "__tls")]
+pub mod retry;
+pub mod robots;
+#[cfg(feature = "cookies")]
+pub mod session;
+pub mod signing;
+mod ssrf;
 pub mod tls;
+pub mod token;
+pub mod uri_template;
+pub mod url_policy;
 mod util;
 mod version;
+pub mod webdav;
 pub use version::Version;
+
+pub use self::clock::{Clock, TimeSource};
+pub use self::proxy::{NoProxy, Proxy};
+
+#[cfg(feature = "log")]
+pub use self::logging::LogLevels;
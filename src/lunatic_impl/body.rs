@@ -1,37 +1,52 @@
-use bytes::Bytes;
-use serde::{Deserialize, Serialize};
+use bytes::{Buf, Bytes};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Body struct
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
-pub struct Body(Vec<u8>);
+///
+/// The bytes are reference counted, so cloning a body, to resend it after a
+/// redirect for example, doesn't copy them.
+///
+/// A body made with `Body::from_reader` isn't held in memory, it is read while
+/// the request is sent.
+#[derive(Clone)]
+pub struct Body(Inner);
+
+#[derive(Clone)]
+enum Inner {
+    Bytes(Bytes),
+    Reader(Reader, Option<u64>),
+}
+
+/// Clones of a body share its reader, like `RequestBuilder::tee` writers.
+pub(crate) type Reader = Arc<Mutex<dyn Read + Send>>;
 
 impl From<String> for Body {
     fn from(s: String) -> Body {
-        Body(s.into())
+        Body::bytes(s.into())
     }
 }
 
 impl From<&str> for Body {
     fn from(s: &str) -> Body {
-        Body(s.into())
+        Body::bytes(Bytes::copy_from_slice(s.as_bytes()))
     }
 }
 
 impl From<Bytes> for Body {
     fn from(b: Bytes) -> Body {
-        Body(b.into())
+        Body::bytes(b)
     }
 }
 
 impl From<Vec<u8>> for Body {
     fn from(v: Vec<u8>) -> Body {
-        Body(v)
+        Body::bytes(v.into())
     }
 }
 
 impl From<&[u8]> for Body {
     fn from(slice: &[u8]) -> Body {
-        Body(slice.into())
+        Body::bytes(Bytes::copy_from_slice(slice))
     }
 }
 
@@ -43,13 +58,13 @@ impl From<()> for Body {
 
 impl From<HttpResponse> for Body {
     fn from(res: HttpResponse) -> Self {
-        res.body.into()
+        Body::bytes(res.body)
     }
 }
 
 impl From<Body> for Bytes {
     fn from(body: Body) -> Bytes {
-        Bytes::from(body.0)
+        body.into_bytes()
     }
 }
 
@@ -57,35 +72,136 @@ impl TryInto<String> for Body {
     type Error = FromUtf8Error;
 
     fn try_into(self) -> Result<String, Self::Error> {
-        String::from_utf8(self.0)
+        String::from_utf8(self.inner())
+    }
+}
+
+impl Default for Body {
+    fn default() -> Body {
+        Body::empty()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Inner::Bytes(ref bytes) => f.debug_tuple("Body").field(bytes).finish(),
+            Inner::Reader(_, len) => f.debug_struct("Body").field("len", &len).finish(),
+        }
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Inner::Bytes(ref bytes) => serializer.serialize_newtype_struct("Body", bytes),
+            Inner::Reader(..) => Err(serde::ser::Error::custom(
+                "a body read from a reader can't be sent to another process",
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Body, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Body")]
+        struct Repr(Bytes);
+
+        Repr::deserialize(deserializer).map(|repr| Body::bytes(repr.0))
     }
 }
 
 impl Body {
-    /// empty body
-    pub fn empty() -> Body {
-        Body(vec![])
+    fn bytes(bytes: Bytes) -> Body {
+        Body(Inner::Bytes(bytes))
     }
 
-    /// length of body
+    /// empty body
+    pub fn empty() -> Body {
+        Body::bytes(Bytes::new())
+    }
+
+    /// A body read from `reader` while the request is sent, so a large file
+    /// can be uploaded without loading it into memory.
+    ///
+    /// With a known `len` the request has a `Content-Length`, and the reader
+    /// must provide exactly that many bytes. Otherwise the body is sent with
+    /// `Transfer-Encoding: chunked`, which HTTP/1.0 servers don't understand.
+    ///
+    /// The body can only be sent once: `RequestBuilder::try_clone` refuses to
+    /// clone it, and a redirect is followed without it.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = std::fs::File::open("backup.tar")?;
+    /// let len = file.metadata()?.len();
+    /// let res = nightfly::Client::new()
+    ///     .put("https://storage.example.com/backups/backup.tar")
+    ///     .body(nightfly::Body::from_reader(file, Some(len)))
+    ///     .send()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, len: Option<u64>) -> Body {
+        Body(Inner::Reader(Arc::new(Mutex::new(reader)), len))
+    }
+
+    /// length of body, or 0 for a body read from a reader of unknown length
     pub fn len(&self) -> usize {
-        self.0.len()
+        match self.0 {
+            Inner::Bytes(ref bytes) => bytes.len(),
+            Inner::Reader(_, len) => len.unwrap_or(0) as usize,
+        }
     }
 
     /// tells whether body is empty
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        match self.0 {
+            Inner::Bytes(ref bytes) => bytes.is_empty(),
+            Inner::Reader(_, len) => len == Some(0),
+        }
     }
 
-    /// retrieve body
+    /// retrieve body, reading all of it from its reader if it has one
     pub fn inner(self) -> Vec<u8> {
-        self.0
+        self.into_bytes().into()
+    }
+
+    /// retrieve body without copying it, reading all of it from its reader
+    /// if it has one
+    pub fn into_bytes(self) -> Bytes {
+        match self.0 {
+            Inner::Bytes(bytes) => bytes,
+            Inner::Reader(reader, _) => {
+                let mut buf = Vec::new();
+                // there's no way to report the error, keep what was read
+                let _ = lock(&reader).read_to_end(&mut buf);
+                buf.into()
+            }
+        }
+    }
+
+    /// borrow the bytes of the body, empty for a body read from a reader
+    pub fn as_bytes(&self) -> &[u8] {
+        match self.0 {
+            Inner::Bytes(ref bytes) => bytes,
+            Inner::Reader(..) => &[],
+        }
+    }
+
+    /// The reader of a body made with `Body::from_reader`, and its length.
+    pub(crate) fn reader(&self) -> Option<(Reader, Option<u64>)> {
+        match self.0 {
+            Inner::Bytes(_) => None,
+            Inner::Reader(ref reader, len) => Some((reader.clone(), len)),
+        }
     }
 
     /// create a json body
     pub fn json<T: Serialize>(data: T) -> crate::Result<Body> {
         match serde_json::to_string(&data) {
-            Ok(r) => Ok(Body(r.into())),
+            Ok(r) => Ok(Body::bytes(r.into())),
             Err(_e) => Err(crate::Error::new(
                 crate::error::Kind::Request,
                 Some("".to_string()),
@@ -95,20 +211,29 @@ impl Body {
 
     /// create a regular text body
     pub fn text<T: Into<Vec<u8>>>(data: T) -> crate::Result<Body> {
-        Ok(Body(data.into()))
+        Ok(Body::bytes(data.into().into()))
     }
 }
 
 impl Read for Body {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        Cursor::new(self.0.clone()).read(buf)
+        match self.0 {
+            Inner::Bytes(ref mut bytes) => {
+                let n = buf.len().min(bytes.len());
+                buf[..n].copy_from_slice(&bytes[..n]);
+                bytes.advance(n);
+                Ok(n)
+            }
+            Inner::Reader(ref reader, _) => lock(reader).read(buf),
+        }
     }
 }
 
-use std::{
-    convert::TryInto,
-    io::{Cursor, Read},
-    string::FromUtf8Error,
-};
+pub(crate) fn lock(reader: &Reader) -> MutexGuard<'_, dyn Read + Send + 'static> {
+    reader.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{convert::TryInto, fmt, io::Read, string::FromUtf8Error};
 
 use crate::HttpResponse;
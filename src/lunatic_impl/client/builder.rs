@@ -1,29 +1,54 @@
 use core::fmt;
 #[cfg(feature = "cookies")]
-use std::sync::Arc;
+use std::path::PathBuf;
 use std::{
     collections::HashMap,
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     net::{IpAddr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
 use http::{
-    header::{ACCEPT, USER_AGENT},
+    header::{HeaderName, ACCEPT, ACCEPT_LANGUAGE, USER_AGENT},
     HeaderMap, HeaderValue,
 };
 use lunatic::AbstractProcess;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[cfg(feature = "cookies")]
-use crate::cookie::Jar;
+use crate::cookie::{CookieLimits, Jar, SerializableJar};
+#[cfg(feature = "fault-injection")]
+use crate::fault::{FaultInjection, FaultInjector};
+#[cfg(feature = "log")]
+use crate::logging::LogLevels;
+#[cfg(feature = "mock")]
+use crate::mock::{MockRule, MockTransport};
 
 use crate::{
-    lunatic_impl::{decoder::Accepts, request::header_map_from_hashmap},
-    redirect, Client,
+    connect::{self, ConnectHook, RemoteConnectHook},
+    lunatic_impl::{
+        decoder::Accepts, header_policy::HeaderPolicy, http_stream::Connector, pool::Pool,
+        request::header_map_from_hashmap, spacing::HostSpacing,
+    },
+    mirror::{Mirror, Mirrorer},
+    proxy::{NoProxy, Proxy},
+    proxy_protocol::ProxyHeader,
+    recent::RecentRequests,
+    redirect::{self, RedirectAuthPolicy},
+    retry::{self, RemoteRetryObserver, Retrier, RetryObserver, RetryPolicy},
+    robots::{Robots, RobotsPolicy},
+    signing::{self, RemoteSigner, RequestSigner},
+    tls::Certificate,
+    token::{self, CachedToken, RemoteTokenSource, TokenSource},
+    url_policy::UrlPolicy,
+    Client, TimeSource,
 };
 
-use super::InnerClient;
+use super::{ClientConfig, InnerClient};
+
+/// Header used by `ClientBuilder::request_id` when no other name is given.
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 /// A `ClientBuilder` can be used to create a `Client` with custom configuration.
 #[must_use]
@@ -32,8 +57,49 @@ pub struct ClientBuilder {
     pub(crate) config: Config,
 }
 
+/// Where `ClientBuilder::api_key` puts the key in each request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Send the key as the value of the named header.
+    Header(&'static str),
+    /// Append the key to the URL query as the named parameter.
+    Query(&'static str),
+    /// Send the key as `Authorization: Bearer <key>`.
+    Bearer,
+}
+
+/// What a `Client` does when a response repeats, with different values, a
+/// header that may only appear once, such as `Content-Length` or
+/// `Content-Type`. See `ClientBuilder::duplicate_headers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateHeaders {
+    /// Fail the request.
+    Error,
+    /// Keep the first value.
+    FirstWins,
+    /// Keep the last value.
+    LastWins,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum ApiKey {
+    Header { name: String, value: String },
+    Query { name: String, value: String },
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the key itself.
+        match self {
+            ApiKey::Header { name, .. } => f.debug_tuple("Header").field(name).finish(),
+            ApiKey::Query { name, .. } => f.debug_tuple("Query").field(name).finish(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 enum HttpVersionPref {
+    Http10,
     Http1,
     Http2,
     All,
@@ -44,30 +110,34 @@ pub(crate) struct Config {
     // NOTE: When adding a new field, update `fmt::Debug for ClientBuilder`
     pub(crate) accepts: Accepts,
     headers: HashMap<String, Vec<String>>,
-    #[cfg(feature = "native-tls")]
-    hostname_verification: bool,
-    #[cfg(feature = "__tls")]
-    certs_verification: bool,
     connect_timeout: Option<Duration>,
+    tls_handshake_timeout: Option<Duration>,
+    body_idle_timeout: Option<Duration>,
+    max_download_rate: Option<u64>,
+    max_upload_rate: Option<u64>,
+    min_delay_per_host: Option<Duration>,
+    verify_integrity: bool,
+    content_digest: bool,
+    lenient_framing: bool,
+    expect_continue_threshold: Option<u64>,
+    duplicate_headers: DuplicateHeaders,
+    join_header_values: bool,
     connection_verbose: bool,
     pool_idle_timeout: Option<Duration>,
     pool_max_idle_per_host: usize,
+    retry_stale_connections: bool,
+    pool_health_check: Option<Duration>,
     tcp_keepalive: Option<Duration>,
-    #[cfg(any(feature = "native-tls", feature = "__rustls"))]
-    identity: Option<Identity>,
-    // proxies: Vec<Proxy>,
-    // auto_sys_proxy: bool,
+    proxies: Vec<Proxy>,
+    system_proxies: bool,
+    no_proxy: NoProxy,
     redirect_policy: redirect::Policy,
+    redirect_auth: RedirectAuthPolicy,
     referer: bool,
     timeout: Option<Duration>,
-    #[cfg(feature = "__tls")]
     root_certs: Vec<Certificate>,
     #[cfg(feature = "__tls")]
     tls_built_in_root_certs: bool,
-    #[cfg(feature = "__tls")]
-    min_tls_version: Option<tls::Version>,
-    #[cfg(feature = "__tls")]
-    max_tls_version: Option<tls::Version>,
     http_version_pref: HttpVersionPref,
     http09_responses: bool,
     http1_title_case_headers: bool,
@@ -83,13 +153,72 @@ pub(crate) struct Config {
     nodelay: bool,
     // #[cfg(feature = "cookies")]
     // cookie_store: Option<Arc<Jar>>,
+    #[cfg(feature = "cookies")]
+    cookie_limits: CookieLimits,
+    #[cfg(feature = "cookies")]
+    cookie_file: Option<PathBuf>,
+    #[cfg(feature = "cookies")]
+    cookies: Option<SerializableJar>,
+    #[cfg(feature = "cookies-psl")]
+    cookie_public_suffix_list: Option<String>,
     // trust_dns: bool,
+    request_id_header: Option<String>,
+    record_requests: usize,
+    retry: Option<RetryPolicy>,
+    retry_observer: Option<RemoteRetryObserver>,
+    api_key: Option<ApiKey>,
+    time_source: Option<TimeSource>,
+    request_signer: Option<RemoteSigner>,
+    token_source: Option<RemoteTokenSource>,
+    connect_hook: Option<RemoteConnectHook>,
+    proxy_protocol: Option<ProxyHeader>,
+    robots: Option<RobotsPolicy>,
+    mirror: Option<Mirror>,
+    // the client sending the shadow requests, started by `build`
+    shadow_client: Option<Client>,
+    #[cfg(feature = "fault-injection")]
+    fault_injection: Option<FaultInjection>,
+    #[cfg(feature = "mock")]
+    mock_rules: Option<Vec<MockRule>>,
+    #[cfg(feature = "log")]
+    log_levels: LogLevels,
     error: Option<crate::Error>,
     https_only: bool,
+    block_private_addresses: bool,
+    url_policy: UrlPolicy,
     dns_overrides: HashMap<String, Vec<SocketAddr>>,
 }
 
 impl Config {
+    /// A client's cookie jar, filled from `cookies`, or else from
+    /// `cookie_file`.
+    #[cfg(feature = "cookies")]
+    fn cookie_jar(&self, time: TimeSource) -> crate::Result<Jar> {
+        let jar = Jar::with_limits(self.cookie_limits).with_time_source(time);
+        #[cfg(feature = "cookies-psl")]
+        let jar = match self.cookie_public_suffix_list {
+            Some(ref list) => jar.with_public_suffix_list(list)?,
+            None => jar,
+        };
+        match (&self.cookies, &self.cookie_file) {
+            (Some(cookies), _) => jar.restore(cookies),
+            (None, Some(path)) => jar.load_file(path),
+            (None, None) => Ok(()),
+        }
+        .map_err(crate::error::builder)?;
+        Ok(jar)
+    }
+
+    /// Whether `cookie_jar` reads anything that can be invalid.
+    #[cfg(feature = "cookies")]
+    fn checks_cookies(&self) -> bool {
+        #[cfg(feature = "cookies-psl")]
+        if self.cookie_public_suffix_list.is_some() {
+            return true;
+        }
+        self.cookie_file.is_some() || self.cookies.is_some()
+    }
+
     pub(crate) fn fmt_fields(&self, f: &mut fmt::DebugStruct<'_, '_>) {
         // Instead of deriving Debug, only print fields when their output
         // would provide relevant or interesting data.
@@ -101,22 +230,128 @@ impl Config {
         //     }
         // }
 
+        #[cfg(feature = "cookies")]
+        {
+            if self.cookie_limits != CookieLimits::default() {
+                f.field("cookie_limits", &self.cookie_limits);
+            }
+            if let Some(ref path) = self.cookie_file {
+                f.field("cookie_file", path);
+            }
+            if let Some(ref cookies) = self.cookies {
+                f.field("cookies", &cookies.len());
+            }
+            #[cfg(feature = "cookies-psl")]
+            if self.cookie_public_suffix_list.is_some() {
+                f.field("cookie_public_suffix_list", &true);
+            }
+        }
+
         f.field("accepts", &self.accepts);
 
-        // if !self.proxies.is_empty() {
-        //     f.field("proxies", &self.proxies);
-        // }
+        if !self.proxies.is_empty() {
+            f.field("proxies", &self.proxies);
+        }
+
+        if self.system_proxies {
+            f.field("system_proxies", &true);
+        }
+
+        if !self.no_proxy.is_empty() {
+            f.field("no_proxy", &self.no_proxy);
+        }
+
+        if self.block_private_addresses {
+            f.field("block_private_addresses", &true);
+        }
+
+        if !self.url_policy.is_empty() {
+            f.field("url_policy", &self.url_policy);
+        }
 
         if !self.redirect_policy.is_default() {
             f.field("redirect_policy", &self.redirect_policy);
         }
 
+        if !self.redirect_auth.is_default() {
+            f.field("redirect_auth", &self.redirect_auth);
+        }
+
         if self.referer {
             f.field("referer", &true);
         }
 
         f.field("default_headers", &self.headers);
 
+        if let Some(ref name) = self.request_id_header {
+            f.field("request_id_header", name);
+        }
+
+        if self.record_requests > 0 {
+            f.field("record_requests", &self.record_requests);
+        }
+
+        if let Some(ref retry) = self.retry {
+            f.field("retry", retry);
+        }
+
+        if let Some(ref api_key) = self.api_key {
+            f.field("api_key", api_key);
+        }
+
+        if let Some(ref time) = self.time_source {
+            f.field("time_source", time);
+        }
+
+        if self.request_signer.is_some() {
+            f.field("request_signer", &true);
+        }
+
+        if self.retry_observer.is_some() {
+            f.field("retry_observer", &true);
+        }
+
+        if self.token_source.is_some() {
+            f.field("token_source", &true);
+        }
+
+        if self.connect_hook.is_some() {
+            f.field("connect_hook", &true);
+        }
+
+        if let Some(ref header) = self.proxy_protocol {
+            f.field("proxy_protocol", header);
+        }
+
+        if let Some(ref robots) = self.robots {
+            f.field("robots", robots);
+        }
+
+        if let Some(ref mirror) = self.mirror {
+            f.field("mirror", mirror);
+        }
+
+        #[cfg(feature = "fault-injection")]
+        {
+            if let Some(ref faults) = self.fault_injection {
+                f.field("fault_injection", faults);
+            }
+        }
+
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref rules) = self.mock_rules {
+                f.field("mock_rules", rules);
+            }
+        }
+
+        #[cfg(feature = "log")]
+        {
+            if self.log_levels != LogLevels::default() {
+                f.field("log_levels", &self.log_levels);
+            }
+        }
+
         if self.http1_title_case_headers {
             f.field("http1_title_case_headers", &true);
         }
@@ -125,6 +360,10 @@ impl Config {
             f.field("http1_allow_obsolete_multiline_headers_in_responses", &true);
         }
 
+        if matches!(self.http_version_pref, HttpVersionPref::Http10) {
+            f.field("http1_0_only", &true);
+        }
+
         if matches!(self.http_version_pref, HttpVersionPref::Http1) {
             f.field("http1_only", &true);
         }
@@ -137,38 +376,72 @@ impl Config {
             f.field("connect_timeout", d);
         }
 
-        if let Some(ref d) = self.timeout {
-            f.field("timeout", d);
+        if let Some(ref d) = self.tls_handshake_timeout {
+            f.field("tls_handshake_timeout", d);
         }
 
-        if let Some(ref v) = self.local_address {
-            f.field("local_address", v);
+        if let Some(ref d) = self.body_idle_timeout {
+            f.field("body_idle_timeout", d);
         }
 
-        if self.nodelay {
-            f.field("tcp_nodelay", &true);
+        if !self.root_certs.is_empty() {
+            f.field("root_certs", &self.root_certs);
         }
 
-        #[cfg(feature = "native-tls")]
-        {
-            if !self.hostname_verification {
-                f.field("danger_accept_invalid_hostnames", &true);
-            }
+        if let Some(ref rate) = self.max_download_rate {
+            f.field("max_download_rate", rate);
         }
 
-        #[cfg(feature = "__tls")]
-        {
-            if !self.certs_verification {
-                f.field("danger_accept_invalid_certs", &true);
-            }
+        if let Some(ref rate) = self.max_upload_rate {
+            f.field("max_upload_rate", rate);
+        }
 
-            if let Some(ref min_tls_version) = self.min_tls_version {
-                f.field("min_tls_version", min_tls_version);
-            }
+        if let Some(ref delay) = self.min_delay_per_host {
+            f.field("min_delay_per_host", delay);
+        }
 
-            if let Some(ref max_tls_version) = self.max_tls_version {
-                f.field("max_tls_version", max_tls_version);
-            }
+        if self.verify_integrity {
+            f.field("verify_integrity", &true);
+        }
+
+        if self.content_digest {
+            f.field("content_digest", &true);
+        }
+
+        if self.lenient_framing {
+            f.field("lenient_framing", &true);
+        }
+
+        if let Some(ref v) = self.expect_continue_threshold {
+            f.field("expect_continue_threshold", v);
+        }
+
+        if !self.retry_stale_connections {
+            f.field("retry_stale_connections", &false);
+        }
+
+        if let Some(ref v) = self.pool_health_check {
+            f.field("pool_health_check", v);
+        }
+
+        if self.duplicate_headers != DuplicateHeaders::FirstWins {
+            f.field("duplicate_headers", &self.duplicate_headers);
+        }
+
+        if self.join_header_values {
+            f.field("join_header_values", &true);
+        }
+
+        if let Some(ref d) = self.timeout {
+            f.field("timeout", d);
+        }
+
+        if let Some(ref v) = self.local_address {
+            f.field("local_address", v);
+        }
+
+        if self.nodelay {
+            f.field("tcp_nodelay", &true);
         }
 
         #[cfg(all(feature = "native-tls-crate", feature = "__rustls"))]
@@ -203,32 +476,36 @@ impl ClientBuilder {
                 error: None,
                 accepts: Accepts::default(),
                 headers,
-                #[cfg(feature = "native-tls")]
-                hostname_verification: true,
-                #[cfg(feature = "__tls")]
-                certs_verification: true,
                 connect_timeout: None,
+                tls_handshake_timeout: None,
+                body_idle_timeout: None,
+                max_download_rate: None,
+                max_upload_rate: None,
+                min_delay_per_host: None,
+                verify_integrity: false,
+                content_digest: false,
+                lenient_framing: false,
+                expect_continue_threshold: None,
+                duplicate_headers: DuplicateHeaders::FirstWins,
+                join_header_values: false,
                 connection_verbose: false,
                 pool_idle_timeout: Some(Duration::from_secs(90)),
                 pool_max_idle_per_host: std::usize::MAX,
+                retry_stale_connections: true,
+                pool_health_check: None,
                 // TODO: Re-enable default duration once hyper's HttpConnector is fixed
                 // to no longer error when an option fails.
                 tcp_keepalive: None, //Some(Duration::from_secs(60)),
-                // proxies: Vec::new(),
-                // auto_sys_proxy: true,
+                proxies: Vec::new(),
+                system_proxies: false,
+                no_proxy: NoProxy::new(),
                 redirect_policy: crate::redirect::Policy::default(),
+                redirect_auth: RedirectAuthPolicy::default(),
                 referer: true,
                 timeout: None,
-                #[cfg(feature = "__tls")]
                 root_certs: Vec::new(),
                 #[cfg(feature = "__tls")]
                 tls_built_in_root_certs: true,
-                #[cfg(any(feature = "native-tls", feature = "__rustls"))]
-                identity: None,
-                #[cfg(feature = "__tls")]
-                min_tls_version: None,
-                #[cfg(feature = "__tls")]
-                max_tls_version: None,
                 #[cfg(feature = "__tls")]
                 tls: TlsBackend::default(),
                 http_version_pref: HttpVersionPref::All,
@@ -246,12 +523,103 @@ impl ClientBuilder {
                 nodelay: true,
                 // #[cfg(feature = "cookies")]
                 // cookie_store: None,
+                #[cfg(feature = "cookies")]
+                cookie_limits: CookieLimits::default(),
+                #[cfg(feature = "cookies")]
+                cookie_file: None,
+                #[cfg(feature = "cookies")]
+                cookies: None,
+                #[cfg(feature = "cookies-psl")]
+                cookie_public_suffix_list: None,
+                request_id_header: None,
+                record_requests: 0,
+                retry: None,
+                retry_observer: None,
+                api_key: None,
+                time_source: None,
+                request_signer: None,
+                token_source: None,
+                connect_hook: None,
+                proxy_protocol: None,
+                robots: None,
+                mirror: None,
+                shadow_client: None,
+                #[cfg(feature = "fault-injection")]
+                fault_injection: None,
+                #[cfg(feature = "mock")]
+                mock_rules: None,
+                #[cfg(feature = "log")]
+                log_levels: LogLevels::default(),
                 https_only: false,
+                block_private_addresses: false,
+                url_policy: UrlPolicy::new(),
                 dns_overrides: HashMap::new(),
             },
         }
     }
 
+    /// A builder set up to look like a desktop web browser, for sites that
+    /// treat other clients differently.
+    ///
+    /// It sends a Firefox `User-Agent` along with the `Accept` and
+    /// `Accept-Language` headers a browser would, accepts gzip, brotli and
+    /// deflate, and sends a `Referer` when following redirects. Cookies are
+    /// kept when the `cookies` feature is enabled. Every setting can still be
+    /// changed on the returned builder.
+    ///
+    /// ```rust
+    /// # fn doc() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::ClientBuilder::browser_like().build()?;
+    /// let page = client.get("https://www.rust-lang.org").send()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn browser_like() -> ClientBuilder {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8",
+            ),
+        );
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.5"));
+        ClientBuilder::new()
+            .user_agent(
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:131.0) Gecko/20100101 Firefox/131.0",
+            )
+            .default_headers(headers)
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
+            .referer(true)
+    }
+
+    /// A builder set up for calling JSON APIs.
+    ///
+    /// It asks for `application/json`, names itself `nightfly/<version>`,
+    /// gives up connecting after 10 seconds and on the whole request after
+    /// 30, and retries idempotent requests with the default `RetryPolicy`.
+    /// Every setting can still be changed on the returned builder.
+    ///
+    /// ```rust
+    /// # fn doc() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::ClientBuilder::api_default()
+    ///     .user_agent("my-app/1.0")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn api_default() -> ClientBuilder {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        ClientBuilder::new()
+            .user_agent(concat!("nightfly/", env!("CARGO_PKG_VERSION")))
+            .default_headers(headers)
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .retry(RetryPolicy::new())
+    }
+
     /// Returns a `Client` that uses this `ClientBuilder` configuration.
     ///
     /// # Errors
@@ -265,12 +633,6 @@ impl ClientBuilder {
             return Err(err);
         }
 
-        // let mut proxies = config.proxies;
-        // if config.auto_sys_proxy {
-        //     proxies.push(Proxy::system());
-        // }
-        // let proxies = Arc::new(proxies);
-
         // let mut builder = Client::builder();
         // if matches!(config.http_version_pref, HttpVersionPref::Http2) {
         //     builder.http2_only(true);
@@ -300,8 +662,6 @@ impl ClientBuilder {
         //     builder.http2_keep_alive_while_idle(true);
         // }
 
-        // builder.pool_idle_timeout(config.pool_idle_timeout);
-        // builder.pool_max_idle_per_host(config.pool_max_idle_per_host);
         // connector.set_keepalive(config.tcp_keepalive);
 
         // if config.http09_responses {
@@ -318,11 +678,33 @@ impl ClientBuilder {
 
         // let hyper_client = builder.build(connector);
 
-        // let proxies_maybe_http_auth = proxies.iter().any(|p| p.maybe_has_http_auth());
+        let mut builder = self;
+        // the environment of this process, not the client's
+        if builder.config.system_proxies {
+            builder.config.system_proxies = false;
+            builder.config.proxies.extend(Proxy::system());
+        }
+        if builder.config.mirror.is_some() {
+            let mut shadow = builder.clone();
+            shadow.config.mirror = None;
+            builder.config.shadow_client = Some(shadow.build()?);
+        }
+
+        // Fill the cookie jar here, so that `build` fails on a bad cookie
+        // file, public suffix list or snapshot instead of the client
+        // process. The file's cookies travel as a snapshot.
+        #[cfg(feature = "cookies")]
+        if builder.config.checks_cookies() {
+            let time = builder.config.time_source.clone().unwrap_or_default();
+            let jar = builder.config.cookie_jar(time)?;
+            if builder.config.cookie_file.is_some() {
+                builder.config.cookies = Some(jar.snapshot());
+            }
+        }
 
         let proc = InnerClient::link()
-            .start(self)
-            .expect("Failed to spawn InnerClient");
+            .start(builder)
+            .map_err(|e| crate::error::builder(format!("failed to start the client: {:?}", e)))?;
         Ok(Client(proc))
     }
 
@@ -333,18 +715,125 @@ impl ClientBuilder {
             return Err(err);
         }
 
+        let time = config.time_source.clone().unwrap_or_default();
+        time.link();
+        #[cfg(feature = "cookies")]
+        let cookie_store = {
+            let jar = config.cookie_jar(time.clone())?;
+            match config.cookie_file {
+                Some(path) => jar.with_file(path),
+                None => jar,
+            }
+        };
+        // a hook that dies takes the client with it
+        let signer = config.request_signer.map(|signer| {
+            signer.link();
+            Arc::new(signer) as Arc<dyn RequestSigner>
+        });
+        let connect_hook = config.connect_hook.map(|hook| {
+            hook.link();
+            Arc::new(hook) as Arc<dyn ConnectHook>
+        });
+        let retry_observer = config.retry_observer.map(|observer| {
+            observer.link();
+            Arc::new(observer) as Arc<dyn RetryObserver>
+        });
+        let token = config.token_source.map(|source| {
+            source.link();
+            CachedToken::new(Arc::new(source))
+        });
+        config.redirect_policy.link();
+        let request_id_header = match config.request_id_header {
+            Some(name) => {
+                Some(HeaderName::from_bytes(name.as_bytes()).map_err(crate::error::builder)?)
+            }
+            None => None,
+        };
+
+        let mut headers = header_map_from_hashmap(config.headers);
+        let mut api_key_query = None;
+        let mut redirect_auth = config.redirect_auth;
+        match config.api_key {
+            Some(ApiKey::Header { name, value }) => {
+                let name =
+                    HeaderName::from_bytes(name.as_bytes()).map_err(crate::error::builder)?;
+                let mut value = HeaderValue::from_str(&value).map_err(crate::error::builder)?;
+                value.set_sensitive(true);
+                redirect_auth = redirect_auth.guard(&name);
+                headers.insert(name, value);
+            }
+            Some(ApiKey::Query { name, value }) => api_key_query = Some((name, value)),
+            None => {}
+        }
+
         Ok(InnerClient {
             accepts: config.accepts,
             #[cfg(feature = "cookies")]
-            cookie_store: Some(Arc::new(Jar::default())),
-            headers: header_map_from_hashmap(config.headers),
+            cookie_store: Some(Arc::new(cookie_store)),
+            headers,
+            api_key_query,
             redirect_policy: config.redirect_policy,
+            redirect_auth,
             referer: config.referer,
             request_timeout: config.timeout,
-            // proxies,
-            // proxies_maybe_http_auth: false,
+            body_idle_timeout: config.body_idle_timeout,
+            request_id_header,
+            #[cfg(feature = "log")]
+            log_levels: config.log_levels,
+            proxies: config.proxies,
+            no_proxy: config.no_proxy,
             https_only: config.https_only,
-            stream_map: HashMap::new(),
+            block_private_addresses: config.block_private_addresses,
+            url_policy: config.url_policy,
+            connector: Connector {
+                connect_timeout: config.connect_timeout,
+                tls_handshake_timeout: config.tls_handshake_timeout,
+                max_download_rate: config.max_download_rate,
+                max_upload_rate: config.max_upload_rate,
+                root_certs: config
+                    .root_certs
+                    .iter()
+                    .map(|cert| cert.pem().to_string())
+                    .collect(),
+            },
+            signer,
+            connect_hook,
+            proxy_protocol: config.proxy_protocol,
+            token,
+            robots: config.robots.map(Robots::new),
+            host_spacing: config.min_delay_per_host.map(HostSpacing::new),
+            verify_integrity: config.verify_integrity,
+            content_digest: config.content_digest,
+            lenient_framing: config.lenient_framing,
+            expect_continue_threshold: config.expect_continue_threshold,
+            retry_stale_connections: config.retry_stale_connections,
+            pool_health_check: config.pool_health_check,
+            pool_checked: None,
+            http1_0_only: matches!(config.http_version_pref, HttpVersionPref::Http10),
+            obs_fold: config.http1_allow_obsolete_multiline_headers_in_responses,
+            header_policy: HeaderPolicy {
+                duplicates: config.duplicate_headers,
+                join: config.join_header_values,
+            },
+            metrics: Default::default(),
+            recent: match config.record_requests {
+                0 => None,
+                capacity => Some(RecentRequests::new(capacity)),
+            },
+            retrier: config.retry.map(Retrier::new),
+            retry_observer,
+            mirror: config
+                .mirror
+                .zip(config.shadow_client)
+                .map(|(mirror, client)| (Mirrorer::new(mirror), client)),
+            #[cfg(feature = "fault-injection")]
+            faults: config
+                .fault_injection
+                .map(|faults| FaultInjector::new(faults, &time)),
+            #[cfg(feature = "mock")]
+            mock: config.mock_rules.map(MockTransport::new),
+            time,
+            pool: Pool::new(config.pool_idle_timeout, config.pool_max_idle_per_host),
         })
     }
 
@@ -420,24 +909,337 @@ impl ClientBuilder {
     /// let mut headers = header::HeaderMap::new();
     /// headers.insert("X-MY-HEADER", header::HeaderValue::from_static("value"));
     ///
-    /// // get a client builder
-    /// let client = nightfly::Client::builder()
-    ///     .default_headers(headers)
-    ///     .build()?;
-    /// let res = client
-    ///     .get("https://www.rust-lang.org")
-    ///     .header("X-MY-HEADER", "new_value")
-    ///     .send()
-    ///     ;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn default_headers(mut self, headers: HeaderMap) -> ClientBuilder {
-        for (key, value) in headers.iter() {
-            self.config
-                .headers
-                .insert(key.to_string(), vec![value.to_str().unwrap().to_string()]);
-        }
+    /// // get a client builder
+    /// let client = nightfly::Client::builder()
+    ///     .default_headers(headers)
+    ///     .build()?;
+    /// let res = client
+    ///     .get("https://www.rust-lang.org")
+    ///     .header("X-MY-HEADER", "new_value")
+    ///     .send()
+    ///     ;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn default_headers(mut self, headers: HeaderMap) -> ClientBuilder {
+        for (key, value) in headers.iter() {
+            self.config
+                .headers
+                .insert(key.to_string(), vec![value.to_str().unwrap().to_string()]);
+        }
+        self
+    }
+
+    /// Authenticate every request with an API key.
+    ///
+    /// `placement` chooses how the key is sent:
+    ///
+    /// ```rust
+    /// use nightfly::Placement;
+    ///
+    /// let client = nightfly::Client::builder()
+    ///     .api_key("s3cr3t", Placement::Header("X-Api-Key"))
+    ///     .build();
+    /// ```
+    ///
+    /// A header set on the request itself takes precedence over the key, as
+    /// with `default_headers`, and so does a query parameter of the same
+    /// name already in the URL. Keys sent in the query end up in the URLs
+    /// of responses and errors, so prefer a header when the API allows it.
+    ///
+    /// Wherever it is sent, the key follows redirects only as far as
+    /// `RedirectAuthPolicy` lets an `Authorization` header go, so by default
+    /// it isn't sent to another origin.
+    pub fn api_key<K: Into<String>>(mut self, key: K, placement: Placement) -> ClientBuilder {
+        let value = key.into();
+        let api_key = match placement {
+            Placement::Header(name) => match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(name) => ApiKey::Header {
+                    name: name.as_str().to_string(),
+                    value,
+                },
+                Err(e) => {
+                    self.config.error = Some(crate::error::builder(e));
+                    return self;
+                }
+            },
+            Placement::Query(name) => ApiKey::Query {
+                name: name.to_string(),
+                value,
+            },
+            Placement::Bearer => ApiKey::Header {
+                name: http::header::AUTHORIZATION.as_str().to_string(),
+                value: format!("Bearer {}", value),
+            },
+        };
+        if let ApiKey::Header { ref value, .. } = api_key {
+            if let Err(e) = HeaderValue::from_str(value) {
+                self.config.error = Some(crate::error::builder(e));
+                return self;
+            }
+        }
+        self.config.api_key = Some(api_key);
+        self
+    }
+
+    /// Stamp every request with a unique `X-Request-Id` header.
+    ///
+    /// A fresh UUID is generated for each request sent through the client,
+    /// unless the request already carries the header. The same value is kept
+    /// when following redirects and can be read back with
+    /// `HttpResponse::request_id()` to correlate logs.
+    ///
+    /// By default, no request ID is added.
+    pub fn request_id(mut self, enable: bool) -> ClientBuilder {
+        self.config.request_id_header = if enable {
+            Some(REQUEST_ID_HEADER.to_string())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Like `request_id(true)`, but uses `name` instead of `X-Request-Id`.
+    pub fn request_id_header<K>(mut self, name: K) -> ClientBuilder
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+    {
+        match <HeaderName as TryFrom<K>>::try_from(name) {
+            Ok(name) => {
+                self.config.request_id_header = Some(name.as_str().to_string());
+            }
+            Err(e) => {
+                self.config.error = Some(crate::error::builder(e.into()));
+            }
+        }
+        self
+    }
+
+    /// Keep a summary of the last `capacity` requests, which
+    /// `Client::recent_requests` returns.
+    ///
+    /// The summaries are kept by the client process, so a supervisor can
+    /// still read them after a process using the client crashed. See the
+    /// `recent` module.
+    ///
+    /// Default is 0, nothing is kept.
+    pub fn record_requests(mut self, capacity: usize) -> ClientBuilder {
+        self.config.record_requests = capacity;
+        self
+    }
+
+    /// Retry idempotent requests that couldn't connect or were answered
+    /// with a temporary error, such as `503 Service Unavailable`.
+    ///
+    /// Retries are limited by the policy's `RetryBudget`, shared by every
+    /// process using the client. See the `retry` module.
+    ///
+    /// Default is no retries.
+    pub fn retry(mut self, policy: RetryPolicy) -> ClientBuilder {
+        self.config.retry = Some(policy);
+        self
+    }
+
+    /// Tell `observer` about every retry, with its cause, attempt number and
+    /// delay. See the [`retry`](crate::retry) module.
+    ///
+    /// Only has an effect together with `retry`.
+    pub fn retry_observer<O>(mut self, observer: O) -> ClientBuilder
+    where
+        O: RetryObserver + Serialize + DeserializeOwned,
+    {
+        self.config.retry_observer = Some(retry::spawn(observer));
+        self
+    }
+
+    /// Set the levels at which requests, responses and errors are logged.
+    ///
+    /// Events go through the `log` crate under the `nightfly` target, with
+    /// credentials redacted. See `LogLevels` for the defaults.
+    #[cfg(feature = "log")]
+    pub fn log_levels(mut self, levels: LogLevels) -> ClientBuilder {
+        self.config.log_levels = levels;
+        self
+    }
+
+    /// Sign every request with `signer`.
+    ///
+    /// The signer runs right before each request is written, redirects
+    /// included, and the headers it returns are added to the request. See
+    /// the [`signing`](crate::signing) module.
+    pub fn request_signer<S>(mut self, signer: S) -> ClientBuilder
+    where
+        S: RequestSigner + Serialize + DeserializeOwned,
+    {
+        self.config.request_signer = Some(signing::spawn(signer));
+        self
+    }
+
+    /// Hand every new connection to `hook` before a request is written to
+    /// it. See the [`connect`](crate::connect) module.
+    pub fn connect_hook<H>(mut self, hook: H) -> ClientBuilder
+    where
+        H: ConnectHook + Serialize + DeserializeOwned,
+    {
+        self.config.connect_hook = Some(connect::spawn(hook));
+        self
+    }
+
+    /// Start every new connection with a PROXY protocol `header`, for
+    /// servers behind a load balancer that expect one. See the
+    /// [`proxy_protocol`](crate::proxy_protocol) module.
+    ///
+    /// Requests to `https` URLs fail while a header is set. By default no
+    /// header is sent.
+    pub fn proxy_protocol(mut self, header: ProxyHeader) -> ClientBuilder {
+        self.config.proxy_protocol = Some(header);
+        self
+    }
+
+    /// Authenticate every request with a bearer token from `source`.
+    ///
+    /// The token is fetched before the first request and replaced shortly
+    /// before it expires. Requests that already carry an `Authorization`
+    /// header are sent as they are, and redirects only get the token as far
+    /// as `RedirectAuthPolicy` lets an `Authorization` header go. See the
+    /// [`token`](crate::token) module.
+    pub fn token_source<S>(mut self, source: S) -> ClientBuilder
+    where
+        S: TokenSource + Serialize + DeserializeOwned,
+    {
+        self.config.token_source = Some(token::spawn(source));
+        self
+    }
+
+    /// Check response bodies against the `Content-MD5`, `Digest` and
+    /// `Repr-Digest` headers sent by the server.
+    ///
+    /// A body that doesn't match fails the request with an error for which
+    /// `Error::is_integrity` is true. Responses without those headers are not
+    /// checked.
+    ///
+    /// Default is `false`.
+    pub fn verify_integrity(mut self, enabled: bool) -> ClientBuilder {
+        self.config.verify_integrity = enabled;
+        self
+    }
+
+    /// Send a `Content-Digest` header with the SHA-256 digest of every
+    /// request body, as described in RFC 9530.
+    ///
+    /// The header is added before a `request_signer` runs, so signatures can
+    /// cover it. Requests that already carry one are left as they are.
+    ///
+    /// Default is `false`.
+    pub fn content_digest(mut self, enabled: bool) -> ClientBuilder {
+        self.config.content_digest = enabled;
+        self
+    }
+
+    /// Accept responses, and send requests, whose framing could be read in
+    /// more than one way.
+    ///
+    /// By default the client refuses messages that combine `Content-Length`
+    /// with `Transfer-Encoding`, carry an invalid or conflicting
+    /// `Content-Length`, or end header lines with a bare CR or LF, as such
+    /// messages are used to smuggle requests past proxies. These fail with
+    /// an error for which `Error::is_framing` is true. Enable this only to
+    /// talk to known-broken servers; `Transfer-Encoding` then takes
+    /// precedence over `Content-Length`.
+    ///
+    /// Default is `false`.
+    pub fn lenient_framing(mut self, enabled: bool) -> ClientBuilder {
+        self.config.lenient_framing = enabled;
+        self
+    }
+
+    /// Send `Expect: 100-continue` with request bodies larger than `bytes`,
+    /// so the server can turn the request down, e.g. for lacking
+    /// credentials, before the body is uploaded. Smaller bodies are sent
+    /// right away, sparing them the extra round trip.
+    ///
+    /// The body is sent anyway if the server doesn't answer within a
+    /// second, and the request is repeated without the expectation if the
+    /// server doesn't support it. Requests that set their own `Expect`
+    /// header, and HTTP/1.0 requests, are left alone.
+    ///
+    /// Default is not to send `Expect` at all.
+    pub fn expect_continue_threshold(mut self, bytes: u64) -> ClientBuilder {
+        self.config.expect_continue_threshold = Some(bytes);
+        self
+    }
+
+    /// Choose what happens when a response repeats a header that may only
+    /// appear once, such as `Content-Length`, `Content-Type` or `Location`,
+    /// with different values.
+    ///
+    /// Under `DuplicateHeaders::Error` the request fails with an error for
+    /// which `Error::is_duplicate_header` is true. Repeats of the same value
+    /// are always merged into one.
+    ///
+    /// Default is `DuplicateHeaders::FirstWins`.
+    pub fn duplicate_headers(mut self, policy: DuplicateHeaders) -> ClientBuilder {
+        self.config.duplicate_headers = policy;
+        self
+    }
+
+    /// Join the values of headers a response sends more than once, such as
+    /// `Vary` or `Warning`, into one comma separated value, instead of
+    /// keeping them as separate entries of `HttpResponse::headers`.
+    ///
+    /// `Set-Cookie` headers are never joined, since cookies may contain
+    /// commas. `HttpResponse::raw_headers` is not affected.
+    ///
+    /// Default is `false`.
+    pub fn join_header_values(mut self, enabled: bool) -> ClientBuilder {
+        self.config.join_header_values = enabled;
+        self
+    }
+
+    /// Follow the robots.txt of every host the client talks to.
+    ///
+    /// See the [`robots`](crate::robots) module.
+    ///
+    /// By default, robots.txt is ignored.
+    pub fn robots(mut self, policy: RobotsPolicy) -> ClientBuilder {
+        self.config.robots = Some(policy);
+        self
+    }
+
+    /// Send a copy of a sample of the requests to a second backend, and
+    /// ignore its responses; see the [`mirror`](crate::mirror) module.
+    ///
+    /// By default, no request is mirrored.
+    pub fn mirror(mut self, mirror: Mirror) -> ClientBuilder {
+        self.config.mirror = Some(mirror);
+        self
+    }
+
+    /// Set the `TimeSource` used for cookie expiry and request timings.
+    ///
+    /// Default is the system clock.
+    pub fn time_source(mut self, time: TimeSource) -> ClientBuilder {
+        self.config.time_source = Some(time);
+        self
+    }
+
+    /// Inject artificial latency, dropped connections and server errors
+    /// into the requests of this client.
+    ///
+    /// Meant for testing how callers cope with an unreliable network; see
+    /// the [`fault`](crate::fault) module.
+    #[cfg(feature = "fault-injection")]
+    pub fn fault_injection(mut self, faults: FaultInjection) -> ClientBuilder {
+        self.config.fault_injection = Some(faults);
+        self
+    }
+
+    /// Answer requests from `rules` instead of the network, see
+    /// `MockClient`.
+    #[cfg(feature = "mock")]
+    pub(crate) fn mock(mut self, rules: Vec<MockRule>) -> ClientBuilder {
+        self.config.mock_rules = Some(rules);
         self
     }
 
@@ -471,6 +1273,87 @@ impl ClientBuilder {
     //     self
     // }
 
+    /// Set the limits of the client's cookie store.
+    ///
+    /// Once a limit is exceeded, the least recently used cookies are evicted.
+    /// Defaults to [`CookieLimits::default()`].
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies` feature to be enabled.
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    pub fn cookie_limits(mut self, limits: CookieLimits) -> ClientBuilder {
+        self.config.cookie_limits = limits;
+        self
+    }
+
+    /// Keep the client's cookies in the file at `path`, so that they
+    /// survive restarts.
+    ///
+    /// The cookies are loaded from the file when the client is built, if it
+    /// exists, and the file is rewritten after every response that sets
+    /// cookies. See `Jar::save_json` for its format.
+    ///
+    /// Default is to keep cookies in memory only.
+    ///
+    /// # Errors
+    ///
+    /// `build` fails if the file exists but can't be read.
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies` feature to be enabled.
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    pub fn cookie_file<P: Into<PathBuf>>(mut self, path: P) -> ClientBuilder {
+        self.config.cookie_file = Some(path.into());
+        self
+    }
+
+    /// Start with the cookies of `snapshot`, taken from another client with
+    /// `Client::cookie_snapshot`, for example to carry on a session in
+    /// another process or on another node.
+    ///
+    /// They replace any cookies loaded from `cookie_file`.
+    ///
+    /// Default is to start without cookies.
+    ///
+    /// # Errors
+    ///
+    /// `build` fails if a cookie in `snapshot` can't be read.
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies` feature to be enabled.
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    pub fn restore_cookies(mut self, snapshot: SerializableJar) -> ClientBuilder {
+        self.config.cookies = Some(snapshot);
+        self
+    }
+
+    /// Reject cookies set for a public suffix, such as `github.io`, using
+    /// `list`, the text of the Public Suffix List. See
+    /// `Jar::with_public_suffix_list`.
+    ///
+    /// Default is to accept a cookie for any domain the response's host is
+    /// in.
+    ///
+    /// # Errors
+    ///
+    /// `build` fails if `list` isn't a valid public suffix list.
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies-psl` feature to be enabled.
+    #[cfg(feature = "cookies-psl")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies-psl")))]
+    pub fn cookie_public_suffix_list<S: Into<String>>(mut self, list: S) -> ClientBuilder {
+        self.config.cookie_public_suffix_list = Some(list.into());
+        self
+    }
+
     /// Enable auto gzip decompression by checking the `Content-Encoding` response header.
     ///
     /// If auto gzip decompression is turned on:
@@ -500,6 +1383,8 @@ impl ClientBuilder {
     ///   `br`, both `Content-Encoding` and `Content-Length` are removed from the
     ///   headers' set. The response body is automatically decompressed.
     ///
+    /// Has no effect unless the `brotli` feature, which is on by default, is
+    /// enabled. If it is, the default option is enabled.
     pub fn brotli(mut self, enable: bool) -> ClientBuilder {
         self.config.accepts.brotli = enable;
         self
@@ -516,6 +1401,8 @@ impl ClientBuilder {
     ///   equals to `deflate`, both values `Content-Encoding` and `Content-Length` are removed from the
     ///   headers' set. The response body is automatically decompressed.
     ///
+    /// Has no effect unless the `deflate` feature, which is on by default, is
+    /// enabled. If it is, the default option is enabled.
     pub fn deflate(mut self, enable: bool) -> ClientBuilder {
         self.config.accepts.deflate = enable;
         self
@@ -558,6 +1445,19 @@ impl ClientBuilder {
         self
     }
 
+    /// Set which credentials are kept when following a redirect, see
+    /// `RedirectAuthPolicy`.
+    ///
+    /// Use `RedirectAuthPolicy::trust` to keep them on redirects to other
+    /// domains you trust.
+    ///
+    /// Default keeps `Authorization`, `Proxy-Authorization` and `Cookie`
+    /// headers on redirects to the same origin only.
+    pub fn redirect_auth(mut self, policy: RedirectAuthPolicy) -> ClientBuilder {
+        self.config.redirect_auth = policy;
+        self
+    }
+
     /// Enable or disable automatic setting of the `Referer` header.
     ///
     /// Default is `true`.
@@ -568,25 +1468,43 @@ impl ClientBuilder {
 
     // Proxy options
 
-    // /// Add a `Proxy` to the list of proxies the `Client` will use.
-    // ///
-    // /// # Note
-    // ///
-    // /// Adding a proxy will disable the automatic usage of the "system" proxy.
-    // pub fn proxy(mut self, proxy: Proxy) -> ClientBuilder {
-    //     self.config.proxies.push(proxy);
-    //     self.config.auto_sys_proxy = false;
-    //     self
-    // }
+    /// Add a `Proxy` to the list of proxies the `Client` will use.
+    ///
+    /// Requests go through the first proxy in the list that intercepts their
+    /// URL, see the [`proxy`](crate::proxy) module. Default is no proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> ClientBuilder {
+        self.config.proxies.push(proxy);
+        self
+    }
 
-    // /// Clear all `Proxies`, so `Client` will use no proxy anymore.
-    // ///
-    // /// This also disables the automatic usage of the "system" proxy.
-    // pub fn no_proxy(mut self) -> ClientBuilder {
-    //     self.config.proxies.clear();
-    //     self.config.auto_sys_proxy = false;
-    //     self
-    // }
+    /// Also use the proxies named by the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `ALL_PROXY` environment variables, skipping the hosts in `NO_PROXY`,
+    /// as curl does. The lowercase forms of the variables take precedence.
+    ///
+    /// The variables are read by `build`, and the proxies come after those
+    /// added with `proxy`. With `HTTPS_PROXY` or `ALL_PROXY` set, `https`
+    /// requests fail with a proxy error, see the [`proxy`](crate::proxy)
+    /// module. Default is to ignore the environment.
+    pub fn use_system_proxies(mut self) -> ClientBuilder {
+        self.config.system_proxies = true;
+        self
+    }
+
+    /// Send requests to the hosts of `no_proxy` directly, whichever proxy
+    /// would intercept them, see [`NoProxy`](crate::NoProxy). Default is to
+    /// leave out no host.
+    pub fn no_proxy_for(mut self, no_proxy: NoProxy) -> ClientBuilder {
+        self.config.no_proxy = no_proxy;
+        self
+    }
+
+    /// Clear all `Proxies`, so `Client` will use no proxy anymore, not even
+    /// those of `use_system_proxies`.
+    pub fn no_proxy(mut self) -> ClientBuilder {
+        self.config.proxies.clear();
+        self.config.system_proxies = false;
+        self
+    }
 
     // Timeout options
 
@@ -609,6 +1527,66 @@ impl ClientBuilder {
         self
     }
 
+    /// Set a timeout for the TLS handshake of a `Client`'s HTTPS connections.
+    ///
+    /// A peer that accepts the TCP connection but stalls during the handshake
+    /// fails with a timeout error once this budget is spent, instead of
+    /// hanging until the request timeout. The lunatic host opens the socket
+    /// and performs the handshake in one step, so when a `connect_timeout`
+    /// is set as well both durations are added up.
+    ///
+    /// Default is `None`.
+    pub fn tls_handshake_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.tls_handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the longest a `Client` waits for more of a response body.
+    ///
+    /// Unlike `timeout`, the wait starts over whenever bytes arrive, so a
+    /// large download that keeps moving is never cut short, while a server
+    /// that trickles out a byte a minute fails with a timeout error. It can
+    /// be set per request with `RequestBuilder::timeouts` and
+    /// `TimeoutPhase::BodyIdle`.
+    ///
+    /// Default is `None`.
+    pub fn body_idle_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.body_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how fast response bodies are read, in bytes per second.
+    ///
+    /// Reads from the connection are paced so that each response arrives
+    /// no faster than this rate, which keeps background jobs from
+    /// saturating the host's link.
+    ///
+    /// Default is no limit.
+    pub fn max_download_rate(mut self, bytes_per_sec: u64) -> ClientBuilder {
+        self.config.max_download_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Wait at least `delay` between two requests to the same host.
+    ///
+    /// The spacing holds across every process that shares the `Client` and
+    /// applies to redirects too. It's a fixed pause between requests, not a
+    /// budget that can be spent in bursts.
+    ///
+    /// Default is no delay.
+    pub fn min_delay_per_host(mut self, delay: Duration) -> ClientBuilder {
+        self.config.min_delay_per_host = Some(delay);
+        self
+    }
+
+    /// Limit how fast requests are written, in bytes per second.
+    ///
+    /// Default is no limit.
+    pub fn max_upload_rate(mut self, bytes_per_sec: u64) -> ClientBuilder {
+        self.config.max_upload_rate = Some(bytes_per_sec);
+        self
+    }
+
     /// Set whether connections should emit verbose logs.
     ///
     /// Enabling this option will emit [log][] messages at the `TRACE` level
@@ -624,7 +1602,8 @@ impl ClientBuilder {
 
     /// Set an optional timeout for idle sockets being kept-alive.
     ///
-    /// Pass `None` to disable timeout.
+    /// A pooled connection that has been idle for longer is closed instead
+    /// of being reused. Pass `None` to disable timeout.
     ///
     /// Default is 90 seconds.
     pub fn pool_idle_timeout<D>(mut self, val: D) -> ClientBuilder
@@ -636,11 +1615,58 @@ impl ClientBuilder {
     }
 
     /// Sets the maximum idle connection per host allowed in the pool.
+    ///
+    /// Once a host has this many idle connections, the one idle for the
+    /// longest is closed when another is returned to the pool. Pass 0 to
+    /// close every connection after its response instead.
+    ///
+    /// Default is no limit.
     pub fn pool_max_idle_per_host(mut self, max: usize) -> ClientBuilder {
         self.config.pool_max_idle_per_host = max;
         self
     }
 
+    /// Send a request again on a new connection, once, when the pooled
+    /// connection it was written to turns out to have been closed by the
+    /// server while idle.
+    ///
+    /// Servers close idle keep-alive connections whenever they like, and
+    /// the client only notices when it sends the next request. If writing
+    /// that request fails, it never reached the server, so it is sent again
+    /// whatever its method. If the write succeeds but the connection closes
+    /// before any of the response arrives, only idempotent requests, such as
+    /// GET or PUT, are sent again, as the server may have acted on it. The
+    /// retry always opens a new connection. With this disabled, such a
+    /// request fails with an error for which `Error::is_request` is true.
+    ///
+    /// Default is `true`.
+    pub fn retry_stale_connections(mut self, enabled: bool) -> ClientBuilder {
+        self.config.retry_stale_connections = enabled;
+        self
+    }
+
+    /// Check that idle pooled connections are still open before reusing
+    /// them, and drop the ones the servers closed from the pool every
+    /// `interval`, so that the first request after a quiet period doesn't
+    /// fail.
+    ///
+    /// A connection is checked by peeking at it for a millisecond: one that
+    /// was closed, or has unexpected bytes waiting, is dropped. TLS
+    /// connections can't be peeked at and are kept; a request that finds
+    /// one closed is covered by `retry_stale_connections` instead. The
+    /// client process has no timer of its own, so the pool is swept when
+    /// the client handles a request and `interval` has passed since the
+    /// last sweep.
+    ///
+    /// Pass `None` to disable health checks. Default is `None`.
+    pub fn pool_health_check<D>(mut self, interval: D) -> ClientBuilder
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.config.pool_health_check = interval.into();
+        self
+    }
+
     /// Send headers as title case instead of lowercase.
     pub fn http1_title_case_headers(mut self) -> ClientBuilder {
         self.config.http1_title_case_headers = true;
@@ -651,7 +1677,11 @@ impl ClientBuilder {
     /// header values.
     ///
     /// Newline codepoints (`\r` and `\n`) will be transformed to spaces when
-    /// parsing.
+    /// parsing. Line folding is obsolete, but some legacy devices still use
+    /// it; without this option their responses fail to parse.
+    /// `HttpResponse::raw_headers` keeps the folded lines.
+    ///
+    /// Default is `false`.
     pub fn http1_allow_obsolete_multiline_headers_in_responses(
         mut self,
         value: bool,
@@ -667,6 +1697,17 @@ impl ClientBuilder {
         self
     }
 
+    /// Send every request as HTTP/1.0, for ancient appliances that don't
+    /// understand HTTP/1.1.
+    ///
+    /// Responses are read the HTTP/1.0 way whatever the request was sent
+    /// as, when the server answers with HTTP/1.0: without chunked encoding,
+    /// and up to the end of the connection if there is no `Content-Length`.
+    pub fn http1_0_only(mut self) -> ClientBuilder {
+        self.config.http_version_pref = HttpVersionPref::Http10;
+        self
+    }
+
     /// Allow HTTP/0.9 responses
     pub fn http09_responses(mut self) -> ClientBuilder {
         self.config.http09_responses = true;
@@ -793,21 +1834,18 @@ impl ClientBuilder {
     /// Add a custom root certificate.
     ///
     /// This can be used to connect to a server that has a self-signed
-    /// certificate for example.
+    /// certificate for example, or to internal services signed by a
+    /// corporate CA. It is trusted on top of the lunatic host's built-in
+    /// root certificates.
     ///
-    /// # Optional
-    ///
-    /// This requires the optional `default-tls`, `native-tls`, or `rustls-tls(-...)`
-    /// feature to be enabled.
-    #[cfg(feature = "__tls")]
-    #[cfg_attr(
-        docsrs,
-        doc(cfg(any(
-            feature = "default-tls",
-            feature = "native-tls",
-            feature = "rustls-tls"
-        )))
-    )]
+    /// ```rust
+    /// # fn run(pem: &[u8]) -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::builder()
+    ///     .add_root_certificate(nightfly::Certificate::from_pem(pem)?)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn add_root_certificate(mut self, cert: Certificate) -> ClientBuilder {
         self.config.root_certs.push(cert);
         self
@@ -835,131 +1873,6 @@ impl ClientBuilder {
         self
     }
 
-    /// Sets the identity to be used for client certificate authentication.
-    ///
-    /// # Optional
-    ///
-    /// This requires the optional `native-tls` or `rustls-tls(-...)` feature to be
-    /// enabled.
-    #[cfg(any(feature = "native-tls", feature = "__rustls"))]
-    #[cfg_attr(docsrs, doc(cfg(any(feature = "native-tls", feature = "rustls-tls"))))]
-    pub fn identity(mut self, identity: Identity) -> ClientBuilder {
-        self.config.identity = Some(identity);
-        self
-    }
-
-    /// Controls the use of hostname verification.
-    ///
-    /// Defaults to `false`.
-    ///
-    /// # Warning
-    ///
-    /// You should think very carefully before you use this method. If
-    /// hostname verification is not used, any valid certificate for any
-    /// site will be trusted for use from any other. This introduces a
-    /// significant vulnerability to man-in-the-middle attacks.
-    ///
-    /// # Optional
-    ///
-    /// This requires the optional `native-tls` feature to be enabled.
-    #[cfg(feature = "native-tls")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "native-tls")))]
-    pub fn danger_accept_invalid_hostnames(
-        mut self,
-        accept_invalid_hostname: bool,
-    ) -> ClientBuilder {
-        self.config.hostname_verification = !accept_invalid_hostname;
-        self
-    }
-
-    /// Controls the use of certificate validation.
-    ///
-    /// Defaults to `false`.
-    ///
-    /// # Warning
-    ///
-    /// You should think very carefully before using this method. If
-    /// invalid certificates are trusted, *any* certificate for *any* site
-    /// will be trusted for use. This includes expired certificates. This
-    /// introduces significant vulnerabilities, and should only be used
-    /// as a last resort.
-    ///
-    /// # Optional
-    ///
-    /// This requires the optional `default-tls`, `native-tls`, or `rustls-tls(-...)`
-    /// feature to be enabled.
-    #[cfg(feature = "__tls")]
-    #[cfg_attr(
-        docsrs,
-        doc(cfg(any(
-            feature = "default-tls",
-            feature = "native-tls",
-            feature = "rustls-tls"
-        )))
-    )]
-    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> ClientBuilder {
-        self.config.certs_verification = !accept_invalid_certs;
-        self
-    }
-
-    /// Set the minimum required TLS version for connections.
-    ///
-    /// By default the TLS backend's own default is used.
-    ///
-    /// # Errors
-    ///
-    /// A value of `tls::Version::TLS_1_3` will cause an error with the
-    /// `native-tls`/`default-tls` backend. This does not mean the version
-    /// isn't supported, just that it can't be set as a minimum due to
-    /// technical limitations.
-    ///
-    /// # Optional
-    ///
-    /// This requires the optional `default-tls`, `native-tls`, or `rustls-tls(-...)`
-    /// feature to be enabled.
-    #[cfg(feature = "__tls")]
-    #[cfg_attr(
-        docsrs,
-        doc(cfg(any(
-            feature = "default-tls",
-            feature = "native-tls",
-            feature = "rustls-tls"
-        )))
-    )]
-    pub fn min_tls_version(mut self, version: tls::Version) -> ClientBuilder {
-        self.config.min_tls_version = Some(version);
-        self
-    }
-
-    /// Set the maximum allowed TLS version for connections.
-    ///
-    /// By default there's no maximum.
-    ///
-    /// # Errors
-    ///
-    /// A value of `tls::Version::TLS_1_3` will cause an error with the
-    /// `native-tls`/`default-tls` backend. This does not mean the version
-    /// isn't supported, just that it can't be set as a maximum due to
-    /// technical limitations.
-    ///
-    /// # Optional
-    ///
-    /// This requires the optional `default-tls`, `native-tls`, or `rustls-tls(-...)`
-    /// feature to be enabled.
-    #[cfg(feature = "__tls")]
-    #[cfg_attr(
-        docsrs,
-        doc(cfg(any(
-            feature = "default-tls",
-            feature = "native-tls",
-            feature = "rustls-tls"
-        )))
-    )]
-    pub fn max_tls_version(mut self, version: tls::Version) -> ClientBuilder {
-        self.config.max_tls_version = Some(version);
-        self
-    }
-
     /// Force using the native TLS backend.
     ///
     /// Since multiple TLS backends can be optionally enabled, this option will
@@ -1079,6 +1992,38 @@ impl ClientBuilder {
         self
     }
 
+    /// Refuse to send requests to loopback, private, link-local and other
+    /// addresses that aren't reachable on the public internet, such as
+    /// `127.0.0.1`, `10.0.0.0/8` or the `169.254.169.254` of cloud metadata
+    /// services.
+    ///
+    /// Meant for clients fetching URLs supplied by users, which could
+    /// otherwise make the client reach internal services. The host of every
+    /// request is resolved and all of its addresses checked before sending,
+    /// and so is the target of every redirect. Refused requests fail with an
+    /// error for which `Error::is_disallowed` is true.
+    ///
+    /// Plain `http` connections are then made to the addresses that were
+    /// checked. The lunatic host only opens TLS connections by name, and
+    /// resolves it again to do so, so for `https` URLs a name whose
+    /// addresses change between the two lookups isn't caught. Requests
+    /// through a proxy are checked, but the proxy connects on its own.
+    ///
+    /// Defaults to false.
+    pub fn block_private_addresses(mut self, enabled: bool) -> ClientBuilder {
+        self.config.block_private_addresses = enabled;
+        self
+    }
+
+    /// Only send requests, and follow redirects, to the URLs `policy`
+    /// allows, see the [`url_policy`](crate::url_policy) module.
+    ///
+    /// Default allows every URL.
+    pub fn url_policy(mut self, policy: UrlPolicy) -> ClientBuilder {
+        self.config.url_policy = policy;
+        self
+    }
+
     /// Override DNS resolution for specific domains to a particular IP address.
     ///
     /// Warning
@@ -1106,3 +2051,29 @@ impl ClientBuilder {
         self
     }
 }
+
+impl From<&ClientBuilder> for ClientConfig {
+    fn from(builder: &ClientBuilder) -> ClientConfig {
+        let config = &builder.config;
+        ClientConfig {
+            timeout: config.timeout,
+            connect_timeout: config.connect_timeout,
+            tls_handshake_timeout: config.tls_handshake_timeout,
+            body_idle_timeout: config.body_idle_timeout,
+            max_redirects: config.redirect_policy.max_redirects(),
+            pool_idle_timeout: config.pool_idle_timeout,
+            pool_max_idle_per_host: match config.pool_max_idle_per_host {
+                usize::MAX => None,
+                max => Some(max),
+            },
+            max_download_rate: config.max_download_rate,
+            max_upload_rate: config.max_upload_rate,
+            user_agent: config
+                .headers
+                .get(USER_AGENT.as_str())
+                .and_then(|values| values.first().cloned()),
+            https_only: config.https_only,
+            block_private_addresses: config.block_private_addresses,
+        }
+    }
+}
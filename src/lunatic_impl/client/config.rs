@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{redirect, ClientBuilder};
+
+/// Settings of a `Client` that can be loaded from a configuration file, or
+/// sent to another process, and turned into a `ClientBuilder`.
+///
+/// Durations are written as whole milliseconds, and every field may be left
+/// out to keep its default.
+///
+/// ```rust
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let config: nightfly::ClientConfig = serde_json::from_str(
+///     r#"{ "timeout": 30000, "max_redirects": 3, "user_agent": "my-service" }"#,
+/// )?;
+/// let client = nightfly::ClientBuilder::from(config).build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Only settings that are plain data are covered. Anything else, such as a
+/// request signer or a cookie jar, is set on the `ClientBuilder` afterwards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// See `ClientBuilder::timeout`.
+    #[serde(with = "millis")]
+    pub timeout: Option<Duration>,
+    /// See `ClientBuilder::connect_timeout`.
+    #[serde(with = "millis")]
+    pub connect_timeout: Option<Duration>,
+    /// See `ClientBuilder::tls_handshake_timeout`.
+    #[serde(with = "millis")]
+    pub tls_handshake_timeout: Option<Duration>,
+    /// See `ClientBuilder::body_idle_timeout`.
+    #[serde(with = "millis")]
+    pub body_idle_timeout: Option<Duration>,
+    /// The most redirects to follow in a row, 0 to follow none. See
+    /// `ClientBuilder::redirect`.
+    pub max_redirects: usize,
+    /// See `ClientBuilder::pool_idle_timeout`.
+    #[serde(with = "millis")]
+    pub pool_idle_timeout: Option<Duration>,
+    /// See `ClientBuilder::pool_max_idle_per_host`, `None` for no limit.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// See `ClientBuilder::max_download_rate`.
+    pub max_download_rate: Option<u64>,
+    /// See `ClientBuilder::max_upload_rate`.
+    pub max_upload_rate: Option<u64>,
+    /// See `ClientBuilder::user_agent`.
+    pub user_agent: Option<String>,
+    /// See `ClientBuilder::https_only`.
+    pub https_only: bool,
+    /// See `ClientBuilder::block_private_addresses`.
+    pub block_private_addresses: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig::from(&ClientBuilder::new())
+    }
+}
+
+impl From<ClientConfig> for ClientBuilder {
+    fn from(config: ClientConfig) -> ClientBuilder {
+        let mut builder = ClientBuilder::new()
+            .redirect(match config.max_redirects {
+                0 => redirect::Policy::none(),
+                max => redirect::Policy::limited(max),
+            })
+            .pool_idle_timeout(config.pool_idle_timeout)
+            .pool_max_idle_per_host(config.pool_max_idle_per_host.unwrap_or(usize::MAX))
+            .https_only(config.https_only)
+            .block_private_addresses(config.block_private_addresses);
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = config.tls_handshake_timeout {
+            builder = builder.tls_handshake_timeout(timeout);
+        }
+        if let Some(timeout) = config.body_idle_timeout {
+            builder = builder.body_idle_timeout(timeout);
+        }
+        if let Some(rate) = config.max_download_rate {
+            builder = builder.max_download_rate(rate);
+        }
+        if let Some(rate) = config.max_upload_rate {
+            builder = builder.max_upload_rate(rate);
+        }
+        if let Some(user_agent) = config.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        builder
+    }
+}
+
+/// Optional durations as whole milliseconds.
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(duration) => serializer.serialize_some(&(duration.as_millis() as u64)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ClientConfig;
+    use crate::ClientBuilder;
+
+    #[lunatic::test]
+    fn defaults_match_builder() {
+        let config: ClientConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, ClientConfig::default());
+        assert_eq!(config.max_redirects, 10);
+        assert_eq!(config.pool_idle_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(config.pool_max_idle_per_host, None);
+        assert_eq!(config.timeout, None);
+    }
+
+    #[lunatic::test]
+    fn builder_round_trip() {
+        let config: ClientConfig = serde_json::from_str(
+            r#"{
+                "timeout": 1500,
+                "connect_timeout": 250,
+                "body_idle_timeout": 5000,
+                "max_redirects": 0,
+                "pool_idle_timeout": null,
+                "pool_max_idle_per_host": 4,
+                "max_download_rate": 1024,
+                "user_agent": "svc/1.0",
+                "https_only": true,
+                "block_private_addresses": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_millis(1500)));
+
+        let builder = ClientBuilder::from(config.clone());
+        assert_eq!(ClientConfig::from(&builder), config);
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["timeout"], 1500);
+        assert_eq!(json["tls_handshake_timeout"], serde_json::Value::Null);
+    }
+
+    #[lunatic::test]
+    fn rejects_unknown_settings() {
+        assert!(serde_json::from_str::<ClientConfig>(r#"{ "timeuot": 5 }"#).is_err());
+    }
+}
@@ -1,55 +1,139 @@
 pub mod builder;
+mod config;
 
 pub use builder::*;
+pub use config::ClientConfig;
 
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
-use std::io::Write;
-use std::time::Duration;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
 
-use http::header::{self, Entry, HeaderMap, HeaderValue, ACCEPT_ENCODING, RANGE};
+use http::header::{self, Entry, HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, RANGE};
 use http::Version;
 use lunatic::ap::{AbstractProcess, Config, ProcessRef};
 use lunatic::{abstract_process, Tag};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::batch::Batch;
+use crate::connect::ConnectHook;
 #[cfg(feature = "cookies")]
 use crate::cookie;
 use crate::error;
-use crate::lunatic_impl::request::{hashmap_from_header_map, InnerRequest};
+use crate::integrity;
+use crate::lunatic_impl::request::InnerRequest;
 use crate::lunatic_impl::response::SerializableResponse;
 use crate::lunatic_impl::{
-    decoder::{parse_response, Accepts},
-    http_stream::HttpStream,
+    decoder::{parse_response, Accepts, ParseResponseError},
+    expect::{self, Continue},
+    framing,
+    header_policy::HeaderPolicy,
+    http_stream::{Connector, HttpStream},
+    pool::Pool,
     request::{PendingRequest, Request, RequestBuilder},
     response::HttpResponse,
+    spacing::HostSpacing,
+    upload::{self, Upload},
 };
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::mirror::Mirrorer;
+use crate::proxy::{NoProxy, Proxy};
+use crate::proxy_protocol::ProxyHeader;
+use crate::recent::{RecentRequests, RequestSummary};
 use crate::redirect;
+use crate::retry::{Retrier, RetryCause, RetryEvent, RetryObserver};
+use crate::robots::Robots;
+use crate::signing::{sha256_hex, RequestSigner, SigningRequest, UNSIGNED_PAYLOAD};
+use crate::token::CachedToken;
+use crate::url_policy::UrlPolicy;
+use crate::webdav::{self, Depth};
 pub use crate::{Body, ClientBuilder};
-use crate::{IntoUrl, Method, Url};
-#[cfg(feature = "cookies")]
+use crate::{IntoUrl, Method, TimeSource, Url};
 use std::sync::Arc;
 
+const CONTENT_DIGEST: &str = "content-digest";
+
 #[derive(Clone)]
 pub struct InnerClient {
     pub(crate) accepts: Accepts,
     #[cfg(feature = "cookies")]
     pub(crate) cookie_store: Option<Arc<cookie::Jar>>,
     pub(crate) headers: HeaderMap,
+    pub(crate) api_key_query: Option<(String, String)>,
     pub(crate) redirect_policy: redirect::Policy,
+    pub(crate) redirect_auth: redirect::RedirectAuthPolicy,
     pub(crate) referer: bool,
     pub(crate) request_timeout: Option<Duration>,
-    // pub(crate) proxies: Arc<Vec<Proxy>>,
-    // pub(crate) proxies_maybe_http_auth: bool,
+    pub(crate) body_idle_timeout: Option<Duration>,
+    pub(crate) request_id_header: Option<HeaderName>,
+    #[cfg(feature = "log")]
+    pub(crate) log_levels: crate::logging::LogLevels,
+    pub(crate) proxies: Vec<Proxy>,
+    pub(crate) no_proxy: NoProxy,
     pub(crate) https_only: bool,
-    pub(crate) stream_map: HashMap<HostRef, HttpStream>,
+    pub(crate) block_private_addresses: bool,
+    pub(crate) url_policy: UrlPolicy,
+    pub(crate) connector: Connector,
+    pub(crate) signer: Option<Arc<dyn RequestSigner>>,
+    pub(crate) connect_hook: Option<Arc<dyn ConnectHook>>,
+    pub(crate) proxy_protocol: Option<ProxyHeader>,
+    pub(crate) token: Option<CachedToken>,
+    pub(crate) robots: Option<Robots>,
+    pub(crate) host_spacing: Option<HostSpacing>,
+    pub(crate) verify_integrity: bool,
+    pub(crate) content_digest: bool,
+    pub(crate) lenient_framing: bool,
+    pub(crate) http1_0_only: bool,
+    pub(crate) expect_continue_threshold: Option<u64>,
+    pub(crate) retry_stale_connections: bool,
+    pub(crate) pool_health_check: Option<Duration>,
+    pub(crate) pool_checked: Option<SystemTime>,
+    pub(crate) obs_fold: bool,
+    pub(crate) header_policy: HeaderPolicy,
+    pub(crate) metrics: Metrics,
+    pub(crate) recent: Option<RecentRequests>,
+    pub(crate) retrier: Option<Retrier>,
+    pub(crate) retry_observer: Option<Arc<dyn RetryObserver>>,
+    pub(crate) mirror: Option<(Mirrorer, Client)>,
+    pub(crate) time: TimeSource,
+    #[cfg(feature = "fault-injection")]
+    pub(crate) faults: Option<crate::fault::FaultInjector>,
+    #[cfg(feature = "mock")]
+    pub(crate) mock: Option<crate::mock::MockTransport>,
+    pub(crate) pool: Pool,
 }
 
 /// encode request as http text
 pub fn request_to_vec(
     method: Method,
     uri: Url,
+    headers: HeaderMap,
+    body: Option<Body>,
+    version: Version,
+) -> Vec<u8> {
+    encode_request(method, &origin_form(&uri), headers, body, version)
+}
+
+/// The request target for a request sent directly to the server of `url`.
+fn origin_form(url: &Url) -> String {
+    if let Some(query) = url.query() {
+        format!("{}?{}", url.path(), query)
+    } else {
+        url.path().to_string()
+    }
+}
+
+/// The request target for a request sent to a proxy, the whole URL without
+/// its fragment.
+fn absolute_form(url: &Url) -> String {
+    url[..url::Position::AfterQuery].to_string()
+}
+
+/// Encodes a request with `target` as the target of its request line.
+fn encode_request(
+    method: Method,
+    target: &str,
     mut headers: HeaderMap,
     body: Option<Body>,
     version: Version,
@@ -60,12 +144,7 @@ pub fn request_to_vec(
     }
 
     // writing status line
-    let path = if let Some(query) = uri.query() {
-        format!("{}?{}", uri.path(), query)
-    } else {
-        uri.path().to_string()
-    };
-    request_buffer.extend(format!("{} {} {:?}\r\n", method, path, version,).as_bytes());
+    request_buffer.extend(format!("{} {} {:?}\r\n", method, target, version,).as_bytes());
     // writing headers
     for (key, value) in headers.iter() {
         if let Ok(value) = String::from_utf8(value.as_ref().to_vec()) {
@@ -75,12 +154,29 @@ pub fn request_to_vec(
     // separator between header and data
     request_buffer.extend("\r\n".as_bytes());
     if let Some(body) = body {
-        request_buffer.extend(body.inner());
+        request_buffer.extend_from_slice(body.as_bytes());
     }
 
     request_buffer
 }
 
+/// A request ready to be written, see `InnerClient::prepare_request`.
+struct Head {
+    method: Method,
+    url: Url,
+    /// The target of the request line, see `encode_request`.
+    target: String,
+    /// The proxy to connect to instead of the server of `url`, if any.
+    proxy: Option<Url>,
+    /// The addresses of the server of `url` that `ssrf::check` let through,
+    /// to connect to instead of resolving its name again. Empty unless
+    /// `block_private_addresses` is set, and for requests through a proxy.
+    addrs: Vec<SocketAddr>,
+    headers: HeaderMap,
+    body: Option<Body>,
+    version: crate::Version,
+}
+
 #[abstract_process(visibility = pub)]
 impl InnerClient {
     // type Arg = ClientBuilder;
@@ -106,14 +202,42 @@ impl InnerClient {
         &mut self,
         request: InnerRequest,
     ) -> crate::Result<SerializableResponse> {
-        let res = self.execute_request(request, vec![])?;
-        Ok(SerializableResponse {
-            body: res.body,
-            status: res.status.as_u16(),
-            version: res.version,
-            headers: hashmap_from_header_map(res.headers),
-            url: res.url,
-            redirect_chain: res.redirect_chain,
+        if let Some((ref mut mirrorer, ref shadow_client)) = self.mirror {
+            if let Some(shadow) = mirrorer.shadow(&request) {
+                shadow_client.0.handle_shadow_request(shadow);
+            }
+        }
+        self.complete(request, |client, request| {
+            client.execute_with_retries(request)
+        })
+    }
+
+    /// Sends a copy of a request made to a mirroring client, see
+    /// `ClientBuilder::mirror`. Nobody waits for the response.
+    #[handle_message]
+    fn handle_shadow_request(&mut self, request: InnerRequest) {
+        let _ = self.complete(request, |client, request| {
+            client.execute_with_retries(request)
+        });
+    }
+
+    /// Writes the head of a request whose body the caller streams itself.
+    #[handle_request]
+    fn open_upload(&mut self, request: InnerRequest, len: Option<u64>) -> crate::Result<Upload> {
+        let mut request = request;
+        self.stamp_request_id(&mut request);
+        self.start_upload(request, len)
+    }
+
+    /// Reads the response to a request opened with `open_upload`.
+    #[handle_request]
+    fn close_upload(
+        &mut self,
+        request: InnerRequest,
+        stream: HttpStream,
+    ) -> crate::Result<SerializableResponse> {
+        self.complete(request, |client, request| {
+            client.finish_upload(request, stream)
         })
     }
 
@@ -121,6 +245,40 @@ impl InnerClient {
     fn get_request_timeout(&mut self) -> Option<Duration> {
         self.request_timeout
     }
+
+    #[handle_request]
+    fn get_metrics_snapshot(&mut self) -> MetricsSnapshot {
+        self.metrics.snapshot(self.pool.len())
+    }
+
+    #[handle_request]
+    fn get_recent_requests(&mut self) -> Vec<RequestSummary> {
+        self.recent
+            .as_ref()
+            .map(RecentRequests::to_vec)
+            .unwrap_or_default()
+    }
+
+    /// How many requests each rule of a mock answered, see
+    /// `MockClient::calls`.
+    #[handle_request]
+    fn get_mock_calls(&mut self) -> Vec<usize> {
+        #[cfg(feature = "mock")]
+        if let Some(ref mock) = self.mock {
+            return mock.calls();
+        }
+        Vec::new()
+    }
+
+    /// The cookies of the client's jar, see `Client::cookie_snapshot`.
+    #[handle_request]
+    fn get_cookies(&mut self) -> Vec<String> {
+        #[cfg(feature = "cookies")]
+        if let Some(ref jar) = self.cookie_store {
+            return jar.to_json_lines();
+        }
+        Vec::new()
+    }
 }
 
 /// An http `Client` to make Requests with.
@@ -222,6 +380,172 @@ impl Client {
         self.request(Method::HEAD, url)
     }
 
+    /// Convenience method to make an `OPTIONS` request to a URL.
+    ///
+    /// `HttpResponse::capabilities` reads what the answer allows.
+    ///
+    /// # Errors
+    ///
+    /// This method fails whenever the supplied `Url` cannot be parsed.
+    pub fn options<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::OPTIONS, url)
+    }
+
+    /// Send the CORS preflight request a browser would send before a
+    /// request from `origin` using `method` and `headers`, and read what the
+    /// server allows. See the `cors` module.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the `Url` can't be parsed or the request fails.
+    pub fn preflight<U: IntoUrl>(
+        &self,
+        url: U,
+        origin: &str,
+        method: Method,
+        headers: &[&str],
+    ) -> crate::Result<crate::cors::Capabilities> {
+        let mut req = self
+            .options(url)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method.as_str());
+        if !headers.is_empty() {
+            req = req.header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers.join(","));
+        }
+        Ok(req.send()?.capabilities())
+    }
+
+    /// Send the requests of `batch` together as a `multipart/mixed` `POST`
+    /// to the batch endpoint `url`, and split the answer into one response
+    /// per request. See the `batch` module.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the `Url` can't be parsed, the batch request
+    /// fails or is answered with an error status, or the answer isn't a
+    /// batch response.
+    pub fn batch<U: IntoUrl>(&self, url: U, batch: &Batch) -> crate::Result<Vec<HttpResponse>> {
+        self.post(url)
+            .batch(batch)
+            .send()?
+            .error_for_status()?
+            .batch_parts()
+    }
+
+    /// Fetch a JSON value with a `GET`, asking for `application/json`.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// #[derive(serde::Deserialize)]
+    /// struct Repo {
+    ///     stargazers_count: u64,
+    /// }
+    ///
+    /// let client = nightfly::Client::new();
+    /// let repo: Repo = client.get_json("https://api.github.com/repos/lunatic-solutions/lunatic")?;
+    /// println!("{} stars", repo.stargazers_count);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the `Url` can't be parsed, the request fails,
+    /// the response has an error status, or its body isn't valid JSON for
+    /// `T`.
+    pub fn get_json<T, U>(&self, url: U) -> crate::Result<T>
+    where
+        T: DeserializeOwned,
+        U: IntoUrl,
+    {
+        self.get(url)
+            .header(header::ACCEPT, "application/json")
+            .send()?
+            .error_for_status()?
+            .json()
+    }
+
+    /// Send `body` as JSON with a `POST`, and read the JSON value the
+    /// server answers with.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// #[derive(serde::Serialize)]
+    /// struct NewItem<'a> {
+    ///     name: &'a str,
+    /// }
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Item {
+    ///     id: u64,
+    /// }
+    ///
+    /// let client = nightfly::Client::new();
+    /// let item: Item = client.post_json("https://example.com/items", &NewItem { name: "lamp" })?;
+    /// println!("created item {}", item.id);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the `Url` can't be parsed, `body` can't be
+    /// serialized, the request fails, the response has an error status, or
+    /// its body isn't valid JSON for `T`.
+    pub fn post_json<B, T, U>(&self, url: U, body: &B) -> crate::Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+        U: IntoUrl,
+    {
+        self.post(url)
+            .header(header::ACCEPT, "application/json")
+            .json(body)
+            .send()?
+            .error_for_status()?
+            .json()
+    }
+
+    /// Start a WebDAV `PROPFIND` request, which lists the properties of a
+    /// resource and, with `depth`, of its members. An empty body asks for
+    /// all properties.
+    ///
+    /// # Errors
+    ///
+    /// This method fails whenever the supplied `Url` cannot be parsed.
+    pub fn propfind<U: IntoUrl>(&self, url: U, depth: Depth) -> RequestBuilder {
+        self.request(webdav::method("PROPFIND"), url).depth(depth)
+    }
+
+    /// Start a WebDAV `MKCOL` request, which creates a collection.
+    ///
+    /// # Errors
+    ///
+    /// This method fails whenever the supplied `Url` cannot be parsed.
+    pub fn mkcol<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(webdav::method("MKCOL"), url)
+    }
+
+    /// Start a WebDAV `COPY` request from `source` to `destination`.
+    ///
+    /// # Errors
+    ///
+    /// This method fails whenever either `Url` cannot be parsed.
+    pub fn copy_to<U: IntoUrl, D: IntoUrl>(&self, source: U, destination: D) -> RequestBuilder {
+        self.request(webdav::method("COPY"), source)
+            .destination(destination)
+    }
+
+    /// Start a WebDAV `MOVE` request from `source` to `destination`.
+    ///
+    /// # Errors
+    ///
+    /// This method fails whenever either `Url` cannot be parsed.
+    pub fn move_to<U: IntoUrl, D: IntoUrl>(&self, source: U, destination: D) -> RequestBuilder {
+        self.request(webdav::method("MOVE"), source)
+            .destination(destination)
+    }
+
     /// Start building a `Request` with the `Method` and `Url`.
     ///
     /// Returns a `RequestBuilder`, which will allow setting headers and
@@ -235,6 +559,46 @@ impl Client {
         RequestBuilder::new(self.clone(), req)
     }
 
+    /// Convenience method to make a `GET` request to the URL expanded from
+    /// an RFC 6570 URI template, see `request_template`.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let res = client
+    ///     .get_template(
+    ///         "https://api.github.com/repos/{owner}/{repo}/issues{?state}",
+    ///         &[("owner", "lunatic-solutions"), ("repo", "lunatic"), ("state", "open")],
+    ///     )
+    ///     .send()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_template<T: Serialize + ?Sized>(&self, template: &str, vars: &T) -> RequestBuilder {
+        self.request_template(Method::GET, template, vars)
+    }
+
+    /// Start building a `Request` with the `Method` and the URL expanded
+    /// from the URI template `template` with the variables in `vars`. See
+    /// the `uri_template` module.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the template is invalid, `vars` doesn't
+    /// serialize to a map or a sequence of pairs, or the expanded `Url`
+    /// cannot be parsed.
+    pub fn request_template<T: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        template: &str,
+        vars: &T,
+    ) -> RequestBuilder {
+        match crate::uri_template::expand(template, vars) {
+            Ok(url) => self.request(method, url),
+            Err(err) => RequestBuilder::new(self.clone(), Err(err)),
+        }
+    }
+
     /// Executes a `Request`.
     ///
     /// A `Request` can be built manually with `Request::new()` or obtained
@@ -248,20 +612,125 @@ impl Client {
     /// This method fails if there was an error while sending request,
     /// redirect loop was detected or redirect limit was exhausted.
     pub fn execute(&mut self, request: Request) -> Result<HttpResponse, crate::Error> {
+        if let Some((reader, len)) = request.body().and_then(Body::reader) {
+            return upload::execute(self, request, reader, len);
+        }
         let inner: InnerRequest = request.try_into()?;
         let url = inner.url.clone();
         let user_timeout = inner.timeout.or_else(|| self.0.get_request_timeout());
         let res = if let Some(timeout) = user_timeout {
+            let started = Instant::now();
             self.0
                 .with_timeout(timeout)
                 .handle_http_request(inner)
-                .unwrap_or_else(|_| Err(crate::error::timeout(url)))?
+                .unwrap_or_else(|_| {
+                    Err(crate::error::timeout_in(
+                        crate::TimeoutPhase::Request,
+                        started.elapsed(),
+                        timeout,
+                        url,
+                    ))
+                })?
         } else {
             self.0.handle_http_request(inner)?
         };
         res.try_into()
     }
 
+    /// Find out the size of a resource without downloading it.
+    ///
+    /// Sends a `HEAD` request, and falls back to asking for the first byte of
+    /// the body with a `GET` when the server rejects `HEAD` or leaves out the
+    /// length. Also tells whether the server serves byte ranges, and the
+    /// `ETag` of the resource, which helps to decide how to download it.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let info = client.content_length("https://example.com/large.bin")?;
+    /// if info.accepts_ranges {
+    ///     println!("{:?} bytes, can be fetched in parts", info.length);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the `Url` can't be parsed, if a request fails, or
+    /// if the fallback `GET` gets an error status.
+    pub fn content_length<U: IntoUrl>(&self, url: U) -> crate::Result<crate::ContentInfo> {
+        crate::probe::probe(self, url.into_url()?)
+    }
+
+    /// Fetch a JSON value, sending the `ETag` of the copy in `entry` with
+    /// `If-None-Match` so the server only sends the value again when it
+    /// changed.
+    ///
+    /// Returns the cached value on `304 Not Modified`, and otherwise decodes
+    /// the new one and stores it in `entry` along with its `ETag`.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let mut entry = nightfly::CacheEntry::new();
+    /// loop {
+    ///     let status: &serde_json::Value =
+    ///         client.fetch_json_cached("https://example.com/status", &mut entry)?;
+    ///     if entry.changed() {
+    ///         println!("new status: {}", status);
+    ///     }
+    ///     # break;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the request fails, the response has an error
+    /// status, or its body isn't valid JSON for `T`.
+    pub fn fetch_json_cached<'a, T, U>(
+        &self,
+        url: U,
+        entry: &'a mut crate::CacheEntry<T>,
+    ) -> crate::Result<&'a T>
+    where
+        T: DeserializeOwned,
+        U: IntoUrl,
+    {
+        crate::etag::fetch_json(self, url, entry)
+    }
+
+    /// Returns a copy of the metrics collected by this `Client`.
+    ///
+    /// Counters cover every request sent through any clone of the `Client`,
+    /// since they all share the same process.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.0.get_metrics_snapshot()
+    }
+
+    /// Returns summaries of the last requests sent through any clone of this
+    /// `Client`, oldest first.
+    ///
+    /// Empty unless `ClientBuilder::record_requests` was set.
+    pub fn recent_requests(&self) -> Vec<RequestSummary> {
+        self.0.get_recent_requests()
+    }
+
+    /// Returns a copy of the cookies of this `Client`, which can be sent
+    /// along with it to another process or node and restored there with
+    /// `ClientBuilder::restore_cookies`.
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies` feature to be enabled.
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    pub fn cookie_snapshot(&self) -> cookie::SerializableJar {
+        cookie::SerializableJar::from_json_lines(self.0.get_cookies())
+    }
+
     /// Creates a `ClientBuilder` to configure a `Client`.
     ///
     /// This is the same as `ClientBuilder::new()`.
@@ -277,28 +746,176 @@ pub(crate) enum HostRef {
 }
 
 impl HostRef {
+    /// The server `url` is on. Connections to different ports of a host go
+    /// to different servers, so they are told apart.
     pub(crate) fn new(url: &Url) -> Self {
-        let protocol = url.scheme();
-        if protocol == "https" {
-            return HostRef::Https(format!("{}", url.host().unwrap()));
+        let conn_str = format!(
+            "{}:{}",
+            url.host().unwrap(),
+            url.port_or_known_default().unwrap_or(80)
+        );
+        if url.scheme() == "https" {
+            return HostRef::Https(conn_str);
         }
-        let conn_str = format!("{}:{}", url.host().unwrap(), url.port().unwrap_or(80));
         HostRef::Http(conn_str)
     }
 }
 
+/// Whether writing to a pooled connection failed because the server had
+/// closed it.
+fn is_stale(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
 impl InnerClient {
     pub(crate) fn accepts(&self) -> Accepts {
         self.accepts
     }
 
     /// ensures connection
-    pub fn ensure_connection(&mut self, url: Url) -> crate::Result<HttpStream> {
-        let host_ref = HostRef::new(&url);
-        if let Some(stream) = self.stream_map.get(&host_ref) {
-            return Ok(stream.to_owned());
+    pub fn ensure_connection(
+        &mut self,
+        url: Url,
+        addrs: &[SocketAddr],
+        req: &InnerRequest,
+    ) -> crate::Result<HttpStream> {
+        self.connection(url, addrs, req, true)
+            .map(|(stream, _)| stream)
+    }
+
+    /// The pool connections for `req` to `server` are taken from and
+    /// returned to, if any.
+    ///
+    /// Pooled connections were verified against the client's roots, and
+    /// started with the client's PROXY header, only.
+    fn pool_key(&self, server: &Url, req: &InnerRequest) -> Option<HostRef> {
+        if req.root_certs.is_empty() && req.proxy_protocol.is_none() {
+            Some(HostRef::new(server))
+        } else {
+            None
         }
-        HttpStream::connect(url)
+    }
+
+    /// Keeps an idle connection to `host` for the next request to it.
+    pub(crate) fn release(&mut self, host: HostRef, stream: HttpStream) {
+        let time = self.time.clone();
+        self.pool.put(host, stream, &time);
+    }
+
+    /// A connection for `url`, and whether it was taken from the pool. With
+    /// `reuse` false, a new connection is opened even if one is pooled. A new
+    /// plain TCP connection is made to one of `addrs`, if any.
+    fn connection(
+        &mut self,
+        url: Url,
+        addrs: &[SocketAddr],
+        req: &InnerRequest,
+        reuse: bool,
+    ) -> crate::Result<(HttpStream, bool)> {
+        self.check_pool();
+        let proxy_header = req.proxy_protocol.or(self.proxy_protocol);
+        if proxy_header.is_some() && url.scheme() == "https" {
+            return Err(error::builder(
+                "a PROXY protocol header can't be sent on https connections",
+            )
+            .with_url(url));
+        }
+        // taken out of the pool while in use, see `release`
+        let pooled = match self.pool_key(&url, req) {
+            Some(key) if reuse => self.pool.take(&key, &self.time),
+            _ => None,
+        };
+        if let Some(mut stream) = pooled {
+            if self.pool_health_check.is_none() || !stream.is_dead() {
+                return Ok((stream, true));
+            }
+            lunatic_log::debug!("Dropping dead pooled connection to {}", url);
+        }
+        let mut stream = HttpStream::connect(url.clone(), addrs, &self.connector.for_request(req))?;
+        if let (Some(header), HttpStream::Tcp(tcp)) = (proxy_header, &mut stream) {
+            let written = tcp
+                .peer_addr()
+                .and_then(|peer| header.write_to(&mut *tcp, peer));
+            written.map_err(|e| error::connect(e, url.clone()))?;
+        }
+        if let (Some(hook), HttpStream::Tcp(tcp)) = (&self.connect_hook, &mut stream) {
+            hook.connected(tcp, &url)
+                .map_err(|e| error::connect(io::Error::other(e), url))?;
+        }
+        Ok((stream, false))
+    }
+
+    /// Drops the pooled connections the servers closed, at most once per
+    /// `ClientBuilder::pool_health_check` interval.
+    fn check_pool(&mut self) {
+        let interval = match self.pool_health_check {
+            Some(interval) => interval,
+            None => return,
+        };
+        if let Some(checked) = self.pool_checked {
+            if self.time.since(checked) < interval {
+                return;
+            }
+        }
+        self.pool_checked = Some(self.time.now());
+        self.pool.retain(|stream| !stream.is_dead());
+    }
+
+    /// Adds the request ID header, unless the caller already set one, and
+    /// returns its value. Redirects reuse the request's headers, so the ID
+    /// stays the same for the whole redirect chain.
+    fn stamp_request_id(&self, req: &mut InnerRequest) -> Option<String> {
+        let name = self.request_id_header.as_ref()?.as_str();
+        let values = req.headers.entry(name.to_string()).or_default();
+        if values.is_empty() {
+            values.push(uuid::Uuid::new_v4().to_string());
+        }
+        values.first().cloned()
+    }
+
+    /// Runs `send` for a request, recording metrics, logs and traces.
+    fn complete<F>(&mut self, request: InnerRequest, send: F) -> crate::Result<SerializableResponse>
+    where
+        F: FnOnce(&mut InnerClient, InnerRequest) -> crate::Result<HttpResponse>,
+    {
+        let mut request = request;
+        let request_id = self.stamp_request_id(&mut request);
+        #[cfg(feature = "otel")]
+        let cx = crate::otel::request_context(&request);
+        #[cfg(feature = "otel")]
+        let _guard = cx.clone().attach();
+        #[cfg(feature = "log")]
+        let (method, url) = (request.method.clone(), request.url.clone());
+        let labels = request.labels.clone();
+        let started = self.time.now();
+        let summary = self
+            .recent
+            .as_ref()
+            .map(|_| RequestSummary::start(started, &request, request_id.clone()));
+        let res = send(self, request);
+        let elapsed = self.time.since(started);
+        self.metrics
+            .record(res.as_ref().ok().map(|res| res.status), elapsed, &labels);
+        if let (Some(recent), Some(mut summary)) = (self.recent.as_mut(), summary) {
+            summary.finish(elapsed, &res);
+            recent.push(summary);
+        }
+        #[cfg(feature = "log")]
+        if let Err(ref e) = res {
+            self.log_levels.error(&method, &url, &labels, e);
+        }
+        #[cfg(feature = "otel")]
+        crate::otel::end_request(&cx, &res);
+        let mut res = res?;
+        res.request_id = request_id;
+        Ok(res.into())
     }
 
     fn fmt_fields(&self, f: &mut fmt::DebugStruct<'_, '_>) {
@@ -314,9 +931,13 @@ impl InnerClient {
 
         f.field("accepts", &self.accepts);
 
-        // if !self.proxies.is_empty() {
-        //     f.field("proxies", &self.proxies);
-        // }
+        if !self.proxies.is_empty() {
+            f.field("proxies", &self.proxies);
+        }
+
+        if !self.no_proxy.is_empty() {
+            f.field("no_proxy", &self.no_proxy);
+        }
 
         // if !self.redirect_policy.is_default() {
         //     f.field("redirect_policy", &self.redirect_policy);
@@ -331,6 +952,40 @@ impl InnerClient {
         if let Some(ref d) = self.request_timeout {
             f.field("timeout", d);
         }
+
+        if let Some(ref d) = self.body_idle_timeout {
+            f.field("body_idle_timeout", d);
+        }
+    }
+
+    /// Executes a request, retrying it as long as the `RetryPolicy` allows.
+    fn execute_with_retries(&mut self, req: InnerRequest) -> crate::Result<HttpResponse> {
+        let mut retries = 0;
+        loop {
+            let res = self.execute_request(req.clone(), vec![]);
+            let time = self.time.clone();
+            let delay = match self.retrier.as_mut() {
+                Some(retrier) => retrier.retry(&req.method, &res, retries, &time),
+                None => None,
+            };
+            match delay {
+                Some(delay) => {
+                    lunatic_log::debug!("Retrying {} {} in {:?}", req.method, req.url, delay);
+                    if let Some(ref observer) = self.retry_observer {
+                        observer.retrying(&RetryEvent {
+                            method: &req.method,
+                            url: &req.url,
+                            attempt: retries + 2,
+                            delay,
+                            cause: RetryCause::of(&res),
+                        });
+                    }
+                    self.time.sleep(delay);
+                    retries += 1;
+                }
+                None => return res,
+            }
+        }
     }
 
     pub(crate) fn execute_request(
@@ -338,7 +993,240 @@ impl InnerClient {
         req: InnerRequest,
         urls: Vec<Url>,
     ) -> crate::Result<HttpResponse> {
-        let (method, url, mut headers, body, _timeout, version) = req.clone().pieces();
+        let Head {
+            method,
+            url,
+            target,
+            proxy,
+            addrs,
+            mut headers,
+            body,
+            version,
+        } = match self.prepare_request(&req, &urls, None)? {
+            Ok(head) => head,
+            Err(answer) => return answer,
+        };
+
+        let body_len = body.as_ref().map(Body::len).unwrap_or(0);
+        let mut added_expect = false;
+        if let Some(threshold) = self.expect_continue_threshold {
+            if body_len as u64 > threshold
+                && version != crate::Version::HTTP_10
+                && !headers.contains_key(header::EXPECT)
+            {
+                headers.insert(header::EXPECT, HeaderValue::from_static("100-continue"));
+                added_expect = true;
+            }
+        }
+
+        #[cfg(feature = "log")]
+        self.log_levels.request(&req, &headers);
+        #[cfg(feature = "otel")]
+        let span = crate::otel::attempt_span(&req);
+        let server = proxy.unwrap_or_else(|| url.clone());
+        // Digests are computed over the body as sent, so keep a copy of it.
+        let mut parse_req = req.clone();
+        parse_req.tee |= self.verify_integrity;
+        let mut pool = match self.pool_key(&server, &req) {
+            Some(key) if !framing::closes_connection(&headers) => Some(key),
+            _ => None,
+        };
+        // set once a pooled connection turned out to be closed, the request
+        // is then sent once more on a fresh connection
+        let mut reconnected = false;
+        let parsed = loop {
+            let (stream, response_buffer, pooled) = loop {
+                let encoded = encode_request(
+                    method.clone(),
+                    &target,
+                    headers.clone(),
+                    body.clone(),
+                    version.into(),
+                );
+                lunatic_log::debug!(
+                    "Encoded headers {:?} | Encoded request {:?}",
+                    headers,
+                    String::from_utf8(encoded.clone())
+                );
+
+                let (stream, pooled) =
+                    self.connection(server.clone(), &addrs, &req, !reconnected)?;
+                let mut stream = self.connector.pace(stream, self.time.clone());
+                // if let Some(timeout) = self.request_timeout {
+                //     stream.set
+                // }
+
+                let wants_continue = expect::wants_continue(&headers) && body_len != 0;
+                // send the head, and the body only if the server wants it
+                let (head, body) = if wants_continue {
+                    encoded.split_at(encoded.len() - body_len)
+                } else {
+                    (&encoded[..], &[][..])
+                };
+                if let Err(e) = stream.write_all(head) {
+                    // nothing reached the server, a fresh connection may do
+                    if pooled && self.retry_stale_connections && is_stale(&e) {
+                        lunatic_log::debug!(
+                            "Pooled connection to {} was closed, reconnecting",
+                            url
+                        );
+                        reconnected = true;
+                        continue;
+                    }
+                    return Err(error::request(e).with_url(url.clone()));
+                }
+                if !wants_continue {
+                    break (stream, Vec::new(), pooled);
+                }
+                match expect::await_continue(&mut stream)
+                    .map_err(|e| error::request(e).with_url(url.clone()))?
+                {
+                    Continue::Proceed(rest) => {
+                        stream
+                            .write_all(body)
+                            .map_err(|e| error::request(e).with_url(url.clone()))?;
+                        break (stream, rest, pooled);
+                    }
+                    // the expectation we added isn't understood, send it all
+                    Continue::Final(buffer)
+                        if added_expect && expect::status(&buffer) == Some(417) =>
+                    {
+                        headers.remove(header::EXPECT);
+                        added_expect = false;
+                    }
+                    // The server answered without the body, which it may
+                    // still read as the start of the next request: close
+                    // the connection instead of pooling it.
+                    Continue::Final(buffer) => {
+                        pool = None;
+                        break (stream, buffer, pooled);
+                    }
+                }
+            };
+
+            match parse_response(
+                response_buffer,
+                stream,
+                parse_req.clone(),
+                self,
+                pool.clone(),
+            ) {
+                // The server closed the connection while it sat in the pool,
+                // but the request was written before that was noticed. It
+                // may have been read, so only send it again if that's safe.
+                Err(ParseResponseError::TcpStreamClosedWithoutData)
+                    if pooled
+                        && self.retry_stale_connections
+                        && crate::retry::is_idempotent(method.as_str()) =>
+                {
+                    lunatic_log::debug!("Pooled connection to {} was closed, reconnecting", url);
+                    reconnected = true;
+                }
+                parsed => break parsed,
+            }
+        };
+        #[cfg(feature = "otel")]
+        crate::otel::end_attempt(span, &parsed);
+        #[cfg(feature = "log")]
+        if let Ok(ref res) = parsed {
+            self.log_levels.response(&req, res);
+            self.log_levels.notice(&req, res);
+        }
+        self.finish_request(req, urls, parsed)
+    }
+
+    /// Writes the head of a request whose body the caller streams from a
+    /// reader, see `upload`. `len` is the size of the body, if known.
+    fn start_upload(&mut self, req: InnerRequest, len: Option<u64>) -> crate::Result<Upload> {
+        let Head {
+            method,
+            url,
+            target,
+            proxy,
+            addrs,
+            mut headers,
+            version,
+            ..
+        } = match self.prepare_request(&req, &[], Some(len))? {
+            Ok(head) => head,
+            Err(answer) => return answer.map(|res| Upload::Answered(res.into())),
+        };
+
+        let chunked = match len {
+            Some(len) => {
+                if !headers.contains_key(header::CONTENT_LENGTH) {
+                    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+                }
+                false
+            }
+            // the caller knows better
+            None if headers.contains_key(header::CONTENT_LENGTH) => false,
+            None if version == crate::Version::HTTP_10 => {
+                return Err(error::framing(
+                    "HTTP/1.0 can't send a body of unknown length".to_string(),
+                    url,
+                ));
+            }
+            None => {
+                headers.insert(
+                    header::TRANSFER_ENCODING,
+                    HeaderValue::from_static("chunked"),
+                );
+                true
+            }
+        };
+
+        #[cfg(feature = "log")]
+        self.log_levels.request(&req, &headers);
+        let head = encode_request(method, &target, headers, None, version.into());
+        let mut stream =
+            self.ensure_connection(proxy.unwrap_or_else(|| url.clone()), &addrs, &req)?;
+        stream
+            .write_all(&head)
+            .map_err(|e| error::request(e).with_url(url))?;
+        Ok(Upload::Open {
+            stream,
+            request: req,
+            chunked,
+        })
+    }
+
+    /// Reads the response to a request started with `start_upload`, once the
+    /// caller has written its body.
+    fn finish_upload(
+        &mut self,
+        req: InnerRequest,
+        stream: HttpStream,
+    ) -> crate::Result<HttpResponse> {
+        let stream = self.connector.pace(stream, self.time.clone());
+        #[cfg(feature = "otel")]
+        let span = crate::otel::attempt_span(&req);
+        let mut parse_req = req.clone();
+        parse_req.tee |= self.verify_integrity;
+        let parsed = parse_response(Vec::new(), stream, parse_req, self, None);
+        #[cfg(feature = "otel")]
+        crate::otel::end_attempt(span, &parsed);
+        #[cfg(feature = "log")]
+        if let Ok(ref res) = parsed {
+            self.log_levels.response(&req, res);
+            self.log_levels.notice(&req, res);
+        }
+        self.finish_request(req, Vec::new(), parsed)
+    }
+
+    /// Checks a request and works out the head to send for it. `streamed` is
+    /// `Some` when the caller streams the body itself, with its length if
+    /// known.
+    ///
+    /// Returns `Err` with the answer to give instead of sending the request,
+    /// when a fault is injected.
+    fn prepare_request(
+        &mut self,
+        req: &InnerRequest,
+        urls: &[Url],
+        streamed: Option<Option<u64>>,
+    ) -> crate::Result<Result<Head, crate::Result<HttpResponse>>> {
+        let (method, mut url, mut headers, body, _timeout, mut version) = req.clone().pieces();
         if url.scheme() != "http" && url.scheme() != "https" {
             return Err(error::url_bad_scheme(url));
         }
@@ -348,6 +1236,60 @@ impl InnerClient {
             return Err(error::url_bad_scheme(url));
         }
 
+        // HTTP/3 runs over QUIC, which the lunatic runtime does not provide.
+        // Writing an `HTTP/3.0` request line onto a TCP stream would only
+        // confuse the server, so fail early instead.
+        if version == crate::Version::HTTP_3 {
+            return Err(error::unsupported_version(version, url));
+        }
+        if self.http1_0_only {
+            version = crate::Version::HTTP_10;
+        }
+
+        // client credentials are added again on every redirect, so they only
+        // go as far as an `Authorization` header set on the request would
+        let authorized = self.redirect_auth.keeps_authorization(&url, urls);
+
+        if let Some((name, value)) = self.api_key_query.as_ref().filter(|_| authorized) {
+            if !url.query_pairs().any(|(k, _)| k == name.as_str()) {
+                url.query_pairs_mut().append_pair(name, value);
+            }
+        }
+
+        #[cfg(feature = "mock")]
+        if let Some(ref mut mock) = self.mock {
+            return Ok(Err(mock.answer(method.as_str(), url)));
+        }
+
+        self.url_policy.check(&url)?;
+        let addrs = if self.block_private_addresses {
+            crate::ssrf::check(&url)?
+        } else {
+            Vec::new()
+        };
+
+        if let Some(robots_url) = self.robots.as_ref().and_then(|r| r.to_fetch(&url)) {
+            let res = InnerRequest::try_from(Request::new(Method::GET, robots_url))
+                .and_then(|req| self.execute_request(req, Vec::new()));
+            if let Some(ref mut robots) = self.robots {
+                robots.learn(&url, res);
+            }
+        }
+        if let Some(ref mut robots) = self.robots {
+            robots.check(&url, &self.time)?;
+        }
+        if let Some(ref mut spacing) = self.host_spacing {
+            spacing.wait(&url, &self.time);
+        }
+
+        #[cfg(feature = "fault-injection")]
+        {
+            let time = self.time.clone();
+            if let Some(fault) = self.faults.as_mut().and_then(|f| f.inject(&time)) {
+                return Ok(Err(fault.into_result(url)));
+            }
+        }
+
         if let Some(host) = url.host() {
             if !self.headers.contains_key("Host") {
                 headers.append("Host", HeaderValue::from_str(&host.to_string()).unwrap());
@@ -356,7 +1298,9 @@ impl InnerClient {
 
         // insert default headers in the request headers
         // without overwriting already appended headers.
-        for (key, value) in &self.headers {
+        let mut defaults = self.headers.clone();
+        self.redirect_auth.strip_chain(&mut defaults, &url, urls);
+        for (key, value) in &defaults {
             if let Entry::Vacant(entry) = headers.entry(key) {
                 entry.insert(value.clone());
             }
@@ -366,7 +1310,7 @@ impl InnerClient {
         #[cfg(feature = "cookies")]
         {
             if let Some(cookie_store) = self.cookie_store.as_ref() {
-                if headers.get(crate::header::COOKIE).is_none() {
+                if !req.no_cookies && headers.get(crate::header::COOKIE).is_none() {
                     add_cookie_header(&mut headers, cookie_store.clone(), &url);
                 }
             }
@@ -380,64 +1324,115 @@ impl InnerClient {
             }
         }
 
-        // let uri = expect_uri(&url);
+        let proxy = self
+            .proxies
+            .iter()
+            .find(|proxy| proxy.intercepts(&url))
+            .filter(|_| !self.no_proxy.matches(&url));
+        if let Some(proxy) = proxy {
+            // a CONNECT tunnel would need a TLS handshake over it, which the
+            // host can't do
+            if url.scheme() == "https" {
+                return Err(error::proxy(
+                    "tunneling https requests through a proxy isn't supported",
+                    url,
+                ));
+            }
+            if let Some(auth) = proxy.auth() {
+                if let Entry::Vacant(entry) = headers.entry(header::PROXY_AUTHORIZATION) {
+                    entry.insert(auth);
+                }
+            }
+        }
+        let (target, proxy, addrs) = match proxy {
+            Some(proxy) => (absolute_form(&url), Some(proxy.url().clone()), Vec::new()),
+            None => (origin_form(&url), None, addrs),
+        };
 
-        // self.proxy_auth(&uri, &mut headers);
+        let time = self.time.clone();
+        if let Some(ref mut token) = self.token {
+            if authorized && !headers.contains_key(header::AUTHORIZATION) {
+                let token = token
+                    .get(&time)
+                    .map_err(|e| error::request(e).with_url(url.clone()))?;
+                let mut value = HeaderValue::from_str(&format!("Bearer {}", token.value()))
+                    .map_err(|e| error::request(e).with_url(url.clone()))?;
+                value.set_sensitive(true);
+                headers.insert(header::AUTHORIZATION, value);
+            }
+        }
 
-        let encoded = request_to_vec(
-            method,
-            url.clone(),
-            headers.clone(),
-            body,
-            version.try_into().unwrap(),
-        );
-        lunatic_log::debug!(
-            "Encoded headers {:?} | Encoded request {:?}",
-            headers,
-            String::from_utf8(encoded.clone())
-        );
+        if self.content_digest {
+            if let Some(ref body) = body {
+                if let Entry::Vacant(entry) = headers.entry(CONTENT_DIGEST) {
+                    entry.insert(integrity::content_digest(body.as_bytes()));
+                }
+            }
+        }
 
-        let mut stream = self.ensure_connection(url)?;
-        // if let Some(timeout) = self.request_timeout {
-        //     stream.set
-        // }
+        if let Some(ref signer) = self.signer {
+            // a streamed body is only read once it's too late to sign it
+            let body_sha256 = match streamed {
+                Some(_) => UNSIGNED_PAYLOAD.to_string(),
+                None => sha256_hex(body.as_ref().map(Body::as_bytes).unwrap_or_default()),
+            };
+            let signed = signer
+                .sign(&SigningRequest {
+                    method: &method,
+                    url: &url,
+                    headers: &headers,
+                    body_sha256: &body_sha256,
+                })
+                .map_err(|e| error::request(e).with_url(url.clone()))?;
+            crate::util::replace_headers(&mut headers, signed);
+        }
 
-        stream.write_all(&encoded).unwrap();
+        if !self.lenient_framing {
+            let body_len = match streamed {
+                Some(len) => len.map(|len| len as usize),
+                None => body.as_ref().map(Body::len),
+            };
+            framing::check_request(&headers, body_len)
+                .map_err(|problem| error::framing(problem, url.clone()))?;
+        }
 
-        let response_buffer = Vec::new();
+        Ok(Ok(Head {
+            method,
+            url,
+            target,
+            proxy,
+            addrs,
+            headers,
+            body,
+            version,
+        }))
+    }
 
-        match parse_response(response_buffer, stream.clone(), req.clone(), self) {
-            Ok(res) => PendingRequest::new(res, self, req, urls).resolve(),
-            Err(_e) => unimplemented!(),
+    /// Checks a parsed response and follows redirects.
+    fn finish_request(
+        &mut self,
+        req: InnerRequest,
+        urls: Vec<Url>,
+        parsed: Result<HttpResponse, ParseResponseError>,
+    ) -> crate::Result<HttpResponse> {
+        match parsed {
+            Ok(mut res) => {
+                if self.verify_integrity {
+                    let method: Method = req.method.parse().unwrap();
+                    if integrity::applies_to(&method, res.status) {
+                        let raw = res.raw_body.as_deref().unwrap_or_default();
+                        integrity::verify(&res.headers, raw)
+                            .map_err(|digest| error::integrity(digest, res.url.clone()))?;
+                    }
+                    if !req.tee {
+                        res.raw_body = None;
+                    }
+                }
+                PendingRequest::new(res, self, req, urls).resolve()
+            }
+            Err(e) => Err(parse_error(e, &req.url)),
         }
     }
-
-    // fn proxy_auth(&self, dst: &Uri, headers: &mut HeaderMap) {
-    //     if !self.proxies_maybe_http_auth {
-    //         return;
-    //     }
-
-    //     // Only set the header here if the destination scheme is 'http',
-    //     // since otherwise, the header will be included in the CONNECT tunnel
-    //     // request instead.
-    //     if dst.scheme() != Some(&Scheme::HTTP) {
-    //         return;
-    //     }
-
-    //     // if headers.contains_key(PROXY_AUTHORIZATION) {
-    //     //     return;
-    //     // }
-
-    //     // for proxy in self.proxies.iter() {
-    //     //     if proxy.is_match(dst) {
-    //     //         if let Some(header) = proxy.http_basic_auth(dst) {
-    //     //             headers.insert(PROXY_AUTHORIZATION, header);
-    //     //         }
-
-    //     //         break;
-    //     //     }
-    //     // }
-    // }
 }
 
 impl fmt::Debug for InnerClient {
@@ -535,6 +1530,42 @@ pub(crate) fn add_cookie_header(
     }
 }
 
+fn parse_error(e: ParseResponseError, url: &Url) -> crate::Error {
+    match e {
+        ParseResponseError::LengthMismatch { expected, received } => {
+            error::length_mismatch(expected as u64, received as u64, url.clone())
+        }
+        ParseResponseError::Framing(problem) => error::framing(problem, url.clone()),
+        ParseResponseError::DuplicateHeader(name) => {
+            error::duplicate_header(name.as_str(), url.clone())
+        }
+        ParseResponseError::Body(e) => error::decode_io(e).with_url(url.clone()),
+        ParseResponseError::TimedOut(detail) => {
+            error::timeout_in(detail.phase, detail.elapsed, detail.allowed, url.clone())
+        }
+        ParseResponseError::Interrupted { cause, partial } => {
+            parse_error(*cause, url).with_partial_body(partial)
+        }
+        ParseResponseError::TcpStreamClosedWithoutData => error::request(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before any response was received",
+        ))
+        .with_url(url.clone()),
+        ParseResponseError::TcpStreamClosed => error::request(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before the response head was complete",
+        ))
+        .with_url(url.clone()),
+        ParseResponseError::HttpParseError(e) => error::request(e).with_url(url.clone()),
+        ParseResponseError::ResponseTooLarge => {
+            error::request("response head is too large").with_url(url.clone())
+        }
+        ParseResponseError::UnknownCode => {
+            error::request("invalid response status code").with_url(url.clone())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[lunatic::test]
@@ -548,4 +1579,98 @@ mod tests {
         assert!(err.is_builder());
         assert_eq!(url_str, err.url().unwrap().as_str());
     }
+
+    #[lunatic::test]
+    fn execute_request_rejects_http3() {
+        let result = crate::Client::new()
+            .get("http://www.rust-lang.org/")
+            .version(crate::Version::HTTP_3)
+            .send();
+
+        let err = result.unwrap_err();
+        assert!(err.is_builder());
+    }
+
+    #[lunatic::test]
+    fn proxied_requests_target_the_whole_url() {
+        let url = url::Url::parse("http://example.com:8080/a/b?c=d#e").unwrap();
+        assert_eq!(super::origin_form(&url), "/a/b?c=d");
+        assert_eq!(
+            super::absolute_form(&url),
+            "http://example.com:8080/a/b?c=d"
+        );
+    }
+
+    #[lunatic::test]
+    fn https_requests_are_not_proxied() {
+        let err = crate::Client::builder()
+            .proxy(crate::Proxy::all("http://127.0.0.1:3128").unwrap())
+            .build()
+            .unwrap()
+            .get("https://www.rust-lang.org/")
+            .send()
+            .unwrap_err();
+        assert!(err.is_proxy());
+    }
+
+    #[lunatic::test]
+    fn unreadable_responses_are_request_errors() {
+        use super::ParseResponseError;
+
+        let url = url::Url::parse("http://example.com/").unwrap();
+        for e in [
+            ParseResponseError::TcpStreamClosedWithoutData,
+            ParseResponseError::TcpStreamClosed,
+            ParseResponseError::HttpParseError(httparse::Error::Status),
+            ParseResponseError::ResponseTooLarge,
+            ParseResponseError::UnknownCode,
+        ] {
+            let err = super::parse_error(e, &url);
+            assert!(err.is_request(), "{:?}", err);
+            assert_eq!(err.url(), Some(&url));
+        }
+    }
+
+    #[lunatic::test]
+    fn pooled_connections_are_kept_per_port() {
+        use super::HostRef;
+
+        let host = |url: &str| HostRef::new(&url::Url::parse(url).unwrap());
+        assert_eq!(
+            host("https://example.com/"),
+            HostRef::Https("example.com:443".to_string())
+        );
+        assert_eq!(
+            host("https://example.com:443/a"),
+            host("https://example.com/b")
+        );
+        assert_ne!(
+            host("https://example.com:8443/"),
+            host("https://example.com/")
+        );
+        assert_ne!(
+            host("http://example.com:8080/"),
+            host("http://example.com/")
+        );
+        assert_ne!(
+            host("http://example.com:443/"),
+            host("https://example.com/")
+        );
+    }
+
+    #[lunatic::test]
+    fn only_closed_connections_are_stale() {
+        use std::io::ErrorKind;
+
+        assert!(super::is_stale(&ErrorKind::BrokenPipe.into()));
+        assert!(super::is_stale(&ErrorKind::ConnectionReset.into()));
+        assert!(!super::is_stale(&ErrorKind::TimedOut.into()));
+        assert!(!super::is_stale(&ErrorKind::InvalidData.into()));
+    }
+
+    #[lunatic::test]
+    fn api_default_retries() {
+        let builder = crate::ClientBuilder::api_default();
+        assert!(format!("{:?}", builder).contains("retry"));
+    }
 }
@@ -2,20 +2,25 @@ use std::fmt;
 use std::io::Read;
 use std::{convert::TryFrom, str::FromStr};
 
-use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::read::GzDecoder;
+#[cfg(feature = "deflate")]
+use flate2::read::ZlibDecoder;
 
 use http::{
-    header::{CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING},
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, ETAG, LAST_MODIFIED, TRANSFER_ENCODING},
     HeaderMap, Method,
 };
 
 use httparse::{Status, EMPTY_HEADER};
 use serde::{Deserialize, Serialize};
 
-use super::http_stream::HttpStream;
+use super::client::HostRef;
+use super::framing;
+use super::http_stream::{PacedStream, PhaseTimedOut};
 use super::request::InnerRequest;
 use super::InnerClient;
-use crate::HttpResponse;
+use crate::integrity::BodyHasher;
+use crate::{HttpResponse, PartialBody, TimeoutDetail, TimeoutPhase};
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) struct Accepts {
@@ -35,7 +40,9 @@ pub(crate) struct Decoder {
 #[derive(Debug)]
 enum MessageEncoding {
     Gzip,
+    #[cfg(feature = "brotli")]
     Brotli,
+    #[cfg(feature = "deflate")]
     Deflate,
     Octets,
 }
@@ -70,6 +77,7 @@ impl Decoder {
     /// A brotli decoder.
     ///
     /// This decoder will buffer and decompress chunks that are brotlied.
+    #[cfg(feature = "brotli")]
     fn brotli(reader: HttpBodyReader) -> Decoder {
         Decoder {
             reader,
@@ -80,6 +88,7 @@ impl Decoder {
     /// A deflate decoder.
     ///
     /// This decoder will buffer and decompress chunks that are deflated.
+    #[cfg(feature = "deflate")]
     fn deflate(reader: HttpBodyReader) -> Decoder {
         Decoder {
             reader,
@@ -87,63 +96,120 @@ impl Decoder {
         }
     }
 
-    pub fn decode(&mut self) -> HttpResponse {
-        if let MessageEncoding::Octets = self.encoding {
-            let reader = &mut self.reader;
-            let body = if let Some(content_length) = reader.content_length() {
-                let mut body = vec![0u8; content_length];
-                reader.read_exact(&mut body).unwrap();
-                body
-            } else if reader.no_content_length_required() {
-                vec![]
-            } else {
-                // this should not happen
-                panic!("Content-encoded body without content-length");
-            };
-            return HttpResponse {
-                headers: reader.res.headers().to_owned(),
-                status: reader.res.status().to_owned(),
-                // transform type into http::Version type
-                version: reader.res.version().into(),
-                body,
-                url: reader.req.url.clone(),
-                redirect_chain: vec![],
-            };
-        }
-
-        let buf = if !self.reader.no_content_length_required() {
-            match &self.encoding {
-                MessageEncoding::Brotli => {
-                    let mut decoder = brotli::Decompressor::new(&mut self.reader, 4096);
-                    let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
-                    buf
+    /// Reads the body through the decompressor, which pulls the compressed
+    /// data from the stream as it needs it.
+    pub fn decode(&mut self) -> ResponseResult {
+        let mut body = Vec::new();
+        let mut hasher = self.reader.req.hash_body.map(BodyHasher::new);
+        let compressed = !matches!(self.encoding, MessageEncoding::Octets);
+        if !self.reader.no_content_length_required() {
+            let read = match &self.encoding {
+                MessageEncoding::Octets => {
+                    Hashing::new(&mut self.reader, hasher.as_mut()).read_to_end(&mut body)
                 }
+                #[cfg(feature = "brotli")]
+                MessageEncoding::Brotli => Hashing::new(
+                    brotli::Decompressor::new(&mut self.reader, 4096),
+                    hasher.as_mut(),
+                )
+                .read_to_end(&mut body),
                 MessageEncoding::Gzip => {
-                    let mut decoder = GzDecoder::new(&mut self.reader);
-                    let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
-                    // end_buf
-                    buf
+                    Hashing::new(GzDecoder::new(&mut self.reader), hasher.as_mut())
+                        .read_to_end(&mut body)
                 }
+                #[cfg(feature = "deflate")]
                 MessageEncoding::Deflate => {
-                    let mut decoder = ZlibDecoder::new(&mut self.reader);
-                    let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
-                    buf
+                    Hashing::new(ZlibDecoder::new(&mut self.reader), hasher.as_mut())
+                        .read_to_end(&mut body)
+                }
+            };
+            // a decompressor fails on a truncated body, so check the length
+            // before reporting that
+            if let Err(ref e) = read {
+                if let Some(invalid) = e.get_ref().and_then(|e| e.downcast_ref::<InvalidChunk>()) {
+                    let cause = ParseResponseError::Framing(invalid.to_string());
+                    return Err(self.interrupted(cause, body));
+                }
+                if let Some(detail) = PhaseTimedOut::find(e) {
+                    let cause = ParseResponseError::TimedOut(detail);
+                    return Err(self.interrupted(cause, body));
                 }
-                _ => panic!("Cannot happen"),
+                if e.kind() != std::io::ErrorKind::UnexpectedEof {
+                    let cause = ParseResponseError::Body(read.unwrap_err());
+                    return Err(self.interrupted(cause, body));
+                }
+            }
+            if let Some(expected) = self.reader.content_length() {
+                let received = self.reader.received();
+                if !self.reader.is_chunked() && received != expected {
+                    let cause = ParseResponseError::LengthMismatch { expected, received };
+                    if received > expected {
+                        return Err(cause);
+                    }
+                    return Err(self.interrupted(cause, body));
+                }
+            }
+            if let Err(e) = read {
+                return Err(self.interrupted(ParseResponseError::Body(e), body));
+            }
+            // they described the body as sent, not as decompressed
+            if compressed {
+                let headers = self.reader.res.headers_mut();
+                headers.remove(CONTENT_ENCODING);
+                headers.remove(CONTENT_LENGTH);
             }
+        }
+        let raw_body = if self.reader.req.tee {
+            self.reader.tee.take()
         } else {
-            vec![]
+            None
         };
-        HttpResponse {
+        Ok(HttpResponse {
             headers: self.reader.res.headers().to_owned(),
             status: self.reader.res.status().to_owned(),
+            // transform type into http::Version type
             version: self.reader.res.version().into(),
-            body: buf,
+            body: body.into(),
             url: self.reader.req.url.clone(),
             redirect_chain: vec![],
+            request_id: None,
+            raw_body,
+            raw_headers: vec![],
+            body_hash: hasher.map(BodyHasher::finish),
+        })
+    }
+
+    /// Attaches what was read of the body to an error that cut reading it
+    /// short. A compressed body is only kept as sent if the request asked
+    /// for it with `tee`, otherwise there is nothing to resume from.
+    fn interrupted(&mut self, cause: ParseResponseError, body: Vec<u8>) -> ParseResponseError {
+        let headers = self.reader.res.headers();
+        let content_encoding = match self.encoding {
+            MessageEncoding::Octets => None,
+            _ => headers
+                .get(CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        };
+        let validator = headers
+            .get(ETAG)
+            .or_else(|| headers.get(LAST_MODIFIED))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let raw = match self.encoding {
+            MessageEncoding::Octets => body,
+            _ => match self.reader.tee.take() {
+                Some(raw) => raw,
+                None => return cause,
+            },
+        };
+        ParseResponseError::Interrupted {
+            cause: Box::new(cause),
+            partial: PartialBody {
+                raw,
+                content_encoding,
+                validator,
+            },
         }
     }
 
@@ -166,10 +232,6 @@ impl Decoder {
                 }
             }
         }
-        // if is_content_encoded {
-        //     headers.remove(CONTENT_ENCODING);
-        //     headers.remove(CONTENT_LENGTH);
-        // }
         is_content_encoded
     }
 
@@ -180,14 +242,21 @@ impl Decoder {
     ///
     /// Uses the correct variant by inspecting the Content-Encoding header.
     pub(super) fn detect(mut reader: HttpBodyReader, _accepts: Accepts) -> Decoder {
+        // part of a compressed body can't be decompressed on its own, see
+        // `RequestBuilder::resume`
+        if reader.res.status() == http::StatusCode::PARTIAL_CONTENT {
+            return Decoder::plain_text(reader);
+        }
         let _headers = reader.res.headers_mut();
-        if _accepts.gzip && Decoder::detect_encoding(_headers, "gzip") {
+        if _accepts.is_gzip() && Decoder::detect_encoding(_headers, "gzip") {
             return Decoder::gzip(reader);
         }
-        if _accepts.brotli && Decoder::detect_encoding(_headers, "br") {
+        #[cfg(feature = "brotli")]
+        if _accepts.is_brotli() && Decoder::detect_encoding(_headers, "br") {
             return Decoder::brotli(reader);
         }
-        if _accepts.deflate && Decoder::detect_encoding(_headers, "deflate") {
+        #[cfg(feature = "deflate")]
+        if _accepts.is_deflate() && Decoder::detect_encoding(_headers, "deflate") {
             return Decoder::deflate(reader);
         }
 
@@ -209,16 +278,42 @@ pub(crate) enum ParseResponseError {
     HttpParseError(httparse::Error),
     ResponseTooLarge,
     UnknownCode,
+    LengthMismatch {
+        expected: usize,
+        received: usize,
+    },
+    Framing(String),
+    DuplicateHeader(http::HeaderName),
+    Body(std::io::Error),
+    /// A phase given a budget with `RequestBuilder::timeouts` ran out.
+    TimedOut(TimeoutDetail),
+    /// Reading the body failed partway, after `partial` was read.
+    Interrupted {
+        cause: Box<ParseResponseError>,
+        partial: PartialBody,
+    },
 }
 
+/// Reads the response to `req` from `stream`. Once the response has been
+/// read, the connection goes back to the client's pool under `pool`, unless
+/// it is `None` or the connection can't carry another request.
 pub(crate) fn parse_response(
     mut response_buffer: Vec<u8>,
-    mut stream: HttpStream,
+    mut stream: PacedStream,
     req: InnerRequest,
     client: &mut InnerClient,
+    pool: Option<HostRef>,
 ) -> ResponseResult {
     let mut buffer = [0_u8; REQUEST_BUFFER_SIZE];
     let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+    stream
+        .time_limit(
+            TimeoutPhase::FirstByte,
+            req.phase_timeout(TimeoutPhase::FirstByte),
+        )
+        .map_err(ParseResponseError::Body)?;
+    let mut parser = httparse::ParserConfig::default();
+    parser.allow_obsolete_multiline_headers_in_responses(client.obs_fold);
 
     // Loop until at least one complete response is read.
     let (response_raw, offset) = loop {
@@ -226,7 +321,7 @@ pub(crate) fn parse_response(
         // prefilled with some data, and we should attempt to parse it into a response
         // before we decide to read more from `TcpStream`.
         let mut response_raw = httparse::Response::new(&mut headers);
-        match response_raw.parse(&response_buffer) {
+        match parser.parse_response(&mut response_raw, &response_buffer) {
             Ok(state) => match state {
                 Status::Complete(offset) => {
                     // Continue outside the loop.
@@ -235,6 +330,9 @@ pub(crate) fn parse_response(
                 Status::Partial => {
                     // Read more data from TCP stream
                     let n = stream.read(&mut buffer);
+                    if let Some(detail) = n.as_ref().err().and_then(PhaseTimedOut::find) {
+                        return Err(ParseResponseError::TimedOut(detail));
+                    }
                     if n.is_err() || *n.as_ref().unwrap() == 0 {
                         if response_buffer.is_empty() {
                             return Err(ParseResponseError::TcpStreamClosedWithoutData);
@@ -270,37 +368,123 @@ pub(crate) fn parse_response(
             return Err(ParseResponseError::UnknownCode);
         }
     };
-    let response = http::Response::builder().status(status_code);
+    let version = match response_raw.version {
+        Some(0) => http::Version::HTTP_10,
+        _ => http::Version::HTTP_11,
+    };
+    let response = http::Response::builder()
+        .status(status_code)
+        .version(version);
     let response = response_raw
         .headers
         .iter()
         .fold(response, |response, header| {
-            response.header(header.name, header.value)
+            response.header(header.name, framing::unfold(header.value))
         });
 
+    let res = response.body(vec![]).unwrap();
+    let raw_headers = response_raw
+        .headers
+        .iter()
+        .map(|header| (header.name.to_string(), header.value.to_vec()))
+        .collect();
+    if !client.lenient_framing {
+        framing::check_response(&response_buffer[..offset], version, res.headers())
+            .map_err(ParseResponseError::Framing)?;
+    }
+
+    stream
+        .time_limit(TimeoutPhase::Body, req.phase_timeout(TimeoutPhase::Body))
+        .and_then(|_| {
+            let idle = req.phase_timeout(TimeoutPhase::BodyIdle);
+            stream.idle_limit(idle.or(client.body_idle_timeout))
+        })
+        .map_err(ParseResponseError::Body)?;
+    let tee = if req.tee { Some(Vec::new()) } else { None };
     let reader = HttpBodyReader {
         stream,
+        tee,
         response_buffer,
         offset,
         body_offset: offset,
-        res: response.body(vec![]).unwrap(),
+        res,
         req,
-        chunk_body: vec![],
-        chunk_offset: 0,
+        chunk_remaining: 0,
+        chunks_done: false,
     };
-    Ok(Decoder::detect(reader, client.accepts()).decode())
+    let mut decoder = Decoder::detect(reader, client.accepts());
+    let mut res = decoder.decode()?;
+    if let Some(host) = pool {
+        if decoder.reader.is_reusable() {
+            if let Some(stream) = decoder.reader.stream.into_inner() {
+                client.release(host, stream);
+            }
+        }
+    }
+    res.raw_headers = raw_headers;
+    client
+        .header_policy
+        .apply(&mut res.headers)
+        .map_err(ParseResponseError::DuplicateHeader)?;
+    Ok(res)
+}
+
+/// Feeds what is read from `inner` to a hasher, see
+/// `RequestBuilder::hash_body`.
+struct Hashing<'a, R> {
+    inner: R,
+    hasher: Option<&'a mut BodyHasher>,
+}
+
+impl<'a, R: Read> Hashing<'a, R> {
+    fn new(inner: R, hasher: Option<&'a mut BodyHasher>) -> Hashing<'a, R> {
+        Hashing { inner, hasher }
+    }
+}
+
+impl<R: Read> Read for Hashing<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// A chunked body that can't be delimited, which is reported as a framing
+/// error: reading on could take the start of another message for its end.
+#[derive(Debug)]
+enum InvalidChunk {
+    Size,
+    MissingCrlf,
 }
 
+impl fmt::Display for InvalidChunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidChunk::Size => f.write_str("invalid chunk size"),
+            InvalidChunk::MissingCrlf => f.write_str("chunk data not followed by CRLF"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidChunk {}
+
 pub struct HttpBodyReader {
-    pub(crate) stream: HttpStream,
+    pub(crate) stream: PacedStream,
     // used to check headers, but has no body yet
     pub(crate) res: http::Response<Vec<u8>>,
     pub(crate) response_buffer: Vec<u8>,
     pub(crate) offset: usize,
     pub(crate) req: InnerRequest,
     pub(crate) body_offset: usize,
-    pub(crate) chunk_body: Vec<u8>,
-    pub(crate) chunk_offset: usize, // pub(crate) client: &'a mut Client,
+    // bytes of the current chunk that haven't been read yet
+    pub(crate) chunk_remaining: usize,
+    // the last, empty chunk has been read
+    pub(crate) chunks_done: bool,
+    // copy of the body as read, before decompression, see `RequestBuilder::tee`
+    pub(crate) tee: Option<Vec<u8>>,
 }
 
 impl HttpBodyReader {
@@ -308,10 +492,8 @@ impl HttpBodyReader {
         self.res
             .headers()
             .get(http::header::CONTENT_LENGTH)
-            .map(|header| {
-                let value_string = std::str::from_utf8(header.as_bytes()).unwrap();
-                value_string.parse::<usize>().unwrap()
-            })
+            .and_then(framing::parse_length)
+            .map(|len| len as usize)
     }
 
     pub fn transfer_encoding(&self) -> Vec<String> {
@@ -327,10 +509,20 @@ impl HttpBodyReader {
             .collect()
     }
 
+    /// HTTP/1.0 has no chunked encoding, so its responses are delimited by
+    /// `Content-Length` or by closing the connection.
     pub fn is_chunked(&self) -> bool {
-        self.transfer_encoding()
-            .iter()
-            .any(|e| e.as_str() == "chunked")
+        self.res.version() != http::Version::HTTP_10
+            && self
+                .transfer_encoding()
+                .iter()
+                .any(|e| e.as_str() == "chunked")
+    }
+
+    /// The number of body bytes the server has sent, including any past the
+    /// end of the body.
+    fn received(&self) -> usize {
+        self.response_buffer.len() - self.body_offset
     }
 
     pub fn no_content_length_required(&self) -> bool {
@@ -343,6 +535,28 @@ impl HttpBodyReader {
             || (100..200).contains(&status_num)
     }
 
+    /// Whether, with the body read, the connection can carry another
+    /// request: the server keeps HTTP/1.1 connections open unless it says
+    /// otherwise, and the body has to have ended where its framing says,
+    /// with nothing after it.
+    fn is_reusable(&self) -> bool {
+        let status = self.res.status();
+        if self.res.version() == http::Version::HTTP_10
+            || status.is_informational()
+            || framing::closes_connection(self.res.headers())
+        {
+            return false;
+        }
+        let complete = if self.no_content_length_required() {
+            true
+        } else if self.is_chunked() {
+            self.chunks_done
+        } else {
+            self.content_length() == Some(self.offset - self.body_offset)
+        };
+        complete && self.offset == self.response_buffer.len()
+    }
+
     // simply load a bit more data from the underlying stream
     // because the parser is probably missing some data from the buffer
     fn load_more(&mut self) -> std::io::Result<usize> {
@@ -380,40 +594,52 @@ impl HttpBodyReader {
         Ok(len_read)
     }
 
-    fn read_chunk(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        lunatic_log::debug!(
-            "Reading from chunked body: chunk_offset {} | len {}",
-            self.chunk_offset,
-            self.chunk_body.len(),
-        );
-        if self.chunk_offset >= self.chunk_body.len() {
-            // start reading from tcp stream
-            return Ok(0);
-        }
-        let mut len_read = 0;
-        for (idx, byte) in self.chunk_body[self.chunk_offset..].iter().enumerate() {
-            if idx >= buf.len() {
-                break;
+    /// Loads more data until at least `n` bytes past `offset` are in the
+    /// response buffer.
+    fn fill(&mut self, n: usize) -> std::io::Result<()> {
+        while self.response_buffer.len() - self.offset < n {
+            if self.load_more()? == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "decoder::HttpBodyReader::read stream closed in chunk",
+                ));
             }
-            len_read += 1;
-            buf[idx] = *byte;
         }
-        self.chunk_offset += len_read;
-        Ok(len_read)
+        Ok(())
     }
 
-    fn skip_clrf(&mut self) -> std::io::Result<()> {
-        // if the clrf of the chunk is not yet in the response
-        // buffer we need to load the data first
-        if self.response_buffer.len() - self.offset < 2 {
-            let mut clrf = [0u8; REQUEST_BUFFER_SIZE];
-            let len = self.stream.read(&mut clrf)?;
-            self.response_buffer.extend(clrf[..len].to_vec());
+    /// Skips the CRLF that ends the data of a chunk.
+    fn skip_crlf(&mut self) -> std::io::Result<()> {
+        self.fill(2)?;
+        if &self.response_buffer[self.offset..self.offset + 2] != b"\r\n" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                InvalidChunk::MissingCrlf,
+            ));
         }
-        // in any case we need to "skip" the clrf tokens at the end of the chunk
         self.offset += 2;
         Ok(())
     }
+
+    /// Skips the trailer fields after the last chunk, up to and including
+    /// the empty line that ends the body.
+    fn skip_trailers(&mut self) -> std::io::Result<()> {
+        loop {
+            self.fill(2)?;
+            let line = &self.response_buffer[self.offset..];
+            match line.windows(2).position(|crlf| crlf == b"\r\n") {
+                Some(0) => {
+                    self.offset += 2;
+                    return Ok(());
+                }
+                Some(end) => self.offset += end + 2,
+                None => {
+                    let needed = line.len() + 1;
+                    self.fill(needed)?;
+                }
+            }
+        }
+    }
 }
 
 impl Read for HttpBodyReader {
@@ -435,72 +661,85 @@ impl Read for HttpBodyReader {
     // }
 
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.read_body(buf)?;
+        if let Some(ref mut tee) = self.tee {
+            tee.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl HttpBodyReader {
+    fn read_body(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         if self.is_chunked() {
-            // if we're done with reading the chunked data
-            // we just read from the `self.chunk_body` buffer
-            if !self.chunk_body.is_empty() {
-                return self.read_chunk(buf);
+            return self.read_chunked(buf);
+        }
+
+        if let Some(len) = self.content_length() {
+            let read = self.offset - self.body_offset;
+            if read >= len {
+                return Ok(0);
             }
-            // if data is transfered as chunked we want to consume it into `self.response_buffer()`
-            // this means that we need to pre-read the data because
-            loop {
-                let chunk = httparse::parse_chunk_size(&self.response_buffer[self.offset..]);
-                match chunk {
-                    // idx is the offset at which the content begins
-                    // so there's the size as well as CRLF
-                    // this means that the next chunk of size `size` starts from `idx`
-                    Ok(Status::Complete((idx, size))) => {
-                        // first, we need to consume the size and CRLF bytes
-                        if size == 0
-                            && self.response_buffer[self.offset + idx..].starts_with(b"\r\n")
-                        {
-                            // break because the reading has been completed and
-                            // the whole response body has been written into `self.chunk_body`
-                            self.skip_clrf()?;
-                            return self.read_chunk(buf);
-                        }
+            // don't read into a pipelined response
+            let len = buf.len().min(len - read);
+            return self.inner_read(&mut buf[..len]);
+        }
+        self.inner_read(buf)
+    }
 
-                        self.offset += idx;
-
-                        // now that we skipped the prefix we can consume the rest from
-                        // either the `response_buffer` or the `stream` in case the response_buffer
-                        // does not yet have all the data
-                        let mut read_from_chunk = 0;
-
-                        // not done reading current chunk from tcp/tls stream
-                        //need to finish reading by moving data from response_buffer
-                        // into `self.chunk_body`
-                        while read_from_chunk < size as usize {
-                            let mut chunk = vec![0u8; size as usize - read_from_chunk];
-                            let read_chunk_size = self.inner_read(&mut chunk)?;
-                            read_from_chunk += read_chunk_size;
-                            self.chunk_body.extend(chunk[..read_chunk_size].to_vec());
-                        }
-                        // skip CRLF tokens
-                        self.skip_clrf()?;
-                    }
-                    // partial in this context means that the chunk header
-                    // was not fully read, meaning that we need to attempt to read
-                    // from the tcp/tls stream in order to get the rest of the chunk header
-                    Ok(Status::Partial) => {
-                        let _size = self.load_more()?;
+    /// Reads the data of one chunk at a time, so the decompressor gets each
+    /// chunk as soon as it has arrived instead of after the whole body.
+    fn read_chunked(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.chunks_done {
+                return Ok(0);
+            }
+            if self.chunk_remaining > 0 {
+                let len = buf.len().min(self.chunk_remaining);
+                let read = self.inner_read(&mut buf[..len])?;
+                if read == 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "decoder::HttpBodyReader::read chunk ended early",
+                    ));
+                }
+                self.chunk_remaining -= read;
+                if self.chunk_remaining == 0 {
+                    self.skip_crlf()?;
+                }
+                return Ok(read);
+            }
+            // idx is the offset at which the content begins
+            // so there's the size as well as CRLF
+            match httparse::parse_chunk_size(&self.response_buffer[self.offset..]) {
+                Ok(Status::Complete((idx, size))) => {
+                    self.offset += idx;
+                    if size == 0 {
+                        self.skip_trailers()?;
+                        self.chunks_done = true;
+                    } else {
+                        self.chunk_remaining = size as usize;
                     }
-                    Err(_) => {
+                }
+                // partial in this context means that the chunk header
+                // was not fully read, meaning that we need to attempt to read
+                // from the tcp/tls stream in order to get the rest of the chunk header
+                Ok(Status::Partial) => {
+                    if self.load_more()? == 0 {
                         return Err(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            "decoder::HttpBodyReader::read InvalidChunkSize",
+                            std::io::ErrorKind::UnexpectedEof,
+                            "decoder::HttpBodyReader::read stream closed in chunk size",
                         ));
                     }
                 }
+                Err(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        InvalidChunk::Size,
+                    ));
+                }
             }
         }
-
-        if let Some(len) = self.content_length() {
-            if self.offset - self.body_offset >= len {
-                return Ok(0);
-            }
-        }
-        self.inner_read(buf)
     }
 }
 
@@ -533,11 +772,11 @@ impl Accepts {
     }
 
     fn is_brotli(&self) -> bool {
-        self.brotli
+        self.brotli && cfg!(feature = "brotli")
     }
 
     fn is_deflate(&self) -> bool {
-        self.deflate
+        self.deflate && cfg!(feature = "deflate")
     }
 }
 
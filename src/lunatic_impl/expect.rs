@@ -0,0 +1,117 @@
+//! `Expect: 100-continue`, which lets a server turn down a request before
+//! its body is sent.
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use http::header::{HeaderMap, EXPECT};
+use httparse::{Status, EMPTY_HEADER};
+
+use super::http_stream::PacedStream;
+
+/// How long to wait for the server to answer the head of a request before
+/// sending the body anyway, as not every server supports the expectation.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) enum Continue {
+    /// Send the body. Holds whatever the server sent after its
+    /// `100 Continue`.
+    Proceed(Vec<u8>),
+    /// The server answered without waiting for the body. Holds the response
+    /// as received so far.
+    Final(Vec<u8>),
+}
+
+/// Returns true if the request asks the server to confirm it wants the body.
+pub(crate) fn wants_continue(headers: &HeaderMap) -> bool {
+    headers
+        .get(EXPECT)
+        .map(|value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+        .unwrap_or(false)
+}
+
+/// Waits for the server's answer to the head of a request.
+pub(crate) fn await_continue(stream: &mut PacedStream) -> io::Result<Continue> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let answer = loop {
+        if let Some(answer) = interim(&mut buffer) {
+            break Ok(answer);
+        }
+        match stream.read(&mut chunk) {
+            // let the response parser report the closed connection
+            Ok(0) => break Ok(Continue::Final(buffer)),
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break Ok(Continue::Proceed(buffer))
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    stream.set_read_timeout(None)?;
+    answer
+}
+
+/// Consumes interim responses at the start of `buffer`, and decides once
+/// the server has said enough.
+fn interim(buffer: &mut Vec<u8>) -> Option<Continue> {
+    loop {
+        let mut headers = [EMPTY_HEADER; 64];
+        let mut response = httparse::Response::new(&mut headers);
+        match response.parse(buffer) {
+            Ok(Status::Complete(offset)) => match response.code {
+                Some(100) => {
+                    buffer.drain(..offset);
+                    return Some(Continue::Proceed(std::mem::take(buffer)));
+                }
+                // other interim responses, such as 103 Early Hints
+                Some(code) if code < 200 => {
+                    buffer.drain(..offset);
+                }
+                _ => return Some(Continue::Final(std::mem::take(buffer))),
+            },
+            Ok(Status::Partial) => return None,
+            Err(_) => return Some(Continue::Final(std::mem::take(buffer))),
+        }
+    }
+}
+
+/// The status code of the response at the start of `buffer`, if its head is
+/// complete.
+pub(crate) fn status(buffer: &[u8]) -> Option<u16> {
+    let mut headers = [EMPTY_HEADER; 64];
+    let mut response = httparse::Response::new(&mut headers);
+    match response.parse(buffer) {
+        Ok(Status::Complete(_)) => response.code,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn reads_interim_responses() {
+        let mut buffer = b"HTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\nHTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 2".to_vec();
+        match interim(&mut buffer) {
+            Some(Continue::Proceed(rest)) => assert_eq!(rest, b"HTTP/1.1 2"),
+            _ => panic!("expected to proceed"),
+        }
+
+        let mut buffer = b"HTTP/1.1 100 Cont".to_vec();
+        assert!(interim(&mut buffer).is_none());
+
+        let mut buffer = b"HTTP/1.1 417 Expectation Failed\r\n\r\n".to_vec();
+        match interim(&mut buffer) {
+            Some(Continue::Final(response)) => assert_eq!(status(&response), Some(417)),
+            _ => panic!("expected a final response"),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Checks on how messages delimit their bodies.
+//!
+//! When a client and the servers or proxies in front of it disagree on where
+//! a body ends, an attacker can smuggle a second message inside the first.
+//! Unless `ClientBuilder::lenient_framing` is set, the client refuses to send
+//! and to accept messages that could be read in more than one way.
+
+use http::header::{CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderValue, Version};
+
+/// Checks the headers of a request about to be sent with a body of
+/// `body_len` bytes. The client adds a `Content-Length` to every request
+/// with a body.
+pub(crate) fn check_request(headers: &HeaderMap, body_len: Option<usize>) -> Result<(), String> {
+    let has_length = body_len.is_some() || headers.contains_key(CONTENT_LENGTH);
+    if has_length && headers.contains_key(TRANSFER_ENCODING) {
+        return Err("request has both Content-Length and Transfer-Encoding".to_string());
+    }
+    if let Some(len) = body_len {
+        for value in headers.get_all(CONTENT_LENGTH) {
+            if parse_length(value) != Some(len as u64) {
+                return Err(format!(
+                    "request Content-Length doesn't match its {} byte body",
+                    len
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the head of a response, as received and as parsed.
+pub(crate) fn check_response(
+    head: &[u8],
+    version: Version,
+    headers: &HeaderMap,
+) -> Result<(), String> {
+    for (i, byte) in head.iter().enumerate() {
+        let bare = match byte {
+            b'\r' => head.get(i + 1) != Some(&b'\n'),
+            b'\n' => i == 0 || head[i - 1] != b'\r',
+            _ => false,
+        };
+        if bare {
+            return Err("response head has a bare CR or LF".to_string());
+        }
+    }
+    if headers.contains_key(CONTENT_LENGTH) && headers.contains_key(TRANSFER_ENCODING) {
+        return Err("response has both Content-Length and Transfer-Encoding".to_string());
+    }
+    // HTTP/1.0 has no Transfer-Encoding, so a proxy may have let it through
+    if version == Version::HTTP_10 && headers.contains_key(TRANSFER_ENCODING) {
+        return Err("HTTP/1.0 response has Transfer-Encoding".to_string());
+    }
+    let mut length = None;
+    for value in headers.get_all(CONTENT_LENGTH) {
+        match (parse_length(value), length) {
+            (None, _) => return Err("response has an invalid Content-Length".to_string()),
+            (Some(value), Some(length)) if value != length => {
+                return Err("response has conflicting Content-Lengths".to_string())
+            }
+            (value, _) => length = value,
+        }
+    }
+    Ok(())
+}
+
+/// Joins the lines of a header value folded over several lines, which is
+/// obsolete but still sent by some devices, with single spaces.
+pub(crate) fn unfold(value: &[u8]) -> Vec<u8> {
+    let mut unfolded = Vec::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.iter().position(|&b| b == b'\r' || b == b'\n') {
+        unfolded.extend_from_slice(&rest[..at]);
+        while unfolded
+            .last()
+            .map(|b| *b == b' ' || *b == b'\t')
+            .unwrap_or(false)
+        {
+            unfolded.pop();
+        }
+        unfolded.push(b' ');
+        let skip = rest[at..]
+            .iter()
+            .position(|&b| !matches!(b, b'\r' | b'\n' | b' ' | b'\t'))
+            .unwrap_or(rest.len() - at);
+        rest = &rest[at + skip..];
+    }
+    unfolded.extend_from_slice(rest);
+    unfolded
+}
+
+/// Whether a `Connection: close` header asks to close the connection after
+/// this message.
+pub(crate) fn closes_connection(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|option| option.trim().eq_ignore_ascii_case("close"))
+}
+
+/// Parses a `Content-Length`, which is nothing but digits.
+pub(crate) fn parse_length(value: &HeaderValue) -> Option<u64> {
+    let value = value.to_str().ok()?.trim();
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn header_map(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[lunatic::test]
+    fn rejects_ambiguous_requests() {
+        assert!(check_request(&header_map(&[]), Some(3)).is_ok());
+        assert!(check_request(&header_map(&[("content-length", "3")]), Some(3)).is_ok());
+        assert!(check_request(&header_map(&[("content-length", "4")]), Some(3)).is_err());
+        assert!(check_request(&header_map(&[("transfer-encoding", "chunked")]), Some(3)).is_err());
+        assert!(check_request(&header_map(&[("transfer-encoding", "chunked")]), None).is_ok());
+    }
+
+    #[lunatic::test]
+    fn connection_close() {
+        assert!(closes_connection(&header_map(&[("connection", "close")])));
+        assert!(closes_connection(&header_map(&[(
+            "connection",
+            "Upgrade, Close"
+        )])));
+        assert!(!closes_connection(&header_map(&[(
+            "connection",
+            "keep-alive"
+        )])));
+        assert!(!closes_connection(&header_map(&[])));
+    }
+
+    #[lunatic::test]
+    fn rejects_ambiguous_responses() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n";
+        let length = header_map(&[("content-length", "3")]);
+        assert!(check_response(head, Version::HTTP_11, &length).is_ok());
+
+        let bare_lf = b"HTTP/1.1 200 OK\nContent-Length: 3\r\n\r\n";
+        assert!(check_response(bare_lf, Version::HTTP_11, &length).is_err());
+
+        let both = header_map(&[("content-length", "3"), ("transfer-encoding", "chunked")]);
+        assert!(check_response(head, Version::HTTP_11, &both).is_err());
+
+        let twice = header_map(&[("content-length", "3"), ("content-length", "3")]);
+        assert!(check_response(head, Version::HTTP_11, &twice).is_ok());
+        let conflicting = header_map(&[("content-length", "3"), ("content-length", "4")]);
+        assert!(check_response(head, Version::HTTP_11, &conflicting).is_err());
+        let invalid = header_map(&[("content-length", "+3")]);
+        assert!(check_response(head, Version::HTTP_11, &invalid).is_err());
+
+        let chunked = header_map(&[("transfer-encoding", "chunked")]);
+        assert!(check_response(head, Version::HTTP_11, &chunked).is_ok());
+        assert!(check_response(head, Version::HTTP_10, &chunked).is_err());
+    }
+
+    #[lunatic::test]
+    fn unfolds_obsolete_line_folding() {
+        assert_eq!(unfold(b"text/plain"), b"text/plain");
+        assert_eq!(unfold(b"a,\r\n  b,\r\n\tc"), b"a, b, c");
+        assert_eq!(unfold(b"a \r\n b"), b"a b");
+    }
+}
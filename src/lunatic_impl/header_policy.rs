@@ -0,0 +1,138 @@
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+use super::client::DuplicateHeaders;
+
+/// Headers that may only appear once in a response.
+const SINGLETONS: &[HeaderName] = &[
+    header::AGE,
+    header::CONTENT_LENGTH,
+    header::CONTENT_LOCATION,
+    header::CONTENT_RANGE,
+    header::CONTENT_TYPE,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+    header::LOCATION,
+    header::RETRY_AFTER,
+];
+
+/// How the headers of a response are exposed when some are repeated.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HeaderPolicy {
+    pub(crate) duplicates: DuplicateHeaders,
+    pub(crate) join: bool,
+}
+
+impl HeaderPolicy {
+    /// Settles repeated singleton headers and, if asked to, joins the values
+    /// of every other repeated header into one.
+    ///
+    /// Returns the name of a singleton header that was repeated with
+    /// different values under `DuplicateHeaders::Error`.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) -> Result<(), HeaderName> {
+        for name in SINGLETONS {
+            let values: Vec<HeaderValue> = headers.get_all(name).iter().cloned().collect();
+            if values.len() < 2 {
+                continue;
+            }
+            let keep = if values.iter().all(|value| *value == values[0]) {
+                values[0].clone()
+            } else {
+                match self.duplicates {
+                    DuplicateHeaders::Error => return Err(name.clone()),
+                    DuplicateHeaders::FirstWins => values[0].clone(),
+                    DuplicateHeaders::LastWins => values[values.len() - 1].clone(),
+                }
+            };
+            headers.insert(name, keep);
+        }
+
+        if self.join {
+            let names: Vec<HeaderName> = headers.keys().cloned().collect();
+            for name in names {
+                // cookies may contain commas, so they can't be joined
+                if name == header::SET_COOKIE {
+                    continue;
+                }
+                let values: Vec<&[u8]> = headers
+                    .get_all(&name)
+                    .iter()
+                    .map(|v| v.as_bytes())
+                    .collect();
+                if values.len() < 2 {
+                    continue;
+                }
+                let joined = HeaderValue::from_bytes(&values.join(&b", "[..]))
+                    .expect("joined header values are valid");
+                headers.insert(name, joined);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_map(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[lunatic::test]
+    fn settles_singletons() {
+        let repeated = header_map(&[
+            ("content-type", "text/html"),
+            ("content-type", "text/plain"),
+        ]);
+        let policy = |duplicates| HeaderPolicy {
+            duplicates,
+            join: false,
+        };
+
+        let mut headers = repeated.clone();
+        policy(DuplicateHeaders::FirstWins)
+            .apply(&mut headers)
+            .unwrap();
+        assert_eq!(headers.get_all("content-type").iter().count(), 1);
+        assert_eq!(headers["content-type"], "text/html");
+
+        let mut headers = repeated.clone();
+        policy(DuplicateHeaders::LastWins)
+            .apply(&mut headers)
+            .unwrap();
+        assert_eq!(headers["content-type"], "text/plain");
+
+        let mut headers = repeated;
+        let err = policy(DuplicateHeaders::Error).apply(&mut headers);
+        assert_eq!(err, Err(header::CONTENT_TYPE));
+
+        // the same value twice isn't a conflict
+        let mut headers = header_map(&[("content-length", "3"), ("content-length", "3")]);
+        policy(DuplicateHeaders::Error).apply(&mut headers).unwrap();
+        assert_eq!(headers.get_all("content-length").iter().count(), 1);
+    }
+
+    #[lunatic::test]
+    fn joins_lists() {
+        let mut headers = header_map(&[
+            ("vary", "accept"),
+            ("vary", "accept-encoding"),
+            ("set-cookie", "a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("set-cookie", "b=2"),
+        ]);
+        let policy = HeaderPolicy {
+            duplicates: DuplicateHeaders::FirstWins,
+            join: true,
+        };
+        policy.apply(&mut headers).unwrap();
+        assert_eq!(headers.get_all("vary").iter().count(), 1);
+        assert_eq!(headers["vary"], "accept, accept-encoding");
+        assert_eq!(headers.get_all("set-cookie").iter().count(), 2);
+    }
+}
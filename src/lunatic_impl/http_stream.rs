@@ -1,10 +1,14 @@
+use std::fmt;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
 
 use lunatic::net::{TcpStream, TlsStream};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::error::Kind;
+use super::request::InnerRequest;
+use crate::{TimeSource, TimeoutDetail, TimeoutPhase};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum HttpStream {
@@ -12,35 +16,199 @@ pub enum HttpStream {
     Tls(TlsStream),
 }
 
+/// Settings applied whenever a new connection is opened.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Connector {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) tls_handshake_timeout: Option<Duration>,
+    pub(crate) max_download_rate: Option<u64>,
+    pub(crate) max_upload_rate: Option<u64>,
+    /// PEM encoded certificates trusted on top of the host's, see
+    /// `ClientBuilder::add_root_certificate`.
+    pub(crate) root_certs: Vec<String>,
+}
+
+/// Connects to the first of `addrs` that accepts, or fails like the last.
+fn connect_any(addrs: &[SocketAddr], timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    let mut failed = None;
+    for addr in addrs {
+        let stream = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(*addr, timeout),
+            None => TcpStream::connect(*addr),
+        };
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(e) => failed = Some(e),
+        }
+    }
+    Err(failed.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to connect to")
+    }))
+}
+
+/// The lunatic host reads a timeout of `u64::MAX` milliseconds as none.
+const NO_TIMEOUT: Duration = Duration::from_millis(u64::MAX);
+
+impl Connector {
+    /// Wraps `stream` so that a single request/response exchange stays
+    /// within the configured transfer rates.
+    pub(crate) fn pace(&self, stream: HttpStream, time: TimeSource) -> PacedStream {
+        PacedStream {
+            stream,
+            download: self
+                .max_download_rate
+                .map(|rate| Pacer::new(rate, time.clone())),
+            upload: self.max_upload_rate.map(|rate| Pacer::new(rate, time)),
+            deadline: None,
+            idle: None,
+        }
+    }
+
+    /// This connector with the connect and TLS handshake budgets of `req`,
+    /// see `RequestBuilder::timeouts`, and its root certificates.
+    pub(crate) fn for_request(&self, req: &InnerRequest) -> Connector {
+        let mut connector = self.clone();
+        if let Some(timeout) = req.phase_timeout(TimeoutPhase::Connect) {
+            connector.connect_timeout = Some(timeout);
+        }
+        if let Some(timeout) = req.phase_timeout(TimeoutPhase::TlsHandshake) {
+            connector.tls_handshake_timeout = Some(timeout);
+        }
+        connector
+            .root_certs
+            .extend(req.root_certs.iter().map(|cert| cert.pem().to_string()));
+        connector
+    }
+
+    /// Time allowed for opening a TLS connection.
+    ///
+    /// The lunatic host connects the TCP socket and performs the TLS handshake
+    /// in a single call, so both budgets are added up.
+    fn tls_timeout(&self) -> Option<Duration> {
+        match (self.connect_timeout, self.tls_handshake_timeout) {
+            (Some(connect), Some(handshake)) => Some(connect + handshake),
+            (connect, handshake) => connect.or(handshake),
+        }
+    }
+}
+
 impl HttpStream {
-    pub fn connect(url: Url) -> crate::Result<HttpStream> {
+    /// Opens a connection to the server of `url`. A plain TCP connection is
+    /// made to one of `addrs` if there are any, instead of resolving the
+    /// host's name. The lunatic host only opens TLS connections by name.
+    pub(crate) fn connect(
+        url: Url,
+        addrs: &[SocketAddr],
+        connector: &Connector,
+    ) -> crate::Result<HttpStream> {
         let protocol = url.scheme();
+        let started = Instant::now();
         if protocol == "https" {
             let conn_str = format!("{}", url.host().unwrap());
-            return match TlsStream::connect(&conn_str, url.port().unwrap_or(443).into()) {
+            let port = url.port().unwrap_or(443).into();
+            let timeout = connector.tls_timeout();
+            // only `connect_timeout` takes extra root certificates
+            let stream = match timeout {
+                Some(timeout) => TlsStream::connect_timeout(
+                    &conn_str,
+                    timeout,
+                    port,
+                    connector.root_certs.clone(),
+                ),
+                None if !connector.root_certs.is_empty() => TlsStream::connect_timeout(
+                    &conn_str,
+                    NO_TIMEOUT,
+                    port,
+                    connector.root_certs.clone(),
+                ),
+                None => TlsStream::connect(&conn_str, port),
+            };
+            return match stream {
                 Ok(stream) => Ok(HttpStream::Tls(stream)),
                 Err(e) => {
                     lunatic_log::error!("Failed to connect via TLS {:?}", e);
-                    Err(crate::Error::new(
-                        Kind::Builder,
-                        Some("Failed to connect".to_string()),
-                    ))
+                    let phase = match connector.tls_handshake_timeout {
+                        Some(_) => TimeoutPhase::TlsHandshake,
+                        None => TimeoutPhase::Connect,
+                    };
+                    Err(connect_error(e, url, phase, started, timeout))
                 }
             };
         }
         let conn_str = format!("{}:{}", url.host().unwrap(), url.port().unwrap_or(80));
         lunatic_log::debug!("Connecting {:?} | {:?}", protocol, conn_str);
-        match TcpStream::connect(conn_str) {
+        let stream = if addrs.is_empty() {
+            match connector.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(conn_str, timeout),
+                None => TcpStream::connect(conn_str),
+            }
+        } else {
+            connect_any(addrs, connector.connect_timeout)
+        };
+        match stream {
             Ok(stream) => Ok(HttpStream::Tcp(stream)),
             Err(e) => {
                 lunatic_log::error!("Failed to connect via TCP {:?}", e);
-                Err(crate::Error::new(
-                    Kind::Builder,
-                    Some("Failed to connect".to_string()),
+                Err(connect_error(
+                    e,
+                    url,
+                    TimeoutPhase::Connect,
+                    started,
+                    connector.connect_timeout,
                 ))
             }
         }
     }
+
+    pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            HttpStream::Tcp(stream) => stream.set_read_timeout(timeout),
+            HttpStream::Tls(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Whether an idle connection was closed by the server, or has bytes
+    /// waiting that no request asked for, and can't be reused.
+    ///
+    /// Only plain TCP connections can be peeked at, TLS ones are assumed to
+    /// be alive.
+    pub(crate) fn is_dead(&mut self) -> bool {
+        match self {
+            HttpStream::Tcp(stream) => {
+                let mut buf = [0; 1];
+                if stream.set_peek_timeout(Some(PEEK_TIMEOUT)).is_err() {
+                    return false;
+                }
+                match stream.peek(&mut buf) {
+                    Ok(_) => true,
+                    Err(e) => !matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ),
+                }
+            }
+            HttpStream::Tls(_) => false,
+        }
+    }
+}
+
+/// How long to wait for an idle connection to show it was closed.
+const PEEK_TIMEOUT: Duration = Duration::from_millis(1);
+
+fn connect_error(
+    e: std::io::Error,
+    url: Url,
+    phase: TimeoutPhase,
+    started: Instant,
+    allowed: Option<Duration>,
+) -> crate::Error {
+    if e.kind() == std::io::ErrorKind::TimedOut {
+        return match allowed {
+            Some(allowed) => crate::error::timeout_in(phase, started.elapsed(), allowed, url),
+            None => crate::error::timeout(url),
+        };
+    }
+    crate::error::connect(e, url)
 }
 
 impl Read for HttpStream {
@@ -67,3 +235,267 @@ impl Write for HttpStream {
         }
     }
 }
+
+/// An `HttpStream` with optional caps on its read and write throughput, and
+/// an optional deadline and idle timeout for its reads.
+#[derive(Debug)]
+pub(crate) struct PacedStream {
+    stream: HttpStream,
+    download: Option<Pacer>,
+    upload: Option<Pacer>,
+    deadline: Option<Deadline>,
+    idle: Option<Duration>,
+}
+
+/// A phase of a request that reads must finish within.
+#[derive(Clone, Copy, Debug)]
+struct Deadline {
+    phase: TimeoutPhase,
+    started: Instant,
+    allowed: Duration,
+}
+
+/// The error reads fail with once their `Deadline` has passed.
+#[derive(Debug)]
+pub(crate) struct PhaseTimedOut(pub(crate) TimeoutDetail);
+
+impl fmt::Display for PhaseTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out", self.0.phase)
+    }
+}
+
+impl std::error::Error for PhaseTimedOut {}
+
+impl PhaseTimedOut {
+    /// The timeout behind `e`, if it is one.
+    pub(crate) fn find(e: &std::io::Error) -> Option<TimeoutDetail> {
+        e.get_ref()
+            .and_then(|e| e.downcast_ref::<PhaseTimedOut>())
+            .map(|e| e.0.clone())
+    }
+}
+
+impl PacedStream {
+    /// The connection, to be used for another request, without the read
+    /// timeout the limits of this one left on it. `None` if it can't be
+    /// lifted.
+    pub(crate) fn into_inner(mut self) -> Option<HttpStream> {
+        self.stream.set_read_timeout(None).ok()?;
+        Some(self.stream)
+    }
+
+    pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Fails reads that don't finish within `allowed` from now, as having
+    /// timed out in `phase`. `None` lifts the deadline.
+    pub(crate) fn time_limit(
+        &mut self,
+        phase: TimeoutPhase,
+        allowed: Option<Duration>,
+    ) -> std::io::Result<()> {
+        if allowed.is_none() && self.deadline.is_some() {
+            self.stream.set_read_timeout(None)?;
+        }
+        self.deadline = allowed.map(|allowed| Deadline {
+            phase,
+            started: Instant::now(),
+            allowed,
+        });
+        Ok(())
+    }
+
+    /// Fails reads that wait longer than `allowed` for data, as having timed
+    /// out in `TimeoutPhase::BodyIdle`. `None` lifts the limit.
+    pub(crate) fn idle_limit(&mut self, allowed: Option<Duration>) -> std::io::Result<()> {
+        if allowed.is_none() && self.idle.is_some() && self.deadline.is_none() {
+            self.stream.set_read_timeout(None)?;
+        }
+        self.idle = allowed;
+        Ok(())
+    }
+
+    fn read_paced(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.download {
+            Some(ref mut pacer) => {
+                let len = buf.len().min(pacer.chunk_size());
+                let n = self.stream.read(&mut buf[..len])?;
+                pacer.pace(n);
+                Ok(n)
+            }
+            None => self.stream.read(buf),
+        }
+    }
+}
+
+/// The limit the next read has to finish within, and the time it has left:
+/// what is left of `deadline`, or `idle` if that is shorter.
+fn read_limit(deadline: Option<Deadline>, idle: Option<Duration>) -> Option<(Deadline, Duration)> {
+    let deadline = deadline.map(|deadline| {
+        let left = deadline.allowed.saturating_sub(deadline.started.elapsed());
+        (deadline, left)
+    });
+    let idle = idle.map(|allowed| {
+        let idle = Deadline {
+            phase: TimeoutPhase::BodyIdle,
+            started: Instant::now(),
+            allowed,
+        };
+        (idle, allowed)
+    });
+    match (deadline, idle) {
+        (Some(deadline), Some(idle)) if idle.1 < deadline.1 => Some(idle),
+        (Some(deadline), _) => Some(deadline),
+        (None, idle) => idle,
+    }
+}
+
+impl Read for PacedStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (deadline, left) = match read_limit(self.deadline, self.idle) {
+            Some(limit) => limit,
+            None => return self.read_paced(buf),
+        };
+        let timed_out = || {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                PhaseTimedOut(TimeoutDetail {
+                    phase: deadline.phase,
+                    elapsed: deadline.started.elapsed(),
+                    allowed: deadline.allowed,
+                }),
+            )
+        };
+        if left.is_zero() {
+            return Err(timed_out());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        match self.read_paced(buf) {
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Err(timed_out())
+            }
+            res => res,
+        }
+    }
+}
+
+impl Write for PacedStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.upload {
+            Some(ref mut pacer) => {
+                let len = buf.len().min(pacer.chunk_size());
+                let n = self.stream.write(&buf[..len])?;
+                pacer.pace(n);
+                Ok(n)
+            }
+            None => self.stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Sleeps as needed to keep a transfer at or under `bytes_per_sec`.
+#[derive(Debug)]
+struct Pacer {
+    bytes_per_sec: u64,
+    time: TimeSource,
+    started: SystemTime,
+    transferred: u64,
+}
+
+impl Pacer {
+    fn new(bytes_per_sec: u64, time: TimeSource) -> Pacer {
+        Pacer {
+            bytes_per_sec: bytes_per_sec.max(1),
+            started: time.now(),
+            time,
+            transferred: 0,
+        }
+    }
+
+    /// Transfer at most a tenth of a second's worth of bytes at once, so the
+    /// rate stays smooth instead of bursting.
+    fn chunk_size(&self) -> usize {
+        (self.bytes_per_sec / 10).clamp(1, 64 * 1024) as usize
+    }
+
+    fn pace(&mut self, n: usize) {
+        self.transferred += n as u64;
+        let due = Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.time.since(self.started);
+        if due > elapsed {
+            self.time.sleep(due - elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+
+    #[lunatic::test]
+    fn pacer_sleeps_to_keep_the_rate() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut pacer = Pacer::new(1000, time.clone());
+        assert_eq!(pacer.chunk_size(), 100);
+
+        pacer.pace(100);
+        assert_eq!(time.since(pacer.started), Duration::from_millis(100));
+
+        // only the time not spent yet is waited for
+        pacer.pace(400);
+        assert_eq!(time.since(pacer.started), Duration::from_millis(500));
+    }
+
+    #[lunatic::test]
+    fn reads_are_limited_by_the_tighter_timeout() {
+        let deadline = Deadline {
+            phase: TimeoutPhase::Body,
+            started: Instant::now(),
+            allowed: Duration::from_secs(60),
+        };
+        assert!(read_limit(None, None).is_none());
+
+        let (limit, left) = read_limit(Some(deadline), Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(limit.phase, TimeoutPhase::BodyIdle);
+        assert_eq!(left, Duration::from_secs(5));
+
+        let (limit, left) = read_limit(Some(deadline), Some(Duration::from_secs(90))).unwrap();
+        assert_eq!(limit.phase, TimeoutPhase::Body);
+        assert!(left <= Duration::from_secs(60));
+
+        let (limit, _) = read_limit(None, Some(Duration::from_secs(90))).unwrap();
+        assert_eq!(limit.phase, TimeoutPhase::BodyIdle);
+    }
+
+    #[lunatic::test]
+    fn request_root_certs_add_to_the_client_ones() {
+        use std::convert::TryFrom;
+
+        let ca = crate::Certificate::from_der(&[0x30, 0x00]).unwrap();
+        let connector = Connector {
+            root_certs: vec![ca.pem().to_string()],
+            ..Connector::default()
+        };
+        let mut req = crate::Request::new(
+            crate::Method::GET,
+            Url::parse("https://internal.example.com/").unwrap(),
+        );
+        req.root_certs.push(ca.clone());
+        let req = InnerRequest::try_from(req).unwrap();
+
+        assert_eq!(connector.for_request(&req).root_certs.len(), 2);
+        assert_eq!(connector.root_certs.len(), 1);
+    }
+}
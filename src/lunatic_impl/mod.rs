@@ -1,15 +1,24 @@
 pub use self::body::Body;
 pub use self::client::{Client, ClientBuilder, InnerClient};
-pub use self::request::{Request, RequestBuilder};
-pub use self::response::{HttpResponse, SerializableResponse};
+pub use self::request::{Request, RequestBuilder, SerializableRequest};
+pub use self::response::{Chunks, HttpResponse, SerializableResponse, TextChunks};
+pub use self::schema::SCHEMA_VERSION;
 // pub use self::upgrade::Upgraded;
 
 pub mod body;
 pub mod client;
 pub mod decoder;
+mod expect;
+pub(crate) mod framing;
+pub(crate) mod header_policy;
 mod http_stream;
 // #[cfg(feature = "multipart")]
 // pub mod multipart;
+pub(crate) mod pool;
+pub(crate) mod remote;
 pub(crate) mod request;
 mod response;
+pub(crate) mod schema;
+pub(crate) mod spacing;
+mod upload;
 // mod upgrade;
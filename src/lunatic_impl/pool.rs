@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use super::client::HostRef;
+use super::http_stream::HttpStream;
+use crate::TimeSource;
+
+/// Idle keep-alive connections, per host, waiting for the next request.
+///
+/// At most `max_idle_per_host` connections are kept for each host, the
+/// longest idle ones giving way first, and none is handed out once it has
+/// been idle for longer than `idle_timeout`.
+#[derive(Clone, Debug)]
+pub(crate) struct Pool<S = HttpStream> {
+    idle_timeout: Option<Duration>,
+    max_idle_per_host: usize,
+    // per host, from the longest idle to the most recently returned
+    idle: HashMap<HostRef, Vec<(S, SystemTime)>>,
+}
+
+impl<S> Pool<S> {
+    pub(crate) fn new(idle_timeout: Option<Duration>, max_idle_per_host: usize) -> Pool<S> {
+        Pool {
+            idle_timeout,
+            max_idle_per_host,
+            idle: HashMap::new(),
+        }
+    }
+
+    /// Takes the most recently returned connection to `host` that hasn't
+    /// been idle for too long.
+    pub(crate) fn take(&mut self, host: &HostRef, time: &TimeSource) -> Option<S> {
+        let idle_timeout = self.idle_timeout;
+        let expired = |since| match idle_timeout {
+            Some(timeout) => time.since(since) > timeout,
+            None => false,
+        };
+        let idle = self.idle.get_mut(host)?;
+        let stream = match idle.pop() {
+            Some((_, since)) if expired(since) => {
+                // the ones before it have been idle for even longer
+                idle.clear();
+                None
+            }
+            Some((stream, _)) => Some(stream),
+            None => None,
+        };
+        if idle.is_empty() {
+            self.idle.remove(host);
+        }
+        stream
+    }
+
+    /// Keeps an idle connection to `host`, dropping the longest idle one if
+    /// there are too many.
+    pub(crate) fn put(&mut self, host: HostRef, stream: S, time: &TimeSource) {
+        if self.max_idle_per_host == 0 {
+            return;
+        }
+        let idle = self.idle.entry(host).or_default();
+        if idle.len() >= self.max_idle_per_host {
+            idle.remove(0);
+        }
+        idle.push((stream, time.now()));
+    }
+
+    /// Keeps only the connections for which `keep` returns true.
+    pub(crate) fn retain<F: FnMut(&mut S) -> bool>(&mut self, mut keep: F) {
+        self.idle.retain(|_, idle| {
+            idle.retain_mut(|(stream, _)| keep(stream));
+            !idle.is_empty()
+        });
+    }
+
+    /// The number of idle connections, to all hosts.
+    pub(crate) fn len(&self) -> usize {
+        self.idle.values().map(Vec::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+
+    fn host(name: &str) -> HostRef {
+        HostRef::Http(format!("{}:80", name))
+    }
+
+    #[lunatic::test]
+    fn keeps_the_most_recent_connections() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut pool = Pool::new(None, 2);
+        pool.put(host("a"), 1, &time);
+        pool.put(host("a"), 2, &time);
+        pool.put(host("a"), 3, &time);
+        pool.put(host("b"), 4, &time);
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.take(&host("a"), &time), Some(3));
+        assert_eq!(pool.take(&host("a"), &time), Some(2));
+        assert_eq!(pool.take(&host("a"), &time), None);
+
+        let mut pool = Pool::new(None, 0);
+        pool.put(host("a"), 1, &time);
+        assert_eq!(pool.take(&host("a"), &time), None);
+    }
+
+    #[lunatic::test]
+    fn drops_connections_idle_for_too_long() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut pool = Pool::new(Some(Duration::from_secs(90)), usize::MAX);
+        pool.put(host("a"), 1, &time);
+        time.sleep(Duration::from_secs(60));
+        pool.put(host("a"), 2, &time);
+        time.sleep(Duration::from_secs(60));
+        assert_eq!(pool.take(&host("a"), &time), Some(2));
+        assert_eq!(pool.take(&host("a"), &time), None);
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[lunatic::test]
+    fn retains_live_connections() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut pool = Pool::new(None, usize::MAX);
+        pool.put(host("a"), 1, &time);
+        pool.put(host("a"), 2, &time);
+        pool.retain(|stream| *stream != 2);
+        assert_eq!(pool.take(&host("a"), &time), Some(1));
+        assert_eq!(pool.len(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! Running user supplied hooks in processes of their own.
+//!
+//! The configuration of a `ClientBuilder` is serialized and sent to the
+//! process that runs the client, and code can't travel with it. So a hook is
+//! spawned in a process of its own as soon as it is installed, from a
+//! function that knows its concrete type, and the configuration only keeps a
+//! handle to that process, which is plain data. The client calls the hook by
+//! sending it a message and waiting for the answer.
+//!
+//! The hook's process is linked to the process that installed it, and the
+//! client links itself to it when it starts, so a hook that panics takes the
+//! client down with it, as if it had run in the client process.
+
+use std::fmt;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+use lunatic::{Mailbox, Process, Tag};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A hook, answering the calls of a client from its own process.
+pub(crate) trait Serve: Serialize + DeserializeOwned + 'static {
+    type Request: Serialize + DeserializeOwned;
+    type Response: Serialize + DeserializeOwned;
+
+    fn serve(&mut self, request: Self::Request) -> Self::Response;
+}
+
+/// A call to a hook, and where to send its answer.
+#[derive(Serialize, Deserialize)]
+struct Call<Req, Res> {
+    request: Req,
+    reply: Process<Res>,
+    tag: Tag,
+}
+
+/// A handle to a hook running in a process of its own, see the module
+/// documentation.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Remote<Req, Res> {
+    process: Process<Call<Req, Res>>,
+}
+
+impl<Req, Res> Remote<Req, Res>
+where
+    Req: Serialize + DeserializeOwned,
+    Res: Serialize + DeserializeOwned,
+{
+    /// Spawns `hook` in a process linked to the current one.
+    pub(crate) fn spawn<H>(hook: H) -> Remote<Req, Res>
+    where
+        H: Serve<Request = Req, Response = Res>,
+    {
+        Remote {
+            process: Process::spawn_link(hook, serve::<H>),
+        }
+    }
+
+    /// Calls the hook and waits for its answer.
+    pub(crate) fn call(&self, request: Req) -> Res {
+        let tag = Tag::new();
+        // SAFETY: only the answer to this call is tagged with `tag`, and it
+        // is a `Res`.
+        let mailbox = unsafe { Mailbox::<Res>::new() };
+        self.process.send(Call {
+            request,
+            reply: mailbox.this(),
+            tag,
+        });
+        mailbox.tag_receive(&[tag])
+    }
+
+    /// Links the current process to the hook's.
+    pub(crate) fn link(&self) {
+        self.process.link();
+    }
+}
+
+fn serve<H: Serve>(mut hook: H, mailbox: Mailbox<Call<H::Request, H::Response>>) {
+    loop {
+        let call = mailbox.receive();
+        let response = hook.serve(call.request);
+        call.reply.tag_send(call.tag, response);
+    }
+}
+
+impl<Req, Res> Clone for Remote<Req, Res> {
+    fn clone(&self) -> Self {
+        Remote {
+            process: self.process.clone(),
+        }
+    }
+}
+
+impl<Req, Res> fmt::Debug for Remote<Req, Res> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Remote").finish()
+    }
+}
+
+/// Headers on their way to or from a hook. Unlike a `HashMap<String,
+/// Vec<String>>`, values don't have to be valid UTF-8.
+pub(crate) type Headers = Vec<(String, Vec<u8>)>;
+
+pub(crate) fn to_wire(headers: &HeaderMap) -> Headers {
+    headers
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .collect()
+}
+
+/// The headers sent by `to_wire`, skipping any that aren't valid.
+pub(crate) fn from_wire(headers: Headers) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_bytes(&value),
+        ) {
+            map.append(name, value);
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, HeaderValue};
+
+    use super::{from_wire, to_wire};
+
+    #[lunatic::test]
+    fn headers_survive_the_wire() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-sig", HeaderValue::from_static("a"));
+        headers.append("x-sig", HeaderValue::from_static("b"));
+        headers.insert("x-raw", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        assert_eq!(from_wire(to_wire(&headers)), headers);
+    }
+}
@@ -3,9 +3,11 @@ use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::io::Write;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use base64::write::EncoderWriter as Base64Encoder;
+use bytes::Bytes;
 use http::header::{CONTENT_ENCODING, CONTENT_LENGTH, LOCATION, REFERER, TRANSFER_ENCODING};
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
@@ -14,16 +16,21 @@ use super::client::InnerClient;
 // #[cfg(feature = "multipart")]
 // use super::multipart;
 use super::response::HttpResponse;
+use super::schema::{self, SCHEMA_VERSION};
 #[cfg(feature = "cookies")]
 use crate::cookie::{self, CookieStore};
 #[cfg(feature = "multipart")]
 use crate::header::CONTENT_LENGTH;
-use crate::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use crate::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, IF_RANGE, RANGE};
 use crate::into_url::try_uri;
 #[cfg(feature = "cookies")]
 use crate::lunatic_impl::client::add_cookie_header;
-use crate::redirect::remove_sensitive_headers;
-use crate::{error, redirect, Body, Client, Method, Url, Version};
+use crate::proxy_protocol::ProxyHeader;
+use crate::tls::Certificate;
+use crate::{
+    error, redirect, Algorithm, Body, Client, IntoUrl, InvalidHeader, Method, TimeoutPhase, Url,
+    Version,
+};
 use http::{request::Parts, Request as HttpRequest};
 
 /// A request which can be executed with `Client::execute()`.
@@ -34,17 +41,41 @@ pub struct Request {
     pub(crate) headers: HeaderMap,
     pub(crate) body: Option<Body>,
     pub(crate) timeout: Option<Duration>,
+    pub(crate) phase_timeouts: Vec<(TimeoutPhase, Duration)>,
     pub(crate) version: Version,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) tee: bool,
+    pub(crate) hash_body: Option<Algorithm>,
+    pub(crate) no_cookies: bool,
+    pub(crate) root_certs: Vec<Certificate>,
+    pub(crate) proxy_protocol: Option<ProxyHeader>,
 }
 
+/// A `Request` as sent to the client process.
+///
+/// Versioned like `SerializableResponse`, see `SCHEMA_VERSION`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InnerRequest {
+    #[serde(default = "schema::current", deserialize_with = "schema::check")]
+    pub(crate) schema: u32,
     pub(crate) method: String,
     pub(crate) url: Url,
     pub(crate) headers: HashMap<String, Vec<String>>,
     pub(crate) body: Option<Body>,
     pub(crate) timeout: Option<Duration>,
+    #[serde(default)]
+    pub(crate) phase_timeouts: Vec<(TimeoutPhase, Duration)>,
     pub(crate) version: Version,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) tee: bool,
+    #[serde(default)]
+    pub(crate) hash_body: Option<Algorithm>,
+    #[serde(default)]
+    pub(crate) no_cookies: bool,
+    #[serde(default)]
+    pub(crate) root_certs: Vec<Certificate>,
+    #[serde(default)]
+    pub(crate) proxy_protocol: Option<ProxyHeader>,
 }
 
 /// A builder to construct the properties of a `Request`.
@@ -55,23 +86,134 @@ pub struct InnerRequest {
 pub struct RequestBuilder {
     client: Client,
     request: crate::Result<Request>,
+    #[cfg(feature = "oauth1")]
+    oauth1: Option<crate::oauth1::Credentials>,
+    tee: Option<Tee>,
 }
 
+type Tee = Arc<Mutex<dyn Write + Send>>;
+
 impl TryFrom<Request> for InnerRequest {
     type Error = crate::Error;
 
     fn try_from(value: Request) -> Result<Self, Self::Error> {
         Ok(InnerRequest {
+            schema: SCHEMA_VERSION,
             method: value.method.to_string(),
             url: value.url,
             headers: hashmap_from_header_map(value.headers),
             body: value.body,
             timeout: value.timeout,
+            phase_timeouts: value.phase_timeouts,
             version: value.version,
+            labels: value.labels,
+            tee: value.tee,
+            hash_body: value.hash_body,
+            no_cookies: value.no_cookies,
+            root_certs: value.root_certs,
+            proxy_protocol: value.proxy_protocol,
         })
     }
 }
 
+/// A `Request` that can be stored or sent to another process or node, and
+/// turned back into a `Request` to be sent later, such as a queue of
+/// requests waiting to be retried.
+///
+/// Only the method, URL, headers, body, timeout and labels are kept.
+/// Versioned like `SerializableResponse`, see `SCHEMA_VERSION`.
+///
+/// ```rust
+/// use std::convert::TryFrom;
+///
+/// use nightfly::{Request, SerializableRequest};
+///
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = nightfly::Client::new();
+/// let req = client.post("https://example.com/events").body("{}").build()?;
+/// let saved = serde_json::to_vec(&SerializableRequest::try_from(req)?)?;
+///
+/// // later, maybe in another process
+/// let req: SerializableRequest = serde_json::from_slice(&saved)?;
+/// let res = client.execute(Request::try_from(req)?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableRequest {
+    /// schema version the message was written with
+    #[serde(default = "schema::current", deserialize_with = "schema::check")]
+    pub schema: u32,
+    /// The request method, such as `GET`
+    pub method: String,
+    /// The URL to send the request to
+    pub url: Url,
+    /// The request's headers, as a map from name to values
+    pub headers: HashMap<String, Vec<String>>,
+    /// The request's body, if it has one
+    pub body: Option<Bytes>,
+    /// The request's timeout, see `RequestBuilder::timeout`
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+    /// The request's labels, see `RequestBuilder::label`
+    #[serde(default)]
+    pub labels: Vec<(String, String)>,
+}
+
+impl TryFrom<Request> for SerializableRequest {
+    type Error = crate::Error;
+
+    /// Fails if the body is read from a reader, or a header value isn't
+    /// text.
+    fn try_from(req: Request) -> Result<Self, Self::Error> {
+        if req.body().and_then(Body::reader).is_some() {
+            return Err(error::builder(
+                "a body read from a reader can't be serialized",
+            ));
+        }
+        let mut headers: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, value) in req.headers.iter() {
+            let value = value.to_str().map_err(error::builder)?;
+            headers
+                .entry(name.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+        Ok(SerializableRequest {
+            schema: SCHEMA_VERSION,
+            method: req.method.to_string(),
+            url: req.url,
+            headers,
+            body: req.body.map(Body::into_bytes),
+            timeout: req.timeout,
+            labels: req.labels,
+        })
+    }
+}
+
+impl TryFrom<SerializableRequest> for Request {
+    type Error = crate::Error;
+
+    /// Fails if the method or a header isn't valid.
+    fn try_from(req: SerializableRequest) -> Result<Self, Self::Error> {
+        let method = Method::from_bytes(req.method.as_bytes()).map_err(error::builder)?;
+        let mut headers = HeaderMap::new();
+        for (name, values) in req.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(error::builder)?;
+            for value in values {
+                let value = HeaderValue::from_str(&value).map_err(error::builder)?;
+                headers.append(name.clone(), value);
+            }
+        }
+        let mut request = Request::new(method, req.url);
+        request.headers = headers;
+        request.body = req.body.map(Body::from);
+        request.timeout = req.timeout;
+        request.labels = req.labels;
+        Ok(request)
+    }
+}
+
 pub(crate) fn hashmap_from_header_map(headers: HeaderMap) -> HashMap<String, Vec<String>> {
     let mut map: HashMap<String, Vec<String>> = HashMap::new();
     let mut curr_key = String::new();
@@ -102,6 +244,15 @@ pub(crate) fn header_map_from_hashmap(headers: HashMap<String, Vec<String>>) ->
 }
 
 impl InnerRequest {
+    /// The time allowed for `phase` of this request, see
+    /// `RequestBuilder::timeouts`.
+    pub(crate) fn phase_timeout(&self, phase: TimeoutPhase) -> Option<Duration> {
+        self.phase_timeouts
+            .iter()
+            .find(|(p, _)| *p == phase)
+            .map(|(_, timeout)| *timeout)
+    }
+
     pub(super) fn pieces(
         self,
     ) -> (
@@ -134,7 +285,14 @@ impl Request {
             headers: HeaderMap::new(),
             body: None,
             timeout: None,
+            phase_timeouts: Vec::new(),
             version: Version::default(),
+            labels: Vec::new(),
+            tee: false,
+            hash_body: None,
+            no_cookies: false,
+            root_certs: Vec::new(),
+            proxy_protocol: None,
         }
     }
 
@@ -209,11 +367,23 @@ impl Request {
     pub fn version_mut(&mut self) -> &mut Version {
         &mut self.version
     }
+
+    /// Get the labels attached to this request.
+    #[inline]
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
 }
 
 impl RequestBuilder {
     pub(super) fn new(client: Client, request: crate::Result<Request>) -> RequestBuilder {
-        let mut builder = RequestBuilder { client, request };
+        let mut builder = RequestBuilder {
+            client,
+            request,
+            #[cfg(feature = "oauth1")]
+            oauth1: None,
+            tee: None,
+        };
 
         let auth = builder
             .request
@@ -239,6 +409,43 @@ impl RequestBuilder {
         self.header_sensitive(key, value, false)
     }
 
+    /// Add a `Header` to this Request, failing right away if its name or
+    /// value is invalid.
+    ///
+    /// `header` keeps such an error until the request is sent, and can't
+    /// tell which header it was if the name was invalid. The error returned
+    /// here always names the header, see `Error::invalid_header`.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let err = client
+    ///     .get("https://example.com/")
+    ///     .try_header("x api key", "secret")
+    ///     .unwrap_err();
+    /// assert_eq!(err.invalid_header().unwrap().name.as_deref(), Some("x api key"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_header<K, V>(mut self, key: K, value: V) -> crate::Result<RequestBuilder>
+    where
+        K: AsRef<str>,
+        HeaderValue: TryFrom<V>,
+    {
+        let invalid = |invalid_name| {
+            crate::error::invalid_header(InvalidHeader {
+                name: Some(key.as_ref().to_owned()),
+                invalid_name,
+            })
+        };
+        let name = HeaderName::from_bytes(key.as_ref().as_bytes()).map_err(|_| invalid(true))?;
+        let value = <HeaderValue as TryFrom<V>>::try_from(value).map_err(|_| invalid(false))?;
+        if let Ok(ref mut req) = self.request {
+            req.headers_mut().append(name, value);
+        }
+        Ok(self)
+    }
+
     /// Add a `Header` to this Request with ability to define if header_value is sensitive.
     fn header_sensitive<K, V>(mut self, key: K, value: V, sensitive: bool) -> RequestBuilder
     where
@@ -260,9 +467,19 @@ impl RequestBuilder {
                         }
                         req.headers_mut().append(key, value);
                     }
-                    Err(e) => error = Some(crate::error::builder(e.into())),
+                    Err(_) => {
+                        error = Some(crate::error::invalid_header(InvalidHeader {
+                            name: Some(key.to_string()),
+                            invalid_name: false,
+                        }))
+                    }
                 },
-                Err(e) => error = Some(crate::error::builder(e.into())),
+                Err(_) => {
+                    error = Some(crate::error::invalid_header(InvalidHeader {
+                        name: None,
+                        invalid_name: true,
+                    }))
+                }
             };
         }
         if let Some(err) = error {
@@ -322,6 +539,101 @@ impl RequestBuilder {
         self.header_sensitive(crate::header::AUTHORIZATION, header_value, true)
     }
 
+    /// Sign the request with OAuth 1.0a.
+    ///
+    /// The signature covers the method, URL, query and, for
+    /// `application/x-www-form-urlencoded` requests, the form body, so it is
+    /// computed when the request is built or sent rather than right away.
+    #[cfg(feature = "oauth1")]
+    pub fn oauth1(mut self, credentials: crate::oauth1::Credentials) -> RequestBuilder {
+        self.oauth1 = Some(credentials);
+        self
+    }
+
+    /// Send a CSRF token in the header the site expects it in.
+    ///
+    /// See the [`csrf`](crate::csrf) module for finding tokens.
+    pub fn csrf_token(self, token: &crate::csrf::CsrfToken) -> RequestBuilder {
+        self.header_sensitive(token.header_name().clone(), token.value(), true)
+    }
+
+    /// Copy the response body, as received and before decompression, to
+    /// `writer`, for example a file kept as an audit trail or a hasher.
+    ///
+    /// The response is still decoded as usual. The copy is written once the
+    /// response has arrived, before `send` returns; clones of this builder
+    /// share the writer.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let raw = std::fs::File::create("response.gz")?;
+    /// let text = nightfly::Client::new()
+    ///     .get("https://example.com/")
+    ///     .tee(raw)
+    ///     .send()?
+    ///     .text()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tee<W: Write + Send + 'static>(mut self, writer: W) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.tee = true;
+        }
+        self.tee = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Compute a digest of the response body while it is read, which
+    /// `HttpResponse::body_hash` returns.
+    ///
+    /// The digest covers the body as `HttpResponse::bytes` returns it,
+    /// after decompression, so that it can be compared with the checksum
+    /// published for a download without going over the body again.
+    ///
+    /// ```rust
+    /// use nightfly::Algorithm;
+    ///
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let res = nightfly::Client::new()
+    ///     .get("https://example.com/release.tar.gz")
+    ///     .hash_body(Algorithm::Sha256)
+    ///     .send()?;
+    /// let sha256: String = res
+    ///     .body_hash()
+    ///     .unwrap_or_default()
+    ///     .iter()
+    ///     .map(|b| format!("{:02x}", b))
+    ///     .collect();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn hash_body(mut self, algorithm: Algorithm) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.hash_body = Some(algorithm);
+        }
+        self
+    }
+
+    /// Keep this request away from the client's cookie store: no cookies
+    /// are sent with it, and cookies its responses set are dropped,
+    /// redirects included. A `Cookie` header set on the request is still
+    /// sent.
+    ///
+    /// Health checks and admin probes use it to stay out of the session
+    /// the client keeps for everything else.
+    ///
+    /// # Optional
+    ///
+    /// This requires the optional `cookies` feature to be enabled.
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    pub fn no_cookies(mut self) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.no_cookies = true;
+        }
+        self
+    }
+
     /// Set a body that can be turned into a `Body`
     pub fn body<T: Into<Body>>(mut self, body: T) -> RequestBuilder {
         if let Ok(ref mut req) = self.request {
@@ -363,6 +675,18 @@ impl RequestBuilder {
         self
     }
 
+    /// Set the request body to the requests of `batch`, as a
+    /// `multipart/mixed` batch. See the `batch` module.
+    pub fn batch(mut self, batch: &crate::batch::Batch) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            let content_type = HeaderValue::from_str(&batch.content_type())
+                .expect("batch boundaries are valid header values");
+            req.headers_mut().insert(CONTENT_TYPE, content_type);
+            *req.body_mut() = Some(batch.body().into());
+        }
+        self
+    }
+
     /// Enables a request timeout.
     ///
     /// The timeout is applied from when the request starts connecting until the
@@ -375,6 +699,82 @@ impl RequestBuilder {
         self
     }
 
+    /// Sets time budgets for the phases of this request, so that a slow
+    /// handshake can be told apart from a slow body.
+    ///
+    /// - `Connect` and `TlsHandshake` override `ClientBuilder::connect_timeout`
+    ///   and `ClientBuilder::tls_handshake_timeout` when a connection is
+    ///   opened.
+    /// - `FirstByte` runs from when the request has been sent until the
+    ///   response head has arrived.
+    /// - `Body` runs from then until the whole body has been read.
+    /// - `BodyIdle` is the longest wait for more of the body, and overrides
+    ///   `ClientBuilder::body_idle_timeout`.
+    /// - `Request` is the same as `timeout`.
+    ///
+    /// A phase that runs out fails the request with an error whose
+    /// `Error::timeout_detail` names it. Setting a phase again replaces its
+    /// budget.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use nightfly::TimeoutPhase;
+    ///
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let res = nightfly::Client::new()
+    ///     .get("https://example.com/report")
+    ///     .timeouts([
+    ///         (TimeoutPhase::Connect, Duration::from_secs(1)),
+    ///         (TimeoutPhase::FirstByte, Duration::from_secs(2)),
+    ///         (TimeoutPhase::Request, Duration::from_secs(10)),
+    ///     ])
+    ///     .send()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn timeouts<I>(mut self, phases: I) -> RequestBuilder
+    where
+        I: IntoIterator<Item = (TimeoutPhase, Duration)>,
+    {
+        if let Ok(ref mut req) = self.request {
+            for (phase, timeout) in phases {
+                if phase == TimeoutPhase::Request {
+                    req.timeout = Some(timeout);
+                    continue;
+                }
+                req.phase_timeouts.retain(|(p, _)| *p != phase);
+                req.phase_timeouts.push((phase, timeout));
+            }
+        }
+        self
+    }
+
+    /// Trust `cert` as a root certificate for this request, on top of the
+    /// client's, see `ClientBuilder::add_root_certificate`.
+    ///
+    /// For clients that talk to many endpoints trusted differently, such as
+    /// internal services signed by several CAs. A request with its own root
+    /// certificates always opens a new connection, it never reuses one that
+    /// was verified against other roots.
+    pub fn add_root_certificate(mut self, cert: Certificate) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.root_certs.push(cert);
+        }
+        self
+    }
+
+    /// Start the connection of this request with a PROXY protocol `header`,
+    /// instead of the client's, see `ClientBuilder::proxy_protocol`.
+    ///
+    /// A request with its own header always opens a new connection, the
+    /// header describes that connection only.
+    pub fn proxy_protocol(mut self, header: ProxyHeader) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.proxy_protocol = Some(header);
+        }
+        self
+    }
+
     // /// Sends a multipart/form-data body.
     // ///
     // /// ```
@@ -461,6 +861,91 @@ impl RequestBuilder {
         self
     }
 
+    /// Ask only for the rest of a body that failed to download, see
+    /// `Error::partial_body`.
+    ///
+    /// Sends a `Range` request from where the body was cut short, with an
+    /// `If-Range` validator when the failed response had one. A server that
+    /// can't resume answers with the whole body instead, so check for
+    /// `206 Partial Content` before appending the body to `partial.raw`.
+    /// Partial bodies are never decompressed.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let url = "https://example.com/large.bin";
+    /// let body = match client.get(url).send() {
+    ///     Ok(res) => res.body(),
+    ///     Err(e) => match e.partial_body() {
+    ///         Some(partial) => {
+    ///             let rest = client.get(url).resume(partial).send()?;
+    ///             if rest.status() == nightfly::StatusCode::PARTIAL_CONTENT {
+    ///                 [partial.raw.clone(), rest.body()].concat()
+    ///             } else {
+    ///                 rest.body()
+    ///             }
+    ///         }
+    ///         None => return Err(e),
+    ///     },
+    /// };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resume(self, partial: &crate::PartialBody) -> RequestBuilder {
+        let builder = self.header(RANGE, format!("bytes={}-", partial.offset()));
+        match partial.validator {
+            Some(ref validator) => builder.header(IF_RANGE, validator.as_str()),
+            None => builder,
+        }
+    }
+
+    /// Set the WebDAV `Depth` header.
+    pub fn depth(self, depth: crate::webdav::Depth) -> RequestBuilder {
+        self.header(&crate::webdav::DEPTH, depth.as_str())
+    }
+
+    /// Set the WebDAV `Destination` header, the target of a `COPY` or
+    /// `MOVE`.
+    pub fn destination<U: IntoUrl>(mut self, destination: U) -> RequestBuilder {
+        match destination.into_url() {
+            Ok(url) => self.header(&crate::webdav::DESTINATION, url.as_str()),
+            Err(e) => {
+                self.request = Err(e);
+                self
+            }
+        }
+    }
+
+    /// Set the WebDAV `Overwrite` header, whether a `COPY` or `MOVE` may
+    /// replace an existing target. Servers overwrite by default.
+    pub fn overwrite(self, overwrite: bool) -> RequestBuilder {
+        self.header(&crate::webdav::OVERWRITE, if overwrite { "T" } else { "F" })
+    }
+
+    /// Attach a key-value label to this request.
+    ///
+    /// Labels are never sent to the server. They name the logical operation
+    /// a request belongs to, and are added to the client's metrics, tracing
+    /// spans and log events so that those can be grouped by operation rather
+    /// than by raw URL.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let client = nightfly::Client::new();
+    /// let res = client
+    ///     .get("https://example.com/users/42")
+    ///     .label("endpoint", "get_user")
+    ///     .send()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn label<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.labels.push((key.into(), value.into()));
+        }
+        self
+    }
+
     /// Send a form body.
     ///
     /// Sets the body to the url encoded serialization of the passed value,
@@ -524,7 +1009,26 @@ impl RequestBuilder {
     /// Build a `Request`, which can be inspected, modified and executed with
     /// `Client::execute()`.
     pub fn build(self) -> crate::Result<Request> {
-        self.request
+        self.finish().1
+    }
+
+    fn finish(self) -> (Client, crate::Result<Request>) {
+        #[cfg(feature = "oauth1")]
+        if let Some(ref credentials) = self.oauth1 {
+            let request = self.request.and_then(|mut req| {
+                let form = match req.headers.get(CONTENT_TYPE) {
+                    Some(ct) if ct == "application/x-www-form-urlencoded" => {
+                        req.body.as_ref().map(|body| body.as_bytes())
+                    }
+                    _ => None,
+                };
+                let value = credentials.authorization(&req.method, &req.url, form)?;
+                req.headers.insert(crate::header::AUTHORIZATION, value);
+                Ok(req)
+            });
+            return (self.client, request);
+        }
+        (self.client, self.request)
     }
 
     /// Constructs the Request and sends it to the target URL, returning a
@@ -549,41 +1053,87 @@ impl RequestBuilder {
     /// # }
     /// ```
     pub fn send(mut self) -> Result<HttpResponse, crate::Error> {
-        match self.request {
-            Ok(req) => self.client.execute(req),
-            Err(err) => Err(err),
+        let tee = self.tee.take();
+        let (mut client, request) = self.finish();
+        let mut res = client.execute(request?)?;
+        if let (Some(tee), Some(raw)) = (tee, res.raw_body.take()) {
+            let mut writer = tee.lock().unwrap_or_else(|e| e.into_inner());
+            writer
+                .write_all(&raw)
+                .and_then(|_| writer.flush())
+                .map_err(|e| error::body(e).with_url(res.url.clone()))?;
         }
+        Ok(res)
     }
 
-    // /// Attempt to clone the RequestBuilder.
-    // ///
-    // /// `None` is returned if the RequestBuilder can not be cloned,
-    // /// i.e. if the request body is a stream.
-    // ///
-    // /// # Examples
-    // ///
-    // /// ```
-    // /// # use nightfly::Error;
-    // /// #
-    // /// # fn run() -> Result<(), Error> {
-    // /// let client = nightfly::Client::new();
-    // /// let builder = client.post("http://httpbin.org/post")
-    // ///     .body("from a &str!");
-    // /// let clone = builder.try_clone();
-    // /// assert!(clone.is_some());
-    // /// # Ok(())
-    // /// # }
-    // /// ```
-    // pub fn try_clone(&self) -> Option<RequestBuilder> {
-    //     self.request
-    //         .as_ref()
-    //         .ok()
-    //         .and_then(|req| req.try_clone())
-    //         .map(|req| RequestBuilder {
-    //             client: self.client.clone(),
-    //             request: Ok(req),
-    //         })
-    // }
+    /// Attempt to clone the RequestBuilder.
+    ///
+    /// `None` is returned if the RequestBuilder can not be cloned, i.e. if
+    /// building the request has already failed or its body is read from a
+    /// reader. A `tee` writer is shared between the clones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nightfly::Error;
+    /// #
+    /// # fn run() -> Result<(), Error> {
+    /// let client = nightfly::Client::new();
+    /// let builder = client.post("http://httpbin.org/post")
+    ///     .body("from a &str!");
+    /// let clone = builder.try_clone();
+    /// assert!(clone.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_clone(&self) -> Option<RequestBuilder> {
+        let req = self.request.as_ref().ok()?;
+        if req.body().and_then(Body::reader).is_some() {
+            return None;
+        }
+        Some(self.clone())
+    }
+
+    // inspecting
+
+    /// Get the method of the request, unless building it has failed.
+    pub fn method(&self) -> Option<&Method> {
+        self.request.as_ref().ok().map(Request::method)
+    }
+
+    /// Get the URL of the request so far, with any `query` parameters added,
+    /// unless building it has failed.
+    pub fn url(&self) -> Option<&Url> {
+        self.request.as_ref().ok().map(Request::url)
+    }
+
+    /// Get the headers set on the request so far, unless building it has
+    /// failed.
+    ///
+    /// The client's default headers, such as `User-Agent`, and headers it
+    /// computes, such as `Content-Length`, are added when the request is
+    /// sent.
+    pub fn current_headers(&self) -> Option<&HeaderMap> {
+        self.request.as_ref().ok().map(Request::headers)
+    }
+
+    /// Get the size of the request body in bytes, if it has one.
+    pub fn body_len(&self) -> Option<usize> {
+        self.request.as_ref().ok()?.body().map(Body::len)
+    }
+
+    /// Get the error building the request has run into, if any, which
+    /// `send` would return.
+    ///
+    /// ```
+    /// let builder = nightfly::Client::new()
+    ///     .get("https://example.com")
+    ///     .header("invalid header name", "value");
+    /// assert!(builder.error().unwrap().is_builder());
+    /// ```
+    pub fn error(&self) -> Option<&crate::Error> {
+        self.request.as_ref().err()
+    }
 }
 
 impl fmt::Debug for Request {
@@ -608,7 +1158,20 @@ fn fmt_request_fields<'a, 'b>(
 ) -> &'a mut fmt::DebugStruct<'a, 'b> {
     f.field("method", &req.method)
         .field("url", &req.url)
-        .field("headers", &req.headers)
+        .field("headers", &req.headers);
+    if !req.labels.is_empty() {
+        f.field("labels", &req.labels);
+    }
+    if !req.phase_timeouts.is_empty() {
+        f.field("phase_timeouts", &req.phase_timeouts);
+    }
+    if !req.root_certs.is_empty() {
+        f.field("root_certs", &req.root_certs);
+    }
+    if let Some(ref header) = req.proxy_protocol {
+        f.field("proxy_protocol", header);
+    }
+    f
 }
 
 /// Check the request URL for a "username:password" type authority, and if
@@ -661,7 +1224,14 @@ where
             headers,
             body: Some(body.into()),
             timeout: None,
+            phase_timeouts: Vec::new(),
             version: Version::from(version),
+            labels: Vec::new(),
+            tee: false,
+            hash_body: None,
+            no_cookies: false,
+            root_certs: Vec::new(),
+            proxy_protocol: None,
         })
     }
 }
@@ -723,12 +1293,15 @@ impl<'a> PendingRequest<'a> {
     pub fn resolve(mut self) -> Result<HttpResponse, crate::Error> {
         #[cfg(feature = "cookies")]
         {
-            if let Some(ref cookie_store) = self.client.cookie_store {
-                let mut cookies =
-                    cookie::extract_response_cookie_headers(self.res.headers()).peekable();
-                if cookies.peek().is_some() {
-                    cookie_store.set_cookies(&mut cookies, &self.req.url);
+            match self.client.cookie_store {
+                Some(ref cookie_store) if !self.req.no_cookies => {
+                    let mut cookies =
+                        cookie::extract_response_cookie_headers(self.res.headers()).peekable();
+                    if cookies.peek().is_some() {
+                        cookie_store.set_cookies(&mut cookies, &self.req.url);
+                    }
                 }
+                _ => {}
             }
         }
         self.urls.push(self.req.url.clone());
@@ -793,10 +1366,12 @@ impl<'a> PendingRequest<'a> {
                     }
                 }
 
-                let action = self
-                    .client
-                    .redirect_policy
-                    .check(self.res.status(), &loc, &self.urls);
+                let action = self.client.redirect_policy.check(
+                    self.res.status(),
+                    self.res.headers(),
+                    &loc,
+                    &self.urls,
+                );
 
                 match action {
                     redirect::ActionKind::Follow => {
@@ -813,7 +1388,9 @@ impl<'a> PendingRequest<'a> {
 
                         self.req.url = loc.clone();
 
-                        remove_sensitive_headers(&mut headers, &self.req.url, &self.urls);
+                        self.client
+                            .redirect_auth
+                            .strip(&mut headers, &self.req.url, &self.urls);
 
                         // push new url to chain of redirects
                         self.urls.push(loc);
@@ -824,16 +1401,24 @@ impl<'a> PendingRequest<'a> {
                             self.req.url.clone(),
                         );
                         req.headers = headers.clone();
+                        req.labels = self.req.labels.clone();
+                        req.tee = self.req.tee;
+                        req.hash_body = self.req.hash_body;
+                        req.no_cookies = self.req.no_cookies;
+                        req.root_certs = self.req.root_certs.clone();
+                        req.proxy_protocol = self.req.proxy_protocol;
+                        req.phase_timeouts = self.req.phase_timeouts.clone();
 
                         // Add cookies from the cookie store.
                         #[cfg(feature = "cookies")]
                         {
-                            if let Some(ref cookie_store) = self.client.cookie_store {
-                                add_cookie_header(
+                            match self.client.cookie_store {
+                                Some(ref cookie_store) if !req.no_cookies => add_cookie_header(
                                     &mut headers,
                                     cookie_store.clone(),
                                     &self.req.url,
-                                );
+                                ),
+                                _ => {}
                             }
                         }
 
@@ -1005,6 +1590,53 @@ mod tests {
         assert!(req.body().is_none());
     }
 
+    #[lunatic::test]
+    fn phase_timeouts() {
+        use std::convert::TryFrom;
+        use std::time::Duration;
+
+        use super::InnerRequest;
+        use crate::TimeoutPhase;
+
+        let client = Client::new();
+        let req = client
+            .get("https://example.com/")
+            .timeouts([
+                (TimeoutPhase::Connect, Duration::from_secs(1)),
+                (TimeoutPhase::FirstByte, Duration::from_secs(2)),
+                (TimeoutPhase::Request, Duration::from_secs(10)),
+            ])
+            .timeouts([(TimeoutPhase::Connect, Duration::from_secs(3))])
+            .build()
+            .unwrap();
+        assert_eq!(req.timeout(), Some(&Duration::from_secs(10)));
+
+        let req = InnerRequest::try_from(req).unwrap();
+        assert_eq!(
+            req.phase_timeout(TimeoutPhase::Connect),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            req.phase_timeout(TimeoutPhase::FirstByte),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(req.phase_timeout(TimeoutPhase::Body), None);
+    }
+
+    #[lunatic::test]
+    fn try_clone_reader_body() {
+        let client = Client::new();
+        let builder = client
+            .post("http://httpbin.org/post")
+            .body(crate::Body::from_reader(&b"streamed"[..], Some(8)));
+        assert!(builder.try_clone().is_none());
+        assert_eq!(builder.body_len(), Some(8));
+
+        let req = builder.build().expect("request is valid");
+        assert!(serde_json::to_string(req.body().unwrap()).is_err());
+        assert_eq!(req.body.unwrap().inner(), b"streamed");
+    }
+
     #[lunatic::test]
     fn convert_url_authority_into_basic_auth() {
         let client = Client::new();
@@ -1073,6 +1705,48 @@ mod tests {
         assert!(req.headers()["hiding"].is_sensitive());
     }
 
+    #[lunatic::test]
+    fn test_invalid_header_is_named() {
+        let client = Client::new();
+        let some_url = "https://localhost/";
+
+        let err = client
+            .get(some_url)
+            .header("X-Api-Key", "line\nbreak")
+            .build()
+            .unwrap_err();
+        assert!(err.is_builder());
+        let header = err.invalid_header().unwrap();
+        assert_eq!(header.name.as_deref(), Some("x-api-key"));
+        assert!(!header.invalid_name);
+        assert!(err
+            .to_string()
+            .ends_with("invalid value for header x-api-key"));
+
+        let err = client
+            .get(some_url)
+            .header("x api key", "secret")
+            .build()
+            .unwrap_err();
+        assert!(err.invalid_header().unwrap().invalid_name);
+
+        let err = client
+            .get(some_url)
+            .try_header("x api key", "secret")
+            .unwrap_err();
+        assert_eq!(
+            err.invalid_header().unwrap().name.as_deref(),
+            Some("x api key")
+        );
+        let req = client
+            .get(some_url)
+            .try_header("X-Api-Key", "secret")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(req.headers()["x-api-key"], "secret");
+    }
+
     use serde_json;
     use std::collections::HashMap;
 
@@ -1218,4 +1892,76 @@ mod tests {
         println!("BUILDER ERR {:?}", res);
         assert!(res.unwrap_err().is_serialization());
     }
+
+    #[lunatic::test]
+    fn inspect_builder() {
+        let client = Client::new();
+        let r = client
+            .post("https://google.com/")
+            .query(&[("foo", "bar")])
+            .header("x-trace", "1")
+            .body("hello");
+
+        assert_eq!(r.method(), Some(&Method::POST));
+        assert_eq!(r.url().unwrap().as_str(), "https://google.com/?foo=bar");
+        assert_eq!(r.current_headers().unwrap()["x-trace"], "1");
+        assert_eq!(r.body_len(), Some(5));
+        assert!(r.error().is_none());
+
+        let clone = r.try_clone().expect("clonable");
+        assert_eq!(clone.body_len(), Some(5));
+
+        let r = r.header("bad header", "value");
+        assert!(r.error().unwrap().is_builder());
+        assert_eq!(r.method(), None);
+        assert!(r.try_clone().is_none());
+    }
+
+    #[lunatic::test]
+    fn resume_asks_for_the_rest() {
+        let client = Client::new();
+        let partial = crate::PartialBody {
+            raw: b"hello".to_vec(),
+            content_encoding: None,
+            validator: Some("\"v1\"".to_string()),
+        };
+        let r = client
+            .get("https://example.com/large.bin")
+            .resume(&partial)
+            .build()
+            .unwrap();
+        assert_eq!(r.headers()["range"], "bytes=5-");
+        assert_eq!(r.headers()["if-range"], "\"v1\"");
+    }
+
+    #[lunatic::test]
+    fn webdav_requests() {
+        use crate::webdav::Depth;
+
+        let client = Client::new();
+        let r = client
+            .propfind("https://dav.example.com/files/", Depth::One)
+            .build()
+            .unwrap();
+        assert_eq!(r.method().as_str(), "PROPFIND");
+        assert_eq!(r.headers()["depth"], "1");
+
+        let r = client
+            .move_to(
+                "https://dav.example.com/files/a.txt",
+                "https://dav.example.com/files/b.txt",
+            )
+            .overwrite(false)
+            .build()
+            .unwrap();
+        assert_eq!(r.method().as_str(), "MOVE");
+        assert_eq!(
+            r.headers()["destination"],
+            "https://dav.example.com/files/b.txt"
+        );
+        assert_eq!(r.headers()["overwrite"], "F");
+
+        let r = client.copy_to("https://dav.example.com/a", "not a url");
+        assert!(r.build().unwrap_err().is_builder());
+    }
 }
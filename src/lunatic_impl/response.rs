@@ -1,10 +1,11 @@
 use std::convert::TryFrom;
 use std::fmt;
 use std::net::SocketAddr;
+use std::time::SystemTime;
 use std::{borrow::Cow, collections::HashMap};
 
 use bytes::Bytes;
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{Decoder, Encoding, UTF_8};
 use http::{HeaderMap, HeaderValue, StatusCode};
 use mime::Mime;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -12,9 +13,14 @@ use url::Url;
 
 #[cfg(feature = "cookies")]
 use crate::cookie;
+use crate::cors::Capabilities;
+use crate::notices::{self, Deprecation, Warning};
+use crate::parts::Parts;
+use crate::response::{ResponseDetails, ResponseUrl};
 use crate::Version;
 
-use super::request::header_map_from_hashmap;
+use super::request::{hashmap_from_header_map, header_map_from_hashmap};
+use super::schema::{self, SCHEMA_VERSION};
 
 // /// Extra information about the transport when an HttpConnector is used.
 // #[derive(Clone, Debug)]
@@ -23,11 +29,18 @@ use super::request::header_map_from_hashmap;
 //     local_addr: SocketAddr,
 // }
 
-/// A Response to a submitted `Request`.
+/// A Response to a submitted `Request`, as sent back from the client process.
+///
+/// The layout of this message is versioned, see `SCHEMA_VERSION`, so
+/// processes running different versions of nightfly fail clearly instead of
+/// misreading each other.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SerializableResponse {
+    /// schema version the message was written with
+    #[serde(default = "schema::current", deserialize_with = "schema::check")]
+    pub schema: u32,
     /// body of response
-    pub body: Vec<u8>,
+    pub body: Bytes,
     /// The response's status as u16
     pub status: u16,
 
@@ -42,6 +55,17 @@ pub struct SerializableResponse {
     pub url: Url,
     /// list of urls hopped during redirects
     pub redirect_chain: Vec<Url>,
+    /// value of the request ID header sent with the request, if any
+    pub request_id: Option<String>,
+    /// body as received, before decompression, for `RequestBuilder::tee`
+    #[serde(default)]
+    pub(crate) raw_body: Option<Vec<u8>>,
+    /// headers as received, in order and with their original casing
+    #[serde(default)]
+    pub raw_headers: Vec<(String, Vec<u8>)>,
+    /// digest of the body, for `RequestBuilder::hash_body`
+    #[serde(default)]
+    pub(crate) body_hash: Option<Vec<u8>>,
     // pub info: HttpInfo,
 }
 
@@ -49,21 +73,44 @@ impl TryFrom<SerializableResponse> for HttpResponse {
     type Error = crate::Error;
 
     fn try_from(res: SerializableResponse) -> Result<Self, Self::Error> {
+        let status = StatusCode::from_u16(res.status).map_err(crate::error::decode)?;
         Ok(HttpResponse {
             body: res.body,
-            status: StatusCode::from_u16(res.status).unwrap(),
+            status,
             version: res.version,
             headers: header_map_from_hashmap(res.headers),
             url: res.url,
             redirect_chain: res.redirect_chain,
+            request_id: res.request_id,
+            raw_body: res.raw_body,
+            raw_headers: res.raw_headers,
+            body_hash: res.body_hash,
         })
     }
 }
 
+impl From<HttpResponse> for SerializableResponse {
+    fn from(res: HttpResponse) -> Self {
+        SerializableResponse {
+            schema: SCHEMA_VERSION,
+            body: res.body,
+            status: res.status.as_u16(),
+            version: res.version,
+            headers: hashmap_from_header_map(res.headers),
+            url: res.url,
+            redirect_chain: res.redirect_chain,
+            request_id: res.request_id,
+            raw_body: res.raw_body,
+            raw_headers: res.raw_headers,
+            body_hash: res.body_hash,
+        }
+    }
+}
+
 /// Response of an http request
 pub struct HttpResponse {
     /// body of response
-    pub body: Vec<u8>,
+    pub body: Bytes,
     /// The response's status
     pub status: StatusCode,
 
@@ -78,6 +125,18 @@ pub struct HttpResponse {
 
     /// chain of urls if any redirection happened
     pub redirect_chain: Vec<Url>,
+
+    /// request ID sent with the request, see `ClientBuilder::request_id`
+    pub request_id: Option<String>,
+
+    // body as received, only kept for `RequestBuilder::tee`
+    pub(crate) raw_body: Option<Vec<u8>>,
+
+    // headers as received, see `raw_headers`
+    pub(crate) raw_headers: Vec<(String, Vec<u8>)>,
+
+    // digest of the body, see `body_hash`
+    pub(crate) body_hash: Option<Vec<u8>>,
     // pub info: HttpInfo,
 }
 
@@ -100,6 +159,41 @@ impl HttpResponse {
         &self.headers
     }
 
+    /// Get the headers exactly as the server sent them: in order, with the
+    /// original casing of their names, and with duplicates kept apart.
+    ///
+    /// `headers` normalizes all of that away, which is usually what you
+    /// want; this is for debugging and for passing a response on as is.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let res = nightfly::get("https://example.com")?;
+    /// for (name, value) in res.raw_headers() {
+    ///     println!("{}: {}", name, String::from_utf8_lossy(value));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn raw_headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.raw_headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_slice()))
+    }
+
+    /// Get the digest of the body computed while it was read, if the
+    /// request asked for one with `RequestBuilder::hash_body`.
+    pub fn body_hash(&self) -> Option<&[u8]> {
+        self.body_hash.as_deref()
+    }
+
+    /// Get the request ID that was sent with the request for this `Response`.
+    ///
+    /// Only set when the client was built with `ClientBuilder::request_id`.
+    #[inline]
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
     /// Get a mutable reference to the `Headers` of this `Response`.
     #[inline]
     pub fn headers_mut(&mut self) -> &mut HeaderMap {
@@ -131,6 +225,54 @@ impl HttpResponse {
         cookie::extract_response_cookies(self.headers()).filter_map(Result::ok)
     }
 
+    /// Read the methods and cross-origin access the server allows from the
+    /// `Allow` and `Access-Control-Allow-*` headers, typically of the answer
+    /// to `Client::options`.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_headers(&self.headers)
+    }
+
+    /// Read the entries of the `Warning` headers. See the `notices` module.
+    pub fn warnings(&self) -> Vec<Warning> {
+        notices::warnings(&self.headers)
+    }
+
+    /// Read the `Deprecation` header, which says that the resource is or
+    /// will be deprecated. See the `notices` module.
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        notices::deprecation(&self.headers)
+    }
+
+    /// Read the `Sunset` header, when the resource will stop responding.
+    /// See the `notices` module.
+    pub fn sunset(&self) -> Option<SystemTime> {
+        notices::sunset(&self.headers)
+    }
+
+    /// Go through the parts of a `multipart/mixed`, `multipart/related` or
+    /// other multipart response, each with its own headers, see the `parts`
+    /// module.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the response isn't multipart or has no boundary in its body.
+    /// A malformed part is reported by the iterator.
+    pub fn parts(&self) -> crate::Result<Parts> {
+        Parts::new(self)
+    }
+
+    /// Split the `multipart/mixed` answer to a batch request into its
+    /// responses, see the `batch` module. They all have the `Url` of this
+    /// `Response`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the body isn't a complete `multipart/mixed` body of HTTP
+    /// responses.
+    pub fn batch_parts(&self) -> crate::Result<Vec<HttpResponse>> {
+        crate::batch::split(self)
+    }
+
     /// Get the final `Url` of this `Response`.
     #[inline]
     pub fn url(&self) -> &Url {
@@ -203,27 +345,15 @@ impl HttpResponse {
     /// # }
     /// ```
     pub fn text_with_charset(self, default_encoding: &str) -> crate::Result<String> {
-        let content_type = self
-            .headers()
-            .get(crate::header::CONTENT_TYPE)
-            .and_then(|value| value.to_str().ok())
-            .and_then(|value| value.parse::<Mime>().ok());
-        let encoding_name = content_type
-            .as_ref()
-            .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()))
-            .unwrap_or(default_encoding);
-        let encoding = Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8);
-
-        let full = self.body();
-
-        let (text, _, _) = encoding.decode(&full);
+        let encoding = self.encoding(default_encoding);
+        let (text, _, _) = encoding.decode(&self.body);
         if let Cow::Owned(s) = text {
             return Ok(s);
         }
         unsafe {
             // decoding returned Cow::Borrowed, meaning these bytes
             // are already valid utf8
-            Ok(String::from_utf8_unchecked(full.to_vec()))
+            Ok(String::from_utf8_unchecked(self.body.into()))
         }
     }
 
@@ -271,9 +401,7 @@ impl HttpResponse {
     // #[cfg(feature = "json")]
     // #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     pub fn json<T: DeserializeOwned>(self) -> crate::Result<T> {
-        let full = self.body();
-
-        serde_json::from_slice(&full).map_err(crate::error::decode)
+        serde_json::from_slice(&self.body).map_err(crate::error::decode)
     }
 
     /// Get the full response body as `Bytes`.
@@ -292,37 +420,90 @@ impl HttpResponse {
     /// # }
     /// ```
     pub fn bytes(self) -> crate::Result<Bytes> {
-        Bytes::try_from(self.body)
-            .map_err(|e| crate::Error::new(crate::error::Kind::Decode, Some(e)))
+        Ok(self.body)
     }
 
     /// return vec
     pub fn body(&self) -> Vec<u8> {
-        self.body.clone()
+        self.body.to_vec()
     }
 
-    /// Stream a chunk of the response body.
+    /// Take the next chunk of the response body.
     ///
     /// When the response body has been exhausted, this will return `None`.
+    /// The body reaches this process in one message from the client
+    /// process, so the chunks are slices of it, handed out without copying.
     ///
     /// # Example
     ///
     /// ```
     /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
-    /// let mut res = nightfly::get("https://hyper.rs");
+    /// let mut res = nightfly::get("https://hyper.rs")?;
     ///
-    /// while let Some(chunk) = res.chunk() {
+    /// while let Some(chunk) = res.chunk()? {
     ///     println!("Chunk: {:?}", chunk);
     /// }
     /// # Ok(())
     /// # }
     /// ```
     pub fn chunk(&mut self) -> crate::Result<Option<Bytes>> {
-        // if let Some(item) = self.res.body_mut().next() {
-        //     Ok(Some(item?))
-        // } else {
-        Ok(None)
-        // }
+        if self.body.is_empty() {
+            return Ok(None);
+        }
+        let len = self.body.len().min(CHUNK_SIZE);
+        Ok(Some(self.body.split_to(len)))
+    }
+
+    /// Iterate over the response body in chunks, see `chunk`.
+    ///
+    /// ```
+    /// # use std::io::Write;
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let res = nightfly::get("https://hyper.rs")?;
+    /// let mut file = std::fs::File::create("hyper.html")?;
+    /// for chunk in res.chunks() {
+    ///     file.write_all(&chunk?)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn chunks(self) -> Chunks {
+        Chunks { res: self }
+    }
+
+    /// Iterate over the response body as UTF-8 text, see `chunks`.
+    ///
+    /// The body is decoded as with `text`, chunk by chunk. Characters split
+    /// between two chunks are kept whole, so every item is valid text, and
+    /// line-oriented consumers work whatever the charset of the body.
+    ///
+    /// ```
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let res = nightfly::get("http://example.com/legacy.csv")?;
+    /// let mut pending = String::new();
+    /// for text in res.text_chunks() {
+    ///     pending.push_str(&text?);
+    ///     while let Some(end) = pending.find('\n') {
+    ///         println!("line: {}", &pending[..end]);
+    ///         pending.drain(..=end);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn text_chunks(self) -> TextChunks {
+        self.text_chunks_with_charset("utf-8")
+    }
+
+    /// Iterate over the response body as UTF-8 text, decoded with
+    /// `default_encoding` unless the `charset` parameter of the
+    /// `Content-Type` header names another. See `text_with_charset`.
+    pub fn text_chunks_with_charset(self, default_encoding: &str) -> TextChunks {
+        let decoder = self.encoding(default_encoding).new_decoder();
+        TextChunks {
+            chunks: self.chunks(),
+            decoder: Some(decoder),
+        }
     }
 
     // util methods
@@ -387,8 +568,95 @@ impl HttpResponse {
         }
     }
 
+    /// Convert this response into an `http::Response`, for example to return
+    /// it as is from a submillisecond handler acting as a proxy.
+    ///
+    /// The final `Url`, the redirect chain, the request ID and the raw headers
+    /// are kept in the extensions, so `from_http` gives back the same
+    /// response. The body is moved, not copied, but it has been read in full:
+    /// responses reach the caller whole, so they can't be streamed on.
+    ///
+    /// ```rust
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// let res = nightfly::get("https://example.com")?;
+    /// let res: http::Response<bytes::Bytes> = res.into_http();
+    /// assert!(res.status().is_success());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_http(self) -> http::Response<Bytes> {
+        let mut res = http::Response::new(self.body);
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version.into();
+        *res.headers_mut() = self.headers;
+        let extensions = res.extensions_mut();
+        extensions.insert(ResponseUrl(self.url));
+        extensions.insert(ResponseDetails {
+            redirect_chain: self.redirect_chain,
+            request_id: self.request_id,
+            raw_headers: self.raw_headers,
+        });
+        res
+    }
+
+    /// Build a response from an `http::Response`.
+    ///
+    /// The `Url` is taken from the extensions, as set by `into_http` or
+    /// `ResponseBuilderExt::url`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the response has no `Url`.
+    pub fn from_http<B: Into<Bytes>>(res: http::Response<B>) -> crate::Result<HttpResponse> {
+        let (mut parts, body) = res.into_parts();
+        let url = match parts.extensions.remove::<ResponseUrl>() {
+            Some(ResponseUrl(url)) => url,
+            None => {
+                return Err(crate::error::builder(
+                    "http::Response has no Url, see ResponseBuilderExt::url",
+                ))
+            }
+        };
+        let details = parts.extensions.remove::<ResponseDetails>();
+        let (redirect_chain, request_id, raw_headers) = match details {
+            Some(details) => (
+                details.redirect_chain,
+                details.request_id,
+                details.raw_headers,
+            ),
+            None => (Vec::new(), None, Vec::new()),
+        };
+        Ok(HttpResponse {
+            body: body.into(),
+            status: parts.status,
+            version: parts.version.into(),
+            headers: parts.headers,
+            url,
+            redirect_chain,
+            request_id,
+            raw_body: None,
+            raw_headers,
+            body_hash: None,
+        })
+    }
+
     // private
 
+    /// The encoding named by the `Content-Type` header, or else by
+    /// `default_encoding`, falling back to UTF-8 for unknown labels.
+    fn encoding(&self, default_encoding: &str) -> &'static Encoding {
+        let content_type = self
+            .headers()
+            .get(crate::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Mime>().ok());
+        let encoding_name = content_type
+            .as_ref()
+            .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()))
+            .unwrap_or(default_encoding);
+        Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8)
+    }
+
     // The Response's body is an implementation detail.
     // You no longer need to get a reference to it, there are async methods
     // on the `Response` itself.
@@ -400,6 +668,74 @@ impl HttpResponse {
     }
 }
 
+/// Size of the chunks handed out by `HttpResponse::chunk`.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Iterator over the chunks of a response body, see `HttpResponse::chunks`.
+#[derive(Debug)]
+pub struct Chunks {
+    res: HttpResponse,
+}
+
+impl Iterator for Chunks {
+    type Item = crate::Result<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.res.chunk().transpose()
+    }
+}
+
+/// Iterator over the response body as UTF-8 text, see
+/// `HttpResponse::text_chunks`.
+pub struct TextChunks {
+    chunks: Chunks,
+    // `None` once the end of the body has been decoded
+    decoder: Option<Decoder>,
+}
+
+impl Iterator for TextChunks {
+    type Item = crate::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let decoder = self.decoder.as_mut()?;
+            let (bytes, last) = match self.chunks.next() {
+                Some(Ok(bytes)) => (bytes, false),
+                Some(Err(err)) => return Some(Err(err)),
+                None => (Bytes::new(), true),
+            };
+            let capacity = decoder
+                .max_utf8_buffer_length(bytes.len())
+                .expect("chunks are small");
+            let mut text = String::with_capacity(capacity);
+            let (_, read, _) = decoder.decode_to_string(&bytes, &mut text, last);
+            debug_assert_eq!(read, bytes.len());
+            if last {
+                self.decoder = None;
+            }
+            // a chunk holding only the start of a character yields nothing yet
+            if !text.is_empty() {
+                return Some(Ok(text));
+            }
+        }
+    }
+}
+
+impl fmt::Debug for TextChunks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TextChunks")
+            .field("chunks", &self.chunks)
+            .field(
+                "encoding",
+                &self
+                    .decoder
+                    .as_ref()
+                    .map(|decoder| decoder.encoding().name()),
+            )
+            .finish()
+    }
+}
+
 impl fmt::Debug for HttpResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Response")
@@ -417,24 +753,94 @@ impl fmt::Debug for HttpResponse {
 //     }
 // }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::Response;
-//     use crate::ResponseBuilderExt;
-//     use http::response::Builder;
-//     use url::Url;
-
-//     #[test]
-//     fn test_from_http_response() {
-//         let url = Url::parse("http://example.com").unwrap();
-//         let response = Builder::new()
-//             .status(200)
-//             .url(url.clone())
-//             .body("foo")
-//             .unwrap();
-//         let response = Response::from(response);
-
-//         assert_eq!(response.status(), 200);
-//         assert_eq!(*response.url(), url);
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::HttpResponse;
+    use crate::ResponseBuilderExt;
+    use http::response::Builder;
+    use url::Url;
+
+    #[lunatic::test]
+    fn test_from_http_response() {
+        let url = Url::parse("http://example.com").unwrap();
+        let response = Builder::new()
+            .status(200)
+            .url(url.clone())
+            .body("foo")
+            .unwrap();
+        let response = HttpResponse::from_http(response).unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(*response.url(), url);
+        assert_eq!(response.body(), b"foo");
+
+        let missing_url = Builder::new().status(200).body("foo").unwrap();
+        assert!(HttpResponse::from_http(missing_url).is_err());
+    }
+
+    #[lunatic::test]
+    fn test_http_round_trip() {
+        let url = Url::parse("http://example.com/b").unwrap();
+        let response = Builder::new()
+            .status(404)
+            .version(http::Version::HTTP_10)
+            .header("X-Custom", "1")
+            .url(url.clone())
+            .body(b"gone".to_vec())
+            .unwrap();
+        let mut response = HttpResponse::from_http(response).unwrap();
+        response.redirect_chain = vec![Url::parse("http://example.com/a").unwrap()];
+        response.request_id = Some("abc".to_string());
+        response.raw_headers = vec![("X-Custom".to_string(), b"1".to_vec())];
+
+        let back = HttpResponse::from_http(response.into_http()).unwrap();
+        assert_eq!(back.status(), 404);
+        assert_eq!(back.version(), crate::Version::HTTP_10);
+        assert_eq!(back.headers()["x-custom"], "1");
+        assert_eq!(*back.url(), url);
+        assert_eq!(back.redirect_chain.len(), 1);
+        assert_eq!(back.request_id(), Some("abc"));
+        assert_eq!(back.raw_headers().next(), Some(("X-Custom", &b"1"[..])));
+        assert_eq!(back.body(), b"gone");
+    }
+
+    #[lunatic::test]
+    fn test_chunks() {
+        let body = vec![7u8; super::CHUNK_SIZE + 10];
+        let response = Builder::new()
+            .url(Url::parse("http://example.com").unwrap())
+            .body(body.clone())
+            .unwrap();
+        let response = HttpResponse::from_http(response).unwrap();
+
+        let chunks: Vec<_> = response.chunks().map(Result::unwrap).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), super::CHUNK_SIZE);
+        assert_eq!(chunks.concat(), body);
+    }
+
+    #[lunatic::test]
+    fn test_text_chunks() {
+        // "é" is two bytes in UTF-8, split between the first two chunks
+        let mut body = vec![b'a'; super::CHUNK_SIZE - 1];
+        body.extend_from_slice("é\n".as_bytes());
+        let response = Builder::new()
+            .url(Url::parse("http://example.com").unwrap())
+            .body(body.clone())
+            .unwrap();
+        let response = HttpResponse::from_http(response).unwrap();
+        let chunks: Vec<_> = response.text_chunks().map(Result::unwrap).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1], "é\n");
+        assert_eq!(chunks.concat().as_bytes(), &body[..]);
+
+        let response = Builder::new()
+            .url(Url::parse("http://example.com").unwrap())
+            .header("Content-Type", "text/plain; charset=windows-1252")
+            .body(b"caf\xe9 \x80".to_vec())
+            .unwrap();
+        let response = HttpResponse::from_http(response).unwrap();
+        let text: String = response.text_chunks().map(Result::unwrap).collect();
+        assert_eq!(text, "café €");
+    }
+}
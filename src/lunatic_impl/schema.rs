@@ -0,0 +1,221 @@
+//! Versioning of the messages exchanged with the client process.
+//!
+//! Requests are sent to the client process as `InnerRequest` and responses
+//! come back as `SerializableResponse`. Both carry the schema version they
+//! were written with as their first field, so a process running another
+//! version of nightfly rejects a message it can't read with a clear error,
+//! instead of misreading it.
+//!
+//! # Schema 1
+//!
+//! `SerializableResponse`, in order:
+//!
+//! | field            | type                            |
+//! |------------------|---------------------------------|
+//! | `schema`         | `u32`, always 1                 |
+//! | `body`           | bytes, decoded                  |
+//! | `status`         | `u16`                           |
+//! | `version`        | `Version`                       |
+//! | `headers`        | map of name to list of values   |
+//! | `url`            | `Url`                           |
+//! | `redirect_chain` | list of `Url`                   |
+//! | `request_id`     | optional string                 |
+//! | `raw_body`       | optional bytes                  |
+//! | `raw_headers`    | list of name and value bytes    |
+//! | `body_hash`      | optional bytes                  |
+//!
+//! `InnerRequest`, in order:
+//!
+//! | field            | type                               |
+//! |------------------|------------------------------------|
+//! | `schema`         | `u32`, always 1                    |
+//! | `method`         | string                             |
+//! | `url`            | `Url`                              |
+//! | `headers`        | map of name to list of values      |
+//! | `body`           | optional `Body`                    |
+//! | `timeout`        | optional `Duration`                |
+//! | `phase_timeouts` | list of `TimeoutPhase`, `Duration` |
+//! | `version`        | `Version`                          |
+//! | `labels`         | list of name and value strings     |
+//! | `tee`            | `bool`                             |
+//! | `hash_body`      | optional `Algorithm`               |
+//! | `no_cookies`     | `bool`                             |
+//! | `root_certs`     | list of `Certificate`              |
+//! | `proxy_protocol` | optional `ProxyHeader`             |
+//!
+//! `SerializableRequest`, for requests stored or sent elsewhere before
+//! they are executed, in order:
+//!
+//! | field     | type                           |
+//! |-----------|--------------------------------|
+//! | `schema`  | `u32`, always 1                |
+//! | `method`  | string                         |
+//! | `url`     | `Url`                          |
+//! | `headers` | map of name to list of values  |
+//! | `body`    | optional bytes                 |
+//! | `timeout` | optional `Duration`            |
+//! | `labels`  | list of name and value strings |
+//!
+//! Self-describing formats such as JSON may leave out `schema`, which is
+//! then read as schema 1, and `raw_body`, `raw_headers`, `body_hash`,
+//! `phase_timeouts`, `hash_body`, `no_cookies`, `root_certs`,
+//! `proxy_protocol`, `timeout` and `labels`, which are then empty.
+//!
+//! Any change to the fields above, other than adding fields that may be
+//! left out, bumps `SCHEMA_VERSION`.
+
+use serde::{de, Deserialize, Deserializer};
+
+/// Version of the messages exchanged with the client process, see
+/// `SerializableResponse`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub(crate) fn current() -> u32 {
+    SCHEMA_VERSION
+}
+
+/// Reads the schema version of a message, and fails if it isn't the one this
+/// version of nightfly writes.
+pub(crate) fn check<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let schema = u32::deserialize(deserializer)?;
+    if schema != SCHEMA_VERSION {
+        return Err(de::Error::custom(format_args!(
+            "unsupported nightfly message schema {}, expected {}",
+            schema, SCHEMA_VERSION
+        )));
+    }
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use url::Url;
+
+    use super::SCHEMA_VERSION;
+    use crate::lunatic_impl::request::InnerRequest;
+    use crate::{Body, HttpResponse, Method, Request, SerializableRequest, SerializableResponse};
+
+    const RESPONSE_V1: &str = r#"{
+        "schema": 1,
+        "body": [104, 105],
+        "status": 201,
+        "version": "Http11",
+        "headers": {"content-type": ["text/plain"]},
+        "url": "http://example.com/b",
+        "redirect_chain": ["http://example.com/a"],
+        "request_id": "abc",
+        "raw_body": null,
+        "raw_headers": [["Content-Type", [116, 101, 120, 116, 47, 112, 108, 97, 105, 110]]],
+        "body_hash": null
+    }"#;
+
+    #[lunatic::test]
+    fn reads_schema_1_response() {
+        let res: SerializableResponse = serde_json::from_str(RESPONSE_V1).unwrap();
+        let res = HttpResponse::try_from(res).unwrap();
+        assert_eq!(res.status(), 201);
+        assert_eq!(res.version(), crate::Version::HTTP_11);
+        assert_eq!(res.headers()["content-type"], "text/plain");
+        assert_eq!(res.url().as_str(), "http://example.com/b");
+        assert_eq!(res.redirect_chain.len(), 1);
+        assert_eq!(res.request_id(), Some("abc"));
+        assert_eq!(
+            res.raw_headers().next(),
+            Some(("Content-Type", &b"text/plain"[..]))
+        );
+        assert_eq!(res.body(), b"hi");
+    }
+
+    #[lunatic::test]
+    fn response_round_trip() {
+        let res: SerializableResponse = serde_json::from_str(RESPONSE_V1).unwrap();
+        let res = HttpResponse::try_from(res).unwrap();
+        let json = serde_json::to_value(SerializableResponse::from(res)).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(RESPONSE_V1).unwrap();
+        assert_eq!(json, expected);
+    }
+
+    #[lunatic::test]
+    fn reads_response_without_optional_fields() {
+        let mut json: serde_json::Value = serde_json::from_str(RESPONSE_V1).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("schema");
+        fields.remove("raw_body");
+        fields.remove("raw_headers");
+        fields.remove("body_hash");
+        let res: SerializableResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(res.schema, SCHEMA_VERSION);
+        assert!(res.raw_headers.is_empty());
+    }
+
+    #[lunatic::test]
+    fn rejects_other_schemas() {
+        let mut json: serde_json::Value = serde_json::from_str(RESPONSE_V1).unwrap();
+        json["schema"] = 2.into();
+        let err = serde_json::from_value::<SerializableResponse>(json).unwrap_err();
+        assert!(err.to_string().contains("schema 2"), "{}", err);
+    }
+
+    #[lunatic::test]
+    fn request_round_trip() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut req = Request::new(Method::POST, url.clone());
+        *req.body_mut() = Some("hi".into());
+        let inner = InnerRequest::try_from(req).unwrap();
+        assert_eq!(inner.schema, SCHEMA_VERSION);
+
+        let json = serde_json::to_string(&inner).unwrap();
+        let inner: InnerRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(inner.method, "POST");
+        assert_eq!(inner.url, url);
+
+        let mut json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        json["schema"] = 2.into();
+        assert!(serde_json::from_value::<InnerRequest>(json).is_err());
+    }
+
+    #[lunatic::test]
+    fn serializable_request_round_trip() {
+        let url = Url::parse("http://example.com/events").unwrap();
+        let mut req = Request::new(Method::PUT, url.clone());
+        req.headers_mut()
+            .append("x-tag", http::HeaderValue::from_static("a"));
+        req.headers_mut()
+            .append("x-tag", http::HeaderValue::from_static("b"));
+        *req.body_mut() = Some("hi".into());
+        *req.timeout_mut() = Some(std::time::Duration::from_secs(3));
+
+        let saved = SerializableRequest::try_from(req).unwrap();
+        let json = serde_json::to_string(&saved).unwrap();
+        let read: SerializableRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, saved);
+
+        let req = Request::try_from(read).unwrap();
+        assert_eq!(req.method(), Method::PUT);
+        assert_eq!(req.url(), &url);
+        let tags: Vec<_> = req.headers().get_all("x-tag").iter().collect();
+        assert_eq!(tags, ["a", "b"]);
+        assert_eq!(req.body().unwrap().as_bytes(), b"hi");
+        assert_eq!(req.timeout(), Some(&std::time::Duration::from_secs(3)));
+    }
+
+    #[lunatic::test]
+    fn serializable_request_rejects_what_it_cant_keep() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut req = Request::new(Method::POST, url.clone());
+        *req.body_mut() = Some(Body::from_reader(std::io::empty(), None));
+        assert!(SerializableRequest::try_from(req).unwrap_err().is_builder());
+
+        let json = serde_json::json!({
+            "method": "NOT A METHOD",
+            "url": url,
+            "headers": {},
+            "body": null,
+        });
+        let req: SerializableRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(req.schema, SCHEMA_VERSION);
+        assert!(Request::try_from(req).unwrap_err().is_builder());
+    }
+}
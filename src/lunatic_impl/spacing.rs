@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::{TimeSource, Url};
+
+/// Keeps requests to the same host at least `min_delay` apart.
+///
+/// Every clone of a `Client` sends its requests through the same client
+/// process, so spacing them out here covers all the processes sharing it.
+#[derive(Clone, Debug)]
+pub(crate) struct HostSpacing {
+    min_delay: Duration,
+    last_request: HashMap<String, SystemTime>,
+}
+
+impl HostSpacing {
+    pub(crate) fn new(min_delay: Duration) -> HostSpacing {
+        HostSpacing {
+            min_delay,
+            last_request: HashMap::new(),
+        }
+    }
+
+    /// Sleeps until a request to `url`'s host may be sent, and records it as
+    /// sent.
+    pub(crate) fn wait(&mut self, url: &Url, time: &TimeSource) {
+        let host = url.host_str().unwrap_or_default().to_string();
+        if let Some(last) = self.last_request.get(&host) {
+            let since = time.since(*last);
+            if since < self.min_delay {
+                time.sleep(self.min_delay - since);
+            }
+        }
+        self.last_request.insert(host, time.now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+
+    #[lunatic::test]
+    fn spaces_requests_per_host() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut spacing = HostSpacing::new(Duration::from_millis(500));
+        let a = Url::parse("http://a.example/1").unwrap();
+        let b = Url::parse("http://b.example/1").unwrap();
+
+        spacing.wait(&a, &time);
+        spacing.wait(&b, &time);
+        assert_eq!(time.since(SystemTime::UNIX_EPOCH), Duration::ZERO);
+
+        time.sleep(Duration::from_millis(200));
+        spacing.wait(&a, &time);
+        assert_eq!(
+            time.since(SystemTime::UNIX_EPOCH),
+            Duration::from_millis(500)
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Request bodies read from a reader, see `Body::from_reader`.
+//!
+//! The reader stays in the calling process, as it can't be sent to the
+//! client's. The client writes the request head and hands the connection
+//! over, the body is written onto it from here, and the connection goes back
+//! to the client to read the response.
+
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use lunatic::time::Timeout;
+use serde::{Deserialize, Serialize};
+
+use super::body::{self, Reader};
+use super::client::{InnerClientHandlers, InnerClientTimeoutHandlers};
+use super::http_stream::HttpStream;
+use super::request::InnerRequest;
+use super::response::SerializableResponse;
+use crate::{error, Client, HttpResponse, Request, TimeoutPhase};
+
+/// Size of the chunks a body of unknown length is sent in.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The client's answer to the head of a request.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Upload {
+    /// Write the body onto `stream`, in chunks if `chunked`, then hand it
+    /// back with `request`.
+    Open {
+        stream: HttpStream,
+        request: InnerRequest,
+        chunked: bool,
+    },
+    /// The request was answered without its body, by an injected fault.
+    Answered(SerializableResponse),
+}
+
+pub(crate) fn execute(
+    client: &Client,
+    mut request: Request,
+    reader: Reader,
+    len: Option<u64>,
+) -> crate::Result<HttpResponse> {
+    request.body = None;
+    let inner = InnerRequest::try_from(request)?;
+    let url = inner.url.clone();
+    let timeout = inner.timeout.or_else(|| client.0.get_request_timeout());
+    let started = Instant::now();
+    let timed_out = |allowed: Duration| {
+        error::timeout_in(
+            TimeoutPhase::Request,
+            started.elapsed(),
+            allowed,
+            url.clone(),
+        )
+    };
+
+    let upload = match timeout {
+        Some(allowed) => within(
+            client.0.with_timeout(allowed).open_upload(inner, len),
+            || timed_out(allowed),
+        )?,
+        None => client.0.open_upload(inner, len)?,
+    };
+    let (mut stream, request, chunked) = match upload {
+        Upload::Open {
+            stream,
+            request,
+            chunked,
+        } => (stream, request, chunked),
+        Upload::Answered(res) => return res.try_into(),
+    };
+
+    let mut reader = body::lock(&reader);
+    let written = match (chunked, len) {
+        (true, _) => write_chunked(&mut *reader, &mut stream),
+        (false, Some(len)) => write_sized(&mut *reader, len, &mut stream),
+        (false, None) => io::copy(&mut *reader, &mut stream).map(drop),
+    };
+    drop(reader);
+    written.map_err(|e| error::body(e).with_url(url.clone()))?;
+
+    let res = match timeout {
+        Some(allowed) => {
+            let left = allowed.saturating_sub(started.elapsed());
+            within(
+                client.0.with_timeout(left).close_upload(request, stream),
+                || timed_out(allowed),
+            )?
+        }
+        None => client.0.close_upload(request, stream)?,
+    };
+    res.try_into()
+}
+
+fn within<T>(
+    res: Result<crate::Result<T>, Timeout>,
+    timed_out: impl FnOnce() -> crate::Error,
+) -> crate::Result<T> {
+    res.unwrap_or_else(|_| Err(timed_out()))
+}
+
+/// Copies exactly `len` bytes, the `Content-Length` that was sent.
+fn write_sized<R: Read + ?Sized, W: Write>(
+    reader: &mut R,
+    len: u64,
+    out: &mut W,
+) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(len), out)?;
+    if copied < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("body ended after {} of {} bytes", copied, len),
+        ));
+    }
+    out.flush()
+}
+
+/// Copies everything with `Transfer-Encoding: chunked` framing.
+fn write_chunked<R: Read + ?Sized, W: Write>(reader: &mut R, out: &mut W) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write!(out, "{:x}\r\n", n)?;
+        out.write_all(&buf[..n])?;
+        out.write_all(b"\r\n")?;
+    }
+    out.write_all(b"0\r\n\r\n")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn writes_chunks() {
+        let mut out = Vec::new();
+        write_chunked(&mut &b"hello world"[..], &mut out).unwrap();
+        assert_eq!(out, b"b\r\nhello world\r\n0\r\n\r\n");
+
+        let mut out = Vec::new();
+        write_chunked(&mut io::empty(), &mut out).unwrap();
+        assert_eq!(out, b"0\r\n\r\n");
+    }
+
+    #[lunatic::test]
+    fn writes_declared_length() {
+        let mut out = Vec::new();
+        write_sized(&mut &b"hello world"[..], 5, &mut out).unwrap();
+        assert_eq!(out, b"hello");
+
+        let err = write_sized(&mut &b"hi"[..], 5, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
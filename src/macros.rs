@@ -0,0 +1,180 @@
+//! Support for the `request!`, `get!` and `post!` macros.
+
+#[doc(hidden)]
+pub use serde_json;
+
+/// Fails to compile unless `name` is a valid header name, a non-empty
+/// token as defined by RFC 9110. Upper case letters are allowed, header
+/// names are lower-cased when they are sent.
+#[doc(hidden)]
+pub const fn check_header_name(name: &str) {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() {
+        panic!("header names can't be empty");
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => {}
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_'
+            | b'`' | b'|' | b'~' => {}
+            _ => panic!("invalid character in header name"),
+        }
+        i += 1;
+    }
+}
+
+/// Builds a request from a URL format string, header literals and a JSON
+/// body in one go.
+///
+/// The client and method come first, followed by the URL and its arguments
+/// as they would be passed to `format!`. After a `;`, the request can be
+/// given:
+///
+/// - `headers { "name": value, ... }`, where every name is a string literal
+///   checked at compile time, and every value anything
+///   `RequestBuilder::header` accepts.
+/// - `json: value`, sending any `Serialize` value as the JSON body, or
+///   `json { ... }`, building the body in place with the syntax of
+///   `serde_json::json!`.
+///
+/// The result is a `RequestBuilder`, so anything else can still be set on
+/// it before it is sent.
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let client = nightfly::Client::new();
+/// let (org, key) = ("lunatic", "secret");
+///
+/// let res = nightfly::request!(client, nightfly::Method::PUT, "https://api.example.com/orgs/{}", org;
+///     headers { "x-api-key": key, "accept": "application/json" },
+///     json { "name": org, "public": true },
+/// )
+/// .send()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A header name that isn't valid doesn't compile:
+///
+/// ```compile_fail
+/// let client = nightfly::Client::new();
+/// nightfly::get!(client, "https://example.com/"; headers { "x api key": "secret" });
+/// ```
+///
+/// Requires the `macros` feature.
+#[macro_export]
+macro_rules! request {
+    ($client:expr, $method:expr, $url:literal $(, $arg:expr)* $(; $($options:tt)*)?) => {{
+        let builder = $client.request($method, ::std::format!($url $(, $arg)*));
+        $crate::__request_options!(builder; $($($options)*)?)
+    }};
+}
+
+/// Builds a GET request, see [`request!`].
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let client = nightfly::Client::new();
+/// let user = 42;
+///
+/// let res = nightfly::get!(client, "https://api.example.com/users/{}", user;
+///     headers { "accept": "application/json" },
+/// )
+/// .send()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Requires the `macros` feature.
+#[macro_export]
+macro_rules! get {
+    ($client:expr, $($rest:tt)*) => {
+        $crate::request!($client, $crate::Method::GET, $($rest)*)
+    };
+}
+
+/// Builds a POST request, see [`request!`].
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let client = nightfly::Client::new();
+/// let token = "secret";
+///
+/// let res = nightfly::post!(client, "https://api.example.com/users";
+///     headers { "authorization": format!("Bearer {}", token) },
+///     json { "name": "Ferris", "admin": false },
+/// )
+/// .send()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Requires the `macros` feature.
+#[macro_export]
+macro_rules! post {
+    ($client:expr, $($rest:tt)*) => {
+        $crate::request!($client, $crate::Method::POST, $($rest)*)
+    };
+}
+
+/// Applies the options of a [`request!`] to a `RequestBuilder`, one at a
+/// time.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __request_options {
+    ($builder:ident;) => {
+        $builder
+    };
+    ($builder:ident; headers { $($name:literal : $value:expr),* $(,)? } $(, $($rest:tt)*)?) => {{
+        $(const _: () = $crate::macros::check_header_name($name);)*
+        let builder = $builder $(.header($name, $value))*;
+        $crate::__request_options!(builder; $($($rest)*)?)
+    }};
+    ($builder:ident; json: $body:expr $(, $($rest:tt)*)?) => {{
+        let builder = $builder.json(&$body);
+        $crate::__request_options!(builder; $($($rest)*)?)
+    }};
+    ($builder:ident; json { $($body:tt)* } $(, $($rest:tt)*)?) => {{
+        let builder = $builder.json(&$crate::macros::serde_json::json!({ $($body)* }));
+        $crate::__request_options!(builder; $($($rest)*)?)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Client, Method};
+
+    #[lunatic::test]
+    fn builds_requests() {
+        let client = Client::new();
+        let (user, key) = (42, "secret");
+
+        let req = crate::get!(client, "https://api.example.com/users/{}", user)
+            .build()
+            .unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(req.url().as_str(), "https://api.example.com/users/42");
+
+        let req = crate::post!(client, "https://api.example.com/users";
+            headers { "X-Api-Key": key, "accept": "application/json", },
+            json { "name": "Ferris", "id": user },
+        )
+        .build()
+        .unwrap();
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.headers()["x-api-key"], "secret");
+        assert_eq!(req.headers()["accept"], "application/json");
+        assert_eq!(req.headers()["content-type"], "application/json");
+        let body = req.body().unwrap().as_bytes();
+        assert_eq!(body, br#"{"id":42,"name":"Ferris"}"#);
+
+        let req = crate::request!(client, Method::DELETE, "https://api.example.com/users/{user}";
+            json: ["a", "b"]
+        )
+        .build()
+        .unwrap();
+        assert_eq!(req.method(), Method::DELETE);
+        assert_eq!(req.body().unwrap().as_bytes(), br#"["a","b"]"#);
+    }
+}
@@ -0,0 +1,294 @@
+//! Counters kept by a `Client` about the requests it sends.
+//!
+//! A [`MetricsSnapshot`] is obtained with `Client::metrics_snapshot()` and
+//! can be rendered in the Prometheus text exposition format, which makes it
+//! easy to serve from a `/metrics` endpoint:
+//!
+//! ```rust
+//! # fn run() {
+//! let client = nightfly::Client::new();
+//! let body = client.metrics_snapshot().render();
+//! # }
+//! ```
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds, in seconds, of the request duration histogram buckets.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A point-in-time copy of a `Client`'s metrics.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Number of requests sent, including the ones that failed.
+    pub requests: u64,
+    /// Responses counted by status class: `1xx`, `2xx`, `3xx`, `4xx`, `5xx`.
+    pub responses_by_class: [u64; 5],
+    /// Requests that failed without producing a response.
+    pub errors: u64,
+    /// Cumulative request duration histogram as `(upper bound in seconds, count)`
+    /// pairs. Requests slower than the last bound are only part of `requests`.
+    pub duration_buckets: Vec<(f64, u64)>,
+    /// Sum of the durations of all requests, in seconds.
+    pub duration_sum: f64,
+    /// Number of connections currently kept open by the client.
+    pub open_connections: usize,
+    /// Responses and errors of labelled requests, one entry per distinct
+    /// set of labels (see `RequestBuilder::label`).
+    pub labeled: Vec<LabeledCounts>,
+}
+
+/// Outcomes of the requests that carried a given set of labels.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabeledCounts {
+    /// The labels, in the order they were added to the requests.
+    pub labels: Vec<(String, String)>,
+    /// Responses counted by status class: `1xx`, `2xx`, `3xx`, `4xx`, `5xx`.
+    pub responses_by_class: [u64; 5],
+    /// Requests that failed without producing a response.
+    pub errors: u64,
+}
+
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        // writing into a `String` cannot fail
+        let _ = self.write_to(&mut out);
+        out
+    }
+
+    fn write_to(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "# HELP nightfly_responses_total Responses received by status class."
+        )?;
+        writeln!(out, "# TYPE nightfly_responses_total counter")?;
+        for (i, count) in self.responses_by_class.iter().enumerate() {
+            writeln!(
+                out,
+                "nightfly_responses_total{{class=\"{}xx\"}} {}",
+                i + 1,
+                count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP nightfly_errors_total Requests that failed without a response."
+        )?;
+        writeln!(out, "# TYPE nightfly_errors_total counter")?;
+        writeln!(out, "nightfly_errors_total {}", self.errors)?;
+
+        if !self.labeled.is_empty() {
+            writeln!(
+                out,
+                "# HELP nightfly_labeled_responses_total Responses of labelled requests by status class."
+            )?;
+            writeln!(out, "# TYPE nightfly_labeled_responses_total counter")?;
+            for counts in &self.labeled {
+                let labels = format_labels(&counts.labels);
+                for (i, count) in counts.responses_by_class.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "nightfly_labeled_responses_total{{{},class=\"{}xx\"}} {}",
+                        labels,
+                        i + 1,
+                        count
+                    )?;
+                }
+            }
+
+            writeln!(
+                out,
+                "# HELP nightfly_labeled_errors_total Labelled requests that failed without a response."
+            )?;
+            writeln!(out, "# TYPE nightfly_labeled_errors_total counter")?;
+            for counts in &self.labeled {
+                writeln!(
+                    out,
+                    "nightfly_labeled_errors_total{{{}}} {}",
+                    format_labels(&counts.labels),
+                    counts.errors
+                )?;
+            }
+        }
+
+        writeln!(
+            out,
+            "# HELP nightfly_request_duration_seconds Duration of requests."
+        )?;
+        writeln!(out, "# TYPE nightfly_request_duration_seconds histogram")?;
+        for (le, count) in &self.duration_buckets {
+            writeln!(
+                out,
+                "nightfly_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, count
+            )?;
+        }
+        writeln!(
+            out,
+            "nightfly_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.requests
+        )?;
+        writeln!(
+            out,
+            "nightfly_request_duration_seconds_sum {}",
+            self.duration_sum
+        )?;
+        writeln!(
+            out,
+            "nightfly_request_duration_seconds_count {}",
+            self.requests
+        )?;
+
+        writeln!(
+            out,
+            "# HELP nightfly_open_connections Connections kept open by the client."
+        )?;
+        writeln!(out, "# TYPE nightfly_open_connections gauge")?;
+        writeln!(out, "nightfly_open_connections {}", self.open_connections)
+    }
+}
+
+/// Formats labels as the comma separated `key="value"` pairs of a sample.
+fn format_labels(labels: &[(String, String)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Metrics state owned by the client process.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Metrics {
+    snapshot: MetricsSnapshot,
+    duration_counts: [u64; DURATION_BUCKETS.len()],
+}
+
+impl Metrics {
+    pub(crate) fn record(
+        &mut self,
+        status: Option<StatusCode>,
+        elapsed: Duration,
+        labels: &[(String, String)],
+    ) {
+        self.snapshot.requests += 1;
+        let class = status_class(status);
+        match class {
+            Some(class) => self.snapshot.responses_by_class[class] += 1,
+            None if status.is_none() => self.snapshot.errors += 1,
+            None => {}
+        }
+
+        if !labels.is_empty() {
+            let position = self
+                .snapshot
+                .labeled
+                .iter()
+                .position(|counts| counts.labels == labels);
+            let counts = match position {
+                Some(i) => &mut self.snapshot.labeled[i],
+                None => {
+                    self.snapshot.labeled.push(LabeledCounts {
+                        labels: labels.to_vec(),
+                        ..LabeledCounts::default()
+                    });
+                    self.snapshot.labeled.last_mut().unwrap()
+                }
+            };
+            match class {
+                Some(class) => counts.responses_by_class[class] += 1,
+                None if status.is_none() => counts.errors += 1,
+                None => {}
+            }
+        }
+
+        let secs = elapsed.as_secs_f64();
+        self.snapshot.duration_sum += secs;
+        for (count, le) in self.duration_counts.iter_mut().zip(DURATION_BUCKETS) {
+            if secs <= le {
+                *count += 1;
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self, open_connections: usize) -> MetricsSnapshot {
+        MetricsSnapshot {
+            duration_buckets: DURATION_BUCKETS
+                .iter()
+                .copied()
+                .zip(self.duration_counts.iter().copied())
+                .collect(),
+            open_connections,
+            ..self.snapshot.clone()
+        }
+    }
+}
+
+/// Index of the status class of `status` in `responses_by_class`.
+fn status_class(status: Option<StatusCode>) -> Option<usize> {
+    let class = (status?.as_u16() / 100) as usize;
+    if (1..=5).contains(&class) {
+        Some(class - 1)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn records_status_classes_and_durations() {
+        let mut metrics = Metrics::default();
+        metrics.record(Some(StatusCode::OK), Duration::from_millis(3), &[]);
+        metrics.record(Some(StatusCode::NOT_FOUND), Duration::from_millis(200), &[]);
+        metrics.record(None, Duration::from_secs(30), &[]);
+
+        let snapshot = metrics.snapshot(1);
+        assert_eq!(snapshot.requests, 3);
+        assert_eq!(snapshot.responses_by_class, [0, 1, 0, 1, 0]);
+        assert_eq!(snapshot.errors, 1);
+        assert_eq!(snapshot.duration_buckets[0], (0.005, 1));
+        assert_eq!(snapshot.duration_buckets[5], (0.25, 2));
+        assert_eq!(snapshot.duration_buckets[10], (10.0, 2));
+
+        let text = snapshot.render();
+        assert!(text.contains("nightfly_responses_total{class=\"4xx\"} 1"));
+        assert!(text.contains("nightfly_request_duration_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(text.contains("nightfly_open_connections 1"));
+    }
+
+    #[lunatic::test]
+    fn groups_by_labels() {
+        let labels = vec![("endpoint".to_string(), "get_user".to_string())];
+        let mut metrics = Metrics::default();
+        metrics.record(Some(StatusCode::OK), Duration::from_millis(3), &labels);
+        metrics.record(None, Duration::from_millis(3), &labels);
+        metrics.record(Some(StatusCode::OK), Duration::from_millis(3), &[]);
+
+        let snapshot = metrics.snapshot(0);
+        assert_eq!(snapshot.labeled.len(), 1);
+        assert_eq!(snapshot.labeled[0].responses_by_class, [0, 1, 0, 0, 0]);
+        assert_eq!(snapshot.labeled[0].errors, 1);
+
+        let text = snapshot.render();
+        assert!(text
+            .contains("nightfly_labeled_responses_total{endpoint=\"get_user\",class=\"2xx\"} 1"));
+        assert!(text.contains("nightfly_labeled_errors_total{endpoint=\"get_user\"} 1"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Mirroring a sample of requests to a second backend.
+//!
+//! To try a new backend with production traffic, a client built with
+//! `ClientBuilder::mirror` sends a copy of some of its requests to it as
+//! well. The copies, or shadow requests, go to the mirror's base URL with the
+//! path and query of the original request, and their responses are thrown
+//! away. The caller only ever sees the response of the original request.
+//!
+//! ```rust
+//! use nightfly::mirror::Mirror;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::builder()
+//!     // one request in ten is also sent to https://canary.internal/api/...
+//!     .mirror(Mirror::new("https://canary.internal/api")?.sample(0.1))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Shadow requests are sent by a client process of their own, with the same
+//! configuration as the client that mirrors them. A slow or failing shadow
+//! backend never delays the original requests, only the shadow requests
+//! queued after it. Requests with a streamed body aren't mirrored.
+//!
+//! Every method is mirrored by default. A backend that writes to shared
+//! storage should probably only get `GET` and `HEAD` requests, see
+//! [`Mirror::methods`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::lunatic_impl::request::InnerRequest;
+use crate::{IntoUrl, Method, Url};
+
+/// Where and how many requests to mirror, see the module documentation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mirror {
+    base: Url,
+    sample: f64,
+    methods: Option<Vec<String>>,
+}
+
+impl Mirror {
+    /// Mirror every request to `base`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `base` isn't a valid URL.
+    pub fn new<U: IntoUrl>(base: U) -> crate::Result<Mirror> {
+        Ok(Mirror {
+            base: base.into_url()?,
+            sample: 1.0,
+            methods: None,
+        })
+    }
+
+    /// The share of requests to mirror, such as `0.05` for one request in
+    /// twenty. Requests are picked evenly rather than at random, so the
+    /// share holds however few requests are sent.
+    ///
+    /// Default is 1.0.
+    pub fn sample(mut self, ratio: f64) -> Mirror {
+        self.sample = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Only mirror requests with one of these methods.
+    ///
+    /// Default is every method.
+    pub fn methods<I>(mut self, methods: I) -> Mirror
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.methods = Some(
+            methods
+                .into_iter()
+                .map(|method| method.as_str().to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Where to send the copy of a request to `url`: the path of `url`
+    /// appended to the path of the base URL, and the query of `url`.
+    fn shadow_url(&self, url: &Url) -> Url {
+        let mut shadow = self.base.clone();
+        let path = format!("{}{}", self.base.path().trim_end_matches('/'), url.path());
+        shadow.set_path(&path);
+        shadow.set_query(url.query());
+        shadow
+    }
+}
+
+/// Picks the requests to mirror, inside the client process.
+#[derive(Clone, Debug)]
+pub(crate) struct Mirrorer {
+    config: Mirror,
+    // grows by the sample ratio with every request, a request is mirrored
+    // whenever it reaches 1
+    credit: f64,
+}
+
+impl Mirrorer {
+    pub(crate) fn new(config: Mirror) -> Mirrorer {
+        Mirrorer {
+            config,
+            credit: 0.0,
+        }
+    }
+
+    /// The shadow copy of `req`, if it is to be mirrored.
+    pub(crate) fn shadow(&mut self, req: &InnerRequest) -> Option<InnerRequest> {
+        if let Some(ref methods) = self.config.methods {
+            if !methods.contains(&req.method) {
+                return None;
+            }
+        }
+        self.credit += self.config.sample;
+        if self.credit < 1.0 {
+            return None;
+        }
+        self.credit -= 1.0;
+
+        let mut shadow = req.clone();
+        shadow.url = self.config.shadow_url(&req.url);
+        shadow.headers.remove(http::header::HOST.as_str());
+        Some(shadow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    fn request(method: Method, url: &str) -> InnerRequest {
+        let mut req = crate::Request::new(method, Url::parse(url).unwrap());
+        req.headers_mut()
+            .insert(http::header::HOST, "api.example.com".parse().unwrap());
+        InnerRequest::try_from(req).unwrap()
+    }
+
+    #[lunatic::test]
+    fn rewrites_the_url() {
+        let mirror = Mirror::new("https://canary.internal:8443/api/").unwrap();
+        let mut mirrorer = Mirrorer::new(mirror);
+        let shadow = mirrorer
+            .shadow(&request(
+                Method::GET,
+                "https://api.example.com/users/1?fields=name",
+            ))
+            .unwrap();
+        assert_eq!(
+            shadow.url.as_str(),
+            "https://canary.internal:8443/api/users/1?fields=name"
+        );
+        assert!(!shadow.headers.contains_key("host"));
+    }
+
+    #[lunatic::test]
+    fn samples_evenly() {
+        let mut mirrorer = Mirrorer::new(Mirror::new("http://canary").unwrap().sample(0.25));
+        let req = request(Method::GET, "https://api.example.com/");
+        let mirrored: Vec<bool> = (0..8).map(|_| mirrorer.shadow(&req).is_some()).collect();
+        assert_eq!(
+            mirrored,
+            [false, false, false, true, false, false, false, true]
+        );
+
+        let mut never = Mirrorer::new(Mirror::new("http://canary").unwrap().sample(0.0));
+        assert!((0..100).all(|_| never.shadow(&req).is_none()));
+    }
+
+    #[lunatic::test]
+    fn filters_methods() {
+        let mirror = Mirror::new("http://canary")
+            .unwrap()
+            .methods([Method::GET, Method::HEAD]);
+        let mut mirrorer = Mirrorer::new(mirror);
+        assert!(mirrorer
+            .shadow(&request(Method::POST, "https://api.example.com/"))
+            .is_none());
+        assert!(mirrorer
+            .shadow(&request(Method::HEAD, "https://api.example.com/"))
+            .is_some());
+    }
+}
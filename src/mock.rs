@@ -0,0 +1,368 @@
+//! Canned responses for unit tests.
+//!
+//! A [`MockClient`] hands out a `Client` that never touches the network. It
+//! answers every request with the response of the first rule that matches
+//! it, and fails requests that no rule matches. Counting the calls each rule
+//! got lets tests check what was sent:
+//!
+//! ```rust
+//! use nightfly::mock::MockClient;
+//! use nightfly::Method;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let mut mock = MockClient::new();
+//! mock.when(Method::GET, "/users/*")
+//!     .respond(200)
+//!     .json(&serde_json::json!({ "name": "Ferris" }))
+//!     .times(1);
+//!
+//! let client = mock.client()?;
+//! let user: serde_json::Value = client.get_json("https://api.example.com/users/1")?;
+//! assert_eq!(user["name"], "Ferris");
+//! mock.verify();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only available with the `mock` feature, which is meant for test builds.
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::lunatic_impl::client::InnerClientHandlers;
+use crate::{Client, ClientBuilder, HttpResponse, Method, Url, Version};
+
+/// Hands out a `Client` answering requests from rules, see the module
+/// documentation.
+#[derive(Debug)]
+pub struct MockClient {
+    builder: ClientBuilder,
+    rules: Vec<MockRule>,
+    client: Option<Client>,
+}
+
+/// Which requests a rule matches and how it answers them, see
+/// `MockClient::when`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MockRule {
+    method: String,
+    pattern: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+    times: Option<usize>,
+}
+
+impl MockClient {
+    /// A mock with no rules, for a client with the default configuration.
+    pub fn new() -> MockClient {
+        MockClient::with_builder(ClientBuilder::new())
+    }
+
+    /// A mock with no rules, for a client configured by `builder`.
+    pub fn with_builder(builder: ClientBuilder) -> MockClient {
+        MockClient {
+            builder,
+            rules: Vec::new(),
+            client: None,
+        }
+    }
+
+    /// Adds a rule for requests with `method` and a URL matching `pattern`.
+    /// It answers `200 OK` with an empty body until told otherwise.
+    ///
+    /// A pattern starting with `/` is matched against the path of the URL,
+    /// any other against the whole URL. A `*` in it matches any run of
+    /// characters, `/` included.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client was already started, rules have to be added
+    /// before calling `client`.
+    pub fn when<P: Into<String>>(&mut self, method: Method, pattern: P) -> &mut MockRule {
+        assert!(
+            self.client.is_none(),
+            "MockClient rules must be added before the client is started"
+        );
+        self.rules.push(MockRule {
+            method: method.as_str().to_string(),
+            pattern: pattern.into(),
+            status: 200,
+            headers: Vec::new(),
+            body: Bytes::new(),
+            times: None,
+        });
+        self.rules.last_mut().expect("rule was just added")
+    }
+
+    /// The client answering from the rules. It is started on the first call,
+    /// and every later call returns a clone of it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the `ClientBuilder` given to `with_builder` doesn't build.
+    pub fn client(&mut self) -> crate::Result<Client> {
+        if let Some(ref client) = self.client {
+            return Ok(client.clone());
+        }
+        let client = self.builder.clone().mock(self.rules.clone()).build()?;
+        self.client = Some(client.clone());
+        Ok(client)
+    }
+
+    /// How many requests each rule answered so far, in the order the rules
+    /// were added.
+    pub fn calls(&self) -> Vec<usize> {
+        match self.client {
+            Some(ref client) => client.0.get_mock_calls(),
+            None => vec![0; self.rules.len()],
+        }
+    }
+
+    /// Checks that every rule given a count with `MockRule::times` answered
+    /// exactly that many requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the rules that didn't, if any.
+    pub fn verify(&self) {
+        let failed = self.unmet(&self.calls());
+        assert!(failed.is_empty(), "{}", failed.join("\n"));
+    }
+
+    /// The expectations set with `MockRule::times` that `calls` don't meet.
+    fn unmet(&self, calls: &[usize]) -> Vec<String> {
+        self.rules
+            .iter()
+            .zip(calls)
+            .filter_map(|(rule, calls)| match rule.times {
+                Some(times) if times != *calls => Some(format!(
+                    "{} {}: expected {} calls, got {}",
+                    rule.method, rule.pattern, times, calls
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> MockClient {
+        MockClient::new()
+    }
+}
+
+impl MockRule {
+    /// Answer with this status code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't between 100 and 999.
+    pub fn respond(&mut self, status: u16) -> &mut MockRule {
+        assert!(
+            StatusCode::from_u16(status).is_ok(),
+            "invalid status code {}",
+            status
+        );
+        self.status = status;
+        self
+    }
+
+    /// Add a header to the response.
+    pub fn header<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut MockRule {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Answer with this body.
+    pub fn body<B: Into<Bytes>>(&mut self, body: B) -> &mut MockRule {
+        self.body = body.into();
+        self
+    }
+
+    /// Answer with `value` serialized as JSON, and a
+    /// `Content-Type: application/json` header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` can't be serialized.
+    pub fn json<T: Serialize + ?Sized>(&mut self, value: &T) -> &mut MockRule {
+        let body = serde_json::to_vec(value).expect("mock body can't be serialized as JSON");
+        self.header("content-type", "application/json").body(body)
+    }
+
+    /// Expect the rule to answer exactly `times` requests, see
+    /// `MockClient::verify`.
+    pub fn times(&mut self, times: usize) -> &mut MockRule {
+        self.times = Some(times);
+        self
+    }
+
+    fn matches(&self, method: &str, url: &Url) -> bool {
+        if self.method != method {
+            return false;
+        }
+        if self.pattern.starts_with('/') {
+            glob(&self.pattern, url.path())
+        } else {
+            glob(&self.pattern, url.as_str())
+        }
+    }
+
+    fn response(&self, url: Url) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = http::header::HeaderName::from_bytes(name.as_bytes());
+            if let (Ok(name), Ok(value)) = (name, HeaderValue::from_str(value)) {
+                headers.append(name, value);
+            }
+        }
+        HttpResponse {
+            body: self.body.clone(),
+            status: StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            version: Version::HTTP_11,
+            headers,
+            url,
+            redirect_chain: vec![],
+            request_id: None,
+            raw_body: None,
+            raw_headers: vec![],
+            body_hash: None,
+        }
+    }
+}
+
+/// Answers requests from the rules of a `MockClient`, inside the client
+/// process.
+#[derive(Clone, Debug)]
+pub(crate) struct MockTransport {
+    rules: Vec<MockRule>,
+    calls: Vec<usize>,
+}
+
+impl MockTransport {
+    pub(crate) fn new(rules: Vec<MockRule>) -> MockTransport {
+        MockTransport {
+            calls: vec![0; rules.len()],
+            rules,
+        }
+    }
+
+    /// The answer of the first rule matching the request, or an error if
+    /// none does.
+    pub(crate) fn answer(&mut self, method: &str, url: Url) -> crate::Result<HttpResponse> {
+        match self
+            .rules
+            .iter()
+            .position(|rule| rule.matches(method, &url))
+        {
+            Some(i) => {
+                self.calls[i] += 1;
+                Ok(self.rules[i].response(url))
+            }
+            None => Err(
+                crate::error::request(format!("no mock rule matches {} {}", method, url))
+                    .with_url(url),
+            ),
+        }
+    }
+
+    pub(crate) fn calls(&self) -> Vec<usize> {
+        self.calls.clone()
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        // no `*` at all
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[lunatic::test]
+    fn globs() {
+        assert!(glob("/users/*", "/users/1"));
+        assert!(glob("/users/*", "/users/1/posts"));
+        assert!(!glob("/users/*", "/groups/1"));
+        assert!(glob("/users/*/posts", "/users/1/posts"));
+        assert!(!glob("/users/*/posts", "/users/1/likes"));
+        assert!(glob("/health", "/health"));
+        assert!(!glob("/health", "/healthz"));
+        assert!(glob("*.png", "/logo.png"));
+        assert!(!glob("/a*a", "/a"));
+    }
+
+    #[lunatic::test]
+    fn first_matching_rule_answers() {
+        let mut mock = MockClient::new();
+        mock.when(Method::GET, "/users/me").respond(204);
+        mock.when(Method::GET, "/users/*")
+            .respond(200)
+            .json(&serde_json::json!({ "id": 1 }));
+        mock.when(Method::POST, "https://api.example.com/users")
+            .respond(201)
+            .header("location", "/users/2");
+        let mut transport = MockTransport::new(mock.rules.clone());
+
+        let res = transport
+            .answer("GET", url("https://api.example.com/users/me"))
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = transport
+            .answer("GET", url("https://api.example.com/users/1?full=1"))
+            .unwrap();
+        assert_eq!(res.headers()["content-type"], "application/json");
+        assert_eq!(res.body(), b"{\"id\":1}");
+        let res = transport
+            .answer("POST", url("https://api.example.com/users"))
+            .unwrap();
+        assert_eq!(res.headers()["location"], "/users/2");
+
+        assert!(transport
+            .answer("DELETE", url("https://api.example.com/users/1"))
+            .is_err());
+        assert_eq!(transport.calls(), [1, 1, 1]);
+    }
+
+    #[lunatic::test]
+    fn reports_unmet_expectations() {
+        let mut mock = MockClient::new();
+        mock.when(Method::GET, "/users/*").times(2);
+        mock.when(Method::GET, "/groups/*");
+        mock.when(Method::DELETE, "/users/*").times(0);
+
+        assert!(mock.unmet(&[2, 5, 0]).is_empty());
+        assert_eq!(
+            mock.unmet(&[1, 0, 1]),
+            [
+                "GET /users/*: expected 2 calls, got 1",
+                "DELETE /users/*: expected 0 calls, got 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Warnings and deprecation notices sent along with responses.
+//!
+//! Servers announce that an endpoint is going away with the `Deprecation`
+//! and `Sunset` headers, and pass on other trouble, such as a stale cached
+//! response, in `Warning` headers. `HttpResponse::deprecation`,
+//! `HttpResponse::sunset` and `HttpResponse::warnings` read them, and with
+//! the `log` feature `LogLevels::notice` logs them for every response.
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let res = nightfly::Client::new().get("https://api.example.com/v1/items").send()?;
+//! if let Some(deprecation) = res.deprecation() {
+//!     println!("deprecated since {:?}, gone at {:?}", deprecation.date, res.sunset());
+//! }
+//! for warning in res.warnings() {
+//!     println!("{} {}: {}", warning.code, warning.agent, warning.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use http::header::{HeaderMap, HeaderName, WARNING};
+
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// An entry of a `Warning` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// The warning code, such as 110 for a stale response or 299 for a
+    /// miscellaneous persistent warning.
+    pub code: u16,
+    /// Who added the warning, a host or a pseudonym, `-` if unknown.
+    pub agent: String,
+    /// The warning text.
+    pub text: String,
+    /// When the warning was added, if it says.
+    pub date: Option<SystemTime>,
+}
+
+/// The `Deprecation` header of a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    /// When the resource was or will be deprecated, `None` if the server
+    /// only said that it is.
+    pub date: Option<SystemTime>,
+}
+
+/// Reads every entry of the `Warning` headers. Malformed entries are
+/// skipped, along with the rest of their header.
+pub(crate) fn warnings(headers: &HeaderMap) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for value in headers.get_all(WARNING) {
+        let mut rest = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        while let Some((warning, tail)) = warning(rest) {
+            warnings.push(warning);
+            rest = tail;
+        }
+    }
+    warnings
+}
+
+/// Reads the `Deprecation` header, either a structured date such as
+/// `@1688169599`, or `true` or an HTTP-date as in earlier drafts.
+pub(crate) fn deprecation(headers: &HeaderMap) -> Option<Deprecation> {
+    let value = headers.get(&DEPRECATION)?.to_str().ok()?.trim();
+    if value == "true" || value == "?1" {
+        return Some(Deprecation { date: None });
+    }
+    let date = match value.strip_prefix('@') {
+        Some(seconds) => {
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds.parse().ok()?))?
+        }
+        None => http_date(value)?,
+    };
+    Some(Deprecation { date: Some(date) })
+}
+
+/// Reads the `Sunset` header, when the resource will stop responding.
+pub(crate) fn sunset(headers: &HeaderMap) -> Option<SystemTime> {
+    http_date(headers.get(&SUNSET)?.to_str().ok()?)
+}
+
+/// Parses the first `code agent "text" ["date"]` entry of `s`, returning
+/// the rest after it.
+fn warning(s: &str) -> Option<(Warning, &str)> {
+    let s = s.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    let (code, s) = s.split_once(' ')?;
+    let s = s.trim_start();
+    let (agent, s) = s.split_once(' ')?;
+    let (text, s) = quoted(s.trim_start())?;
+    let after = s.trim_start();
+    let (date, s) = match quoted(after) {
+        Some((date, s)) => (http_date(&date), s),
+        None => (None, s),
+    };
+    let code = code
+        .parse()
+        .ok()
+        .filter(|code| (100..1000).contains(code))?;
+    let warning = Warning {
+        code,
+        agent: agent.to_string(),
+        text,
+        date,
+    };
+    Some((warning, s))
+}
+
+/// Parses a quoted string at the start of `s`, returning it unescaped and
+/// the rest after it.
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut text = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, &s[i + 2..])),
+            '\\' => text.push(chars.next()?.1),
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+/// Parses an HTTP-date in any of its three formats, such as
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, `Sunday, 06-Nov-94 08:49:37 GMT` or
+/// `Sun Nov  6 08:49:37 1994`.
+pub(crate) fn http_date(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let (day, month, year, time) = match s.split_once(", ") {
+        Some((_, rest)) => {
+            let fields: Vec<&str> = rest.split([' ', '-']).collect();
+            match fields[..] {
+                [day, month, year, time, "GMT"] => {
+                    let year: u64 = year.parse().ok()?;
+                    // two digit years are from the RFC 850 format
+                    let year = match (year, year < 100) {
+                        (year, false) => year,
+                        (year, true) if year < 70 => year + 2000,
+                        (year, true) => year + 1900,
+                    };
+                    (day, month, year, time)
+                }
+                _ => return None,
+            }
+        }
+        None => {
+            let fields: Vec<&str> = s.split_whitespace().collect();
+            match fields[..] {
+                [_, month, day, time, year] => (day, month, year.parse().ok()?, time),
+                _ => return None,
+            }
+        }
+    };
+
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let day: u64 = day.parse().ok()?;
+    let mut hms = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = match (hms.next(), hms.next(), hms.next(), hms.next()) {
+        (Some(Some(h)), Some(Some(m)), Some(Some(s)), None) => (h, m, s),
+        _ => return None,
+    };
+    if year < 1970 || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // days since the epoch, from Howard Hinnant's `days_from_civil`
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[lunatic::test]
+    fn parses_http_dates() {
+        let date = Some(at(784_111_777));
+        assert_eq!(http_date("Sun, 06 Nov 1994 08:49:37 GMT"), date);
+        assert_eq!(http_date("Sunday, 06-Nov-94 08:49:37 GMT"), date);
+        assert_eq!(http_date("Sun Nov  6 08:49:37 1994"), date);
+        assert_eq!(
+            http_date("Sat, 01 Mar 2025 00:00:00 GMT"),
+            Some(at(1_740_787_200))
+        );
+        assert_eq!(http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(http_date("yesterday"), None);
+    }
+
+    #[lunatic::test]
+    fn reads_warnings() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            WARNING,
+            HeaderValue::from_static(
+                r#"110 cache.example.com "Response is \"stale\"", 299 - "Deprecated, use v2" "Sun, 06 Nov 1994 08:49:37 GMT""#,
+            ),
+        );
+        headers.append(WARNING, HeaderValue::from_static("not a warning"));
+
+        assert_eq!(
+            warnings(&headers),
+            [
+                Warning {
+                    code: 110,
+                    agent: "cache.example.com".to_string(),
+                    text: "Response is \"stale\"".to_string(),
+                    date: None,
+                },
+                Warning {
+                    code: 299,
+                    agent: "-".to_string(),
+                    text: "Deprecated, use v2".to_string(),
+                    date: Some(at(784_111_777)),
+                },
+            ]
+        );
+    }
+
+    #[lunatic::test]
+    fn reads_deprecation_and_sunset() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(deprecation(&headers), None);
+
+        headers.insert(&DEPRECATION, HeaderValue::from_static("@1688169599"));
+        headers.insert(
+            &SUNSET,
+            HeaderValue::from_static("Sat, 01 Mar 2025 00:00:00 GMT"),
+        );
+        assert_eq!(
+            deprecation(&headers),
+            Some(Deprecation {
+                date: Some(at(1_688_169_599))
+            })
+        );
+        assert_eq!(sunset(&headers), Some(at(1_740_787_200)));
+
+        headers.insert(&DEPRECATION, HeaderValue::from_static("true"));
+        assert_eq!(deprecation(&headers), Some(Deprecation { date: None }));
+    }
+}
@@ -0,0 +1,316 @@
+//! OAuth 1.0a request signing.
+//!
+//! Some older APIs, Twitter's v1.1 API being the best known one, still
+//! authenticate every request with an [OAuth 1.0a][rfc] signature instead of
+//! a bearer token. Pass [`Credentials`] to `RequestBuilder::oauth1` and the
+//! request is signed when it is built or sent, after its query and form body
+//! are final:
+//!
+//! ```rust
+//! use nightfly::oauth1::Credentials;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let credentials = Credentials::hmac_sha1("consumer-key", "consumer-secret")
+//!     .token("access-token", "token-secret");
+//!
+//! let res = nightfly::Client::new()
+//!     .post("https://api.twitter.com/1.1/statuses/update.json")
+//!     .form(&[("status", "Hello from nightfly")])
+//!     .oauth1(credentials)
+//!     .send()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [rfc]: https://www.rfc-editor.org/rfc/rfc5849
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use http::{HeaderValue, Method};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use sha1::{Digest, Sha1};
+use url::{form_urlencoded, Url};
+
+/// Everything but the unreserved characters of RFC 3986, as required by
+/// section 3.6 of RFC 5849.
+const ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The keys used to sign requests on behalf of a client and, optionally, a
+/// resource owner.
+#[derive(Clone)]
+pub struct Credentials {
+    consumer_key: String,
+    method: SignatureMethod,
+    token: Option<String>,
+    token_secret: String,
+    callback: Option<String>,
+    verifier: Option<String>,
+}
+
+#[derive(Clone)]
+enum SignatureMethod {
+    HmacSha1 { consumer_secret: String },
+    RsaSha1 { key: Box<RsaPrivateKey> },
+}
+
+impl Credentials {
+    /// Credentials signing with `HMAC-SHA1`, keyed by the consumer secret
+    /// and the token secret.
+    pub fn hmac_sha1<K, S>(consumer_key: K, consumer_secret: S) -> Credentials
+    where
+        K: Into<String>,
+        S: Into<String>,
+    {
+        Credentials::new(
+            consumer_key.into(),
+            SignatureMethod::HmacSha1 {
+                consumer_secret: consumer_secret.into(),
+            },
+        )
+    }
+
+    /// Credentials signing with `RSA-SHA1`, using a PEM encoded private key
+    /// in either PKCS#8 or PKCS#1 format.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if `private_key_pem` is not a valid RSA private key.
+    pub fn rsa_sha1<K: Into<String>>(
+        consumer_key: K,
+        private_key_pem: &str,
+    ) -> crate::Result<Credentials> {
+        let key = RsaPrivateKey::from_pkcs8_pem(private_key_pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(private_key_pem))
+            .map_err(crate::error::builder)?;
+        Ok(Credentials::new(
+            consumer_key.into(),
+            SignatureMethod::RsaSha1 { key: Box::new(key) },
+        ))
+    }
+
+    fn new(consumer_key: String, method: SignatureMethod) -> Credentials {
+        Credentials {
+            consumer_key,
+            method,
+            token: None,
+            token_secret: String::new(),
+            callback: None,
+            verifier: None,
+        }
+    }
+
+    /// Sign on behalf of the resource owner identified by `token`.
+    pub fn token<T, S>(mut self, token: T, token_secret: S) -> Credentials
+    where
+        T: Into<String>,
+        S: Into<String>,
+    {
+        self.token = Some(token.into());
+        self.token_secret = token_secret.into();
+        self
+    }
+
+    /// Send `oauth_callback`, as required when requesting temporary
+    /// credentials.
+    pub fn callback<T: Into<String>>(mut self, callback: T) -> Credentials {
+        self.callback = Some(callback.into());
+        self
+    }
+
+    /// Send `oauth_verifier`, as required when exchanging temporary
+    /// credentials for an access token.
+    pub fn verifier<T: Into<String>>(mut self, verifier: T) -> Credentials {
+        self.verifier = Some(verifier.into());
+        self
+    }
+
+    /// Computes the `Authorization` header for a request, with a fresh
+    /// nonce and the current time.
+    pub(crate) fn authorization(
+        &self,
+        method: &Method,
+        url: &Url,
+        form: Option<&[u8]>,
+    ) -> crate::Result<HeaderValue> {
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.authorization_with(method, url, form, &nonce, timestamp)
+    }
+
+    fn authorization_with(
+        &self,
+        method: &Method,
+        url: &Url,
+        form: Option<&[u8]>,
+        nonce: &str,
+        timestamp: u64,
+    ) -> crate::Result<HeaderValue> {
+        let mut oauth = vec![
+            ("oauth_consumer_key", self.consumer_key.clone()),
+            ("oauth_nonce", nonce.to_owned()),
+            ("oauth_signature_method", self.method.name().to_owned()),
+            ("oauth_timestamp", timestamp.to_string()),
+            ("oauth_version", "1.0".to_owned()),
+        ];
+        if let Some(ref token) = self.token {
+            oauth.push(("oauth_token", token.clone()));
+        }
+        if let Some(ref callback) = self.callback {
+            oauth.push(("oauth_callback", callback.clone()));
+        }
+        if let Some(ref verifier) = self.verifier {
+            oauth.push(("oauth_verifier", verifier.clone()));
+        }
+
+        let base = signature_base_string(method, url, form, &oauth);
+        let signature = self.sign(&base)?;
+        oauth.push(("oauth_signature", signature));
+        oauth.sort();
+
+        let params = oauth
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, encode(v)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut value =
+            HeaderValue::from_str(&format!("OAuth {}", params)).map_err(crate::error::builder)?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    fn sign(&self, base: &str) -> crate::Result<String> {
+        let signature = match self.method {
+            SignatureMethod::HmacSha1 {
+                ref consumer_secret,
+            } => {
+                let key = format!("{}&{}", encode(consumer_secret), encode(&self.token_secret));
+                let mut mac = Hmac::<Sha1>::new_from_slice(key.as_bytes())
+                    .expect("HMAC accepts keys of any length");
+                mac.update(base.as_bytes());
+                mac.finalize().into_bytes().to_vec()
+            }
+            SignatureMethod::RsaSha1 { ref key } => {
+                let digest = Sha1::digest(base.as_bytes());
+                key.sign(Pkcs1v15Sign::new::<Sha1>(), &digest)
+                    .map_err(crate::error::builder)?
+            }
+        };
+        Ok(base64::encode(signature))
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("consumer_key", &self.consumer_key)
+            .field("signature_method", &self.method.name())
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+impl SignatureMethod {
+    fn name(&self) -> &'static str {
+        match self {
+            SignatureMethod::HmacSha1 { .. } => "HMAC-SHA1",
+            SignatureMethod::RsaSha1 { .. } => "RSA-SHA1",
+        }
+    }
+}
+
+/// Builds the signature base string of section 3.4.1 of RFC 5849 from the
+/// method, the URL without its query, and every query, form and protocol
+/// parameter.
+fn signature_base_string(
+    method: &Method,
+    url: &Url,
+    form: Option<&[u8]>,
+    oauth: &[(&str, String)],
+) -> String {
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .chain(form.map(form_urlencoded::parse).into_iter().flatten())
+        .map(|(k, v)| (encode(&k), encode(&v)))
+        .chain(oauth.iter().map(|(k, v)| (encode(k), encode(v))))
+        .collect();
+    params.sort();
+    let params = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let base_url = format!("{}{}", url.origin().ascii_serialization(), url.path());
+    format!(
+        "{}&{}&{}",
+        method.as_str().to_ascii_uppercase(),
+        encode(&base_url),
+        encode(&params)
+    )
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, ENCODE_SET).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example from Twitter's "Creating a signature" guide.
+    #[lunatic::test]
+    fn signs_twitter_example() {
+        let credentials = Credentials::hmac_sha1(
+            "xvz1evFS4wEEPTGEFPHBog",
+            "kAcSOqF21Fu85e7zjz7ZN2U4ZRhfV3WpwPAoE3Z7kBw",
+        )
+        .token(
+            "370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb",
+            "LswwdoUaIvS8ltyTt5jkRh4J50vUPVVHtR2YPi5kE",
+        );
+        let url =
+            Url::parse("https://api.twitter.com/1.1/statuses/update.json?include_entities=true")
+                .unwrap();
+        let form = b"status=Hello%20Ladies%20%2b%20Gentlemen%2c%20a%20signed%20OAuth%20request%21";
+
+        let header = credentials
+            .authorization_with(
+                &Method::POST,
+                &url,
+                Some(form),
+                "kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg",
+                1318622958,
+            )
+            .unwrap();
+
+        assert_eq!(
+            header.to_str().unwrap(),
+            "OAuth oauth_consumer_key=\"xvz1evFS4wEEPTGEFPHBog\", \
+             oauth_nonce=\"kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg\", \
+             oauth_signature=\"hCtSmYh%2BiHYCEqBWrE7C7hYmtUk%3D\", \
+             oauth_signature_method=\"HMAC-SHA1\", \
+             oauth_timestamp=\"1318622958\", \
+             oauth_token=\"370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb\", \
+             oauth_version=\"1.0\""
+        );
+    }
+
+    #[lunatic::test]
+    fn base_url_drops_default_port_and_query() {
+        let url = Url::parse("HTTP://Example.com:80/r%20v/X?id=123").unwrap();
+        let base = signature_base_string(&Method::GET, &url, None, &[]);
+        assert_eq!(base, "GET&http%3A%2F%2Fexample.com%2Fr%2520v%2FX&id%3D123");
+    }
+}
@@ -0,0 +1,93 @@
+//! OpenTelemetry spans for requests sent through a `Client`.
+//!
+//! Each call to `Client::execute` gets a client span named `HTTP {method}`,
+//! and every request actually written to the wire (the first one and each
+//! followed redirect) is recorded as a child span of it. Labels added with
+//! `RequestBuilder::label` become `label.{key}` attributes. Spans are created
+//! with the globally installed tracer provider.
+
+use opentelemetry::{
+    global::{self, BoxedSpan},
+    trace::{Span, SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+
+use crate::lunatic_impl::request::InnerRequest;
+use crate::HttpResponse;
+
+const TRACER_NAME: &str = "nightfly";
+
+/// Starts the span of a whole logical request and returns a context
+/// carrying it, to be attached while the request runs.
+pub(crate) fn request_context(req: &InnerRequest) -> Context {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(format!("HTTP {}", req.method))
+        .with_kind(SpanKind::Client)
+        .with_attributes(attributes(req))
+        .start(&tracer);
+    Context::current_with_span(span)
+}
+
+/// Records the outcome of a logical request and ends its span.
+pub(crate) fn end_request(cx: &Context, result: &crate::Result<HttpResponse>) {
+    let span = cx.span();
+    match result {
+        Ok(res) => {
+            span.set_attribute(KeyValue::new(
+                "http.status_code",
+                res.status().as_u16() as i64,
+            ));
+            if res.status().is_server_error() {
+                span.set_status(Status::error(""));
+            }
+        }
+        Err(e) => {
+            span.record_error(e);
+            span.set_status(Status::error(e.to_string()));
+        }
+    }
+    span.end();
+}
+
+/// Starts the span of a single request sent on the wire. It is a child of
+/// the span attached by `request_context`.
+pub(crate) fn attempt_span(req: &InnerRequest) -> BoxedSpan {
+    let tracer = global::tracer(TRACER_NAME);
+    tracer
+        .span_builder(format!("HTTP {}", req.method))
+        .with_kind(SpanKind::Client)
+        .with_attributes(attributes(req))
+        .start(&tracer)
+}
+
+/// Records the outcome of a single request and ends its span.
+pub(crate) fn end_attempt<T>(mut span: BoxedSpan, result: &Result<HttpResponse, T>) {
+    if let Ok(res) = result {
+        span.set_attribute(KeyValue::new(
+            "http.status_code",
+            res.status().as_u16() as i64,
+        ));
+    } else {
+        span.set_status(Status::error("failed to read response"));
+    }
+    span.end();
+}
+
+fn attributes(req: &InnerRequest) -> Vec<KeyValue> {
+    let url = &req.url;
+    let mut attributes = vec![
+        KeyValue::new("http.method", req.method.clone()),
+        KeyValue::new("http.url", url.as_str().to_string()),
+    ];
+    if let Some(host) = url.host_str() {
+        attributes.push(KeyValue::new("net.peer.name", host.to_string()));
+    }
+    if let Some(port) = url.port_or_known_default() {
+        attributes.push(KeyValue::new("net.peer.port", port as i64));
+    }
+    for (key, value) in &req.labels {
+        attributes.push(KeyValue::new(format!("label.{}", key), value.clone()));
+    }
+    attributes
+}
@@ -0,0 +1,382 @@
+//! Requests kept until they can be delivered.
+//!
+//! A device at the edge of a network loses its connection every now and
+//! then, and requests it sends meanwhile would be lost. An [`Outbox`] keeps
+//! them instead, in a file that outlives the process, and sends them again
+//! once the server can be reached:
+//!
+//! ```rust
+//! use nightfly::outbox::Outbox;
+//!
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = nightfly::Client::new();
+//! let mut outbox = Outbox::open("/data/outbox.jsonl")?;
+//!
+//! let req = client.post("https://api.example.com/readings").body("21.5").build()?;
+//! match outbox.send(&mut client, req)? {
+//!     Some(res) => println!("sent right away: {}", res.status()),
+//!     None => println!("queued, {} requests waiting", outbox.len()),
+//! }
+//!
+//! // later, and again every so often
+//! for (key, res) in outbox.replay(&mut client)? {
+//!     println!("{} delivered: {:?}", key, res.map(|res| res.status()));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every request gets an `Idempotency-Key` header, unless it already has
+//! one, so a server that supports it can tell a request sent again from a
+//! new one. A request is kept when the client couldn't connect, it timed
+//! out, or the server answered `429 Too Many Requests`, `502 Bad Gateway`,
+//! `503 Service Unavailable` or `504 Gateway Timeout`. It is then sent again
+//! after a wait that doubles with every attempt.
+//!
+//! Requests are stored as [`SerializableRequest`], so a body read from a
+//! reader can't be kept.
+
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use http::header::{HeaderName, HeaderValue};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{Client, HttpResponse, Request, SerializableRequest, TimeSource};
+
+static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Requests waiting to be delivered, see the module documentation.
+#[derive(Debug)]
+pub struct Outbox {
+    entries: Vec<Entry>,
+    file: Option<PathBuf>,
+    base_delay: Duration,
+    max_delay: Duration,
+    time: TimeSource,
+}
+
+/// A request in the outbox, one line of its file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    request: SerializableRequest,
+    attempts: u32,
+    next_attempt: SystemTime,
+}
+
+impl Outbox {
+    /// An outbox kept in memory only.
+    pub fn new() -> Outbox {
+        Outbox {
+            entries: Vec::new(),
+            file: None,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+            time: TimeSource::default(),
+        }
+    }
+
+    /// An outbox kept in the file at `path`, loading the requests already
+    /// in it. The file is created once a request is added, and saved after
+    /// every change.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Outbox> {
+        let path = path.into();
+        let mut outbox = Outbox::new();
+        match File::open(&path) {
+            Ok(file) => outbox.load_json(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        outbox.file = Some(path);
+        Ok(outbox)
+    }
+
+    /// Wait `base` before sending a request again, doubling the wait for
+    /// every attempt after it, but never more than `max`.
+    ///
+    /// Default is 1 second, up to 5 minutes.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Outbox {
+        self.base_delay = base;
+        self.max_delay = max;
+        self
+    }
+
+    /// The clock used to schedule attempts.
+    ///
+    /// Default is `TimeSource::system()`.
+    pub fn time_source(mut self, time: TimeSource) -> Outbox {
+        self.time = time;
+        self
+    }
+
+    /// The number of requests waiting.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no request is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// When the next request is due to be sent again, `None` if none is
+    /// waiting.
+    pub fn next_attempt(&self) -> Option<SystemTime> {
+        self.entries.iter().map(|entry| entry.next_attempt).min()
+    }
+
+    /// Adds a request to the outbox, to be sent by the next `replay`, and
+    /// returns its idempotency key.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be serialized, see `SerializableRequest`,
+    /// or the outbox file can't be saved.
+    pub fn push(&mut self, req: Request) -> crate::Result<String> {
+        let (key, request) = keyed(req)?;
+        self.entries.push(Entry {
+            key: key.clone(),
+            request,
+            attempts: 0,
+            next_attempt: self.time.now(),
+        });
+        self.save().map_err(crate::error::request)?;
+        Ok(key)
+    }
+
+    /// Sends a request, and keeps it in the outbox if it can't be delivered
+    /// now, returning `None`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be serialized, or fails for any other
+    /// reason than those a request is kept for.
+    pub fn send(
+        &mut self,
+        client: &mut Client,
+        req: Request,
+    ) -> crate::Result<Option<HttpResponse>> {
+        let (key, request) = keyed(req)?;
+        let res = client.execute(Request::try_from(request.clone())?);
+        if !undelivered(&res) {
+            return res.map(Some);
+        }
+        self.entries.push(Entry {
+            key,
+            request,
+            attempts: 1,
+            next_attempt: self.time.now() + self.delay(1),
+        });
+        self.save().map_err(crate::error::request)?;
+        Ok(None)
+    }
+
+    /// Sends the requests that are due, oldest first, and returns the
+    /// outcome of those that left the outbox along with their idempotency
+    /// keys. Requests that still can't be delivered are tried again later.
+    ///
+    /// Once the client can't connect, the remaining requests wait for the
+    /// next replay, as they would most likely fail the same way.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the outbox file can't be saved.
+    pub fn replay(
+        &mut self,
+        client: &mut Client,
+    ) -> crate::Result<Vec<(String, crate::Result<HttpResponse>)>> {
+        let mut done = Vec::new();
+        let mut i = 0;
+        while i < self.entries.len() {
+            let now = self.time.now();
+            if self.entries[i].next_attempt > now {
+                i += 1;
+                continue;
+            }
+            let res = Request::try_from(self.entries[i].request.clone())
+                .and_then(|req| client.execute(req));
+            if !undelivered(&res) {
+                done.push((self.entries.remove(i).key, res));
+                continue;
+            }
+            let attempts = self.entries[i].attempts + 1;
+            let delay = self.delay(attempts);
+            let entry = &mut self.entries[i];
+            entry.attempts = attempts;
+            entry.next_attempt = now + delay;
+            if matches!(res, Err(ref e) if e.is_connect()) {
+                break;
+            }
+            i += 1;
+        }
+        self.save().map_err(crate::error::request)?;
+        Ok(done)
+    }
+
+    /// Writes the requests in the outbox as JSON, one per line.
+    pub fn save_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for entry in &self.entries {
+            serde_json::to_writer(&mut *writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the requests in the outbox with those written by
+    /// `save_json`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a line can't be read, leaving the outbox unchanged.
+    pub fn load_json<R: BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        self.entries = entries;
+        Ok(())
+    }
+
+    /// How long to wait after the `attempts`th failed attempt.
+    fn delay(&self, attempts: u32) -> Duration {
+        crate::retry::backoff(self.base_delay, attempts.saturating_sub(1)).min(self.max_delay)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match self.file {
+            Some(ref path) => save_file(self, path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Outbox {
+    fn default() -> Outbox {
+        Outbox::new()
+    }
+}
+
+/// Serializes a request, giving it an idempotency key if it has none.
+fn keyed(mut req: Request) -> crate::Result<(String, SerializableRequest)> {
+    let key = match req.headers().get(&IDEMPOTENCY_KEY) {
+        Some(value) => value.to_str().map_err(crate::error::builder)?.to_string(),
+        None => {
+            let key = uuid::Uuid::new_v4().to_string();
+            let value = HeaderValue::from_str(&key).map_err(crate::error::builder)?;
+            req.headers_mut().insert(IDEMPOTENCY_KEY.clone(), value);
+            key
+        }
+    };
+    Ok((key, SerializableRequest::try_from(req)?))
+}
+
+/// Whether a request should be kept to be sent again.
+fn undelivered(res: &crate::Result<HttpResponse>) -> bool {
+    match res {
+        Ok(res) => matches!(
+            res.status(),
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(e) => e.is_connect() || e.is_timeout(),
+    }
+}
+
+/// Saves the outbox through a temporary file, so that a crash never leaves
+/// half an outbox behind.
+fn save_file(outbox: &Outbox, path: &Path) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    outbox.save_json(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+    use crate::{Method, ResponseBuilderExt, Url};
+
+    fn frozen() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    fn request(path: &str) -> Request {
+        let url = Url::parse("https://example.com/")
+            .unwrap()
+            .join(path)
+            .unwrap();
+        let mut req = Request::new(Method::POST, url);
+        *req.body_mut() = Some("21.5".into());
+        req
+    }
+
+    #[lunatic::test]
+    fn keys_and_keeps_requests() {
+        let mut outbox = Outbox::new().time_source(TimeSource::new(Simulated(frozen())));
+        let key = outbox.push(request("/readings")).unwrap();
+        assert!(uuid::Uuid::parse_str(&key).is_ok());
+
+        let mut req = request("/alerts");
+        req.headers_mut()
+            .insert(&IDEMPOTENCY_KEY, HeaderValue::from_static("alert-7"));
+        assert_eq!(outbox.push(req).unwrap(), "alert-7");
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox.next_attempt(), Some(frozen()));
+
+        let mut saved = Vec::new();
+        outbox.save_json(&mut saved).unwrap();
+        let mut loaded = Outbox::new();
+        loaded.load_json(&saved[..]).unwrap();
+        assert_eq!(loaded.entries, outbox.entries);
+
+        let req = Request::try_from(loaded.entries[0].request.clone()).unwrap();
+        assert_eq!(req.headers()[&IDEMPOTENCY_KEY], key.as_str());
+        assert_eq!(req.body().unwrap().as_bytes(), b"21.5");
+
+        assert!(loaded.load_json(&b"not json\n"[..]).is_err());
+        assert_eq!(loaded.len(), 2);
+    }
+
+    #[lunatic::test]
+    fn backs_off() {
+        let outbox = Outbox::new().backoff(Duration::from_secs(1), Duration::from_secs(5));
+        let delays: Vec<_> = (1..=4).map(|attempts| outbox.delay(attempts)).collect();
+        assert_eq!(delays, [1, 2, 4, 5].map(Duration::from_secs));
+    }
+
+    #[lunatic::test]
+    fn keeps_only_undelivered_requests() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let response = |status: u16| {
+            let res = http::Response::builder()
+                .status(status)
+                .url(url.clone())
+                .body("")
+                .unwrap();
+            HttpResponse::from_http(res)
+        };
+        assert!(undelivered(&response(503)));
+        assert!(!undelivered(&response(201)));
+        assert!(!undelivered(&response(400)));
+        assert!(undelivered(&Err(crate::error::timeout(url.clone()))));
+        assert!(!undelivered(&Err(crate::error::builder("invalid"))));
+    }
+}
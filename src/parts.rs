@@ -0,0 +1,227 @@
+//! Responses made of several parts, such as `multipart/mixed` and
+//! `multipart/related`.
+//!
+//! Storage APIs answer with `multipart/related` to send metadata along with
+//! a file, and mail and batch APIs with `multipart/mixed`.
+//! `HttpResponse::parts` goes through the parts of such a response one at a
+//! time, each with its own headers:
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let res = nightfly::get("https://storage.example.com/objects/report?alt=multipart")?;
+//! for part in res.parts()? {
+//!     let part = part?;
+//!     println!("{:?}: {} bytes", part.headers().get("content-type"), part.body().len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+
+use crate::{HttpResponse, Url};
+
+/// Most headers a single part may have.
+const MAX_HEADERS: usize = 64;
+
+/// One part of a multipart response.
+#[derive(Clone, Debug)]
+pub struct Part {
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Part {
+    /// The headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the part.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body of the part, without copying it.
+    pub fn into_bytes(self) -> Bytes {
+        self.body
+    }
+
+    /// The body of the part as UTF-8 text.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the body isn't valid UTF-8.
+    pub fn text(&self) -> crate::Result<String> {
+        String::from_utf8(self.body.to_vec()).map_err(crate::error::decode)
+    }
+}
+
+/// An iterator over the parts of a multipart response, see
+/// `HttpResponse::parts`.
+///
+/// Parts are split off the body one at a time. A malformed part ends the
+/// iteration with an error.
+#[derive(Clone, Debug)]
+pub struct Parts {
+    body: Bytes,
+    delimiter: String,
+    url: Url,
+    // where the next delimiter starts, `None` once done
+    at: Option<usize>,
+}
+
+impl Parts {
+    pub(crate) fn new(res: &HttpResponse) -> crate::Result<Parts> {
+        let boundary = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok())
+            .filter(|mime| mime.type_() == mime::MULTIPART)
+            .and_then(|mime| mime.get_param(mime::BOUNDARY).map(|b| b.to_string()))
+            .ok_or_else(|| fail("response isn't multipart", res.url()))?;
+        let delimiter = format!("--{}", boundary);
+        // anything before the first delimiter is a preamble
+        let at = find(&res.body, delimiter.as_bytes(), 0);
+        if at.is_none() {
+            return Err(fail("multipart body has no boundary", res.url()));
+        }
+        Ok(Parts {
+            body: res.body.clone(),
+            delimiter,
+            url: res.url().clone(),
+            at,
+        })
+    }
+
+    fn next_part(&mut self, at: usize) -> Result<Option<Part>, &'static str> {
+        let start = at + self.delimiter.len();
+        if self.body[start..].starts_with(b"--") {
+            return Ok(None);
+        }
+        let next = find(
+            &self.body,
+            format!("\r\n{}", self.delimiter).as_bytes(),
+            start,
+        )
+        .ok_or("multipart body has no closing boundary")?;
+        // the rest of the delimiter line, usually only its line break
+        let content = find(&self.body, b"\r\n", start).map_or(next, |eol| (eol + 2).min(next));
+        self.at = Some(next + 2);
+        parse_part(self.body.slice(content..next)).map(Some)
+    }
+}
+
+impl Iterator for Parts {
+    type Item = crate::Result<Part>;
+
+    fn next(&mut self) -> Option<crate::Result<Part>> {
+        let at = self.at.take()?;
+        match self.next_part(at) {
+            Ok(part) => part.map(Ok),
+            Err(msg) => {
+                self.at = None;
+                Some(Err(fail(msg, &self.url)))
+            }
+        }
+    }
+}
+
+/// Splits a part into its headers and its body.
+fn parse_part(part: Bytes) -> Result<Part, &'static str> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let (len, parsed) = match httparse::parse_headers(&part, &mut headers) {
+        Ok(httparse::Status::Complete(parsed)) => parsed,
+        _ => return Err("multipart body has a part with invalid headers"),
+    };
+    let mut header_map = HeaderMap::new();
+    for header in parsed {
+        let name = HeaderName::from_bytes(header.name.as_bytes());
+        let value = HeaderValue::from_bytes(header.value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            header_map.append(name, value);
+        }
+    }
+    Ok(Part {
+        headers: header_map,
+        body: part.slice(len..),
+    })
+}
+
+fn fail(msg: &str, url: &Url) -> crate::Error {
+    crate::error::decode(msg.to_string()).with_url(url.clone())
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| from + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBuilderExt;
+
+    fn response(content_type: &str, body: &'static str) -> HttpResponse {
+        let res = http::Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .url(Url::parse("https://example.com/objects/1").unwrap())
+            .body(body)
+            .unwrap();
+        HttpResponse::from_http(res).unwrap()
+    }
+
+    #[lunatic::test]
+    fn splits_related_parts() {
+        let res = response(
+            "multipart/related; boundary=\"part_x\"",
+            "preamble\r\n\
+             --part_x\r\n\
+             Content-Type: application/json\r\n\r\n\
+             {\"name\":\"report.txt\"}\r\n\
+             --part_x\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Id: <file>\r\n\r\n\
+             line one\r\nline two\r\n\
+             --part_x--\r\n\
+             epilogue",
+        );
+        let parts: Vec<Part> = res.parts().unwrap().collect::<crate::Result<_>>().unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(parts[0].body(), b"{\"name\":\"report.txt\"}");
+        assert_eq!(parts[1].headers()["content-id"], "<file>");
+        assert_eq!(parts[1].text().unwrap(), "line one\r\nline two");
+    }
+
+    #[lunatic::test]
+    fn part_without_headers() {
+        let res = response("multipart/mixed; boundary=b", "--b\r\n\r\nhello\r\n--b--");
+        let parts: Vec<Part> = res.parts().unwrap().collect::<crate::Result<_>>().unwrap();
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].headers().is_empty());
+        assert_eq!(parts[0].body(), b"hello");
+    }
+
+    #[lunatic::test]
+    fn rejects_malformed_bodies() {
+        assert!(response("text/plain", "--b\r\n\r\nhello\r\n--b--")
+            .parts()
+            .is_err());
+        assert!(response("multipart/mixed; boundary=b", "hello")
+            .parts()
+            .is_err());
+
+        let mut parts = response("multipart/mixed; boundary=b", "--b\r\n\r\nhello")
+            .parts()
+            .unwrap();
+        assert!(parts.next().unwrap().is_err());
+        assert!(parts.next().is_none());
+    }
+}
@@ -0,0 +1,123 @@
+//! Finding out how large a resource is before downloading it, see
+//! `Client::content_length`.
+
+use http::header::{HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, RANGE};
+use http::StatusCode;
+
+use crate::lunatic_impl::framing;
+use crate::{Client, Url};
+
+/// What `Client::content_length` learned about a resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentInfo {
+    /// Size of the body in bytes, if the server said.
+    pub length: Option<u64>,
+    /// Whether the server serves byte ranges of the body, so a download can
+    /// be split up or resumed.
+    pub accepts_ranges: bool,
+    /// The `ETag` of the resource, if any.
+    pub etag: Option<String>,
+}
+
+pub(crate) fn probe(client: &Client, url: Url) -> crate::Result<ContentInfo> {
+    let res = client.head(url.clone()).send()?;
+    if res.status().is_success() {
+        let info = from_head(res.headers());
+        if info.length.is_some() {
+            return Ok(info);
+        }
+    }
+
+    // Some servers reject HEAD, or answer it without a length. Asking for the
+    // first byte instead costs little, and tells the full size if they
+    // support ranges.
+    let res = client.get(url).header(RANGE, "bytes=0-0").send()?;
+    let headers = res.headers();
+    let etag = etag(headers);
+    // an empty body has no first byte
+    let empty = res.status() == StatusCode::RANGE_NOT_SATISFIABLE;
+    if res.status() == StatusCode::PARTIAL_CONTENT || empty {
+        return Ok(ContentInfo {
+            length: headers
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(complete_length),
+            accepts_ranges: true,
+            etag,
+        });
+    }
+    let res = res.error_for_status_ref()?;
+    // the range was ignored, so the whole body came back
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(framing::parse_length)
+        .unwrap_or(res.body.len() as u64);
+    Ok(ContentInfo {
+        length: Some(length),
+        accepts_ranges: false,
+        etag,
+    })
+}
+
+fn from_head(headers: &HeaderMap) -> ContentInfo {
+    ContentInfo {
+        length: headers.get(CONTENT_LENGTH).and_then(framing::parse_length),
+        accepts_ranges: headers
+            .get_all(ACCEPT_RANGES)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|unit| unit.trim().eq_ignore_ascii_case("bytes")),
+        etag: etag(headers),
+    }
+}
+
+fn etag(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// The complete length from a `Content-Range` such as `bytes 0-0/1234`, or
+/// `bytes */0` when the range couldn't be satisfied.
+fn complete_length(content_range: &str) -> Option<u64> {
+    let (_, length) = content_range.trim().rsplit_once('/')?;
+    length.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[lunatic::test]
+    fn reads_head_response() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1234"));
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        assert_eq!(
+            from_head(&headers),
+            ContentInfo {
+                length: Some(1234),
+                accepts_ranges: true,
+                etag: Some("\"v1\"".to_string()),
+            }
+        );
+
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("none"));
+        headers.remove(CONTENT_LENGTH);
+        let info = from_head(&headers);
+        assert_eq!(info.length, None);
+        assert!(!info.accepts_ranges);
+    }
+
+    #[lunatic::test]
+    fn reads_content_range() {
+        assert_eq!(complete_length("bytes 0-0/1234"), Some(1234));
+        assert_eq!(complete_length("bytes 0-0/*"), None);
+        assert_eq!(complete_length("bytes */1234"), Some(1234));
+    }
+}
@@ -0,0 +1,176 @@
+//! PROXY protocol headers.
+//!
+//! Servers behind a load balancer such as haproxy only see the balancer's
+//! address. With the PROXY protocol, the connection starts with a header
+//! telling the server the address of the original client. When nightfly sits
+//! in front of such servers, `ClientBuilder::proxy_protocol` or
+//! `RequestBuilder::proxy_protocol` write that header on every new
+//! connection:
+//!
+//! ```rust
+//! use nightfly::proxy_protocol::ProxyHeader;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let res = client
+//!     .get("http://backend.internal/orders")
+//!     .proxy_protocol(ProxyHeader::v2("203.0.113.7:51234".parse().unwrap()))
+//!     .send()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The header has to come before anything else on the connection, and the
+//! lunatic host performs the TLS handshake of `https` connections as it
+//! opens them. Requests to `https` URLs with a PROXY header fail instead.
+//! A request with its own header always opens a new connection.
+
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+/// The v2 header signature.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// A PROXY protocol header, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyHeader {
+    version: Version,
+    source: SocketAddr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Version {
+    V1,
+    V2,
+}
+
+impl ProxyHeader {
+    /// A version 1 header, in text, for connections made on behalf of the
+    /// client at `source`.
+    pub fn v1(source: SocketAddr) -> ProxyHeader {
+        ProxyHeader {
+            version: Version::V1,
+            source,
+        }
+    }
+
+    /// A version 2 header, in binary, for connections made on behalf of the
+    /// client at `source`.
+    pub fn v2(source: SocketAddr) -> ProxyHeader {
+        ProxyHeader {
+            version: Version::V2,
+            source,
+        }
+    }
+
+    /// Writes the header for a connection to `destination`.
+    pub(crate) fn write_to<W: Write>(
+        &self,
+        mut w: W,
+        destination: SocketAddr,
+    ) -> std::io::Result<()> {
+        w.write_all(&self.encode(destination))?;
+        w.flush()
+    }
+
+    /// The header for a connection to `destination`. Addresses of different
+    /// families are both sent as IPv6.
+    fn encode(&self, destination: SocketAddr) -> Vec<u8> {
+        let (source, destination) = match (self.source.ip(), destination.ip()) {
+            (IpAddr::V4(_), IpAddr::V6(_)) | (IpAddr::V6(_), IpAddr::V4(_)) => {
+                (to_v6(self.source), to_v6(destination))
+            }
+            _ => (self.source, destination),
+        };
+        match self.version {
+            Version::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    family,
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes()
+            }
+            Version::V2 => {
+                let mut header = SIGNATURE.to_vec();
+                // version 2, PROXY command
+                header.push(0x21);
+                let addresses: Vec<u8> = match (source.ip(), destination.ip()) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        // TCP over IPv4
+                        header.push(0x11);
+                        [src.octets(), dst.octets()].concat()
+                    }
+                    (src, dst) => {
+                        // TCP over IPv6
+                        header.push(0x21);
+                        [v6_octets(src), v6_octets(dst)].concat()
+                    }
+                };
+                let len = addresses.len() as u16 + 4;
+                header.extend_from_slice(&len.to_be_bytes());
+                header.extend_from_slice(&addresses);
+                header.extend_from_slice(&source.port().to_be_bytes());
+                header.extend_from_slice(&destination.port().to_be_bytes());
+                header
+            }
+        }
+    }
+}
+
+fn to_v6(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(IpAddr::V6(v6(addr.ip())), addr.port())
+}
+
+fn v6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn v6_octets(ip: IpAddr) -> [u8; 16] {
+    v6(ip).octets()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[lunatic::test]
+    fn encodes_v1() {
+        let header = ProxyHeader::v1(addr("203.0.113.7:51234"));
+        assert_eq!(
+            header.encode(addr("10.0.0.2:80")),
+            b"PROXY TCP4 203.0.113.7 10.0.0.2 51234 80\r\n"
+        );
+        assert_eq!(
+            header.encode(addr("[2001:db8::2]:8080")),
+            b"PROXY TCP6 ::ffff:203.0.113.7 2001:db8::2 51234 8080\r\n".to_vec()
+        );
+    }
+
+    #[lunatic::test]
+    fn encodes_v2() {
+        let header = ProxyHeader::v2(addr("203.0.113.7:51234"));
+        let mut expected = SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+        expected.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 2]);
+        expected.extend_from_slice(&[0xc8, 0x22, 0x00, 0x50]);
+        assert_eq!(header.encode(addr("10.0.0.2:80")), expected);
+
+        let v6 = header.encode(addr("[2001:db8::2]:8080"));
+        assert_eq!(&v6[12..16], &[0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(v6.len(), 16 + 36);
+    }
+}
@@ -7,10 +7,15 @@
 use std::error::Error as StdError;
 use std::fmt;
 
-use crate::header::{HeaderMap, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE};
+use crate::header::{
+    HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE,
+};
 use http::StatusCode;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use url::Host;
+
+use crate::lunatic_impl::remote::{self, Remote, Serve};
 use crate::Url;
 
 /// A type that controls the policy on how to handle the following of redirects.
@@ -32,6 +37,7 @@ pub struct Policy {
 #[derive(Debug)]
 pub struct Attempt<'a> {
     status: StatusCode,
+    headers: &'a HeaderMap,
     next: &'a Url,
     previous: &'a [Url],
 }
@@ -42,6 +48,69 @@ pub struct Action {
     inner: ActionKind,
 }
 
+/// A redirect policy of your own, see [`Policy::custom`].
+pub trait CustomPolicy: Send + Sync + 'static {
+    /// Decides what to do with a redirect, usually by calling one of the
+    /// methods of `attempt`.
+    fn redirect(&self, attempt: Attempt<'_>) -> Action;
+}
+
+/// Which credentials a request keeps when a redirect is followed.
+///
+/// Every credential has a [`Reach`], the furthest redirect it survives.
+/// By default `Authorization`, `Proxy-Authorization` and cookies are only
+/// kept on redirects to the same origin, and every other header is always
+/// kept.
+///
+/// ```rust
+/// use nightfly::header::HeaderName;
+/// use nightfly::redirect::{Reach, RedirectAuthPolicy};
+///
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let policy = RedirectAuthPolicy::new()
+///     // a login on example.com that sends us on to api.example.com
+///     .authorization(Reach::SameSite)
+///     .header(HeaderName::from_static("x-api-key"), Reach::SameOrigin);
+/// let client = nightfly::Client::builder().redirect_auth(policy).build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Redirects to a domain passed to [`RedirectAuthPolicy::trust`] keep
+/// every credential, unless they go from `https` to `http`.
+///
+/// Cookies from the client's cookie store are always picked for the URL
+/// they are sent to, the policy only applies to a `Cookie` header set on
+/// the request itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectAuthPolicy {
+    authorization: Reach,
+    cookies: Reach,
+    headers: Vec<(String, Reach)>,
+    trusted: Vec<String>,
+}
+
+/// How far a redirect leads from the URL that answered with it, and so how
+/// far a credential may follow it, see [`RedirectAuthPolicy`].
+///
+/// Two URLs are the same origin when their scheme, host and port are the
+/// same. There's no public suffix list to find registrable domains with, so
+/// they are the same site when they have the same scheme and one host is
+/// the other or a subdomain of it, like `example.com` and `api.example.com`.
+/// Sibling subdomains such as `www.example.com` and `api.example.com` are
+/// cross-site.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Reach {
+    /// Dropped on every redirect.
+    Never,
+    /// Kept on redirects to the same origin.
+    SameOrigin,
+    /// Kept on redirects within the same site.
+    SameSite,
+    /// Kept on every redirect.
+    CrossSite,
+}
+
 impl Policy {
     /// Create a `Policy` with a maximum number of redirects.
     ///
@@ -59,52 +128,70 @@ impl Policy {
         }
     }
 
-    // /// Create a custom `Policy` using the passed function.
-    // ///
-    // /// # Note
-    // ///
-    // /// The default `Policy` handles a maximum loop
-    // /// chain, but the custom variant does not do that for you automatically.
-    // /// The custom policy should have some way of handling those.
-    // ///
-    // /// Information on the next request and previous requests can be found
-    // /// on the [`Attempt`] argument passed to the closure.
-    // ///
-    // /// Actions can be conveniently created from methods on the
-    // /// [`Attempt`].
-    // ///
-    // /// # Example
-    // ///
-    // /// ```rust
-    // /// # use nightfly::{Error, redirect};
-    // /// #
-    // /// # fn run() -> Result<(), Error> {
-    // /// let custom = redirect::Policy::custom(|attempt| {
-    // ///     if attempt.previous().len() > 5 {
-    // ///         attempt.error("too many redirects")
-    // ///     } else if attempt.url().host_str() == Some("example.domain") {
-    // ///         // prevent redirects to 'example.domain'
-    // ///         attempt.stop()
-    // ///     } else {
-    // ///         attempt.follow()
-    // ///     }
-    // /// });
-    // /// let client = nightfly::Client::builder()
-    // ///     .redirect(custom)
-    // ///     .build()?;
-    // /// # Ok(())
-    // /// # }
-    // /// ```
-    // ///
-    // /// [`Attempt`]: struct.Attempt.html
-    // pub fn custom<T>(policy: T) -> Self
-    // where
-    //     T: Fn(Attempt) -> Action + Send + Sync + 'static,
-    // {
-    //     Self {
-    //         inner: PolicyKind::Custom(Box::new(policy)),
-    //     }
-    // }
+    /// Create a custom `Policy` from a [`CustomPolicy`] of your own.
+    ///
+    /// # Note
+    ///
+    /// The default `Policy` handles a maximum loop
+    /// chain, but the custom variant does not do that for you automatically.
+    /// The custom policy should have some way of handling those.
+    ///
+    /// Information on the redirect response, the next request and previous
+    /// requests can be found on the [`Attempt`] argument passed to the
+    /// policy.
+    ///
+    /// Actions can be conveniently created from methods on the
+    /// [`Attempt`].
+    ///
+    /// The policy is spawned in a process of its own, linked to the current
+    /// one, from its serialized state. The `Policy` returned is a handle to
+    /// that process, which can be sent to a client like any other policy.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use nightfly::{Error, redirect};
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Avoid {
+    ///     host: String,
+    /// }
+    ///
+    /// impl redirect::CustomPolicy for Avoid {
+    ///     fn redirect(&self, attempt: redirect::Attempt<'_>) -> redirect::Action {
+    ///         if attempt.previous().len() > 5 {
+    ///             attempt.error("too many redirects")
+    ///         } else if attempt.url().host_str() == Some(self.host.as_str()) {
+    ///             attempt.stop()
+    ///         } else if attempt.headers().contains_key("x-no-follow") {
+    ///             attempt.stop()
+    ///         } else {
+    ///             attempt.follow()
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # fn run() -> Result<(), Error> {
+    /// let custom = redirect::Policy::custom(Avoid {
+    ///     host: "example.domain".into(),
+    /// });
+    /// let client = nightfly::Client::builder()
+    ///     .redirect(custom)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Attempt`]: struct.Attempt.html
+    pub fn custom<P>(policy: P) -> Self
+    where
+        P: CustomPolicy + Serialize + DeserializeOwned,
+    {
+        Self {
+            inner: PolicyKind::Custom(Remote::spawn(Custom(policy))),
+        }
+    }
 
     /// Apply this policy to a given [`Attempt`] to produce a [`Action`].
     ///
@@ -117,18 +204,26 @@ impl Policy {
     ///
     /// ```rust
     /// # use nightfly::{Error, redirect};
-    /// #
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Logged;
+    ///
+    /// impl redirect::CustomPolicy for Logged {
+    ///     fn redirect(&self, attempt: redirect::Attempt<'_>) -> redirect::Action {
+    ///         eprintln!("{}, Location: {:?}", attempt.status(), attempt.url());
+    ///         redirect::Policy::default().redirect(attempt)
+    ///     }
+    /// }
+    ///
     /// # fn run() -> Result<(), Error> {
-    /// let custom = redirect::Policy::custom(|attempt| {
-    ///     eprintln!("{}, Location: {:?}", attempt.status(), attempt.url());
-    ///     redirect::Policy::default().redirect(attempt)
-    /// });
+    /// let custom = redirect::Policy::custom(Logged);
     /// # Ok(())
     /// # }
     /// ```
     pub fn redirect(&self, attempt: Attempt) -> Action {
         match self.inner {
-            // PolicyKind::Custom(ref custom) => custom(attempt),
+            PolicyKind::Custom(ref custom) => custom.redirect(attempt),
             PolicyKind::Limit(max) => {
                 if attempt.previous.len() >= max {
                     attempt.error(TooManyRedirects)
@@ -140,18 +235,42 @@ impl Policy {
         }
     }
 
-    pub(crate) fn check(&self, status: StatusCode, next: &Url, previous: &[Url]) -> ActionKind {
+    pub(crate) fn check(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        next: &Url,
+        previous: &[Url],
+    ) -> ActionKind {
         self.redirect(Attempt {
             status,
+            headers,
             next,
             previous,
         })
         .inner
     }
 
+    /// The most redirects followed in a row, 0 if none are.
+    pub(crate) fn max_redirects(&self) -> usize {
+        match self.inner {
+            PolicyKind::Limit(max) => max,
+            PolicyKind::None => 0,
+            // not something a number can describe, report the default
+            PolicyKind::Custom(_) => Policy::default().max_redirects(),
+        }
+    }
+
     pub(crate) fn is_default(&self) -> bool {
         matches!(self.inner, PolicyKind::Limit(10))
     }
+
+    /// Links the current process to a custom policy's.
+    pub(crate) fn link(&self) {
+        if let PolicyKind::Custom(ref custom) = self.inner {
+            custom.link();
+        }
+    }
 }
 
 impl Default for Policy {
@@ -167,6 +286,12 @@ impl<'a> Attempt<'a> {
         self.status
     }
 
+    /// Get the headers of the redirect response, such as a header telling
+    /// whether the redirect may be followed.
+    pub fn headers(&self) -> &HeaderMap {
+        self.headers
+    }
+
     /// Get the next URL to redirect to.
     pub fn url(&self) -> &Url {
         self.next
@@ -204,11 +329,79 @@ impl<'a> Attempt<'a> {
 
 #[derive(Clone, Serialize, Deserialize)]
 enum PolicyKind {
-    // Custom(Box<dyn Fn(Attempt) -> Action + Send + Sync + 'static>),
+    Custom(RemotePolicy),
     Limit(usize),
     None,
 }
 
+/// A `CustomPolicy` running in a process of its own, see
+/// `lunatic_impl::remote`.
+type RemotePolicy = Remote<Redirect, Decision>;
+
+/// An [`Attempt`] on its way to a custom policy's process.
+#[derive(Serialize, Deserialize)]
+struct Redirect {
+    status: u16,
+    headers: remote::Headers,
+    next: Url,
+    previous: Vec<Url>,
+}
+
+/// The [`Action`] a custom policy decided on.
+#[derive(Serialize, Deserialize)]
+enum Decision {
+    Follow,
+    Stop,
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Custom<P>(P);
+
+impl<P> Serve for Custom<P>
+where
+    P: CustomPolicy + Serialize + DeserializeOwned,
+{
+    type Request = Redirect;
+    type Response = Decision;
+
+    fn serve(&mut self, redirect: Redirect) -> Decision {
+        let status = match StatusCode::from_u16(redirect.status) {
+            Ok(status) => status,
+            Err(e) => return Decision::Error(e.to_string()),
+        };
+        let headers = remote::from_wire(redirect.headers);
+        let action = self.0.redirect(Attempt {
+            status,
+            headers: &headers,
+            next: &redirect.next,
+            previous: &redirect.previous,
+        });
+        match action.inner {
+            ActionKind::Follow => Decision::Follow,
+            ActionKind::Stop => Decision::Stop,
+            ActionKind::Error(e) => Decision::Error(e.to_string()),
+        }
+    }
+}
+
+impl CustomPolicy for RemotePolicy {
+    fn redirect(&self, attempt: Attempt<'_>) -> Action {
+        let decision = self.call(Redirect {
+            status: attempt.status.as_u16(),
+            headers: remote::to_wire(attempt.headers),
+            next: attempt.next.clone(),
+            previous: attempt.previous.to_vec(),
+        });
+        match decision {
+            Decision::Follow => attempt.follow(),
+            Decision::Stop => attempt.stop(),
+            Decision::Error(e) => attempt.error(e),
+        }
+    }
+}
+
 impl fmt::Debug for Policy {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("Policy").field(&self.inner).finish()
@@ -218,7 +411,7 @@ impl fmt::Debug for Policy {
 impl fmt::Debug for PolicyKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            // PolicyKind::Custom(..) => f.pad("Custom"),
+            PolicyKind::Custom(..) => f.pad("Custom"),
             PolicyKind::Limit(max) => f.debug_tuple("Limit").field(&max).finish(),
             PolicyKind::None => f.pad("None"),
         }
@@ -234,20 +427,168 @@ pub(crate) enum ActionKind {
     Error(Box<dyn StdError + Send + Sync>),
 }
 
-pub(crate) fn remove_sensitive_headers(headers: &mut HeaderMap, next: &Url, previous: &[Url]) {
-    if let Some(previous) = previous.last() {
-        let cross_host = next.host_str() != previous.host_str()
-            || next.port_or_known_default() != previous.port_or_known_default();
-        if cross_host {
+impl RedirectAuthPolicy {
+    /// The default policy, keeping credentials on same-origin redirects only.
+    pub fn new() -> RedirectAuthPolicy {
+        RedirectAuthPolicy {
+            authorization: Reach::SameOrigin,
+            cookies: Reach::SameOrigin,
+            headers: Vec::new(),
+            trusted: Vec::new(),
+        }
+    }
+
+    /// How far the `Authorization` and `Proxy-Authorization` headers are
+    /// kept.
+    pub fn authorization(mut self, reach: Reach) -> RedirectAuthPolicy {
+        self.authorization = reach;
+        self
+    }
+
+    /// How far a `Cookie` header set on the request is kept.
+    pub fn cookies(mut self, reach: Reach) -> RedirectAuthPolicy {
+        self.cookies = reach;
+        self
+    }
+
+    /// How far another header carrying a credential, such as an API key, is
+    /// kept. Setting the same header again replaces its reach.
+    pub fn header(mut self, name: HeaderName, reach: Reach) -> RedirectAuthPolicy {
+        let name = name.as_str().to_string();
+        self.headers.retain(|(header, _)| *header != name);
+        self.headers.push((name, reach));
+        self
+    }
+
+    /// Keeps every credential on redirects to `domain` and its subdomains,
+    /// such as a single sign-on service of your own on another domain.
+    pub fn trust(mut self, domain: &str) -> RedirectAuthPolicy {
+        self.trusted
+            .push(domain.trim_end_matches('.').to_ascii_lowercase());
+        self
+    }
+
+    /// Removes the credentials that can't follow a redirect to `next`, from
+    /// the last URL of `previous`.
+    pub(crate) fn strip(&self, headers: &mut HeaderMap, next: &Url, previous: &[Url]) {
+        let previous = match previous.last() {
+            Some(previous) => previous,
+            None => return,
+        };
+        if self.trusts(previous, next) {
+            return;
+        }
+        let hop = Reach::between(previous, next);
+        if hop > self.authorization {
             headers.remove(AUTHORIZATION);
-            headers.remove(COOKIE);
-            headers.remove("cookie2");
             headers.remove(PROXY_AUTHORIZATION);
             headers.remove(WWW_AUTHENTICATE);
         }
+        if hop > self.cookies {
+            headers.remove(COOKIE);
+            headers.remove("cookie2");
+        }
+        for (name, reach) in &self.headers {
+            if hop > *reach {
+                headers.remove(name.as_str());
+            }
+        }
+    }
+
+    /// Removes the credentials that can't have followed every redirect
+    /// from the first of `previous` to `next`. For the credentials a client
+    /// adds to each request itself, which `strip` never saw on earlier hops.
+    pub(crate) fn strip_chain(&self, headers: &mut HeaderMap, next: &Url, previous: &[Url]) {
+        if previous.is_empty() {
+            return;
+        }
+        for (i, to) in previous.iter().skip(1).chain(Some(next)).enumerate() {
+            self.strip(headers, to, &previous[i..=i]);
+        }
+    }
+
+    /// Whether an `Authorization` header sent to the first of `previous` would
+    /// still be sent to `next`, see `strip_chain`.
+    pub(crate) fn keeps_authorization(&self, next: &Url, previous: &[Url]) -> bool {
+        previous
+            .iter()
+            .zip(previous.iter().skip(1).chain(Some(next)))
+            .all(|(from, to)| {
+                self.trusts(from, to) || Reach::between(from, to) <= self.authorization
+            })
+    }
+
+    /// Gives `name` the reach of `Authorization`, unless it already has one.
+    pub(crate) fn guard(mut self, name: &HeaderName) -> RedirectAuthPolicy {
+        let known = *name == AUTHORIZATION
+            || *name == PROXY_AUTHORIZATION
+            || self
+                .headers
+                .iter()
+                .any(|(header, _)| header == name.as_str());
+        if !known {
+            let reach = self.authorization;
+            self = self.header(name.clone(), reach);
+        }
+        self
+    }
+
+    fn trusts(&self, previous: &Url, next: &Url) -> bool {
+        if previous.scheme() == "https" && next.scheme() != "https" {
+            return false;
+        }
+        match next.host() {
+            Some(Host::Domain(host)) => {
+                let host = host.trim_end_matches('.');
+                self.trusted
+                    .iter()
+                    .any(|domain| host.eq_ignore_ascii_case(domain) || is_subdomain(host, domain))
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == RedirectAuthPolicy::new()
+    }
+}
+
+impl Default for RedirectAuthPolicy {
+    fn default() -> RedirectAuthPolicy {
+        RedirectAuthPolicy::new()
+    }
+}
+
+impl Reach {
+    /// The reach a credential needs to follow a redirect from `from` to `to`.
+    fn between(from: &Url, to: &Url) -> Reach {
+        if from.origin() == to.origin() {
+            return Reach::SameOrigin;
+        }
+        let same_site = from.scheme() == to.scheme()
+            && match (from.host(), to.host()) {
+                (Some(Host::Domain(a)), Some(Host::Domain(b))) => {
+                    let (a, b) = (a.trim_end_matches('.'), b.trim_end_matches('.'));
+                    a.eq_ignore_ascii_case(b) || is_subdomain(a, b) || is_subdomain(b, a)
+                }
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            };
+        if same_site {
+            Reach::SameSite
+        } else {
+            Reach::CrossSite
+        }
     }
 }
 
+/// Whether `host` is a subdomain of `parent`.
+fn is_subdomain(host: &str, parent: &str) -> bool {
+    host.len() > parent.len()
+        && host.as_bytes()[host.len() - parent.len() - 1] == b'.'
+        && host[host.len() - parent.len()..].eq_ignore_ascii_case(parent)
+}
+
 #[derive(Debug)]
 struct TooManyRedirects;
 
@@ -267,14 +608,14 @@ fn test_redirect_policy_limit() {
         .map(|i| Url::parse(&format!("http://a.b/c/{}", i)).unwrap())
         .collect::<Vec<_>>();
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &previous) {
         ActionKind::Follow => (),
         other => panic!("unexpected {:?}", other),
     }
 
     previous.push(Url::parse("http://a.b.d/e/33").unwrap());
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &previous) {
         ActionKind::Error(err) if err.is::<TooManyRedirects>() => (),
         other => panic!("unexpected {:?}", other),
     }
@@ -286,34 +627,83 @@ fn test_redirect_policy_limit_to_0() {
     let next = Url::parse("http://x.y/z").unwrap();
     let previous = vec![Url::parse("http://a.b/c").unwrap()];
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &previous) {
         ActionKind::Error(err) if err.is::<TooManyRedirects>() => (),
         other => panic!("unexpected {:?}", other),
     }
 }
 
-// #[test]
-// fn test_redirect_policy_custom() {
-//     let policy = Policy::custom(|attempt| {
-//         if attempt.url().host_str() == Some("foo") {
-//             attempt.stop()
-//         } else {
-//             attempt.follow()
-//         }
-//     });
-
-//     let next = Url::parse("http://bar/baz").unwrap();
-//     match policy.check(StatusCode::FOUND, &next, &[]) {
-//         ActionKind::Follow => (),
-//         other => panic!("unexpected {:?}", other),
-//     }
-
-//     let next = Url::parse("http://foo/baz").unwrap();
-//     match policy.check(StatusCode::FOUND, &next, &[]) {
-//         ActionKind::Stop => (),
-//         other => panic!("unexpected {:?}", other),
-//     }
-// }
+#[lunatic::test]
+fn test_redirect_policy_custom() {
+    #[derive(Serialize, Deserialize)]
+    struct Avoid(String);
+
+    impl CustomPolicy for Avoid {
+        fn redirect(&self, attempt: Attempt<'_>) -> Action {
+            if attempt.url().host_str() == Some(self.0.as_str()) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        }
+    }
+
+    let policy = Policy::custom(Avoid("foo".into()));
+
+    let next = Url::parse("http://bar/baz").unwrap();
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &[]) {
+        ActionKind::Follow => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let next = Url::parse("http://foo/baz").unwrap();
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &[]) {
+        ActionKind::Stop => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // the handle still reaches the same policy once it has traveled
+    let json = serde_json::to_string(&policy).unwrap();
+    let policy: Policy = serde_json::from_str(&json).unwrap();
+    match policy.check(StatusCode::FOUND, &HeaderMap::new(), &next, &[]) {
+        ActionKind::Stop => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let json = serde_json::to_string(&Policy::limited(3)).unwrap();
+    let policy: Policy = serde_json::from_str(&json).unwrap();
+    assert!(matches!(policy.inner, PolicyKind::Limit(3)));
+}
+
+#[lunatic::test]
+fn test_redirect_policy_custom_headers() {
+    #[derive(Serialize, Deserialize)]
+    struct Internal;
+
+    impl CustomPolicy for Internal {
+        fn redirect(&self, attempt: Attempt<'_>) -> Action {
+            match attempt.headers().get("x-internal-redirect") {
+                Some(value) if value == "true" => attempt.follow(),
+                _ => attempt.stop(),
+            }
+        }
+    }
+
+    let policy = Policy::custom(Internal);
+    let next = Url::parse("http://x.y/z").unwrap();
+
+    let mut headers = HeaderMap::new();
+    match policy.check(StatusCode::FOUND, &headers, &next, &[]) {
+        ActionKind::Stop => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    headers.insert("x-internal-redirect", "true".parse().unwrap());
+    match policy.check(StatusCode::FOUND, &headers, &next, &[]) {
+        ActionKind::Follow => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
 
 #[lunatic::test]
 fn test_remove_sensitive_headers() {
@@ -328,13 +718,115 @@ fn test_remove_sensitive_headers() {
     let mut prev = vec![Url::parse("http://initial-domain.com/new_path").unwrap()];
     let mut filtered_headers = headers.clone();
 
-    remove_sensitive_headers(&mut headers, &next, &prev);
+    let policy = RedirectAuthPolicy::default();
+    policy.strip(&mut headers, &next, &prev);
     assert_eq!(headers, filtered_headers);
 
     prev.push(Url::parse("http://new-domain.com/path").unwrap());
     filtered_headers.remove(AUTHORIZATION);
     filtered_headers.remove(COOKIE);
 
-    remove_sensitive_headers(&mut headers, &next, &prev);
+    policy.strip(&mut headers, &next, &prev);
     assert_eq!(headers, filtered_headers);
 }
+
+#[lunatic::test]
+fn test_redirect_auth_policy_matrix() {
+    use http::header::{HeaderValue, AUTHORIZATION, COOKIE};
+
+    let api_key = HeaderName::from_static("x-api-key");
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("let me in"));
+    headers.insert(COOKIE, HeaderValue::from_static("foo=bar"));
+    headers.insert(&api_key, HeaderValue::from_static("secret"));
+
+    let prev = [Url::parse("https://example.com/login").unwrap()];
+    let hops = [
+        ("https://example.com/home", Reach::SameOrigin),
+        ("https://example.com:8443/home", Reach::SameSite),
+        ("https://api.example.com/home", Reach::SameSite),
+        ("http://example.com/home", Reach::CrossSite),
+        ("https://www2.example.com.evil.io/home", Reach::CrossSite),
+        ("https://other.org/home", Reach::CrossSite),
+    ];
+    let reaches = [
+        Reach::Never,
+        Reach::SameOrigin,
+        Reach::SameSite,
+        Reach::CrossSite,
+    ];
+
+    for (next, hop) in &hops {
+        let next = Url::parse(next).unwrap();
+        assert_eq!(Reach::between(&prev[0], &next), *hop, "{}", next);
+        for reach in &reaches {
+            let policy = RedirectAuthPolicy::new()
+                .authorization(*reach)
+                .cookies(Reach::Never)
+                .header(api_key.clone(), *reach);
+            let mut redirected = headers.clone();
+            policy.strip(&mut redirected, &next, &prev);
+
+            let kept = hop <= reach;
+            assert_eq!(redirected.contains_key(AUTHORIZATION), kept, "{}", next);
+            assert_eq!(redirected.contains_key(&api_key), kept, "{}", next);
+            assert!(!redirected.contains_key(COOKIE));
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_redirect_auth_policy_chain() {
+    use http::header::{HeaderValue, AUTHORIZATION};
+
+    let api_key = HeaderName::from_static("x-api-key");
+    let policy = RedirectAuthPolicy::new().guard(&api_key);
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("let me in"));
+    headers.insert(&api_key, HeaderValue::from_static("secret"));
+
+    let chain = [
+        Url::parse("https://example.com/login").unwrap(),
+        Url::parse("https://example.com/home").unwrap(),
+    ];
+    let back = Url::parse("https://example.com/again").unwrap();
+    let mut redirected = headers.clone();
+    policy.strip_chain(&mut redirected, &back, &chain);
+    assert_eq!(redirected, headers);
+    assert!(policy.keeps_authorization(&back, &chain));
+    assert!(policy.keeps_authorization(&back, &[]));
+
+    // once a hop left the origin, coming back doesn't bring them back
+    let chain = [
+        Url::parse("https://example.com/login").unwrap(),
+        Url::parse("https://evil.example/").unwrap(),
+    ];
+    policy.strip_chain(&mut redirected, &back, &chain);
+    assert!(redirected.is_empty());
+    assert!(!policy.keeps_authorization(&back, &chain));
+}
+
+#[lunatic::test]
+fn test_redirect_auth_policy_trust() {
+    use http::header::{HeaderValue, AUTHORIZATION, COOKIE};
+
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("let me in"));
+    headers.insert(COOKIE, HeaderValue::from_static("foo=bar"));
+
+    let prev = [Url::parse("https://example.com/login").unwrap()];
+    let policy = RedirectAuthPolicy::new().trust("Login.Example.ORG.");
+
+    for (next, kept) in &[
+        ("https://login.example.org/", true),
+        ("https://eu.login.example.org/", true),
+        ("http://login.example.org/", false),
+        ("https://example.org/", false),
+        ("https://evil-login.example.org/", false),
+    ] {
+        let mut redirected = headers.clone();
+        policy.strip(&mut redirected, &Url::parse(next).unwrap(), &prev);
+        assert_eq!(redirected.contains_key(AUTHORIZATION), *kept, "{}", next);
+        assert_eq!(redirected.contains_key(COOKIE), *kept, "{}", next);
+    }
+}
@@ -3,6 +3,15 @@ use url::Url;
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct ResponseUrl(pub Url);
 
+/// What an `HttpResponse` knows beyond what `http::Response` has room for,
+/// kept in the extensions by `HttpResponse::into_http`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ResponseDetails {
+    pub(crate) redirect_chain: Vec<Url>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) raw_headers: Vec<(String, Vec<u8>)>,
+}
+
 /// Extension trait for http::response::Builder objects
 ///
 /// Allows the user to add a `Url` to the http::Response
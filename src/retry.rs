@@ -0,0 +1,468 @@
+//! Retrying failed requests, with a budget.
+//!
+//! With `ClientBuilder::retry` set, the client sends an idempotent request
+//! again when it couldn't connect, or when the server answered `429 Too Many
+//! Requests`, `502 Bad Gateway`, `503 Service Unavailable` or `504 Gateway
+//! Timeout`. It waits between attempts, doubling the wait every time, or as
+//! long as a `Retry-After` header asks for.
+//!
+//! Retries are paid for from a [`RetryBudget`], a token bucket that
+//! successful requests refill. When a server is down for everyone, retrying
+//! every request would only multiply the load on it. The budget keeps
+//! retries to a share of the traffic instead. Every clone of a `Client`
+//! shares its process and so its budget, however many processes use it.
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use nightfly::retry::{RetryBudget, RetryPolicy};
+//!
+//! let client = nightfly::Client::builder()
+//!     .retry(
+//!         RetryPolicy::new()
+//!             .max_retries(3)
+//!             .backoff(Duration::from_millis(50), Duration::from_secs(1))
+//!             // retries never exceed 10% of successful requests
+//!             .budget(RetryBudget::new(0.1)),
+//!     )
+//!     .build();
+//! ```
+//!
+//! A timeout set with `ClientBuilder::timeout` or `RequestBuilder::timeout`
+//! covers all the attempts of a request, waits included.
+//!
+//! To see retries as they happen, for instance to alert on a rising retry
+//! rate, install a [`RetryObserver`] with `ClientBuilder::retry_observer`:
+//!
+//! ```rust
+//! use nightfly::retry::{RetryEvent, RetryObserver, RetryPolicy};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct PrintRetries;
+//!
+//! impl RetryObserver for PrintRetries {
+//!     fn retrying(&self, event: &RetryEvent<'_>) {
+//!         println!(
+//!             "retrying {} {} ({:?}), attempt {} in {:?}",
+//!             event.method, event.url, event.cause, event.attempt, event.delay
+//!         );
+//!     }
+//! }
+//!
+//! let client = nightfly::Client::builder()
+//!     .retry(RetryPolicy::new())
+//!     .retry_observer(PrintRetries)
+//!     .build();
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use http::header::RETRY_AFTER;
+use http::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::lunatic_impl::remote::{Remote, Serve};
+use crate::{HttpResponse, TimeSource, TimeoutPhase, Url};
+
+/// When and how often a `Client` retries requests, see the module
+/// documentation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: RetryBudget,
+}
+
+/// How many retries a `Client` may send, as a share of its successful
+/// requests, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetryBudget {
+    ratio: f64,
+    reserve: u32,
+}
+
+impl RetryPolicy {
+    /// Retry up to 2 times, waiting 100ms and then 200ms, within the default
+    /// `RetryBudget`.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            budget: RetryBudget::default(),
+        }
+    }
+
+    /// The most times a request is retried.
+    pub fn max_retries(mut self, max: u32) -> RetryPolicy {
+        self.max_retries = max;
+        self
+    }
+
+    /// Wait `base` before the first retry, doubling the wait for every
+    /// retry after it, but never more than `max`. A `Retry-After` that asks
+    /// for more than `max` isn't retried at all.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> RetryPolicy {
+        self.base_delay = base;
+        self.max_delay = max;
+        self
+    }
+
+    /// The budget retries are paid for from.
+    pub fn budget(mut self, budget: RetryBudget) -> RetryPolicy {
+        self.budget = budget;
+        self
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+impl RetryBudget {
+    /// Allow `ratio` retries per successful request, such as `0.2` for at
+    /// most one retry for every five successes.
+    pub fn new(ratio: f64) -> RetryBudget {
+        RetryBudget {
+            ratio: ratio.max(0.0),
+            reserve: 10,
+        }
+    }
+
+    /// How many retries may be sent in a row, including before any request
+    /// has succeeded. It is the size of the token bucket.
+    ///
+    /// Default is 10.
+    pub fn reserve(mut self, retries: u32) -> RetryBudget {
+        self.reserve = retries;
+        self
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> RetryBudget {
+        RetryBudget::new(0.2)
+    }
+}
+
+/// Sees every retry of a client, see the module documentation.
+///
+/// An observer runs in a process of its own, spawned from its serialized
+/// state when it is installed, which is why `ClientBuilder::retry_observer`
+/// also requires `Serialize` and `DeserializeOwned`. The error of a
+/// [`RetryCause::Error`] it gets is a copy, without its source.
+pub trait RetryObserver: Send + Sync + 'static {
+    /// Called before the client waits to send a request again.
+    fn retrying(&self, event: &RetryEvent<'_>);
+}
+
+/// A retry about to be made, as seen by a [`RetryObserver`].
+#[derive(Debug)]
+pub struct RetryEvent<'a> {
+    /// The request method.
+    pub method: &'a str,
+    /// The request URL.
+    pub url: &'a Url,
+    /// The attempt about to be made, 2 for the first retry.
+    pub attempt: u32,
+    /// How long the client waits before making it.
+    pub delay: Duration,
+    /// What went wrong with the previous attempt.
+    pub cause: RetryCause<'a>,
+}
+
+/// Why a request is retried, see [`RetryEvent::cause`].
+#[derive(Debug)]
+pub enum RetryCause<'a> {
+    /// The server answered with this status.
+    Status(StatusCode),
+    /// The request failed before reaching the server.
+    Error(&'a crate::Error),
+}
+
+impl<'a> RetryCause<'a> {
+    pub(crate) fn of(res: &'a crate::Result<HttpResponse>) -> RetryCause<'a> {
+        match res {
+            Ok(res) => RetryCause::Status(res.status),
+            Err(e) => RetryCause::Error(e),
+        }
+    }
+}
+
+/// A `RetryObserver` running in a process of its own, see
+/// `lunatic_impl::remote`.
+pub(crate) type RemoteRetryObserver = Remote<Retrying, ()>;
+
+/// A [`RetryEvent`] on its way to an observer's process.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Retrying {
+    method: String,
+    url: Url,
+    attempt: u32,
+    delay: Duration,
+    cause: Result<u16, crate::Error>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Observer<O>(O);
+
+impl<O> Serve for Observer<O>
+where
+    O: RetryObserver + Serialize + DeserializeOwned,
+{
+    type Request = Retrying;
+    type Response = ();
+
+    fn serve(&mut self, event: Retrying) {
+        let cause = match event.cause {
+            Ok(status) => match StatusCode::from_u16(status) {
+                Ok(status) => RetryCause::Status(status),
+                Err(_) => return,
+            },
+            Err(ref e) => RetryCause::Error(e),
+        };
+        self.0.retrying(&RetryEvent {
+            method: &event.method,
+            url: &event.url,
+            attempt: event.attempt,
+            delay: event.delay,
+            cause,
+        });
+    }
+}
+
+pub(crate) fn spawn<O>(observer: O) -> RemoteRetryObserver
+where
+    O: RetryObserver + Serialize + DeserializeOwned,
+{
+    Remote::spawn(Observer(observer))
+}
+
+impl RetryObserver for RemoteRetryObserver {
+    fn retrying(&self, event: &RetryEvent<'_>) {
+        self.call(Retrying {
+            method: event.method.to_string(),
+            url: event.url.clone(),
+            attempt: event.attempt,
+            delay: event.delay,
+            cause: match event.cause {
+                RetryCause::Status(status) => Ok(status.as_u16()),
+                RetryCause::Error(e) => Err(e.clone()),
+            },
+        });
+    }
+}
+
+/// A `RetryPolicy` and what is left of its budget, owned by the client
+/// process.
+#[derive(Clone, Debug)]
+pub(crate) struct Retrier {
+    policy: RetryPolicy,
+    tokens: f64,
+}
+
+impl Retrier {
+    pub(crate) fn new(policy: RetryPolicy) -> Retrier {
+        Retrier {
+            tokens: f64::from(policy.budget.reserve),
+            policy,
+        }
+    }
+
+    /// How long to wait before retrying a request that got `res` after
+    /// `retries` retries, or `None` if it shouldn't be. A retry is paid
+    /// for when one is returned.
+    pub(crate) fn retry(
+        &mut self,
+        method: &str,
+        res: &crate::Result<HttpResponse>,
+        retries: u32,
+        time: &TimeSource,
+    ) -> Option<Duration> {
+        if !should_retry(res) {
+            if res.is_ok() {
+                self.tokens = (self.tokens + self.policy.budget.ratio)
+                    .min(f64::from(self.policy.budget.reserve));
+            }
+            return None;
+        }
+        if retries >= self.policy.max_retries || !is_idempotent(method) || self.tokens < 1.0 {
+            return None;
+        }
+        let delay = match res.as_ref().ok().and_then(|res| retry_after(res, time)) {
+            Some(delay) if delay > self.policy.max_delay => return None,
+            Some(delay) => delay,
+            None => backoff(self.policy.base_delay, retries).min(self.policy.max_delay),
+        };
+        self.tokens -= 1.0;
+        Some(delay)
+    }
+}
+
+fn should_retry(res: &crate::Result<HttpResponse>) -> bool {
+    match res {
+        Ok(res) => matches!(
+            res.status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        ),
+        // the request never reached the server
+        Err(e) => match e.timeout_detail() {
+            Some(detail) => matches!(
+                detail.phase,
+                TimeoutPhase::Connect | TimeoutPhase::TlsHandshake
+            ),
+            None => e.is_connect(),
+        },
+    }
+}
+
+pub(crate) fn is_idempotent(method: &str) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::OPTIONS,
+        Method::TRACE,
+        Method::PUT,
+        Method::DELETE,
+    ]
+    .iter()
+    .any(|idempotent| idempotent.as_str() == method)
+}
+
+/// `base` doubled for every retry already made.
+pub(crate) fn backoff(base: Duration, retries: u32) -> Duration {
+    base.checked_mul(1 << retries.min(16))
+        .unwrap_or(Duration::MAX)
+}
+
+/// The wait a `Retry-After` header asks for, in seconds or until a date.
+fn retry_after(res: &HttpResponse, time: &TimeSource) -> Option<Duration> {
+    let value = res.headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at: SystemTime = crate::notices::http_date(value)?;
+    Some(at.duration_since(time.now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Simulated;
+    use crate::ResponseBuilderExt;
+
+    fn response(status: u16, retry_after: Option<&str>) -> crate::Result<HttpResponse> {
+        let mut res = http::Response::builder()
+            .status(status)
+            .url(Url::parse("https://example.com/").unwrap());
+        if let Some(retry_after) = retry_after {
+            res = res.header(RETRY_AFTER, retry_after);
+        }
+        HttpResponse::from_http(res.body("").unwrap())
+    }
+
+    fn frozen() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777)
+    }
+
+    fn time() -> TimeSource {
+        TimeSource::new(Simulated(frozen()))
+    }
+
+    #[lunatic::test]
+    fn backs_off_exponentially() {
+        let mut retrier = Retrier::new(
+            RetryPolicy::new()
+                .max_retries(3)
+                .backoff(Duration::from_millis(100), Duration::from_millis(250)),
+        );
+        let failed = response(503, None);
+        let delays: Vec<_> = (0..4)
+            .map(|retries| retrier.retry("GET", &failed, retries, &time()))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(250)),
+                None,
+            ]
+        );
+    }
+
+    #[lunatic::test]
+    fn retries_only_what_can_be() {
+        let mut retrier = Retrier::new(RetryPolicy::new());
+        assert_eq!(retrier.retry("GET", &response(200, None), 0, &time()), None);
+        assert_eq!(retrier.retry("GET", &response(500, None), 0, &time()), None);
+        assert_eq!(
+            retrier.retry("POST", &response(503, None), 0, &time()),
+            None
+        );
+
+        let refused = Err(crate::error::connect(
+            std::io::ErrorKind::ConnectionRefused.into(),
+            Url::parse("https://example.com/").unwrap(),
+        ));
+        assert!(retrier.retry("PUT", &refused, 0, &time()).is_some());
+    }
+
+    #[lunatic::test]
+    fn honors_retry_after() {
+        let mut retrier = Retrier::new(RetryPolicy::new());
+        let res = response(429, Some("1"));
+        assert_eq!(
+            retrier.retry("GET", &res, 0, &time()),
+            Some(Duration::from_secs(1))
+        );
+        let res = response(503, Some("Sun, 06 Nov 1994 08:49:38 GMT"));
+        assert_eq!(
+            retrier.retry("GET", &res, 0, &time()),
+            Some(Duration::from_secs(1))
+        );
+        // longer than the policy waits
+        let res = response(503, Some("120"));
+        assert_eq!(retrier.retry("GET", &res, 0, &time()), None);
+    }
+
+    #[lunatic::test]
+    fn names_the_cause() {
+        let res = response(503, None);
+        assert!(matches!(
+            RetryCause::of(&res),
+            RetryCause::Status(StatusCode::SERVICE_UNAVAILABLE)
+        ));
+        let refused = Err(crate::error::connect(
+            std::io::ErrorKind::ConnectionRefused.into(),
+            Url::parse("https://example.com/").unwrap(),
+        ));
+        assert!(matches!(RetryCause::of(&refused), RetryCause::Error(e) if e.is_connect()));
+    }
+
+    #[lunatic::test]
+    fn spends_the_budget() {
+        let budget = RetryBudget::new(0.5).reserve(2);
+        let mut retrier = Retrier::new(RetryPolicy::new().budget(budget));
+        let failed = response(502, None);
+        assert!(retrier.retry("GET", &failed, 0, &time()).is_some());
+        assert!(retrier.retry("GET", &failed, 0, &time()).is_some());
+        assert_eq!(retrier.retry("GET", &failed, 0, &time()), None);
+
+        // two successes pay for one more retry
+        let ok = response(200, None);
+        retrier.retry("GET", &ok, 0, &time());
+        assert_eq!(retrier.retry("GET", &failed, 0, &time()), None);
+        retrier.retry("GET", &ok, 0, &time());
+        assert!(retrier.retry("GET", &failed, 0, &time()).is_some());
+        assert_eq!(retrier.retry("GET", &failed, 0, &time()), None);
+    }
+}
@@ -0,0 +1,307 @@
+//! Crawling politely with robots.txt.
+//!
+//! With `ClientBuilder::robots` set, the client fetches `/robots.txt` the
+//! first time it talks to a host, keeps the rules that apply to the crawler,
+//! and checks every request against them, redirects included. Requests to
+//! disallowed paths fail with an error for which
+//! [`Error::is_disallowed`](crate::Error::is_disallowed) is true, or are only
+//! logged with [`RobotsPolicy::warn_only`]. A `Crawl-delay` is honored by
+//! spacing out requests to the host.
+//!
+//! ```rust
+//! use nightfly::robots::RobotsPolicy;
+//!
+//! let client = nightfly::Client::builder()
+//!     .user_agent("ExampleBot/1.0 (+https://example.com/bot)")
+//!     .robots(RobotsPolicy::new("ExampleBot"))
+//!     .build();
+//! ```
+//!
+//! Rules are matched as described in [RFC 9309]: the longest matching
+//! `Allow` or `Disallow` rule wins, with `*` and `$` wildcards. A robots.txt
+//! that can't be found (4xx) allows everything; one that can't be fetched
+//! (5xx or a network error) disallows everything until it can be.
+//!
+//! [RFC 9309]: https://www.rfc-editor.org/rfc/rfc9309
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error, HttpResponse, TimeSource, Url};
+
+/// How a `Client` treats robots.txt, see the module documentation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RobotsPolicy {
+    user_agent: String,
+    warn_only: bool,
+}
+
+impl RobotsPolicy {
+    /// Follow the rules for `user_agent`, the product token of the crawler
+    /// such as `ExampleBot`, falling back to the rules for `*`.
+    pub fn new<T: Into<String>>(user_agent: T) -> RobotsPolicy {
+        RobotsPolicy {
+            user_agent: user_agent.into(),
+            warn_only: false,
+        }
+    }
+
+    /// Log disallowed requests and send them anyway, instead of failing
+    /// them. `Crawl-delay` is still honored.
+    pub fn warn_only(mut self) -> RobotsPolicy {
+        self.warn_only = true;
+        self
+    }
+}
+
+/// The rules of the hosts a client has talked to.
+#[derive(Clone, Debug)]
+pub(crate) struct Robots {
+    policy: RobotsPolicy,
+    hosts: HashMap<String, Host>,
+}
+
+#[derive(Clone, Debug)]
+struct Host {
+    rules: Rules,
+    last_request: Option<SystemTime>,
+}
+
+impl Robots {
+    pub(crate) fn new(policy: RobotsPolicy) -> Robots {
+        Robots {
+            policy,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// Returns the URL of the robots.txt to fetch before sending a request
+    /// to `url`, if it isn't known yet.
+    pub(crate) fn to_fetch(&self, url: &Url) -> Option<Url> {
+        if url.path() == "/robots.txt" || self.hosts.contains_key(&origin(url)) {
+            return None;
+        }
+        url.join("/robots.txt").ok()
+    }
+
+    /// Records the outcome of fetching the robots.txt of `url`'s host.
+    pub(crate) fn learn(&mut self, url: &Url, res: crate::Result<HttpResponse>) {
+        let rules = match res {
+            Ok(ref res) if res.status.is_success() => {
+                Rules::parse(&String::from_utf8_lossy(&res.body), &self.policy.user_agent)
+            }
+            Ok(ref res) if res.status.is_server_error() => return,
+            Ok(_) => Rules::default(),
+            Err(_) => return,
+        };
+        self.hosts.insert(
+            origin(url),
+            Host {
+                rules,
+                last_request: None,
+            },
+        );
+    }
+
+    /// Checks `url` against the rules of its host, waiting out the crawl
+    /// delay if the request may go ahead.
+    pub(crate) fn check(&mut self, url: &Url, time: &TimeSource) -> crate::Result<()> {
+        if url.path() == "/robots.txt" {
+            return Ok(());
+        }
+        let host = match self.hosts.get_mut(&origin(url)) {
+            Some(host) => host,
+            // robots.txt couldn't be fetched
+            None => return self.disallowed(url),
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        if !host.rules.allows(&path) {
+            self.disallowed(url)?;
+        }
+
+        let host = self
+            .hosts
+            .get_mut(&origin(url))
+            .expect("host was found above");
+        if let (Some(delay), Some(last)) = (host.rules.crawl_delay, host.last_request) {
+            let since = time.since(last);
+            if since < delay {
+                time.sleep(delay - since);
+            }
+        }
+        host.last_request = Some(time.now());
+        Ok(())
+    }
+
+    fn disallowed(&self, url: &Url) -> crate::Result<()> {
+        if self.policy.warn_only {
+            lunatic_log::warn!("robots.txt disallows {}", url);
+            Ok(())
+        } else {
+            Err(error::disallowed("robots.txt", url.clone()))
+        }
+    }
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// The rules of a robots.txt group.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Rules {
+    // (allow, pattern)
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Rules {
+    /// Parses `text` and keeps the group for `user_agent`, or the `*` group
+    /// if there is none.
+    fn parse(text: &str, user_agent: &str) -> Rules {
+        let user_agent = user_agent.to_ascii_lowercase();
+        let mut specific: Option<Rules> = None;
+        let mut fallback: Option<Rules> = None;
+
+        let mut agents: Vec<String> = Vec::new();
+        let mut group = Rules::default();
+        let mut in_rules = false;
+
+        let mut finish = |agents: &mut Vec<String>, group: &mut Rules| {
+            let group = std::mem::take(group);
+            for agent in agents.drain(..) {
+                let target = if agent.is_empty() {
+                    continue;
+                } else if agent == "*" {
+                    &mut fallback
+                } else if user_agent.contains(&agent) {
+                    &mut specific
+                } else {
+                    continue;
+                };
+                // Groups for the same agent are merged.
+                let merged = target.get_or_insert_with(Rules::default);
+                merged.rules.extend(group.rules.iter().cloned());
+                merged.crawl_delay = merged.crawl_delay.or(group.crawl_delay);
+            }
+        };
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        finish(&mut agents, &mut group);
+                        in_rules = false;
+                    }
+                    agents.push(value.to_ascii_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    if !value.is_empty() {
+                        group.rules.push((key == "allow", value.to_string()));
+                    }
+                }
+                "crawl-delay" => {
+                    in_rules = true;
+                    group.crawl_delay = value.parse::<f64>().ok().map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        finish(&mut agents, &mut group);
+
+        specific.or(fallback).unwrap_or_default()
+    }
+
+    /// The longest matching rule decides, and `Allow` wins ties.
+    fn allows(&self, path: &str) -> bool {
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if matches(pattern, path) {
+                best = best.max(Some((pattern.len(), *allow)));
+            }
+        }
+        best.map(|(_, allow)| allow).unwrap_or(true)
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` matches any sequence and a
+/// trailing `$` anchors the end.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+
+# The example bot gets its own group
+User-agent: ExampleBot
+User-agent: OtherBot
+Disallow: /*.pdf$
+Disallow: /search
+Crawl-delay: 2.5
+";
+
+    #[lunatic::test]
+    fn picks_the_most_specific_group() {
+        let rules = Rules::parse(ROBOTS, "ExampleBot/1.0");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(2500)));
+        assert!(rules.allows("/private/secret.html"));
+        assert!(!rules.allows("/search?q=rust"));
+        assert!(!rules.allows("/docs/manual.pdf"));
+        assert!(rules.allows("/docs/manual.pdf.html"));
+
+        let rules = Rules::parse(ROBOTS, "SomeoneElse");
+        assert_eq!(rules.crawl_delay, None);
+        assert!(!rules.allows("/private/secret.html"));
+        assert!(rules.allows("/private/public.html"));
+        assert!(rules.allows("/search"));
+    }
+
+    #[lunatic::test]
+    fn matches_wildcards() {
+        assert!(matches("/", "/anything"));
+        assert!(matches("/a*c", "/abbbc/d"));
+        assert!(!matches("/a*c$", "/abbbc/d"));
+        assert!(matches("/a*c$", "/abbbc"));
+        assert!(matches("/exact$", "/exact"));
+        assert!(!matches("/exact$", "/exact/more"));
+        assert!(!matches("/private", "/public"));
+    }
+}
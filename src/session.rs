@@ -0,0 +1,180 @@
+//! Logging in to websites with a form.
+//!
+//! Many sites have no API and authenticate a browser by posting a login form
+//! and handing back a session cookie. [`Session`] wraps a `Client` for that
+//! flow: it posts the form, lets the client's cookie store keep whatever
+//! cookies the site sets, and looks at the page it lands on to tell whether
+//! the login worked.
+//!
+//! ```rust
+//! use nightfly::session::Session;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let mut session = Session::new(nightfly::Client::new());
+//! session.login(
+//!     "https://example.com/login",
+//!     &[("username", "alice"), ("password", "hunter2")],
+//! )?;
+//!
+//! if session.is_authenticated() {
+//!     let inbox = session.client().get("https://example.com/inbox").send()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Serialize;
+
+use crate::{Client, HttpResponse, IntoUrl, Url};
+
+/// Phrases that login pages commonly show when the credentials are wrong.
+const FAILURE_PHRASES: &[&str] = &[
+    "authentication failed",
+    "incorrect password",
+    "incorrect username",
+    "invalid credentials",
+    "invalid password",
+    "invalid username",
+    "login failed",
+    "wrong password",
+];
+
+/// A `Client` that has logged in, or tried to, through a login form.
+#[derive(Clone, Debug)]
+pub struct Session {
+    client: Client,
+    authenticated: bool,
+    failure_markers: Vec<String>,
+}
+
+impl Session {
+    /// Wrap `client`. Its cookie store holds the session once logged in.
+    pub fn new(client: Client) -> Session {
+        Session {
+            client,
+            authenticated: false,
+            failure_markers: Vec::new(),
+        }
+    }
+
+    /// Also treat pages containing `marker` as failed logins, for sites
+    /// whose error message isn't one of the common phrases. The match is
+    /// case insensitive.
+    pub fn failure_marker<T: Into<String>>(mut self, marker: T) -> Session {
+        self.failure_markers.push(marker.into().to_lowercase());
+        self
+    }
+
+    /// Submit `fields` as a urlencoded form to `form_url`.
+    ///
+    /// The login counts as failed when the final response has an error
+    /// status, when it is the login form again, or when it contains a common
+    /// failure message or one of the `failure_marker`s. The outcome is
+    /// reported by `is_authenticated`; the response is returned either way
+    /// so callers can inspect it.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the form can't be sent, not if the credentials
+    /// are rejected.
+    pub fn login<U, T>(&mut self, form_url: U, fields: &T) -> crate::Result<HttpResponse>
+    where
+        U: IntoUrl,
+        T: Serialize + ?Sized,
+    {
+        let form_url = form_url.into_url()?;
+        let res = self.client.post(form_url.clone()).form(fields).send()?;
+        self.authenticated = !is_failure(&form_url, &res, &self.failure_markers);
+        Ok(res)
+    }
+
+    /// Forget the login. The cookies stay in the client's cookie store.
+    pub fn logout(&mut self) {
+        self.authenticated = false;
+    }
+
+    /// Returns true if the last `login` succeeded.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Get the underlying `Client`, to send requests within the session.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Unwrap the underlying `Client`.
+    pub fn into_client(self) -> Client {
+        self.client
+    }
+}
+
+fn is_failure(form_url: &Url, res: &HttpResponse, markers: &[String]) -> bool {
+    if res.status.is_client_error() || res.status.is_server_error() {
+        return true;
+    }
+    let page = String::from_utf8_lossy(&res.body).to_lowercase();
+    let back_on_form = res.url.host_str() == form_url.host_str()
+        && res.url.path() == form_url.path()
+        && page.contains("type=\"password\"");
+    back_on_form
+        || FAILURE_PHRASES.iter().any(|phrase| page.contains(phrase))
+        || markers.iter().any(|marker| page.contains(marker.as_str()))
+}
+
+impl From<Client> for Session {
+    fn from(client: Client) -> Session {
+        Session::new(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::{HeaderMap, StatusCode};
+
+    use super::*;
+    use crate::Version;
+
+    fn response(status: u16, url: &str, body: &str) -> HttpResponse {
+        HttpResponse {
+            body: Bytes::copy_from_slice(body.as_bytes()),
+            status: StatusCode::from_u16(status).unwrap(),
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            url: Url::parse(url).unwrap(),
+            redirect_chain: Vec::new(),
+            request_id: None,
+            raw_body: None,
+            raw_headers: vec![],
+            body_hash: None,
+        }
+    }
+
+    #[lunatic::test]
+    fn detects_failure_pages() {
+        let markers = vec!["try again".to_string()];
+        let form = Url::parse("https://example.com/login").unwrap();
+
+        let ok = response(200, "https://example.com/home", "<h1>Welcome back</h1>");
+        assert!(!is_failure(&form, &ok, &markers));
+
+        let cases = [
+            response(401, "https://example.com/login", ""),
+            response(
+                200,
+                "https://example.com/login",
+                "<input type=\"password\" name=\"password\">",
+            ),
+            response(
+                200,
+                "https://example.com/home",
+                "Invalid username or password",
+            ),
+            response(200, "https://example.com/home", "Please try again"),
+        ];
+        for res in &cases {
+            assert!(is_failure(&form, res, &markers), "{}", res.url);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Request signing.
+//!
+//! APIs that authenticate requests with a signature, usually an HMAC over
+//! the method, URL, some headers and a hash of the body, can implement
+//! [`RequestSigner`] once and install it with
+//! `ClientBuilder::request_signer`. The client calls the signer right before
+//! writing each request, redirects included, once all of its headers are
+//! final.
+//!
+//! ```rust
+//! use nightfly::header::{HeaderMap, HeaderValue};
+//! use nightfly::signing::{RequestSigner, SigningRequest};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct KeyIdSigner {
+//!     key_id: String,
+//! }
+//!
+//! impl RequestSigner for KeyIdSigner {
+//!     fn sign(&self, req: &SigningRequest<'_>) -> Result<HeaderMap, nightfly::signing::BoxError> {
+//!         let mut headers = HeaderMap::new();
+//!         headers.insert("x-key-id", HeaderValue::from_str(&self.key_id)?);
+//!         headers.insert("x-content-sha256", HeaderValue::from_str(req.body_sha256)?);
+//!         Ok(headers)
+//!     }
+//! }
+//!
+//! let client = nightfly::Client::builder()
+//!     .request_signer(KeyIdSigner { key_id: "k1".into() })
+//!     .build();
+//! ```
+
+use http::{HeaderMap, Method};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::lunatic_impl::remote::{self, Remote, Serve};
+
+/// `SigningRequest::body_sha256` of a body streamed with `Body::from_reader`,
+/// which can't be hashed before it is sent.
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Error type returned by signers.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Computes authentication headers for outgoing requests.
+///
+/// A signer runs in a process of its own, spawned from its serialized state
+/// when it is installed, which is why `ClientBuilder::request_signer` also
+/// requires `Serialize` and `DeserializeOwned`.
+pub trait RequestSigner: Send + Sync + 'static {
+    /// Returns the headers to add to `req`. They replace any header of the
+    /// same name already present.
+    fn sign(&self, req: &SigningRequest<'_>) -> Result<HeaderMap, BoxError>;
+}
+
+/// A request about to be sent, as seen by a [`RequestSigner`].
+#[derive(Debug)]
+pub struct SigningRequest<'a> {
+    /// The request method.
+    pub method: &'a Method,
+    /// The full request URL, query included.
+    pub url: &'a Url,
+    /// All the headers that will be sent, before signing.
+    pub headers: &'a HeaderMap,
+    /// Lowercase hex SHA-256 of the request body, or of the empty string if
+    /// the request has no body, or [`UNSIGNED_PAYLOAD`] if the body is read
+    /// from a reader.
+    pub body_sha256: &'a str,
+}
+
+/// A `RequestSigner` running in a process of its own, see
+/// `lunatic_impl::remote`.
+pub(crate) type RemoteSigner = Remote<Sign, Result<remote::Headers, String>>;
+
+/// A [`SigningRequest`] on its way to a signer's process.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Sign {
+    method: String,
+    url: Url,
+    headers: remote::Headers,
+    body_sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Signer<S>(S);
+
+impl<S> Serve for Signer<S>
+where
+    S: RequestSigner + Serialize + DeserializeOwned,
+{
+    type Request = Sign;
+    type Response = Result<remote::Headers, String>;
+
+    fn serve(&mut self, req: Sign) -> Self::Response {
+        let method = Method::from_bytes(req.method.as_bytes()).map_err(|e| e.to_string())?;
+        let headers = remote::from_wire(req.headers);
+        self.0
+            .sign(&SigningRequest {
+                method: &method,
+                url: &req.url,
+                headers: &headers,
+                body_sha256: &req.body_sha256,
+            })
+            .map(|signed| remote::to_wire(&signed))
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub(crate) fn spawn<S>(signer: S) -> RemoteSigner
+where
+    S: RequestSigner + Serialize + DeserializeOwned,
+{
+    Remote::spawn(Signer(signer))
+}
+
+impl RequestSigner for RemoteSigner {
+    fn sign(&self, req: &SigningRequest<'_>) -> Result<HeaderMap, BoxError> {
+        let signed = self.call(Sign {
+            method: req.method.to_string(),
+            url: req.url.clone(),
+            headers: remote::to_wire(req.headers),
+            body_sha256: req.body_sha256.to_string(),
+        })?;
+        Ok(remote::from_wire(signed))
+    }
+}
+
+/// Lowercase hex SHA-256 of `body`.
+pub(crate) fn sha256_hex(body: &[u8]) -> String {
+    Sha256::digest(body)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn hashes_empty_body() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
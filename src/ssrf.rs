@@ -0,0 +1,129 @@
+//! Refusing requests to private addresses, see
+//! `ClientBuilder::block_private_addresses`.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use url::{Host, Url};
+
+use crate::error;
+
+/// Fails if the host of `url` is, or resolves to, an address that isn't
+/// reachable on the public internet, and otherwise returns the addresses it
+/// checked, for a plain TCP connection to be made to.
+///
+/// Every address the host resolves to is checked, so a name can't slip
+/// through with one public and one private address.
+pub(crate) fn check(url: &Url) -> crate::Result<Vec<SocketAddr>> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        Some(Host::Domain(domain)) => lunatic::net::resolve(&format!("{}:{}", domain, port))
+            .map_err(|e| error::connect(e, url.clone()))?
+            .collect(),
+        None => return Err(error::url_bad_scheme(url.clone())),
+    };
+    match addrs.iter().find(|addr| is_private(addr.ip())) {
+        Some(addr) => Err(error::disallowed(
+            &format!("block_private_addresses ({} is private)", addr.ip()),
+            url.clone(),
+        )),
+        None => Ok(addrs),
+    }
+}
+
+/// Whether `ip` is a loopback, private, link-local, shared, multicast or
+/// otherwise reserved address. Cloud metadata services listen on link-local
+/// (`169.254.169.254`, `fd00:ec2::254`) or shared (`100.100.100.200`)
+/// addresses.
+pub(crate) fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => is_private_v6(ip),
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "this network", 0.0.0.0/8
+        || a == 0
+        // shared address space for carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && b & 0xc0 == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking, 198.18.0.0/15
+        || (a == 198 && b & 0xfe == 18)
+        // reserved, 240.0.0.0/4
+        || a >= 240
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // IPv4-mapped, IPv4-compatible and NAT64 addresses reach an IPv4 host
+    if let Some(v4) = ip.to_ipv4() {
+        return is_private_v4(v4);
+    }
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_private_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local, fc00::/7
+        || segments[0] & 0xfe00 == 0xfc00
+        // link-local, fe80::/10, and the deprecated site-local, fec0::/10
+        || segments[0] & 0xff80 == 0xfe80
+        // documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn private(ip: &str) -> bool {
+        is_private(ip.parse().unwrap())
+    }
+
+    #[lunatic::test]
+    fn private_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00:ec2::254",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(private(ip), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "172.32.0.1", "100.128.0.1", "2606:4700::1111"] {
+            assert!(!private(ip), "{}", ip);
+        }
+    }
+
+    #[lunatic::test]
+    fn checks_literal_hosts() {
+        let url = Url::parse("http://169.254.169.254/latest/meta-data/").unwrap();
+        assert!(check(&url).unwrap_err().is_disallowed());
+        let url = Url::parse("http://[::1]:8080/").unwrap();
+        assert!(check(&url).unwrap_err().is_disallowed());
+        assert!(check(&Url::parse("http://1.1.1.1/").unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,226 @@
+//! Bearer tokens that expire.
+//!
+//! Service account JWTs, workload identity and most OAuth2 flows hand out
+//! short lived access tokens. Implement [`TokenSource`] to fetch one and
+//! install it with `ClientBuilder::token_source`: the client asks the source
+//! for a token before its first request, sends it as
+//! `Authorization: Bearer <token>`, and asks for a new one shortly before
+//! the current one expires.
+//!
+//! ```rust
+//! use std::time::{Duration, SystemTime};
+//!
+//! use nightfly::signing::BoxError;
+//! use nightfly::token::{Token, TokenSource};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct ServiceAccount {
+//!     token_url: String,
+//! }
+//!
+//! impl TokenSource for ServiceAccount {
+//!     fn token(&self) -> Result<Token, BoxError> {
+//!         let access_token = nightfly::get(self.token_url.as_str())?.text()?;
+//!         let expires_at = SystemTime::now() + Duration::from_secs(3600);
+//!         Ok(Token::new(access_token).expires_at(expires_at))
+//!     }
+//! }
+//!
+//! let client = nightfly::Client::builder()
+//!     .token_source(ServiceAccount {
+//!         token_url: "http://metadata/token".into(),
+//!     })
+//!     .build();
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::lunatic_impl::remote::{Remote, Serve};
+use crate::signing::BoxError;
+use crate::TimeSource;
+
+/// Fetches access tokens for a `Client`.
+///
+/// A source runs in a process of its own, spawned from its serialized state
+/// when it is installed, which is why `ClientBuilder::token_source` also
+/// requires `Serialize` and `DeserializeOwned`. `refresh_before_expiry` is
+/// asked for once, at that point.
+pub trait TokenSource: Send + Sync + 'static {
+    /// Fetches a new token.
+    fn token(&self) -> Result<Token, BoxError>;
+
+    /// How long before its expiry a token gets replaced. Refreshing early
+    /// keeps a token from expiring while a request is in flight.
+    ///
+    /// Defaults to one minute.
+    fn refresh_before_expiry(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// An access token returned by a [`TokenSource`].
+#[derive(Clone)]
+pub struct Token {
+    value: String,
+    expires_at: Option<SystemTime>,
+}
+
+impl Token {
+    /// A token that never expires.
+    pub fn new<T: Into<String>>(value: T) -> Token {
+        Token {
+            value: value.into(),
+            expires_at: None,
+        }
+    }
+
+    /// Set the time after which the token is no longer valid.
+    pub fn expires_at(mut self, expires_at: SystemTime) -> Token {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Get the token itself.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Get the expiry time, if the token has one.
+    pub fn expiry(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("value", &"<redacted>")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// A `TokenSource` running in a process of its own, see
+/// `lunatic_impl::remote`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct RemoteTokenSource {
+    remote: Remote<(), Result<(String, Option<SystemTime>), String>>,
+    // asked for once, when the source is installed
+    refresh_before_expiry: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Source<S>(S);
+
+impl<S> Serve for Source<S>
+where
+    S: TokenSource + Serialize + DeserializeOwned,
+{
+    type Request = ();
+    type Response = Result<(String, Option<SystemTime>), String>;
+
+    fn serve(&mut self, _: ()) -> Self::Response {
+        let token = self.0.token().map_err(|e| e.to_string())?;
+        Ok((token.value, token.expires_at))
+    }
+}
+
+pub(crate) fn spawn<S>(source: S) -> RemoteTokenSource
+where
+    S: TokenSource + Serialize + DeserializeOwned,
+{
+    RemoteTokenSource {
+        refresh_before_expiry: source.refresh_before_expiry(),
+        remote: Remote::spawn(Source(source)),
+    }
+}
+
+impl RemoteTokenSource {
+    pub(crate) fn link(&self) {
+        self.remote.link();
+    }
+}
+
+impl TokenSource for RemoteTokenSource {
+    fn token(&self) -> Result<Token, BoxError> {
+        let (value, expires_at) = self.remote.call(())?;
+        Ok(Token { value, expires_at })
+    }
+
+    fn refresh_before_expiry(&self) -> Duration {
+        self.refresh_before_expiry
+    }
+}
+
+/// A `TokenSource` together with the last token it returned.
+#[derive(Clone)]
+pub(crate) struct CachedToken {
+    source: Arc<dyn TokenSource>,
+    current: Option<Token>,
+}
+
+impl CachedToken {
+    pub(crate) fn new(source: Arc<dyn TokenSource>) -> CachedToken {
+        CachedToken {
+            source,
+            current: None,
+        }
+    }
+
+    /// Returns the current token, fetching a new one first if there is none
+    /// yet or the current one is about to expire.
+    pub(crate) fn get(&mut self, time: &TimeSource) -> Result<&Token, BoxError> {
+        let margin = self.source.refresh_before_expiry();
+        let fresh = match self.current {
+            Some(Token {
+                expires_at: Some(expires_at),
+                ..
+            }) => time.now() + margin < expires_at,
+            Some(_) => true,
+            None => false,
+        };
+        if !fresh {
+            self.current = Some(self.source.token()?);
+        }
+        Ok(self.current.as_ref().expect("token was just fetched"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    use crate::clock::Simulated;
+
+    static FETCHES: AtomicU64 = AtomicU64::new(0);
+
+    struct Counting(TimeSource);
+
+    impl TokenSource for Counting {
+        fn token(&self) -> Result<Token, BoxError> {
+            let n = FETCHES.fetch_add(1, Ordering::SeqCst);
+            Ok(Token::new(format!("t{}", n)).expires_at(self.0.now() + Duration::from_secs(300)))
+        }
+    }
+
+    #[lunatic::test]
+    fn refreshes_near_expiry() {
+        let time = TimeSource::new(Simulated(SystemTime::UNIX_EPOCH));
+        let mut cached = CachedToken::new(Arc::new(Counting(time.clone())));
+
+        assert_eq!(cached.get(&time).unwrap().value(), "t0");
+        time.sleep(Duration::from_secs(200));
+        assert_eq!(cached.get(&time).unwrap().value(), "t0");
+        // Within a minute of the expiry at 300s.
+        time.sleep(Duration::from_secs(50));
+        assert_eq!(cached.get(&time).unwrap().value(), "t1");
+    }
+}
@@ -0,0 +1,481 @@
+//! URI templates, as described in RFC 6570.
+//!
+//! Hypermedia APIs such as GitHub's hand out links as templates, like
+//! `https://api.github.com/repos/{owner}/{repo}/issues{?state,labels}`,
+//! to be filled in with variables. `Client::get_template` and
+//! `Client::request_template` expand such a template and start a request to
+//! the resulting URL:
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let res = client
+//!     .get_template(
+//!         "https://api.github.com/repos/{owner}/{repo}/issues{?state,labels}",
+//!         &serde_json::json!({
+//!             "owner": "lunatic-solutions",
+//!             "repo": "lunatic",
+//!             "state": "open",
+//!             "labels": ["bug", "help wanted"],
+//!         }),
+//!     )
+//!     .send()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The variables can be any value that serializes to a map, such as a
+//! struct, a `HashMap` or a `serde_json::Value` object, or to a sequence of
+//! name and value pairs, as `RequestBuilder::query` takes. Strings, numbers and
+//! booleans are expanded as strings, sequences as lists and maps as
+//! associative arrays; `None`, empty lists and empty maps count as
+//! undefined, so the expression leaves them out. All four levels of the RFC
+//! are supported, including the `:` prefix and `*` explode modifiers.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A parsed URI template, see the module documentation.
+///
+/// Parsing once and expanding many times spares parsing the same template
+/// on every request, as `uri_template::expand` does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UriTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Expression(Operator, Vec<VarSpec>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Simple,
+    Reserved,
+    Fragment,
+    Label,
+    Path,
+    PathParameter,
+    Query,
+    QueryContinuation,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct VarSpec {
+    name: String,
+    modifier: Modifier,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Modifier {
+    None,
+    Prefix(usize),
+    Explode,
+}
+
+/// Expand `template` with the variables in `vars`.
+///
+/// # Errors
+///
+/// Fails if `template` isn't a valid URI template, or `vars` doesn't
+/// serialize to a map or a sequence of pairs.
+pub fn expand<T: Serialize + ?Sized>(template: &str, vars: &T) -> crate::Result<String> {
+    UriTemplate::parse(template)?.expand(vars)
+}
+
+impl UriTemplate {
+    /// Parse a URI template.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an expression isn't closed, is empty, uses an operator the
+    /// RFC reserves for future use, or names a variable with characters
+    /// other than letters, digits, `_`, `.` and percent-encoded ones.
+    pub fn parse(template: &str) -> crate::Result<UriTemplate> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| {
+                crate::error::builder(format!(
+                    "unclosed expression in URI template {:?}",
+                    template
+                ))
+            })?;
+            parts.push(parse_expression(&rest[start + 1..start + end])?);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_owned()));
+        }
+        Ok(UriTemplate { parts })
+    }
+
+    /// Expand the template with the variables in `vars`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `vars` doesn't serialize to a map or a sequence of pairs, or a
+    /// `:` prefix modifier is used on a list or a map.
+    pub fn expand<T: Serialize + ?Sized>(&self, vars: &T) -> crate::Result<String> {
+        let vars = match serde_json::to_value(vars).map_err(crate::error::builder)? {
+            Value::Object(vars) => vars,
+            // a sequence of pairs, as taken by `RequestBuilder::query`
+            Value::Array(pairs) if pairs.iter().all(is_pair) => pairs
+                .into_iter()
+                .filter_map(|pair| match pair {
+                    Value::Array(mut pair) => {
+                        let value = pair.pop()?;
+                        Some((pair.pop()?.as_str()?.to_owned(), value))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => {
+                return Err(crate::error::builder(
+                    "URI template variables must serialize to a map or a sequence of pairs",
+                ))
+            }
+        };
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => encode(&mut out, literal, true),
+                Part::Expression(op, specs) => {
+                    let mut first = true;
+                    for spec in specs {
+                        let value = match vars.get(&spec.name) {
+                            Some(value) if is_defined(value) => value,
+                            _ => continue,
+                        };
+                        out.push_str(if first { op.first() } else { op.separator() });
+                        first = false;
+                        expand_value(&mut out, *op, spec, value)?;
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn parse_expression(expression: &str) -> crate::Result<Part> {
+    let invalid = || {
+        crate::error::builder(format!(
+            "invalid expression {{{}}} in URI template",
+            expression
+        ))
+    };
+    let (op, list) = match expression.chars().next() {
+        Some('+') => (Operator::Reserved, &expression[1..]),
+        Some('#') => (Operator::Fragment, &expression[1..]),
+        Some('.') => (Operator::Label, &expression[1..]),
+        Some('/') => (Operator::Path, &expression[1..]),
+        Some(';') => (Operator::PathParameter, &expression[1..]),
+        Some('?') => (Operator::Query, &expression[1..]),
+        Some('&') => (Operator::QueryContinuation, &expression[1..]),
+        // reserved for future extensions
+        Some('=') | Some(',') | Some('!') | Some('@') | Some('|') => return Err(invalid()),
+        Some(_) => (Operator::Simple, expression),
+        None => return Err(invalid()),
+    };
+    let mut specs = Vec::new();
+    for spec in list.split(',') {
+        let (name, modifier) = if let Some(name) = spec.strip_suffix('*') {
+            (name, Modifier::Explode)
+        } else if let Some((name, len)) = spec.split_once(':') {
+            if len.is_empty() || len.len() > 4 || !len.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            match len.parse::<usize>() {
+                Ok(len) if len > 0 => (name, Modifier::Prefix(len)),
+                _ => return Err(invalid()),
+            }
+        } else {
+            (spec, Modifier::None)
+        };
+        if !is_var_name(name) {
+            return Err(invalid());
+        }
+        specs.push(VarSpec {
+            name: name.to_owned(),
+            modifier,
+        });
+    }
+    Ok(Part::Expression(op, specs))
+}
+
+fn is_var_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && bytes.iter().enumerate().all(|(i, b)| match b {
+            b'%' => bytes.len() > i + 2 && is_hex(bytes[i + 1]) && is_hex(bytes[i + 2]),
+            _ => b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.') || is_escape(bytes, i),
+        })
+}
+
+// whether `bytes[i]` is one of the two digits following a `%`
+fn is_escape(bytes: &[u8], i: usize) -> bool {
+    (i >= 1 && bytes[i - 1] == b'%') || (i >= 2 && bytes[i - 2] == b'%')
+}
+
+fn is_hex(b: u8) -> bool {
+    b.is_ascii_hexdigit()
+}
+
+impl Operator {
+    fn first(self) -> &'static str {
+        match self {
+            Operator::Simple | Operator::Reserved => "",
+            Operator::Fragment => "#",
+            Operator::Label => ".",
+            Operator::Path => "/",
+            Operator::PathParameter => ";",
+            Operator::Query => "?",
+            Operator::QueryContinuation => "&",
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Operator::Simple | Operator::Reserved | Operator::Fragment => ",",
+            Operator::Label => ".",
+            Operator::Path => "/",
+            Operator::PathParameter => ";",
+            Operator::Query | Operator::QueryContinuation => "&",
+        }
+    }
+
+    // whether values are written as `name=value`
+    fn named(self) -> bool {
+        matches!(
+            self,
+            Operator::PathParameter | Operator::Query | Operator::QueryContinuation
+        )
+    }
+
+    // what follows the name of an empty value
+    fn if_empty(self) -> &'static str {
+        match self {
+            Operator::Query | Operator::QueryContinuation => "=",
+            _ => "",
+        }
+    }
+
+    // whether reserved characters are kept as they are
+    fn allows_reserved(self) -> bool {
+        matches!(self, Operator::Reserved | Operator::Fragment)
+    }
+}
+
+fn is_pair(value: &Value) -> bool {
+    match value {
+        Value::Array(pair) => pair.len() == 2 && pair[0].is_string(),
+        _ => false,
+    }
+}
+
+fn is_defined(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Array(items) => items.iter().any(|item| !item.is_null()),
+        Value::Object(pairs) => pairs.values().any(|value| !value.is_null()),
+        _ => true,
+    }
+}
+
+fn expand_value(
+    out: &mut String,
+    op: Operator,
+    spec: &VarSpec,
+    value: &Value,
+) -> crate::Result<()> {
+    let reserved = op.allows_reserved();
+    let items: Vec<(Option<&str>, String)> = match value {
+        Value::Array(items) => items
+            .iter()
+            .filter(|item| !item.is_null())
+            .map(|item| (None, scalar(item)))
+            .collect(),
+        Value::Object(pairs) => pairs
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (Some(key.as_str()), scalar(value)))
+            .collect(),
+        _ => {
+            let mut value = scalar(value);
+            if let Modifier::Prefix(len) = spec.modifier {
+                if let Some((end, _)) = value.char_indices().nth(len) {
+                    value.truncate(end);
+                }
+            }
+            write_named(out, op, &spec.name, &value);
+            encode(out, &value, reserved);
+            return Ok(());
+        }
+    };
+    match spec.modifier {
+        Modifier::Prefix(_) => Err(crate::error::builder(format!(
+            "URI template variable {} is a list or a map, which can't take a prefix",
+            spec.name
+        ))),
+        Modifier::None => {
+            if op.named() {
+                out.push_str(&spec.name);
+                out.push('=');
+            }
+            for (i, (key, value)) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                if let Some(key) = key {
+                    encode(out, key, reserved);
+                    out.push(',');
+                }
+                encode(out, value, reserved);
+            }
+            Ok(())
+        }
+        Modifier::Explode => {
+            for (i, (key, value)) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(op.separator());
+                }
+                match key {
+                    Some(key) => {
+                        encode(out, key, reserved);
+                        out.push_str(if value.is_empty() && op.named() {
+                            op.if_empty()
+                        } else {
+                            "="
+                        });
+                    }
+                    None => write_named(out, op, &spec.name, value),
+                }
+                encode(out, value, reserved);
+            }
+            Ok(())
+        }
+    }
+}
+
+// `name=` or `name` before a value of a named operator
+fn write_named(out: &mut String, op: Operator, name: &str, value: &str) {
+    if op.named() {
+        out.push_str(name);
+        out.push_str(if value.is_empty() { op.if_empty() } else { "=" });
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Percent-encode `s` onto `out`, keeping unreserved characters and, if
+/// `reserved`, reserved characters and existing percent-encoded triplets.
+fn encode(out: &mut String, s: &str, reserved: bool) {
+    let bytes = s.as_bytes();
+    for (i, c) in s.char_indices() {
+        let keep = match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '.' | '_' | '~' => true,
+            ':' | '/' | '?' | '#' | '[' | ']' | '@' | '!' | '$' | '&' | '\'' | '(' | ')' | '*'
+            | '+' | ',' | ';' | '=' => reserved,
+            '%' => reserved && bytes.len() > i + 2 && is_hex(bytes[i + 1]) && is_hex(bytes[i + 2]),
+            _ => false,
+        };
+        if keep {
+            out.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                // writing to a String can't fail
+                let _ = write!(out, "%{:02X}", b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Value {
+        serde_json::json!({
+            "var": "value",
+            "hello": "Hello World!",
+            "path": "/foo/bar",
+            "empty": "",
+            "list": ["red", "green", "blue"],
+            "keys": { "comma": ",", "dot": ".", "semi": ";" },
+            "x": "1024",
+            "y": 768,
+            "undef": null,
+        })
+    }
+
+    fn check(template: &str, expected: &str) {
+        assert_eq!(expand(template, &vars()).unwrap(), expected, "{}", template);
+    }
+
+    #[lunatic::test]
+    fn expands_strings() {
+        check("{var}", "value");
+        check("{hello}", "Hello%20World%21");
+        check("{+hello}", "Hello%20World!");
+        check("{+path}/here", "/foo/bar/here");
+        check("{#path}", "#/foo/bar");
+        check("X{.var}", "X.value");
+        check("{/var,x}/here", "/value/1024/here");
+        check("{;x,y,empty}", ";x=1024;y=768;empty");
+        check("{?x,y,empty}", "?x=1024&y=768&empty=");
+        check("?fixed=yes{&x}", "?fixed=yes&x=1024");
+        check("{var:3}", "val");
+        check("{?undef,var}", "?var=value");
+        check("{undef}", "");
+    }
+
+    #[lunatic::test]
+    fn expands_lists_and_maps() {
+        check("{list}", "red,green,blue");
+        check("{list*}", "red,green,blue");
+        check("{/list*}", "/red/green/blue");
+        check("{?list}", "?list=red,green,blue");
+        check("{?list*}", "?list=red&list=green&list=blue");
+        check("{keys}", "comma,%2C,dot,.,semi,%3B");
+        check("{+keys}", "comma,,,dot,.,semi,;");
+        check("{keys*}", "comma=%2C,dot=.,semi=%3B");
+        check("{?keys*}", "?comma=%2C&dot=.&semi=%3B");
+        check("{;list*}", ";list=red;list=green;list=blue");
+    }
+
+    #[lunatic::test]
+    fn rejects_invalid_templates() {
+        assert!(UriTemplate::parse("/repos/{owner")
+            .unwrap_err()
+            .is_builder());
+        assert!(UriTemplate::parse("{}").is_err());
+        assert!(UriTemplate::parse("{=var}").is_err());
+        assert!(UriTemplate::parse("{var:0}").is_err());
+        assert!(UriTemplate::parse("{va r}").is_err());
+        assert!(expand("{list:2}", &vars()).is_err());
+        assert!(expand("{var}", &["not", "a", "map"]).is_err());
+    }
+
+    #[lunatic::test]
+    fn takes_pairs() {
+        let url = expand("/repos/{owner}/{repo}", &[("owner", "a b"), ("repo", "c")]).unwrap();
+        assert_eq!(url, "/repos/a%20b/c");
+    }
+}
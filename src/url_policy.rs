@@ -0,0 +1,212 @@
+//! Allowing and denying requests by URL.
+//!
+//! A [`UrlPolicy`] set with `ClientBuilder::url_policy` is checked before a
+//! request is sent, and again for every redirect, so a client running
+//! integrations written by someone else only ever reaches the hosts it is
+//! meant to:
+//!
+//! ```rust
+//! use nightfly::url_policy::UrlPolicy;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let policy = UrlPolicy::new()
+//!     .allow("api.example.com/v1/*")?
+//!     .allow("*.partner.example")?
+//!     .deny("api.example.com/v1/admin/*")?;
+//! let client = nightfly::Client::builder().url_policy(policy).build()?;
+//!
+//! let err = client.get("https://example.org/").send().unwrap_err();
+//! assert!(err.is_policy());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A pattern is a host, optionally followed by a path:
+//!
+//! - `example.com` matches that host only, `*.example.com` its subdomains,
+//!   and `*` any host.
+//! - A path, such as `/v1/*`, has to match the whole path of the URL, where
+//!   `*` stands for any run of characters. Without a path, any path
+//!   matches.
+//!
+//! A URL matching a deny pattern is refused. If there are allow patterns, a
+//! URL has to match one of them too. Refused requests fail with an error for
+//! which [`Error::is_policy`](crate::Error::is_policy) is true.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error;
+
+/// Host and path patterns a `Client` may or may not send requests to, see
+/// the module documentation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlPolicy {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Pattern {
+    host: String,
+    path: Option<String>,
+}
+
+impl UrlPolicy {
+    /// A policy allowing every URL.
+    pub fn new() -> UrlPolicy {
+        UrlPolicy::default()
+    }
+
+    /// Only allow URLs matching `pattern`, or one of the other allow
+    /// patterns.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `pattern` has no host, or a scheme, port or query.
+    pub fn allow(mut self, pattern: &str) -> crate::Result<UrlPolicy> {
+        self.allow.push(Pattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Refuse URLs matching `pattern`, even if an allow pattern matches
+    /// them too.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `pattern` has no host, or a scheme, port or query.
+    pub fn deny(mut self, pattern: &str) -> crate::Result<UrlPolicy> {
+        self.deny.push(Pattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Whether requests to `url` may be sent.
+    pub fn permits(&self, url: &Url) -> bool {
+        !self.deny.iter().any(|pattern| pattern.matches(url))
+            && (self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(url)))
+    }
+
+    /// Fails if requests to `url` may not be sent.
+    pub(crate) fn check(&self, url: &Url) -> crate::Result<()> {
+        if self.permits(url) {
+            Ok(())
+        } else {
+            Err(error::policy(url.clone()))
+        }
+    }
+
+    /// Whether every URL is allowed.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> crate::Result<Pattern> {
+        let invalid = || error::builder(format!("invalid URL policy pattern {:?}", pattern));
+        let (host, path) = match pattern.find('/') {
+            Some(i) => (&pattern[..i], Some(&pattern[i..])),
+            None => (pattern, None),
+        };
+        let valid_host = |host: &str| {
+            !host.is_empty()
+                && host
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.'))
+        };
+        let host_ok = host == "*"
+            || valid_host(host.strip_prefix("*.").unwrap_or(host))
+            || (host.starts_with('[') && host.ends_with(']'));
+        if !host_ok || path.is_some_and(|path| path.contains('?')) {
+            return Err(invalid());
+        }
+        Ok(Pattern {
+            host: host.to_ascii_lowercase(),
+            path: path.map(str::to_owned),
+        })
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return false,
+        };
+        let host_matches = if self.host == "*" {
+            true
+        } else if let Some(domain) = self.host.strip_prefix("*.") {
+            host.len() > domain.len()
+                && host.ends_with(domain)
+                && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+        } else {
+            host == self.host
+        };
+        host_matches
+            && match self.path {
+                Some(ref path) => glob(path.as_bytes(), url.path().as_bytes()),
+                None => true,
+            }
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of
+/// characters.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob(rest, &text[skip..])),
+        Some((c, rest)) => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[lunatic::test]
+    fn allow_and_deny() {
+        let policy = UrlPolicy::new()
+            .allow("api.example.com/v1/*")
+            .unwrap()
+            .allow("*.partner.example")
+            .unwrap()
+            .deny("api.example.com/v1/admin/*")
+            .unwrap();
+        assert!(policy.permits(&url("https://api.example.com/v1/users?page=2")));
+        assert!(!policy.permits(&url("https://api.example.com/v2/users")));
+        assert!(!policy.permits(&url("https://api.example.com/v1/admin/keys")));
+        assert!(policy.permits(&url("http://a.b.partner.example/anything")));
+        assert!(!policy.permits(&url("http://partner.example/")));
+        assert!(!policy.permits(&url("http://evilpartner.example/")));
+        assert!(policy
+            .check(&url("https://example.org/"))
+            .unwrap_err()
+            .is_policy());
+    }
+
+    #[lunatic::test]
+    fn deny_only() {
+        let policy = UrlPolicy::new().deny("*").unwrap();
+        assert!(!policy.permits(&url("http://example.com/")));
+        let policy = UrlPolicy::new()
+            .deny("[::1]")
+            .unwrap()
+            .deny("127.0.0.1")
+            .unwrap();
+        assert!(!policy.permits(&url("http://[::1]:8080/")));
+        assert!(!policy.permits(&url("http://127.0.0.1/")));
+        assert!(policy.permits(&url("http://example.com/")));
+        assert!(UrlPolicy::new().permits(&url("http://example.com/")));
+    }
+
+    #[lunatic::test]
+    fn rejects_invalid_patterns() {
+        assert!(UrlPolicy::new().allow("").unwrap_err().is_builder());
+        assert!(UrlPolicy::new().allow("https://example.com").is_err());
+        assert!(UrlPolicy::new().allow("example.com:8080").is_err());
+        assert!(UrlPolicy::new().allow("example.com/search?q=*").is_err());
+    }
+}
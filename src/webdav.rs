@@ -0,0 +1,68 @@
+//! WebDAV requests.
+//!
+//! `Client::propfind`, `Client::mkcol`, `Client::copy_to` and
+//! `Client::move_to` build the WebDAV requests with the headers they need,
+//! and `RequestBuilder::depth`, `RequestBuilder::destination` and
+//! `RequestBuilder::overwrite` set those headers on any request.
+//!
+//! ```rust
+//! use nightfly::webdav::Depth;
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! let base = "https://cloud.example.com/remote.php/dav/files/alice";
+//! client.mkcol(format!("{}/photos/", base)).send()?;
+//! client
+//!     .move_to(format!("{}/a.jpg", base), format!("{}/photos/a.jpg", base))
+//!     .overwrite(false)
+//!     .send()?;
+//! let listing = client.propfind(format!("{}/photos/", base), Depth::One).send()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use http::header::HeaderName;
+use http::Method;
+
+/// `Depth` header, which tells how far below a collection a request applies.
+pub static DEPTH: HeaderName = HeaderName::from_static("depth");
+/// `Destination` header, the target of a `COPY` or `MOVE`.
+pub static DESTINATION: HeaderName = HeaderName::from_static("destination");
+/// `Overwrite` header, whether a `COPY` or `MOVE` may replace the target.
+pub static OVERWRITE: HeaderName = HeaderName::from_static("overwrite");
+
+/// How far below a collection a request applies, see
+/// `RequestBuilder::depth`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Depth {
+    /// Only the resource itself.
+    Zero,
+    /// The resource and its direct members.
+    One,
+    /// The resource and all of its members, at any depth. Many servers
+    /// refuse this for `PROPFIND`.
+    Infinity,
+}
+
+impl Depth {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity",
+        }
+    }
+}
+
+impl fmt::Display for Depth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The WebDAV method `name`, such as `PROPFIND`.
+pub(crate) fn method(name: &'static str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("WebDAV method names are valid")
+}
@@ -116,6 +116,7 @@ fn test_chunked_gzip_body() {
 
     let client = nightfly::Client::new();
     let res = client.get(&format!("http://{}/gzip", ADDR)).send().unwrap();
+    assert!(res.headers().get("content-encoding").is_none());
 
     let body = res.text().unwrap();
 
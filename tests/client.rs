@@ -43,6 +43,95 @@ fn auto_headers(req: RequestContext) -> SubmsResponse {
     http::Response::default()
 }
 
+fn request_id(req: RequestContext) -> SubmsResponse {
+    let id = req.headers()["x-request-id"].to_str().unwrap();
+    assert_eq!(id.len(), 36);
+    SubmsResponse::new(id.as_bytes().to_vec())
+}
+
+fn api_key_header(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers()["x-api-key"], "s3cr3t");
+    SubmsResponse::default()
+}
+
+fn api_key_query(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.uri().query(), Some("page=2&api_key=s3cr3t"));
+    SubmsResponse::default()
+}
+
+fn signed(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers()["x-key-id"], "k1");
+    // sha256 of the empty body
+    assert_eq!(
+        req.headers()["x-content-sha256"],
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    SubmsResponse::default()
+}
+
+fn browser(req: RequestContext) -> SubmsResponse {
+    assert!(req.headers()["user-agent"]
+        .to_str()
+        .unwrap()
+        .starts_with("Mozilla/5.0"));
+    assert_eq!(req.headers()["accept-language"], "en-US,en;q=0.5");
+    SubmsResponse::default()
+}
+
+fn duplicate_headers() -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("x-dup", "first")
+        .header("x-other", "between")
+        .header("x-dup", "second")
+        .body(vec![])
+        .unwrap()
+}
+
+fn etag(req: RequestContext) -> SubmsResponse {
+    if req.headers().get("if-none-match").map(|v| v == "\"v1\"") == Some(true) {
+        return SubmsResponse::builder().status(304).body(vec![]).unwrap();
+    }
+    SubmsResponse::builder()
+        .header("etag", "\"v1\"")
+        .header("content-type", "application/json")
+        .body(br#"{"n":1}"#.to_vec())
+        .unwrap()
+}
+
+fn json_item(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers()["accept"], "application/json");
+    SubmsResponse::builder()
+        .header("content-type", "application/json")
+        .body(br#"{"id":7,"name":"lamp"}"#.to_vec())
+        .unwrap()
+}
+
+fn json_items(body: Vec<u8>, headers: HeaderMap) -> SubmsResponse {
+    assert_eq!(headers["accept"], "application/json");
+    assert_eq!(headers["content-type"], "application/json");
+    assert_eq!(body, br#"{"name":"desk"}"#.to_vec());
+    SubmsResponse::builder()
+        .status(201)
+        .header("content-type", "application/json")
+        .body(br#"{"id":8,"name":"desk"}"#.to_vec())
+        .unwrap()
+}
+
+fn outbox_ok(body: Vec<u8>, headers: HeaderMap) -> SubmsResponse {
+    assert_eq!(body, b"21.5".to_vec());
+    SubmsResponse::builder()
+        .status(201)
+        .body(headers["idempotency-key"].as_bytes().to_vec())
+        .unwrap()
+}
+
+fn outbox_busy() -> SubmsResponse {
+    SubmsResponse::builder()
+        .status(503)
+        .body(Default::default())
+        .unwrap()
+}
+
 fn get_handler() -> SubmsResponse {
     SubmsResponse::new("pipe me".into())
 }
@@ -60,8 +149,19 @@ static ROUTER: RouterFn = router! {
     GET "/text" => text
     GET "/user-agent" => user_agent
     GET "/auto_headers" => auto_headers
+    GET "/request_id" => request_id
+    GET "/api_key/header" => api_key_header
+    GET "/api_key/query" => api_key_query
+    GET "/signed" => signed
+    GET "/duplicate_headers" => duplicate_headers
+    GET "/browser" => browser
+    GET "/etag" => etag
+    GET "/json/item" => json_item
+    POST "/json/items" => json_items
     GET "/get" => get_handler
     POST "/pipe" => pipe_response
+    POST "/outbox/ok" => outbox_ok
+    POST "/outbox/busy" => outbox_busy
 };
 static ADDR: &'static str = "0.0.0.0:3002";
 
@@ -104,6 +204,140 @@ fn test_user_agent() {
     assert_eq!(res.status(), nightfly::StatusCode::OK);
 }
 
+#[lunatic::test]
+fn test_request_id() {
+    let _ = server::ensure_server();
+
+    let url = format!("http://{}/request_id", ADDR);
+    let res = nightfly::Client::builder()
+        .request_id(true)
+        .build()
+        .expect("client builder")
+        .get(&url)
+        .send()
+        .expect("request");
+
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    let id = res.request_id().expect("request id").to_string();
+    assert_eq!(res.text().expect("text"), id);
+}
+
+#[lunatic::test]
+fn test_recent_requests() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder()
+        .request_id(true)
+        .record_requests(2)
+        .build()
+        .expect("client builder");
+    for path in ["/text", "/request_id", "/get"] {
+        client
+            .get(&format!("http://{}{}", ADDR, path))
+            .send()
+            .expect("request");
+    }
+
+    let recent = client.recent_requests();
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent[0].url.path(), "/request_id");
+    assert_eq!(recent[1].url.path(), "/get");
+    assert_eq!(recent[1].method, "GET");
+    assert_eq!(recent[1].status, Some(200));
+    assert!(recent[1].request_id.is_some());
+}
+
+#[lunatic::test]
+fn test_api_key() {
+    let _ = server::ensure_server();
+
+    let res = nightfly::Client::builder()
+        .api_key("s3cr3t", nightfly::Placement::Header("X-Api-Key"))
+        .build()
+        .expect("client builder")
+        .get(&format!("http://{}/api_key/header", ADDR))
+        .send()
+        .expect("request");
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+
+    let res = nightfly::Client::builder()
+        .api_key("s3cr3t", nightfly::Placement::Query("api_key"))
+        .build()
+        .expect("client builder")
+        .get(&format!("http://{}/api_key/query?page=2", ADDR))
+        .send()
+        .expect("request");
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KeyIdSigner {
+    key_id: String,
+}
+
+impl nightfly::signing::RequestSigner for KeyIdSigner {
+    fn sign(
+        &self,
+        req: &nightfly::signing::SigningRequest<'_>,
+    ) -> Result<HeaderMap, nightfly::signing::BoxError> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-key-id", self.key_id.parse()?);
+        headers.insert("x-content-sha256", req.body_sha256.parse()?);
+        Ok(headers)
+    }
+}
+
+#[lunatic::test]
+fn test_request_signer() {
+    let _ = server::ensure_server();
+
+    let url = format!("http://{}/signed", ADDR);
+    let res = nightfly::Client::builder()
+        .request_signer(KeyIdSigner {
+            key_id: "k1".to_string(),
+        })
+        .build()
+        .expect("client builder")
+        .get(&url)
+        .send()
+        .expect("request");
+
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
+
+#[lunatic::test]
+fn test_browser_like() {
+    let _ = server::ensure_server();
+
+    let res = nightfly::ClientBuilder::browser_like()
+        .build()
+        .expect("client builder")
+        .get(&format!("http://{}/browser", ADDR))
+        .send()
+        .expect("request");
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
+
+#[lunatic::test]
+fn test_raw_headers() {
+    let _ = server::ensure_server();
+
+    let res = nightfly::get(&format!("http://{}/duplicate_headers", ADDR)).expect("request");
+    let raw: Vec<_> = res
+        .raw_headers()
+        .filter(|(name, _)| name.to_ascii_lowercase().starts_with("x-"))
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.to_vec()))
+        .collect();
+    assert_eq!(
+        raw,
+        vec![
+            ("x-dup".to_string(), b"first".to_vec()),
+            ("x-dup".to_string(), b"second".to_vec()),
+            ("x-other".to_string(), b"between".to_vec()),
+        ]
+    );
+}
+
 #[lunatic::test]
 fn test_response_text() {
     let _ = server::ensure_server();
@@ -177,6 +411,19 @@ fn body_pipe_response() {
     assert_eq!(res2.status(), nightfly::StatusCode::OK);
 }
 
+#[lunatic::test]
+fn body_from_reader() {
+    let _ = server::ensure_server();
+
+    let res = Client::new()
+        .post(&format!("http://{}/pipe", ADDR))
+        .body(nightfly::Body::from_reader(&b"pipe me"[..], Some(7)))
+        .send()
+        .expect("upload");
+
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
+
 // #[lunatic::test]
 // fn overridden_dns_resolution_with_gai() {
 //     let _ = server::ensure_server();
@@ -378,3 +625,146 @@ fn test_allowed_methods() {
 
     assert!(resp.is_err());
 }
+
+#[lunatic::test]
+fn test_fetch_json_cached() {
+    let _ = server::ensure_server();
+
+    let client = Client::new();
+    let url = format!("http://{}/etag", ADDR);
+    let mut entry = nightfly::CacheEntry::<serde_json::Value>::new();
+
+    let value = client.fetch_json_cached(&url, &mut entry).unwrap();
+    assert_eq!(value["n"], 1);
+    assert!(entry.changed());
+    assert_eq!(entry.etag(), Some("\"v1\""));
+
+    let value = client.fetch_json_cached(&url, &mut entry).unwrap();
+    assert_eq!(value["n"], 1);
+    assert!(!entry.changed());
+}
+
+#[lunatic::test]
+fn test_json_one_liners() {
+    let _ = server::ensure_server();
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Item {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+        name: String,
+    }
+
+    let client = Client::new();
+    let item: Item = client
+        .get_json(&format!("http://{}/json/item", ADDR))
+        .unwrap();
+    assert_eq!(
+        item,
+        Item {
+            id: Some(7),
+            name: "lamp".to_string()
+        }
+    );
+
+    let new = Item {
+        id: None,
+        name: "desk".to_string(),
+    };
+    let item: Item = client
+        .post_json(&format!("http://{}/json/items", ADDR), &new)
+        .unwrap();
+    assert_eq!(item.id, Some(8));
+
+    let err = client
+        .get_json::<Item, _>(&format!("http://{}/json/missing", ADDR))
+        .unwrap_err();
+    assert_eq!(err.status(), Some(nightfly::StatusCode::NOT_FOUND));
+}
+
+#[lunatic::test]
+fn test_outbox_replay() {
+    let _ = server::ensure_server();
+
+    let mut client = Client::new();
+    let mut outbox = nightfly::outbox::Outbox::new().backoff(
+        std::time::Duration::from_secs(60),
+        std::time::Duration::from_secs(60),
+    );
+    let ok = outbox
+        .push(
+            client
+                .post(&format!("http://{}/outbox/ok", ADDR))
+                .body("21.5")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    outbox
+        .push(
+            client
+                .post(&format!("http://{}/outbox/busy", ADDR))
+                .body("21.5")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+    let delivered = outbox.replay(&mut client).unwrap();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].0, ok);
+    let res = delivered[0].1.as_ref().unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::CREATED);
+    assert_eq!(res.body(), ok.as_bytes());
+
+    // the busy request waits for its backoff
+    assert_eq!(outbox.len(), 1);
+    assert!(outbox.replay(&mut client).unwrap().is_empty());
+    assert!(outbox.next_attempt().unwrap() > std::time::SystemTime::now());
+
+    // nothing listens there
+    let req = client.post("http://127.0.0.1:1/").build().unwrap();
+    assert!(outbox.send(&mut client, req).unwrap().is_none());
+    assert_eq!(outbox.len(), 2);
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct OnlyPort(u16);
+
+impl nightfly::connect::ConnectHook for OnlyPort {
+    fn connected(
+        &self,
+        stream: &mut lunatic::net::TcpStream,
+        url: &nightfly::Url,
+    ) -> Result<(), nightfly::signing::BoxError> {
+        if url.port() != Some(self.0) {
+            return Err("unexpected port".into());
+        }
+        stream.set_write_timeout(Some(std::time::Duration::from_secs(5)))?;
+        Ok(())
+    }
+}
+
+#[lunatic::test]
+fn test_connect_hook() {
+    let _ = server::ensure_server();
+
+    let port = ADDR.rsplit(':').next().unwrap().parse().unwrap();
+    let res = Client::builder()
+        .connect_hook(OnlyPort(port))
+        .build()
+        .expect("client builder")
+        .get(&format!("http://{}/text", ADDR))
+        .send()
+        .expect("request");
+    assert_eq!(res.text().unwrap(), "Hello");
+
+    let err = Client::builder()
+        .connect_hook(OnlyPort(port + 1))
+        .build()
+        .expect("client builder")
+        .get(&format!("http://{}/text", ADDR))
+        .send()
+        .unwrap_err();
+    assert!(err.is_connect());
+}
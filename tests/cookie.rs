@@ -167,6 +167,53 @@ fn cookie_store_simple() {
     client.get(&url).send().unwrap();
 }
 
+#[lunatic::test]
+fn cookie_store_restored_from_snapshot() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder().build().unwrap();
+    let url = format!("http://{}/1", ADDR);
+    client.get(&url).send().unwrap();
+
+    let snapshot = client.cookie_snapshot();
+    assert_eq!(snapshot.len(), 1);
+
+    // the cookie set for the first client is sent by the second one
+    let client = nightfly::Client::builder()
+        .restore_cookies(snapshot)
+        .build()
+        .unwrap();
+    let url = format!("http://{}/2", ADDR);
+    client.get(&url).send().unwrap();
+}
+
+#[lunatic::test]
+fn cookie_store_rejects_a_bad_snapshot() {
+    let snapshot: nightfly::cookie::SerializableJar =
+        serde_json::from_str(r#"{"cookies":["not a cookie"]}"#).unwrap();
+    assert!(nightfly::Client::builder()
+        .restore_cookies(snapshot)
+        .build()
+        .is_err());
+}
+
+#[lunatic::test]
+fn cookie_store_skipped_by_no_cookies() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder().build().unwrap();
+    let url = format!("http://{}/1", ADDR);
+    client.get(&url).no_cookies().send().unwrap();
+    assert!(client.cookie_snapshot().is_empty());
+
+    client.get(&url).send().unwrap();
+    assert_eq!(client.cookie_snapshot().len(), 1);
+
+    // `/max-age` fails if it receives a cookie
+    let url = format!("http://{}/max-age", ADDR);
+    client.get(&url).no_cookies().send().unwrap();
+}
+
 #[lunatic::test]
 fn cookie_store_overwrite_existing() {
     let _ = server::ensure_server();
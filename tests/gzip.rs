@@ -1,5 +1,9 @@
 mod support;
 
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use flate2::{write::GzEncoder, Compression};
 use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
 use support::RouterFn;
 
@@ -13,6 +17,21 @@ fn gzip(req: RequestContext) -> SubmsResponse {
         .unwrap()
 }
 
+fn gzipped_text() -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"Hello tee").unwrap();
+    encoder.finish().unwrap()
+}
+
+fn tee() -> SubmsResponse {
+    let body = gzipped_text();
+    SubmsResponse::builder()
+        .header("content-encoding", "gzip")
+        .header("content-length", body.len())
+        .body(body)
+        .unwrap()
+}
+
 fn accept(req: RequestContext) -> SubmsResponse {
     assert_eq!(req.headers()["accept"], "application/json");
     assert!(req.headers()["accept-encoding"]
@@ -32,6 +51,7 @@ static ADDR: &'static str = "0.0.0.0:3001";
 
 static ROUTER: RouterFn = router! {
     HEAD "/gzip" => gzip
+    GET "/tee" => tee
     GET "/accept" => accept
     GET "/accept-encoding" => accept_encoding
 };
@@ -92,3 +112,47 @@ fn test_accept_encoding_header_is_not_changed_if_set() {
 
     assert_eq!(res.status(), nightfly::StatusCode::OK);
 }
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[lunatic::test]
+fn test_tee_copies_raw_body() {
+    let _ = gzip_server::ensure_server();
+
+    let raw = SharedBuf::default();
+    let res = nightfly::Client::new()
+        .get(&format!("http://{}/tee", ADDR))
+        .tee(raw.clone())
+        .send()
+        .unwrap();
+
+    assert_eq!(res.text().unwrap(), "Hello tee");
+    assert_eq!(*raw.0.lock().unwrap(), gzipped_text());
+}
+
+#[lunatic::test]
+fn test_hash_body_of_decoded_body() {
+    use sha2::{Digest, Sha256};
+
+    let _ = gzip_server::ensure_server();
+
+    let res = nightfly::Client::new()
+        .get(&format!("http://{}/tee", ADDR))
+        .hash_body(nightfly::Algorithm::Sha256)
+        .send()
+        .unwrap();
+
+    let expected = Sha256::digest(b"Hello tee");
+    assert_eq!(res.body_hash(), Some(&expected[..]));
+}
@@ -69,6 +69,35 @@ fn mid_server(req: RequestContext) -> SubmsResponse {
         .unwrap()
 }
 
+fn mid_server_same_site() -> SubmsResponse {
+    http::Response::builder()
+        .status(302)
+        .header("location", format!("http://{}/end-same-site", END_ADDR))
+        .body(Default::default())
+        .unwrap()
+}
+
+fn end_server_same_site(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers()["cookie"], "foo=bar");
+    assert_eq!(req.headers().get("authorization"), None);
+    http::Response::default()
+}
+
+fn mid_server_credentials() -> SubmsResponse {
+    http::Response::builder()
+        .status(302)
+        .header("location", format!("http://{}/end-credentials", END_ADDR))
+        .body(Default::default())
+        .unwrap()
+}
+
+fn end_server_credentials(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers().get("authorization"), None);
+    assert_eq!(req.headers().get("x-api-key"), None);
+    assert_eq!(req.uri().query(), None);
+    http::Response::default()
+}
+
 fn loop_handler(req: RequestContext) -> SubmsResponse {
     assert_eq!(req.uri(), "/loop");
     http::Response::builder()
@@ -134,6 +163,8 @@ static ROUTER: RouterFn = router! {
     GET "/dst" => dst_get
     POST "/dst" => dst_post
     GET "/sensitive" => mid_server
+    GET "/sensitive-same-site" => mid_server_same_site
+    GET "/credentials" => mid_server_credentials
     GET "/loop" => loop_handler
     GET "/no-redirect" => no_redirect
     GET "/no-refer" => no_referer
@@ -145,6 +176,8 @@ static ROUTER: RouterFn = router! {
 
 static END_ROUTER: RouterFn = router! {
     GET "/end" => end_server
+    GET "/end-same-site" => end_server_same_site
+    GET "/end-credentials" => end_server_credentials
 };
 
 static ADDR: &'static str = "0.0.0.0:3000";
@@ -232,6 +265,56 @@ fn test_redirect_removes_sensitive_headers() {
     assert_eq!(res.status, 200);
 }
 
+#[lunatic::test]
+fn test_redirect_auth_policy_keeps_same_site_cookies() {
+    use nightfly::redirect::{Reach, RedirectAuthPolicy};
+
+    let _ = server::ensure_server();
+    let _ = end_server::ensure_server();
+    // only the port changes, so it's another origin on the same site
+    let res = nightfly::Client::builder()
+        .redirect_auth(RedirectAuthPolicy::new().cookies(Reach::SameSite))
+        .build()
+        .unwrap()
+        .get(&format!("http://{}/sensitive-same-site", ADDR))
+        .header(nightfly::header::COOKIE, "foo=bar")
+        .header(nightfly::header::AUTHORIZATION, "Bearer t")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, 200);
+}
+
+#[lunatic::test]
+fn test_redirect_drops_client_credentials_on_other_origins() {
+    use nightfly::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use nightfly::Placement;
+
+    let _ = server::ensure_server();
+    let _ = end_server::ensure_server();
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+    let url = format!("http://{}/credentials", ADDR);
+
+    let res = nightfly::Client::builder()
+        .default_headers(headers)
+        .api_key("s3cr3t", Placement::Header("X-Api-Key"))
+        .build()
+        .unwrap()
+        .get(&url)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, 200);
+
+    let res = nightfly::Client::builder()
+        .api_key("s3cr3t", Placement::Query("key"))
+        .build()
+        .unwrap()
+        .get(&url)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, 200);
+}
+
 #[lunatic::test]
 fn test_redirect_policy_can_return_errors() {
     let _ = server::ensure_server();
@@ -55,6 +55,38 @@ fn request_timeout() {
 
     assert!(err.is_timeout());
     assert_eq!(err.url().map(|u| u.as_str()), Some(url.as_str()));
+
+    let detail = err.timeout_detail().unwrap();
+    assert_eq!(detail.phase, nightfly::TimeoutPhase::Request);
+    assert_eq!(detail.allowed, Duration::from_millis(500));
+    assert!(detail.elapsed >= detail.allowed);
+}
+
+#[lunatic::test]
+fn first_byte_timeout() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder().build().unwrap();
+
+    let url = format!("http://{}/slow", ADDR);
+
+    let res = client
+        .get(&url)
+        .timeouts([
+            (
+                nightfly::TimeoutPhase::FirstByte,
+                Duration::from_millis(500),
+            ),
+            (nightfly::TimeoutPhase::Request, Duration::from_secs(5)),
+        ])
+        .send();
+
+    let err = res.unwrap_err();
+
+    assert!(err.is_timeout());
+    let detail = err.timeout_detail().unwrap();
+    assert_eq!(detail.phase, nightfly::TimeoutPhase::FirstByte);
+    assert_eq!(detail.allowed, Duration::from_millis(500));
 }
 
 // #[lunatic::test]